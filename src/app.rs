@@ -1,27 +1,68 @@
+use crate::commands::{self, Hook, PreparedCommand};
+use crate::config::{self, ActionTarget, Config, ConfigWatcher, ConfirmLevel, PreviewConfig};
+use crate::credentials::{AuthRequired, Credentials};
 use crate::git;
+use crate::keymap::{Action, Key, KeyInput, KeySequence, Keymap};
+use crate::network::{AutoFetch, NetworkOutcome, NetworkTask};
+use crate::precommit::{self, PreCommitRun};
+use crate::profile::StartupProfile;
+use crate::state::{self, FileRef, RepoState};
 use crate::types::{
-    BranchInfo, ConfirmAction, ConfirmPrompt, DiffContent, FileEntry, FlashMessage, MultiSelectSet,
-    Section, UndoAction, VisibleRow,
+    BranchInfo, BranchPrompt, CommitPrompt, ConfirmAction, ConfirmPrompt, CredentialPrompt,
+    DiffContent, DiffLine, DiffLineKind, DiffSelection, DivergenceGraph, ErrorDetail, FileEntry,
+    FileStatus, FlashMessage, Focus, MessagePanel, Modal, MultiSelectSet, NetworkOp, PollReason,
+    PreCommitPanel, RebaseProgress, RecoverPanel, RemoteInput, RemoteInputKind, RemotePanel,
+    Section, SetupForm, StashPanel, UndoAction, UpstreamInfo, UpstreamNotice, VisibleRow,
 };
-use crate::ui;
-use crate::watcher::{FileWatcher, WatcherEvent};
-use anyhow::Result;
+use crate::ui::{self, status_bar::CountLink};
+use crate::watcher::{self, FileWatcher, WatcherEvent};
+use anyhow::{Context, Result};
 use crossterm::{
+    clipboard::CopyToClipboard,
     event::{
-        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
-        MouseEventKind,
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, MouseEventKind,
     },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use git2::Repository;
 use ratatui::{backend::CrosstermBackend, layout::Rect, Terminal};
+use std::collections::{HashSet, VecDeque};
 use std::io;
-use std::path::Path;
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::TryRecvError;
 use std::time::{Duration, Instant};
 
-const FLASH_TIMEOUT: Duration = Duration::from_secs(3);
+const TICK: Duration = Duration::from_millis(100);
+/// Tick used in low-bandwidth mode, where idle frames aren't redrawn.
+const LOW_BANDWIDTH_TICK: Duration = Duration::from_millis(500);
+const GRAPH_COMMIT_LIMIT: usize = 10;
+/// Number of flash messages kept for the message history panel.
+const MESSAGE_HISTORY_LIMIT: usize = 100;
+
+/// How to raise the inotify limit, shown when it is reached.
+const WATCH_LIMIT_HINT: &str = "Raise it with: sudo sysctl fs.inotify.max_user_watches=524288";
+
+/// Watcher errors tolerated before switching to polling for good.
+const WATCHER_ERROR_LIMIT: usize = 3;
+
+/// How often the config file is checked for edits.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Changed paths past which a watcher event refreshes the whole status.
+const INCREMENTAL_PATH_LIMIT: usize = 256;
+
+/// Command-line settings. Unlike [`Config`], these survive config reloads.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// `--low-bandwidth`: force `ui.low_bandwidth` on.
+    pub low_bandwidth: bool,
+    /// `--no-watch`: poll for changes instead of starting a file watcher.
+    pub no_watch: bool,
+    /// `--profile`: report how long each startup phase took.
+    pub profile: bool,
+}
 
 /// Application state for the interactive git status TUI.
 pub struct App {
@@ -31,18 +72,55 @@ pub struct App {
     pub unstaged_files: Vec<FileEntry>,
 
     pub highlight_index: Option<usize>,
-    pub selected: Option<(Section, String)>,
+    pub selected: Option<(Section, Vec<u8>)>,
     pub multi_selected: MultiSelectSet,
     pub file_list_scroll: usize,
 
     pub current_diff: DiffContent,
     pub diff_scroll: usize,
+    /// Show small binary files as a hexdump instead of their metadata.
+    pub hexdump: bool,
+    pub diff_flags: git::DiffFlags,
+    /// Show very long lines in full instead of clipped.
+    pub expand_long_lines: bool,
+    /// Which panel navigation keys act on.
+    pub focus: Focus,
+    /// Index of the diff line the cursor is on.
+    pub diff_cursor: usize,
+    /// Where the diff's visual selection started, if active.
+    pub visual_anchor: Option<usize>,
+    /// Text waiting to be copied to the terminal's clipboard.
+    pub clipboard: Option<String>,
+    /// A `[[commands]]` entry waiting to run with the UI suspended.
+    pub pending_command: Option<PreparedCommand>,
+    /// Set when status is refreshed by polling rather than the file watcher.
+    pub polling: Option<PollReason>,
 
     pub staged_count: usize,
     pub unstaged_count: usize,
     pub untracked_count: usize,
 
     pub branch: BranchInfo,
+    pub upstream: Option<UpstreamInfo>,
+    /// Shown while the upstream has commits the user hasn't pulled or dismissed.
+    pub upstream_notice: Option<UpstreamNotice>,
+    /// The notice the user last dismissed; it reappears only once more
+    /// upstream commits arrive.
+    dismissed_notice: Option<UpstreamNotice>,
+    pub rebase_progress: Option<RebaseProgress>,
+    /// The branch a detached HEAD was checked out from, if it still exists.
+    pub previous_branch: Option<String>,
+
+    /// A partial clone, where a diff may need blobs fetched first.
+    partial_clone: bool,
+    /// Blobs a fetch has already failed for, so refreshes don't retry them.
+    unfetchable: HashSet<git2::Oid>,
+    status_cache: git::StatusCache,
+    /// How long startup took, for `--profile`.
+    pub startup: StartupProfile,
+
+    pub show_graph: bool,
+    pub divergence: Option<DivergenceGraph>,
 
     visible_rows: Vec<VisibleRow>,
 
@@ -50,17 +128,61 @@ pub struct App {
 
     pub file_list_area: Rect,
     pub diff_area: Rect,
+    pub status_bar_area: Rect,
+    /// Where the status bar's clickable counts were last drawn.
+    pub count_links: Vec<(Range<u16>, CountLink)>,
 
     pub confirm_prompt: Option<ConfirmPrompt>,
     pub flash_message: Option<FlashMessage>,
+    /// Every flash shown this session, oldest first, capped at
+    /// `MESSAGE_HISTORY_LIMIT`.
+    pub message_history: VecDeque<FlashMessage>,
     pub last_action: Option<UndoAction>,
+    pub modal: Option<Modal>,
+    pub network_task: Option<NetworkTask>,
+    pre_commit: Option<PreCommitRun>,
+    /// Whether the working tree has a `.pre-commit-config.yaml`.
+    pub has_pre_commit: bool,
+    /// Unstaged files that pre-commit hooks rewrote, flagged for another
+    /// look before they're staged.
+    pub needs_review: HashSet<String>,
+    /// Files whose collapsed (generated or minified) diff was expanded.
+    expanded_diffs: HashSet<Vec<u8>>,
+    /// The directory the app was started in, relative to the repository
+    /// root; `None` when started at the root or outside the working tree.
+    pub launch_dir: Option<String>,
+    /// Show paths relative to `launch_dir` instead of the root.
+    pub relative_paths: bool,
+    pub auto_fetch: Option<AutoFetch>,
+    pub config: Config,
+    pub options: RunOptions,
+    pub keymap: Keymap,
+    /// Keys typed so far toward a multi-key binding.
+    pub key_sequence: KeySequence,
 }
 
 impl App {
     pub fn new(path: &str) -> Result<Self> {
-        let repo = git::get_repo(path)?;
+        let mut startup = StartupProfile::default();
+        let repo = startup.time("repo open", || git::get_repo(path))?;
         let branch = git::get_branch_info(&repo);
-        let status = git::get_status(&repo)?;
+        let upstream = git::get_upstream_info(&repo);
+        let upstream_notice = upstream_notice(upstream.as_ref(), None);
+        let rebase_progress = git::get_rebase_progress(&repo);
+        let previous_branch = match branch {
+            BranchInfo::Detached(_) => git::previous_branch(&repo),
+            _ => None,
+        };
+        let mut status_cache = git::StatusCache::default();
+        let status = startup.time("status", || {
+            git::get_status_cached(&repo, &mut status_cache, PreviewConfig::default())
+        })?;
+        startup.record("  line counts", status.stats_time);
+        let has_pre_commit = repo.workdir().is_some_and(precommit::detect);
+        let partial_clone = git::is_partial_clone(&repo);
+        let launch_dir = std::env::current_dir()
+            .ok()
+            .and_then(|cwd| launch_dir(repo.workdir()?, &cwd));
 
         let visible_rows = build_visible_rows(&status.staged_files, &status.unstaged_files);
         let highlight_index = if visible_rows.is_empty() {
@@ -93,26 +215,223 @@ impl App {
             unstaged_count: status.unstaged_count,
             untracked_count: status.untracked_count,
             branch,
+            upstream,
+            upstream_notice,
+            dismissed_notice: None,
+            rebase_progress,
+            previous_branch,
+            partial_clone,
+            unfetchable: HashSet::new(),
+            status_cache,
+            startup,
+            hexdump: false,
+            expand_long_lines: false,
+            focus: Focus::FileList,
+            diff_cursor: 0,
+            visual_anchor: None,
+            clipboard: None,
+            pending_command: None,
+            polling: None,
+            diff_flags: git::DiffFlags::default(),
+            show_graph: false,
+            divergence: None,
             visible_rows,
             file_list_height: 0,
             file_list_area: Rect::default(),
             diff_area: Rect::default(),
+            status_bar_area: Rect::default(),
+            count_links: Vec::new(),
             confirm_prompt: None,
             flash_message: None,
+            message_history: VecDeque::new(),
             last_action: None,
+            modal: None,
+            network_task: None,
+            pre_commit: None,
+            has_pre_commit,
+            needs_review: HashSet::new(),
+            expanded_diffs: HashSet::new(),
+            launch_dir,
+            relative_paths: false,
+            auto_fetch: None,
+            config: Config::default(),
+            options: RunOptions::default(),
+            keymap: Keymap::default(),
+            key_sequence: KeySequence::default(),
         })
     }
 
+    fn refresh_upstream(&mut self) {
+        self.upstream = git::get_upstream_info(&self.repo);
+        if self.upstream.as_ref().is_none_or(|u| u.behind == 0) {
+            self.dismissed_notice = None;
+        }
+        self.upstream_notice =
+            upstream_notice(self.upstream.as_ref(), self.dismissed_notice.as_ref());
+    }
+
+    /// Hide the upstream notice until more upstream commits arrive.
+    /// Returns false if no notice was showing.
+    pub fn dismiss_upstream_notice(&mut self) -> bool {
+        match self.upstream_notice.take() {
+            Some(notice) => {
+                self.dismissed_notice = Some(notice);
+                true
+            }
+            None => false,
+        }
+    }
+
     fn refresh(&mut self) -> Result<()> {
+        let started = Instant::now();
+        let result = self.reload();
+        tracing::debug!(
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            staged = self.staged_count,
+            unstaged = self.unstaged_count,
+            untracked = self.untracked_count,
+            ok = result.is_ok(),
+            "refresh"
+        );
+        result
+    }
+
+    /// Re-read branch, upstream, and status from the repository.
+    fn reload(&mut self) -> Result<()> {
         self.branch = git::get_branch_info(&self.repo);
+        self.refresh_upstream();
+        self.rebase_progress = git::get_rebase_progress(&self.repo);
+        self.previous_branch = match self.branch {
+            BranchInfo::Detached(_) => git::previous_branch(&self.repo),
+            _ => None,
+        };
+        if self.show_graph {
+            self.divergence = git::get_divergence_graph(&self.repo, GRAPH_COMMIT_LIMIT);
+        }
 
-        let status = git::get_status(&self.repo)?;
+        let status =
+            git::get_status_cached(&self.repo, &mut self.status_cache, self.config.preview)?;
         self.staged_files = status.staged_files;
         self.unstaged_files = status.unstaged_files;
         self.staged_count = status.staged_count;
         self.unstaged_count = status.unstaged_count;
         self.untracked_count = status.untracked_count;
+        self.files_changed();
+        Ok(())
+    }
+
+    /// Refresh after the watcher saw `changed` (absolute paths) change:
+    /// re-read the status of just those paths and patch the file lists in
+    /// place, unless the change could affect other files as well.
+    pub fn refresh_paths(&mut self, changed: &[PathBuf]) -> Result<()> {
+        let Some(paths) = self.incremental_paths(changed) else {
+            return self.refresh();
+        };
+        let started = Instant::now();
+        let result = self.reload_paths(&paths);
+        tracing::debug!(
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            paths = paths.len(),
+            ok = result.is_ok(),
+            "incremental refresh"
+        );
+        result
+    }
+
+    /// `changed` as repository-relative paths, or `None` if a full refresh
+    /// is needed: for the index, HEAD, or anything else in `.git`, for ignore
+    /// and attribute files, inside nested repositories and submodules, for
+    /// renames, and for more paths than are worth patching one by one.
+    fn incremental_paths(&self, changed: &[PathBuf]) -> Option<Vec<Vec<u8>>> {
+        if changed.is_empty() || changed.len() > INCREMENTAL_PATH_LIMIT {
+            return None;
+        }
+        let workdir = self.workdir();
+        let mut paths = Vec::new();
+        for path in changed {
+            let relative = path.strip_prefix(workdir).ok()?;
+            let mut raw = git::raw_path(relative.as_os_str());
+            while raw.last() == Some(&b'/') {
+                raw.pop();
+            }
+            let name = relative.file_name()?;
+            if relative.components().any(|c| c.as_os_str() == ".git")
+                || [".gitignore", ".gitattributes", ".gitmodules"]
+                    .iter()
+                    .any(|n| name == *n)
+                || self.status_cache.in_nested_repo(&raw)
+                || raw.iter().any(|b| b"*?[\\".contains(b))
+            {
+                return None;
+            }
+            paths.push(raw);
+        }
+        paths.sort();
+        paths.dedup();
+
+        // A deleted file may pair up with a new one as a rename anywhere.
+        let renamed = |files: &[FileEntry]| files.iter().any(|f| f.raw_old_path.is_some());
+        let deleted = self
+            .unstaged_files
+            .iter()
+            .any(|f| f.status == FileStatus::Deleted);
+        if renamed(&self.staged_files) || renamed(&self.unstaged_files) || deleted {
+            return None;
+        }
+        Some(paths)
+    }
+
+    /// Re-read the status of `paths` and replace their entries in the file
+    /// lists. Falls back to [`App::reload`] if a rename or deletion turns up.
+    fn reload_paths(&mut self, paths: &[Vec<u8>]) -> Result<()> {
+        let status = git::get_status_of_paths(
+            &self.repo,
+            paths,
+            &mut self.status_cache,
+            self.config.preview,
+        )?;
+        let pairs_up = status.staged_files.iter().any(|f| f.raw_old_path.is_some())
+            || status
+                .unstaged_files
+                .iter()
+                .any(|f| f.raw_old_path.is_some() || f.status == FileStatus::Deleted);
+        if pairs_up {
+            return self.reload();
+        }
+
+        let changed = |file: &FileEntry| {
+            paths.iter().any(|path| {
+                file.raw_path
+                    .strip_prefix(path.as_slice())
+                    .is_some_and(|rest| rest.is_empty() || rest.first() == Some(&b'/'))
+            })
+        };
+        self.staged_files.retain(|f| !changed(f));
+        self.staged_files.extend(status.staged_files);
+        self.staged_files.sort_by(|a, b| a.path.cmp(&b.path));
+        self.unstaged_files.retain(|f| !changed(f));
+        self.unstaged_files.extend(status.unstaged_files);
+        self.unstaged_files.sort_by(|a, b| a.path.cmp(&b.path));
+        let counts = git::FileCounts::of(&self.staged_files, &self.unstaged_files);
+        self.staged_count = counts.staged;
+        self.unstaged_count = counts.unstaged;
+        self.untracked_count = counts.untracked;
+        self.files_changed();
+        Ok(())
+    }
 
+    /// Bring everything derived from the file lists up to date with them:
+    /// the visible rows, the highlight and selection, and the diff.
+    fn files_changed(&mut self) {
+        self.mark_generated();
+        self.has_pre_commit = precommit::detect(self.workdir());
+        let unstaged = &self.unstaged_files;
+        self.needs_review
+            .retain(|path| unstaged.iter().any(|f| &f.path == path));
+
+        let highlighted = self
+            .highlight_index
+            .and_then(|idx| Some((idx, self.visible_rows.get(idx)?.clone())));
         self.visible_rows = build_visible_rows(&self.staged_files, &self.unstaged_files);
 
         if self.visible_rows.is_empty() {
@@ -121,24 +440,18 @@ impl App {
             self.multi_selected.clear();
             self.current_diff = DiffContent::Clean;
             self.diff_scroll = 0;
-            return Ok(());
+            self.clamp_diff_cursor();
+            return;
         }
 
         self.prune_multi_select();
-
-        if let Some(idx) = self.highlight_index {
-            if idx >= self.visible_rows.len() {
-                self.highlight_index = Some(self.visible_rows.len() - 1);
-            }
-        } else {
-            self.highlight_index = Some(0);
-        }
+        self.highlight_index = Some(relocate_highlight(highlighted, &self.visible_rows));
 
         if let Some((section, path)) = &self.selected {
             let still_exists = self
                 .visible_rows
                 .iter()
-                .any(|r| r.section == *section && r.path == *path);
+                .any(|r| r.section == *section && r.raw_path == *path);
             if !still_exists {
                 self.selected = None;
                 self.current_diff = DiffContent::Empty;
@@ -149,47 +462,206 @@ impl App {
         } else {
             self.current_diff = DiffContent::Empty;
         }
+        self.clamp_diff_cursor();
 
         self.update_scroll_for_highlight();
-        Ok(())
     }
 
     fn update_diff_for_selected(&mut self) {
+        let missing = self.selected_missing_blobs();
+        if !missing.is_empty() && !self.selected_collapsed() {
+            // Diff once the blobs arrive; poll_network comes back here.
+            if self.network_task.is_none() {
+                self.network_task = Some(NetworkTask::spawn_object_fetch(
+                    self.repo.path(),
+                    missing,
+                    Credentials::new(),
+                ));
+            }
+            self.current_diff = DiffContent::Fetching;
+            self.clamp_diff_cursor();
+            return;
+        }
         if let Some((section, path)) = &self.selected {
             let file = match section {
-                Section::Staged => self.staged_files.iter().find(|f| &f.path == path),
-                Section::Unstaged => self.unstaged_files.iter().find(|f| &f.path == path),
+                Section::Staged => self.staged_files.iter().find(|f| &f.raw_path == path),
+                Section::Unstaged => self.unstaged_files.iter().find(|f| &f.raw_path == path),
             };
 
             if let Some(file) = file {
-                if file.status == crate::types::FileStatus::Conflict {
-                    self.current_diff = DiffContent::Conflict;
-                } else if file.is_binary {
-                    self.current_diff = DiffContent::Binary;
-                } else if file.status == crate::types::FileStatus::Untracked {
-                    self.current_diff = git::get_untracked_diff(&self.repo, path);
+                let expanded = self.expanded_diffs.contains(path);
+                self.current_diff = if file.generated && !expanded {
+                    DiffContent::Collapsed {
+                        added: file.added_lines,
+                        deleted: file.deleted_lines,
+                    }
                 } else {
-                    self.current_diff =
-                        git::get_diff(&self.repo, path, file.old_path.as_deref(), *section);
-                }
+                    let diff = git::get_file_diff(
+                        &self.repo,
+                        file,
+                        *section,
+                        self.diff_flags,
+                        self.hexdump,
+                        self.config.preview,
+                    );
+                    match git::minified_summary(&diff) {
+                        Some((lines, bytes)) if !expanded => DiffContent::Minified { lines, bytes },
+                        _ => diff,
+                    }
+                };
+            }
+        }
+        self.clamp_diff_cursor();
+    }
+
+    /// Blobs the selected file's diff needs that a partial clone hasn't
+    /// fetched, leaving out ones a fetch already failed for.
+    fn selected_missing_blobs(&self) -> Vec<git2::Oid> {
+        if !self.partial_clone {
+            return Vec::new();
+        }
+        let Some((section, file)) = self.selected_file() else {
+            return Vec::new();
+        };
+        let mut missing = git::missing_blobs(&self.repo, file, section);
+        missing.retain(|oid| !self.unfetchable.contains(oid));
+        missing
+    }
+
+    /// Whether the selected file is a generated one whose diff is hidden.
+    fn selected_collapsed(&self) -> bool {
+        self.selected_file().is_some_and(|(_, file)| {
+            file.generated && !self.expanded_diffs.contains(&file.raw_path)
+        })
+    }
+
+    /// Also mark files matching a `[generated]` pattern as generated.
+    fn mark_generated(&mut self) {
+        let patterns = &self.config.generated.patterns;
+        if patterns.is_empty() {
+            return;
+        }
+        let pathspec = match git2::Pathspec::new(patterns) {
+            Ok(pathspec) => pathspec,
+            Err(e) => {
+                tracing::warn!(error = %e, "invalid [generated] patterns");
+                return;
             }
+        };
+        for file in self.staged_files.iter_mut().chain(&mut self.unstaged_files) {
+            file.generated |=
+                pathspec.matches_path(Path::new(&file.path), git2::PathspecFlags::DEFAULT);
         }
     }
 
     fn select_current(&mut self) {
         if let Some(idx) = self.highlight_index {
             if let Some(row) = self.visible_rows.get(idx) {
-                self.selected = Some((row.section, row.path.clone()));
+                self.selected = Some((row.section, row.raw_path.clone()));
                 self.diff_scroll = 0;
+                self.diff_cursor = 0;
+                self.visual_anchor = None;
                 self.update_diff_for_selected();
             }
         }
     }
 
+    /// Stop relying on the file watcher and refresh on a timer instead.
+    pub fn fall_back_to_polling(&mut self, reason: PollReason) {
+        if self.polling.is_some() && self.polling != Some(PollReason::WatchLimit) {
+            return;
+        }
+        tracing::warn!(%reason, "file watcher unreliable; polling");
+        self.show_flash_error(format!(
+            "File watcher unreliable ({}); polling for changes",
+            reason
+        ));
+        self.polling = Some(reason);
+    }
+
+    /// Keep the partial watcher but poll as well, and say how to raise the
+    /// limit.
+    pub fn note_watch_limit(&mut self) {
+        if self.polling.is_some() {
+            return;
+        }
+        self.show_flash_error(format!(
+            "Too many directories to watch; polling for changes. {}",
+            WATCH_LIMIT_HINT
+        ));
+        self.polling = Some(PollReason::WatchLimit);
+    }
+
+    /// Identifies this repository in the UI state file.
+    pub fn state_key(&self) -> String {
+        self.workdir().to_string_lossy().into_owned()
+    }
+
+    /// The parts of the UI worth restoring next session.
+    pub fn ui_state(&self) -> RepoState {
+        let file_ref = |(section, path): &(Section, Vec<u8>)| FileRef {
+            section: *section,
+            path: path.clone(),
+        };
+        let highlighted = self
+            .highlight_index
+            .and_then(|idx| self.visible_rows.get(idx))
+            .map(|row| file_ref(&(row.section, row.raw_path.clone())));
+        RepoState {
+            highlighted,
+            selected: self.selected.as_ref().map(file_ref),
+            show_graph: self.show_graph,
+            ignore_eol: self.diff_flags.ignore_eol,
+        }
+    }
+
+    /// Put back a previous session's state, skipping files that are no
+    /// longer changed.
+    pub fn restore_ui_state(&mut self, state: RepoState) {
+        if state.show_graph != self.show_graph {
+            self.toggle_graph();
+        }
+        self.diff_flags.ignore_eol = state.ignore_eol;
+        let row_of = |rows: &[VisibleRow], file: &FileRef| {
+            rows.iter()
+                .position(|r| r.section == file.section && r.raw_path == file.path)
+        };
+        if let Some(idx) = state
+            .selected
+            .and_then(|file| row_of(&self.visible_rows, &file))
+        {
+            self.highlight_index = Some(idx);
+            self.select_current();
+        }
+        if let Some(idx) = state
+            .highlighted
+            .and_then(|file| row_of(&self.visible_rows, &file))
+        {
+            self.highlight_index = Some(idx);
+        }
+        self.update_scroll_for_highlight();
+    }
+
+    /// Show the highlighted file's diff if none is open yet.
+    pub fn select_first_file(&mut self) {
+        if self.selected.is_none() {
+            self.select_current();
+        }
+    }
+
+    /// Move the highlight by `delta` and show that file's diff from the top.
+    fn select_adjacent_file(&mut self, delta: isize) {
+        let before = self.highlight_index;
+        self.move_highlight(delta);
+        if self.highlight_index != before || self.selected.is_none() {
+            self.select_current();
+        }
+    }
+
     pub fn toggle_multi_select(&mut self) {
         if let Some(idx) = self.highlight_index {
             if let Some(row) = self.visible_rows.get(idx) {
-                let key = (row.section, row.path.clone());
+                let key = (row.section, row.raw_path.clone());
                 if self.multi_selected.contains(&key) {
                     self.multi_selected.remove(&key);
                 } else {
@@ -199,6 +671,16 @@ impl App {
         }
     }
 
+    /// Show or hide the divergence graph panel.
+    pub fn toggle_graph(&mut self) {
+        self.show_graph = !self.show_graph;
+        self.divergence = if self.show_graph {
+            git::get_divergence_graph(&self.repo, GRAPH_COMMIT_LIMIT)
+        } else {
+            None
+        };
+    }
+
     pub fn clear_multi_select(&mut self) {
         self.multi_selected.clear();
     }
@@ -207,26 +689,171 @@ impl App {
         self.multi_selected.retain(|(section, path)| {
             self.visible_rows
                 .iter()
-                .any(|r| r.section == *section && &r.path == path)
+                .any(|r| r.section == *section && &r.raw_path == path)
         });
     }
 
-    pub fn get_action_targets(&self) -> Vec<(Section, String)> {
-        if self.multi_selected.is_empty() {
-            if let Some(idx) = self.highlight_index {
-                if let Some(row) = self.visible_rows.get(idx) {
-                    return vec![(row.section, row.path.clone())];
+    /// Files that `s`, `u`, and `d` act on, according to the configured
+    /// [`ActionTarget`].
+    pub fn get_action_targets(&self) -> Vec<(Section, Vec<u8>)> {
+        if !self.multi_selected.is_empty() {
+            return self.multi_selected.iter().cloned().collect();
+        }
+        match self.config.actions.target {
+            ActionTarget::Highlighted => self
+                .highlight_index
+                .and_then(|idx| self.visible_rows.get(idx))
+                .map(|row| vec![(row.section, row.raw_path.clone())])
+                .unwrap_or_default(),
+            ActionTarget::Selected => self.selected.iter().cloned().collect(),
+            ActionTarget::MultiSelect => vec![],
+        }
+    }
+
+    fn target_paths(&self) -> Vec<String> {
+        self.get_action_targets()
+            .into_iter()
+            .map(|(_, path)| git::display_path(&path))
+            .collect()
+    }
+
+    /// Status bar key hints for whatever the next file action would apply to.
+    pub fn context_hints(&self) -> Vec<(Action, &'static str)> {
+        let targets = self.get_action_targets();
+        let mut hints = Vec::new();
+
+        if self.focus == Focus::Diff {
+            let (stage, unstage, discard) = if self.visual_anchor.is_some() {
+                ("stage lines", "unstage lines", "discard lines")
+            } else {
+                ("stage line", "unstage line", "discard line")
+            };
+            match self.selected.as_ref().map(|(section, _)| *section) {
+                Some(Section::Unstaged) => {
+                    hints.push((Action::Stage, stage));
+                    hints.push((Action::Discard, discard));
                 }
+                Some(Section::Staged) => hints.push((Action::Unstage, unstage)),
+                None => {}
+            }
+            hints.push((Action::Copy, "copy"));
+            let on_file_header = self
+                .diff_text_lines()
+                .and_then(|lines| lines.get(self.diff_cursor))
+                .is_some_and(|line| git::diff_header_path(&line.content).is_some());
+            if on_file_header {
+                hints.push((Action::Select, "jump to file"));
+            }
+            if self.visual_anchor.is_some() {
+                hints.push((Action::Back, "cancel"));
+            } else {
+                hints.push((Action::VisualSelect, "select"));
+                hints.push((Action::NextFile, "next file"));
+                hints.push((Action::ToggleFocus, "files"));
+            }
+        } else if !self.multi_selected.is_empty() {
+            let any_in = |section| targets.iter().any(|(s, _)| *s == section);
+            if any_in(Section::Unstaged) {
+                hints.push((Action::Stage, "stage marked"));
+            }
+            if any_in(Section::Staged) {
+                hints.push((Action::Unstage, "unstage marked"));
+            }
+            if any_in(Section::Unstaged) {
+                hints.push((Action::Discard, "discard marked"));
+            }
+            hints.push((Action::Back, "clear marks"));
+        } else if let Some((section, path)) = targets.first() {
+            let status = self.file_in(*section, path).map(|f| f.status);
+            match (section, status) {
+                (Section::Staged, _) => hints.push((Action::Unstage, "unstage")),
+                (Section::Unstaged, Some(FileStatus::Conflict)) => {
+                    hints.push((Action::Stage, "mark resolved"));
+                }
+                (Section::Unstaged, Some(FileStatus::Untracked)) => {
+                    hints.push((Action::Stage, "add"));
+                    hints.push((Action::Discard, "delete"));
+                }
+                (Section::Unstaged, _) => {
+                    hints.push((Action::Stage, "stage"));
+                    hints.push((Action::Discard, "discard"));
+                }
+            }
+            let formattable = !matches!(
+                status,
+                Some(FileStatus::Deleted | FileStatus::Conflict) | None
+            );
+            if *section == Section::Unstaged && formattable && self.config.format.command.is_some()
+            {
+                hints.push((Action::FormatStage, "format+stage"));
+            }
+            if *section == Section::Unstaged && formattable && self.has_pre_commit {
+                hints.push((Action::PreCommit, "pre-commit"));
+            }
+            hints.push((Action::ToggleMark, "mark"));
+        } else if !self.visible_rows.is_empty() {
+            // Nothing to act on yet under the configured target mode.
+            match self.config.actions.target {
+                ActionTarget::Selected => hints.push((Action::Select, "select")),
+                _ => hints.push((Action::ToggleMark, "mark")),
             }
-            vec![]
         } else {
-            self.multi_selected.iter().cloned().collect()
+            if self.upstream.as_ref().is_some_and(|u| u.ahead > 0) {
+                hints.push((Action::Push, "push"));
+            }
+            hints.push((Action::Fetch, "fetch"));
+        }
+
+        if matches!(
+            self.current_diff,
+            DiffContent::Collapsed { .. } | DiffContent::Minified { .. }
+        ) {
+            hints.push((Action::ExpandLines, "expand diff"));
+        }
+        if matches!(self.branch, BranchInfo::Unborn(_))
+            && !self.staged_files.is_empty()
+            && self.multi_selected.is_empty()
+        {
+            hints.push((Action::InitialCommit, "initial commit"));
+        }
+        if matches!(self.branch, BranchInfo::Detached(_)) && self.rebase_progress.is_none() {
+            hints.push((Action::CreateBranch, "branch here"));
+            if self.previous_branch.is_some() {
+                hints.push((Action::PreviousBranch, "previous branch"));
+            }
+        }
+        if let DiffContent::Conflict(preview) = &self.current_diff {
+            if !preview.region_starts().is_empty() {
+                hints.push((Action::NextRegion, "next region"));
+            }
+        }
+        if self.conflict_count() > 0 {
+            hints.push((Action::NextConflict, "next conflict"));
+        }
+        hints.push((Action::Quit, "quit"));
+        hints
+    }
+
+    /// Explain why an action did nothing when the target mode needs an
+    /// explicit selection.
+    fn report_missing_targets(&mut self) {
+        match self.config.actions.target {
+            ActionTarget::Highlighted => {}
+            ActionTarget::Selected => {
+                self.show_flash_error("No file selected; press Enter on a file first")
+            }
+            ActionTarget::MultiSelect => {
+                self.show_flash_error("No files marked; press Space to mark files")
+            }
         }
     }
 
     pub fn stage_selected(&mut self) -> Result<()> {
         let targets = self.get_action_targets();
-        let paths: Vec<String> = targets
+        if targets.is_empty() {
+            self.report_missing_targets();
+        }
+        let paths: Vec<Vec<u8>> = targets
             .into_iter()
             .filter(|(section, _)| *section == Section::Unstaged)
             .map(|(_, path)| path)
@@ -236,18 +863,51 @@ impl App {
             return Ok(());
         }
 
+        let marked = self.paths_with_markers(&paths);
+        if !marked.is_empty() {
+            let which = match marked.as_slice() {
+                [path] => format!("{} still has", git::display_path(path)),
+                _ => format!("{} files still have", marked.len()),
+            };
+            self.confirm_prompt = Some(ConfirmPrompt {
+                message: format!("{} conflict markers. Stage anyway? [y/N]", which),
+                action: ConfirmAction::StageWithMarkers { paths },
+            });
+            return Ok(());
+        }
+        self.stage_paths(paths)
+    }
+
+    /// Which of the unstaged `paths` are conflicted files with conflict
+    /// markers still in them.
+    fn paths_with_markers(&self, paths: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        self.unstaged_files
+            .iter()
+            .filter(|f| f.status == FileStatus::Conflict && paths.contains(&f.raw_path))
+            .filter(|f| git::get_conflict_preview(&self.repo, f, self.config.preview).has_markers())
+            .map(|f| f.raw_path.clone())
+            .collect()
+    }
+
+    fn stage_paths(&mut self, paths: Vec<Vec<u8>>) -> Result<()> {
         let count = paths.len();
         git::stage_files(&self.repo, &paths)?;
-        self.last_action = Some(UndoAction::Stage { paths });
+        self.last_action = Some(UndoAction::Stage {
+            paths: paths.clone(),
+        });
         self.clear_multi_select();
         self.refresh()?;
         self.show_flash_success(format!("Staged {} file{}", count, plural_s(count)));
+        self.run_hook(Hook::PostStage, &git::display_paths(&paths));
         Ok(())
     }
 
     pub fn unstage_selected(&mut self) -> Result<()> {
         let targets = self.get_action_targets();
-        let paths: Vec<String> = targets
+        if targets.is_empty() {
+            self.report_missing_targets();
+        }
+        let paths: Vec<Vec<u8>> = targets
             .into_iter()
             .filter(|(section, _)| *section == Section::Staged)
             .map(|(_, path)| path)
@@ -266,6 +926,172 @@ impl App {
         Ok(())
     }
 
+    /// Run the `[format]` command on the target files, then stage them if
+    /// it succeeded. Deleted, binary, and conflicted files are left out.
+    pub fn format_and_stage(&mut self) -> Result<()> {
+        let Some(command) = self.config.format.command.clone() else {
+            self.show_flash_error("No formatter configured; set command in [format]");
+            return Ok(());
+        };
+        let targets = self.get_action_targets();
+        if targets.is_empty() {
+            self.report_missing_targets();
+        }
+        let mut paths: Vec<Vec<u8>> = Vec::new();
+        for (section, path) in targets {
+            let formattable = self.file_in(section, &path).is_some_and(|f| {
+                !f.is_binary
+                    && !f.is_submodule
+                    && !matches!(f.status, FileStatus::Deleted | FileStatus::Conflict)
+            });
+            if formattable && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        let display = git::display_paths(&paths);
+        let out = commands::run_formatter(&command, self.workdir(), &display)?;
+        self.refresh()?;
+        if !out.status.success() {
+            let error = anyhow::anyhow!("{}", out.output)
+                .context(format!("Formatter failed ({})", out.status));
+            self.show_flash_error_detail(
+                format!("Formatter failed: {}", out.summary()),
+                &error,
+                display,
+            );
+            return Ok(());
+        }
+
+        // Staged files the formatter left alone have nothing new to stage.
+        let to_stage: Vec<Vec<u8>> = paths
+            .into_iter()
+            .filter(|path| self.unstaged_files.iter().any(|f| &f.raw_path == path))
+            .collect();
+        let count = to_stage.len();
+        if count > 0 {
+            git::stage_files(&self.repo, &to_stage)?;
+            self.last_action = Some(UndoAction::Stage {
+                paths: to_stage.clone(),
+            });
+            self.clear_multi_select();
+            self.refresh()?;
+        }
+        self.show_flash_success(format!(
+            "Formatted and staged {} file{}",
+            count,
+            plural_s(count)
+        ));
+        self.run_hook(Hook::PostStage, &git::display_paths(&to_stage));
+        Ok(())
+    }
+
+    /// Run pre-commit's hooks on the targets in the background, streaming
+    /// the output into a panel.
+    pub fn run_pre_commit(&mut self) -> Result<()> {
+        self.start_pre_commit(precommit::PROGRAM)
+    }
+
+    /// [`Self::run_pre_commit`] with `program` standing in for `pre-commit`.
+    pub fn start_pre_commit(&mut self, program: &str) -> Result<()> {
+        if self.pre_commit.is_some() {
+            self.show_flash_error("pre-commit is already running");
+            return Ok(());
+        }
+        if !self.has_pre_commit {
+            self.show_flash_error(format!("No {} in this repository", precommit::CONFIG_FILE));
+            return Ok(());
+        }
+        let targets = self.get_action_targets();
+        if targets.is_empty() {
+            self.report_missing_targets();
+        }
+        let mut paths: Vec<Vec<u8>> = Vec::new();
+        for (section, path) in targets {
+            let present = self
+                .file_in(section, &path)
+                .is_some_and(|f| !f.is_submodule && f.status != FileStatus::Deleted);
+            if present && !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        let paths = git::display_paths(&paths);
+        self.pre_commit = Some(PreCommitRun::spawn(program, self.workdir(), &paths)?);
+        self.modal = Some(Modal::PreCommit(PreCommitPanel::default()));
+        Ok(())
+    }
+
+    /// Move new pre-commit output into its panel, and once the hooks finish,
+    /// flag the files they changed and report the result.
+    pub fn poll_pre_commit(&mut self) {
+        let Some(run) = &mut self.pre_commit else {
+            return;
+        };
+        let progress = run.poll();
+        if let Some(Modal::PreCommit(panel)) = &mut self.modal {
+            if panel.scroll_back > 0 {
+                panel.scroll_back += progress.lines.len();
+            }
+            panel.lines.extend(progress.lines);
+        }
+        let Some(result) = progress.finished else {
+            return;
+        };
+        let modified = run.modified_files();
+        self.pre_commit = None;
+
+        if let Err(e) = self.refresh() {
+            self.show_flash_error_detail(format!("Error: {}", e), &e, Vec::new());
+            return;
+        }
+        for path in &modified {
+            if self.unstaged_files.iter().any(|f| &f.path == path) {
+                self.needs_review.insert(path.clone());
+            }
+        }
+
+        let (message, failed) = match result {
+            Ok(status) if status.success() => ("pre-commit passed".to_string(), false),
+            Ok(status) if modified.is_empty() => (format!("pre-commit failed ({})", status), true),
+            Ok(_) => (
+                format!(
+                    "pre-commit hooks modified {} file{}; review before staging",
+                    modified.len(),
+                    plural_s(modified.len())
+                ),
+                true,
+            ),
+            Err(e) => (format!("{:#}", e), true),
+        };
+        if let Some(Modal::PreCommit(panel)) = &mut self.modal {
+            panel.result = Some(message.clone());
+            panel.failed = failed;
+        }
+        if failed {
+            self.show_flash_error(message);
+        } else {
+            self.show_flash_success(message);
+        }
+    }
+
+    /// The entry listed in `section` for `raw_path`. Looked up by the path
+    /// git stores rather than the displayed one, which names that aren't
+    /// valid UTF-8 can share.
+    fn file_in(&self, section: Section, raw_path: &[u8]) -> Option<&FileEntry> {
+        let files = match section {
+            Section::Staged => &self.staged_files,
+            Section::Unstaged => &self.unstaged_files,
+        };
+        files.iter().find(|f| f.raw_path == raw_path)
+    }
+
     pub fn undo(&mut self) -> Result<()> {
         let action = match &self.last_action {
             Some(a) => a.clone(),
@@ -303,10 +1129,11 @@ impl App {
     pub fn show_discard_selected_confirm(&mut self) {
         let targets = self.get_action_targets();
         if targets.is_empty() {
+            self.report_missing_targets();
             return;
         }
 
-        let unstaged_targets: Vec<(Section, String)> = targets
+        let unstaged_targets: Vec<(Section, Vec<u8>)> = targets
             .into_iter()
             .filter(|(section, _)| *section == Section::Unstaged)
             .collect();
@@ -318,7 +1145,7 @@ impl App {
         let has_conflict = unstaged_targets.iter().any(|(_, path)| {
             self.unstaged_files
                 .iter()
-                .any(|f| &f.path == path && f.status == crate::types::FileStatus::Conflict)
+                .any(|f| &f.raw_path == path && f.status == crate::types::FileStatus::Conflict)
         });
 
         if has_conflict {
@@ -329,7 +1156,7 @@ impl App {
         let has_untracked = unstaged_targets.iter().any(|(_, path)| {
             self.unstaged_files
                 .iter()
-                .any(|f| &f.path == path && f.status == crate::types::FileStatus::Untracked)
+                .any(|f| &f.raw_path == path && f.status == crate::types::FileStatus::Untracked)
         });
 
         let count = unstaged_targets.len();
@@ -382,32 +1209,41 @@ impl App {
         });
     }
 
-    fn discard_files(&mut self, paths: &[(Section, String)]) -> Result<()> {
-        let mut count = 0;
+    fn discard_files(&mut self, paths: &[(Section, Vec<u8>)]) -> Result<()> {
+        let unstaged: Vec<&[u8]> = paths
+            .iter()
+            .filter(|(section, _)| *section == Section::Unstaged)
+            .map(|(_, path)| &path[..])
+            .collect();
+        git::backup_discard(&self.repo, &unstaged)
+            .context("Failed to back up files; nothing discarded")?;
+
+        let mut discarded = Vec::new();
         for (section, path) in paths {
             if *section != Section::Unstaged {
                 continue;
             }
 
             let is_untracked = self
-                .unstaged_files
-                .iter()
-                .any(|f| &f.path == path && f.status == crate::types::FileStatus::Untracked);
+                .file_in(Section::Unstaged, path)
+                .is_some_and(|f| f.status == crate::types::FileStatus::Untracked);
 
             if is_untracked {
                 git::discard_untracked_file(&self.repo, path)?;
             } else {
                 git::discard_unstaged_file(&self.repo, path)?;
             }
-            count += 1;
+            discarded.push(git::display_path(path));
         }
 
         self.last_action = None;
         self.clear_multi_select();
         self.refresh()?;
+        let count = discarded.len();
         if count > 0 {
             self.show_flash_success(format!("Discarded {} file{}", count, plural_s(count)));
         }
+        self.run_hook(Hook::PostDiscard, &discarded);
         Ok(())
     }
 
@@ -434,6 +1270,7 @@ impl App {
                 plural_s(skipped_conflicts)
             ));
         }
+        self.run_hook(Hook::PostDiscard, &paths);
         Ok(())
     }
 
@@ -442,7 +1279,27 @@ impl App {
         if count == 0 {
             return;
         }
-        self.confirm_prompt = Some(ConfirmPrompt {
+        let paths: Vec<Vec<u8>> = self
+            .unstaged_files
+            .iter()
+            .map(|f| f.raw_path.clone())
+            .collect();
+        let marked = self.paths_with_markers(&paths).len();
+        if marked > 0 {
+            // Always asked, whatever `confirm.level` says.
+            self.confirm_prompt = Some(ConfirmPrompt {
+                message: format!(
+                    "Stage {} file{}? {} still ha{} conflict markers [y/N]",
+                    count,
+                    plural_s(count),
+                    marked,
+                    if marked == 1 { "s" } else { "ve" }
+                ),
+                action: ConfirmAction::StageAll,
+            });
+            return;
+        }
+        self.request_confirm(ConfirmPrompt {
             message: format!("Stage {} file{}? [y/N]", count, plural_s(count)),
             action: ConfirmAction::StageAll,
         });
@@ -453,32 +1310,89 @@ impl App {
         if count == 0 {
             return;
         }
-        self.confirm_prompt = Some(ConfirmPrompt {
+        self.request_confirm(ConfirmPrompt {
             message: format!("Unstage {} file{}? [y/N]", count, plural_s(count)),
             action: ConfirmAction::UnstageAll,
         });
     }
 
+    /// Show `prompt`, or run its action straight away when `confirm.level`
+    /// only asks before discards.
+    fn request_confirm(&mut self, prompt: ConfirmPrompt) {
+        let skip = self.config.confirm.level == ConfirmLevel::Destructive
+            && matches!(
+                prompt.action,
+                ConfirmAction::StageAll | ConfirmAction::UnstageAll
+            );
+        self.confirm_prompt = Some(prompt);
+        if skip {
+            if let Err(e) = self.handle_confirm(true) {
+                self.show_flash_error_detail(format!("Error: {}", e), &e, Vec::new());
+            }
+        }
+    }
+
     pub fn show_flash_success(&mut self, text: impl Into<String>) {
-        self.flash_message = Some(FlashMessage::success(text));
+        self.show_flash(FlashMessage::success(text));
     }
 
     pub fn show_flash_error(&mut self, text: impl Into<String>) {
-        self.flash_message = Some(FlashMessage::error(text));
+        self.show_flash(FlashMessage::error(text));
+    }
+
+    /// Flash `text` as an error, keeping `error`'s full cause chain and the
+    /// files involved for the error detail popup.
+    pub fn show_flash_error_detail(
+        &mut self,
+        text: impl Into<String>,
+        error: &anyhow::Error,
+        paths: Vec<String>,
+    ) {
+        self.show_flash(FlashMessage::error(text).with_detail(ErrorDetail::new(error, paths)));
+    }
+
+    /// Show `flash` and record it in the message history.
+    fn show_flash(&mut self, flash: FlashMessage) {
+        match (&flash.detail, flash.is_error) {
+            (Some(detail), _) => tracing::warn!(
+                chain = ?detail.chain,
+                paths = ?detail.paths,
+                "{}",
+                flash.text
+            ),
+            (None, true) => tracing::warn!("{}", flash.text),
+            (None, false) => tracing::info!("{}", flash.text),
+        }
+        if self.message_history.len() == MESSAGE_HISTORY_LIMIT {
+            self.message_history.pop_front();
+        }
+        self.message_history.push_back(flash.clone());
+        self.flash_message = Some(flash);
     }
 
     pub fn clear_flash(&mut self) {
         self.flash_message = None;
     }
 
+    /// Clear the flash once its configured duration has passed. Errors stay
+    /// until the next key press when `flash.sticky_errors` is set.
     pub fn check_flash_expiry(&mut self) {
+        let config = &self.config.flash;
         if let Some(ref flash) = self.flash_message {
-            if flash.is_expired(FLASH_TIMEOUT) {
+            if self.flash_expires() && flash.is_expired(config.duration()) {
                 self.flash_message = None;
             }
         }
     }
 
+    /// Whether a flash is showing that will clear by itself, rather than a
+    /// sticky error waiting for a key press.
+    fn flash_expires(&self) -> bool {
+        self.flash_message
+            .as_ref()
+            .is_some_and(|flash| !(flash.is_error && self.config.flash.sticky_errors))
+    }
+
     pub fn handle_confirm(&mut self, confirmed: bool) -> Result<()> {
         if let Some(prompt) = self.confirm_prompt.take() {
             if confirmed {
@@ -487,13 +1401,20 @@ impl App {
                         let paths = git::stage_all(&self.repo)?;
                         let count = paths.len();
                         if count > 0 {
-                            self.last_action = Some(UndoAction::Stage { paths });
+                            self.last_action = Some(UndoAction::Stage {
+                                paths: paths.clone(),
+                            });
                         }
                         self.clear_multi_select();
                         self.refresh()?;
                         if count > 0 {
-                            self.show_flash_success(format!("Staged {} file{}", count, plural_s(count)));
+                            self.show_flash_success(format!(
+                                "Staged {} file{}",
+                                count,
+                                plural_s(count)
+                            ));
                         }
+                        self.run_hook(Hook::PostStage, &git::display_paths(&paths));
                     }
                     ConfirmAction::UnstageAll => {
                         let paths = git::unstage_all(&self.repo)?;
@@ -504,110 +1425,1801 @@ impl App {
                         self.clear_multi_select();
                         self.refresh()?;
                         if count > 0 {
-                            self.show_flash_success(format!("Unstaged {} file{}", count, plural_s(count)));
+                            self.show_flash_success(format!(
+                                "Unstaged {} file{}",
+                                count,
+                                plural_s(count)
+                            ));
                         }
                     }
+                    ConfirmAction::StageWithMarkers { paths } => {
+                        self.stage_paths(paths)?;
+                    }
                     ConfirmAction::DiscardSelected { paths } => {
                         self.discard_files(&paths)?;
                     }
                     ConfirmAction::DiscardAll => {
                         self.discard_all()?;
                     }
+                    ConfirmAction::DiscardLines {
+                        path,
+                        changes,
+                        diff,
+                    } => {
+                        // A refresh while the prompt was open may have
+                        // changed what the picked positions point at.
+                        self.update_diff_for_selected();
+                        if self.diff_fingerprint() == Some(diff) {
+                            self.apply_lines(&path, &changes, git::LineOp::Discard)?;
+                        } else {
+                            self.visual_anchor = None;
+                            self.show_flash_error(format!(
+                                "{} changed since the lines were picked; nothing discarded",
+                                git::display_path(&path)
+                            ));
+                        }
+                    }
                 }
             }
         }
         Ok(())
     }
 
-    pub fn move_highlight(&mut self, delta: isize) {
-        if self.visible_rows.is_empty() {
+    pub fn highlight_first(&mut self) {
+        self.move_highlight(-(self.visible_rows.len() as isize));
+    }
+
+    pub fn highlight_last(&mut self) {
+        self.move_highlight(self.visible_rows.len() as isize);
+    }
+
+    pub fn move_highlight(&mut self, delta: isize) {
+        if self.visible_rows.is_empty() {
+            return;
+        }
+
+        let current = self.highlight_index.unwrap_or(0) as isize;
+        let new_idx = (current + delta).clamp(0, self.visible_rows.len() as isize - 1) as usize;
+        self.highlight_index = Some(new_idx);
+        self.update_scroll_for_highlight();
+    }
+
+    fn update_scroll_for_highlight(&mut self) {
+        if let Some(idx) = self.highlight_index {
+            let header_offset = self.count_headers_before(idx);
+            let visual_idx = idx + header_offset;
+
+            if visual_idx < self.file_list_scroll {
+                self.file_list_scroll = visual_idx;
+            } else if self.file_list_height > 0
+                && visual_idx >= self.file_list_scroll + self.file_list_height
+            {
+                self.file_list_scroll = visual_idx - self.file_list_height + 1;
+            }
+        }
+    }
+
+    fn count_headers_before(&self, file_idx: usize) -> usize {
+        let mut headers = 0;
+        if !self.staged_files.is_empty() {
+            headers += 1;
+        }
+        if !self.unstaged_files.is_empty() && file_idx >= self.staged_files.len() {
+            headers += 1;
+        }
+        headers
+    }
+
+    fn scroll_diff(&mut self, delta: isize, viewport_height: usize, viewport_width: usize) {
+        let max_scroll = crate::ui::diff_panel::max_scroll(
+            &self.current_diff,
+            viewport_height,
+            viewport_width,
+            !self.config.ui.low_bandwidth,
+            self.expand_long_lines,
+        );
+        let current = self.diff_scroll as isize;
+        self.diff_scroll = (current + delta).clamp(0, max_scroll as isize) as usize;
+    }
+
+    fn page_scroll_diff(&mut self, down: bool, viewport_height: usize, viewport_width: usize) {
+        let delta = if down {
+            viewport_height as isize
+        } else {
+            -(viewport_height as isize)
+        };
+        self.scroll_diff(delta, viewport_height, viewport_width);
+    }
+
+    /// Jump to the section whose status bar count is at column `col`.
+    pub fn click_status_bar(&mut self, col: u16) {
+        let link = self
+            .count_links
+            .iter()
+            .find(|(columns, _)| columns.contains(&col))
+            .map(|&(_, link)| link);
+        if let Some(link) = link {
+            self.jump_to(link);
+        }
+    }
+
+    /// How many files have merge conflicts.
+    pub fn conflict_count(&self) -> usize {
+        self.unstaged_files
+            .iter()
+            .filter(|f| f.status == FileStatus::Conflict)
+            .count()
+    }
+
+    fn is_conflict_row(&self, row: &VisibleRow) -> bool {
+        row.section == Section::Unstaged
+            && self
+                .unstaged_files
+                .iter()
+                .any(|f| f.raw_path == row.raw_path && f.status == FileStatus::Conflict)
+    }
+
+    /// Highlight and show the first conflicted file after the highlighted
+    /// one, wrapping around to the top.
+    pub fn next_conflict(&mut self) {
+        let start = self.highlight_index.map_or(0, |i| i + 1);
+        let len = self.visible_rows.len();
+        let next = (0..len)
+            .map(|offset| (start + offset) % len)
+            .find(|&i| self.is_conflict_row(&self.visible_rows[i]));
+        match next {
+            Some(index) => {
+                self.focus = Focus::FileList;
+                self.highlight_index = Some(index);
+                self.update_scroll_for_highlight();
+                self.select_current();
+            }
+            None => self.show_flash_error("No conflicted files"),
+        }
+    }
+
+    /// Scroll a conflicted file's view to the next or previous conflict
+    /// region.
+    fn jump_to_region(&mut self, forward: bool, page: (usize, usize)) {
+        let DiffContent::Conflict(preview) = &self.current_diff else {
+            self.show_flash_error("Not a conflicted file");
+            return;
+        };
+        let (page_height, page_width) = page;
+        let wrap = !self.config.ui.low_bandwidth;
+        let (_, rows) = crate::ui::diff_panel::conflict_lines(
+            preview,
+            page_width,
+            wrap,
+            self.expand_long_lines,
+        );
+        if rows.is_empty() {
+            self.show_flash_error("No conflict markers");
+            return;
+        }
+        let scroll = self.diff_scroll;
+        let target = if forward {
+            rows.iter().find(|&&row| row > scroll)
+        } else {
+            rows.iter().rev().find(|&&row| row < scroll)
+        };
+        let max_scroll = crate::ui::diff_panel::max_scroll(
+            &self.current_diff,
+            page_height,
+            page_width,
+            wrap,
+            self.expand_long_lines,
+        );
+        match target.map(|&row| row.min(max_scroll)) {
+            Some(row) if row != scroll => self.diff_scroll = row,
+            _ => self.show_flash_error(if forward {
+                "No more conflict regions below"
+            } else {
+                "No more conflict regions above"
+            }),
+        }
+    }
+
+    /// Highlight the first file a status bar count stands for.
+    pub fn jump_to(&mut self, link: CountLink) {
+        let first_untracked = self
+            .unstaged_files
+            .iter()
+            .find(|f| f.status == FileStatus::Untracked)
+            .map(|f| f.raw_path.clone());
+        let index = self.visible_rows.iter().position(|row| match link {
+            CountLink::Conflict => self.is_conflict_row(row),
+            CountLink::Staged => row.section == Section::Staged,
+            CountLink::Unstaged => row.section == Section::Unstaged,
+            CountLink::Untracked => {
+                row.section == Section::Unstaged && Some(&row.raw_path) == first_untracked.as_ref()
+            }
+        });
+        match index {
+            Some(index) => {
+                self.focus = Focus::FileList;
+                self.highlight_index = Some(index);
+                self.update_scroll_for_highlight();
+            }
+            None => self.show_flash_error(match link {
+                CountLink::Conflict => "No conflicted files",
+                CountLink::Staged => "No staged files",
+                CountLink::Unstaged => "No unstaged files",
+                CountLink::Untracked => "No untracked files",
+            }),
+        }
+    }
+
+    fn click_file_list(&mut self, row: u16) {
+        let inner_row = row.saturating_sub(self.file_list_area.y + 1) as usize;
+        let visual_row = self.file_list_scroll + inner_row;
+
+        let staged_count = self.staged_files.len();
+        let unstaged_count = self.unstaged_files.len();
+
+        let file_index = if staged_count > 0 && unstaged_count > 0 {
+            let staged_header = 0;
+            let unstaged_header = 1 + staged_count;
+
+            if visual_row == staged_header || visual_row == unstaged_header {
+                return;
+            } else if visual_row < unstaged_header {
+                visual_row - 1
+            } else {
+                visual_row - 2
+            }
+        } else if staged_count > 0 || unstaged_count > 0 {
+            if visual_row == 0 {
+                return;
+            }
+            visual_row - 1
+        } else {
+            return;
+        };
+
+        if file_index < self.visible_rows.len() {
+            self.focus = Focus::FileList;
+            self.highlight_index = Some(file_index);
+            self.select_current();
+        }
+    }
+
+    /// Focus the diff with the cursor on the clicked line.
+    fn click_diff(&mut self, row: u16) {
+        let inner_row = row.saturating_sub(self.diff_area.y + 1) as usize;
+        let width = self.diff_area.width.saturating_sub(2) as usize;
+        let Some(lines) = self.diff_text_lines() else {
+            return;
+        };
+        self.diff_cursor = crate::ui::diff_panel::line_at_row(
+            lines,
+            self.diff_scroll + inner_row,
+            width,
+            !self.config.ui.low_bandwidth,
+            self.expand_long_lines,
+        );
+        self.visual_anchor = None;
+        self.focus = Focus::Diff;
+    }
+
+    pub fn close_modal(&mut self) {
+        self.modal = None;
+    }
+
+    /// Whether the open modal is capturing free-form text input.
+    pub fn modal_has_text_input(&self) -> bool {
+        match &self.modal {
+            Some(Modal::Remotes(panel)) => panel.input.is_some(),
+            Some(Modal::Credentials(_))
+            | Some(Modal::InitialCommit(_))
+            | Some(Modal::NewBranch(_)) => true,
+            Some(Modal::Messages(_))
+            | Some(Modal::Stashes(_))
+            | Some(Modal::Recover(_))
+            | Some(Modal::PreCommit(_))
+            | Some(Modal::ErrorDetail(_))
+            | Some(Modal::Setup(_))
+            | None => false,
+        }
+    }
+
+    /// Route a key press to whichever modal is open.
+    pub fn handle_modal_key(&mut self, code: KeyCode) {
+        match self.modal {
+            Some(Modal::Remotes(_)) => self.handle_remote_panel_key(code),
+            Some(Modal::Credentials(_)) => self.handle_credential_prompt_key(code),
+            Some(Modal::Messages(_)) => self.handle_message_panel_key(code),
+            Some(Modal::Stashes(_)) => self.handle_stash_panel_key(code),
+            Some(Modal::Recover(_)) => self.handle_recover_panel_key(code),
+            Some(Modal::InitialCommit(_)) => self.handle_initial_commit_key(code),
+            Some(Modal::NewBranch(_)) => self.handle_new_branch_key(code),
+            Some(Modal::PreCommit(_)) => self.handle_pre_commit_panel_key(code),
+            Some(Modal::Setup(_)) => self.handle_setup_key(code),
+            Some(Modal::ErrorDetail(_)) if code == KeyCode::Esc => self.close_modal(),
+            Some(Modal::ErrorDetail(_)) | None => {}
+        }
+    }
+
+    /// Feed a key press into the keymap, returning the action once a full
+    /// binding has been typed.
+    pub fn resolve_key(&mut self, key: Key, now: Instant) -> Option<Action> {
+        match self.key_sequence.feed(&self.keymap, key, now) {
+            KeyInput::Action(action) => Some(action),
+            KeyInput::Pending => None,
+            // j/k move the diff cursor unless bound to something else.
+            KeyInput::Unbound if self.focus == Focus::Diff => {
+                if key == Key::char('j') {
+                    Some(Action::Down)
+                } else if key == Key::char('k') {
+                    Some(Action::Up)
+                } else {
+                    None
+                }
+            }
+            KeyInput::Unbound => None,
+        }
+    }
+
+    /// Run a file-list action. `page` is the diff viewport (height, width)
+    /// used for paging. Returns false when the app should quit.
+    pub fn perform_action(&mut self, action: Action, page: (usize, usize)) -> bool {
+        let (page_height, page_width) = page;
+        if self.focus == Focus::Diff && self.perform_diff_action(action, page) {
+            return true;
+        }
+        match action {
+            Action::Quit => return false,
+            Action::Back => {
+                if self.cancel_network_op() {
+                } else if !self.multi_selected.is_empty() {
+                    self.clear_multi_select();
+                } else if !self.dismiss_upstream_notice() {
+                    return false;
+                }
+            }
+            Action::Down => self.move_highlight(1),
+            Action::Up => self.move_highlight(-1),
+            Action::Top => self.highlight_first(),
+            Action::Bottom => self.highlight_last(),
+            Action::PageDown => self.page_scroll_diff(true, page_height, page_width),
+            Action::PageUp => self.page_scroll_diff(false, page_height, page_width),
+            Action::ToggleMark => self.toggle_multi_select(),
+            Action::Select => self.select_current(),
+            Action::Stage => {
+                if let Err(e) = self.stage_selected() {
+                    let paths = self.target_paths();
+                    self.show_flash_error_detail(format!("Error: {}", e), &e, paths);
+                }
+            }
+            Action::Unstage => {
+                if let Err(e) = self.unstage_selected() {
+                    let paths = self.target_paths();
+                    self.show_flash_error_detail(format!("Error: {}", e), &e, paths);
+                }
+            }
+            Action::FormatStage => {
+                if let Err(e) = self.format_and_stage() {
+                    let paths = self.target_paths();
+                    self.show_flash_error_detail(format!("Error: {}", e), &e, paths);
+                }
+            }
+            Action::PreCommit => {
+                if let Err(e) = self.run_pre_commit() {
+                    let paths = self.target_paths();
+                    self.show_flash_error_detail(format!("Error: {}", e), &e, paths);
+                }
+            }
+            Action::StageAll => self.show_stage_all_confirm(),
+            Action::UnstageAll => self.show_unstage_all_confirm(),
+            Action::Discard => self.show_discard_selected_confirm(),
+            Action::DiscardAll => self.show_discard_all_confirm(),
+            Action::Undo => {
+                if let Err(e) = self.undo() {
+                    let paths = self
+                        .last_action
+                        .as_ref()
+                        .map(|action| git::display_paths(action.paths()))
+                        .unwrap_or_default();
+                    self.show_flash_error_detail(format!("Error: {}", e), &e, paths);
+                }
+            }
+            Action::ToggleGraph => self.toggle_graph(),
+            Action::Remotes => self.open_remote_panel(),
+            Action::Stashes => self.open_stash_panel(),
+            Action::Recover => self.open_recover_panel(),
+            Action::InitialCommit => self.open_initial_commit(),
+            Action::CreateBranch => self.open_new_branch(),
+            Action::PreviousBranch => self.return_to_previous_branch(),
+            Action::Messages => self.open_message_panel(),
+            Action::ErrorDetails => self.open_error_detail(),
+            Action::Hexdump => self.toggle_hexdump(),
+            Action::IgnoreEol => self.toggle_ignore_eol(),
+            Action::RelativePaths => self.toggle_relative_paths(),
+            Action::NextConflict => self.next_conflict(),
+            Action::NextRegion => self.jump_to_region(true, page),
+            Action::PrevRegion => self.jump_to_region(false, page),
+            Action::ExpandLines => self.toggle_expand_lines(),
+            Action::ToggleFocus => self.focus_diff(page),
+            Action::NextFile => self.select_adjacent_file(1),
+            Action::PrevFile => self.select_adjacent_file(-1),
+            Action::VisualSelect => self.start_visual_select(page),
+            Action::Copy => self.show_flash_error("Focus the diff to copy lines"),
+            Action::Fetch => self.run_network_op(NetworkOp::Fetch),
+            Action::Push => self.run_network_op(NetworkOp::Push),
+            Action::Pull => self.run_network_op(NetworkOp::Pull),
+            Action::Custom(index) => self.prepare_custom_command(index),
+        }
+        true
+    }
+
+    /// Fill in the placeholders of `[[commands]]` entry `index` and leave it
+    /// for the run loop, which suspends the UI to run it.
+    fn prepare_custom_command(&mut self, index: usize) {
+        let Some(custom) = self.config.commands.get(index) else {
+            return;
+        };
+        let file = self
+            .highlight_index
+            .and_then(|idx| self.visible_rows.get(idx))
+            .map(|row| git::display_path(&row.raw_path));
+        let branch = self.branch.to_string();
+        match PreparedCommand::new(custom, &self.target_paths(), file.as_deref(), &branch) {
+            Ok(command) => self.pending_command = Some(command),
+            Err(e) => self.show_flash_error(format!("{:#}", e)),
+        }
+    }
+
+    /// The working tree root, where custom commands run.
+    pub fn workdir(&self) -> &Path {
+        self.repo.workdir().unwrap_or(self.repo.path())
+    }
+
+    /// Pick up whatever a custom command changed and report how it ended.
+    pub fn finish_custom_command(
+        &mut self,
+        command: &PreparedCommand,
+        result: Result<std::process::ExitStatus>,
+    ) -> Result<()> {
+        self.refresh()?;
+        match result {
+            Ok(status) if status.success() => {
+                self.show_flash_success(format!("Ran '{}'", command.name))
+            }
+            Ok(status) => self.show_flash_error(format!("'{}' failed ({})", command.name, status)),
+            Err(e) => self.show_flash_error(format!("{:#}", e)),
+        }
+        Ok(())
+    }
+
+    /// The file whose diff is showing.
+    pub fn selected_file(&self) -> Option<(Section, &FileEntry)> {
+        let (section, path) = self.selected.as_ref()?;
+        Some((*section, self.file_in(*section, path)?))
+    }
+
+    /// The directory paths are shown relative to, if not the root.
+    pub fn path_base(&self) -> Option<&str> {
+        self.launch_dir.as_deref().filter(|_| self.relative_paths)
+    }
+
+    pub fn toggle_relative_paths(&mut self) {
+        let Some(dir) = &self.launch_dir else {
+            self.show_flash_error(
+                "Started at the repository root; paths are already relative to it",
+            );
+            return;
+        };
+        self.relative_paths = !self.relative_paths;
+        let message = if self.relative_paths {
+            format!("Paths relative to {}/", dir)
+        } else {
+            "Paths relative to the repository root".to_string()
+        };
+        self.show_flash_success(message);
+    }
+
+    pub fn toggle_ignore_eol(&mut self) {
+        self.diff_flags.ignore_eol = !self.diff_flags.ignore_eol;
+        self.show_flash_success(if self.diff_flags.ignore_eol {
+            "Ignoring line-ending changes in diffs"
+        } else {
+            "Showing line-ending changes in diffs"
+        });
+        self.update_diff_for_selected();
+    }
+
+    /// The text diff lines visual mode works on.
+    fn diff_text_lines(&self) -> Option<&[DiffLine]> {
+        match &self.current_diff {
+            DiffContent::Text(lines) => Some(lines),
+            _ => None,
+        }
+    }
+
+    /// A hash of the text diff on show, to tell whether it has changed.
+    fn diff_fingerprint(&self) -> Option<u64> {
+        use std::hash::{Hash, Hasher};
+        let lines = self.diff_text_lines()?;
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        lines.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+
+    /// The visual selection, if one is being made.
+    pub fn diff_selection(&self) -> Option<DiffSelection> {
+        self.visual_anchor.map(|anchor| DiffSelection {
+            anchor,
+            cursor: self.diff_cursor,
+        })
+    }
+
+    /// The diff lines the next line action applies to: the visual
+    /// selection, or just the cursor line.
+    fn picked_lines(&self) -> RangeInclusive<usize> {
+        match self.diff_selection() {
+            Some(selection) => selection.range(),
+            None => self.diff_cursor..=self.diff_cursor,
+        }
+    }
+
+    /// Keep the cursor on the diff, handing focus back to the file list if
+    /// there is no diff left to look at.
+    fn clamp_diff_cursor(&mut self) {
+        match self.diff_text_lines().map(<[DiffLine]>::len) {
+            Some(len) if len > 0 => {
+                self.diff_cursor = self.diff_cursor.min(len - 1);
+                if self.visual_anchor.is_some_and(|anchor| anchor >= len) {
+                    self.visual_anchor = None;
+                }
+            }
+            _ => {
+                self.diff_cursor = 0;
+                self.visual_anchor = None;
+                if matches!(self.current_diff, DiffContent::Empty | DiffContent::Clean) {
+                    self.focus = Focus::FileList;
+                }
+            }
+        }
+    }
+
+    /// The diff line drawn at the top of the viewport.
+    fn top_diff_line(&self, width: usize) -> usize {
+        self.diff_text_lines().map_or(0, |lines| {
+            crate::ui::diff_panel::line_at_row(
+                lines,
+                self.diff_scroll,
+                width,
+                !self.config.ui.low_bandwidth,
+                self.expand_long_lines,
+            )
+        })
+    }
+
+    /// Move key focus to the diff, keeping the cursor where it was if it
+    /// is still in view.
+    fn focus_diff(&mut self, page: (usize, usize)) {
+        if matches!(self.current_diff, DiffContent::Empty | DiffContent::Clean) {
+            self.show_flash_error("Select a file first");
+            return;
+        }
+        self.focus = Focus::Diff;
+        let Some(lines) = self.diff_text_lines() else {
+            return;
+        };
+        let row = crate::ui::diff_panel::row_of_line(
+            lines,
+            self.diff_cursor,
+            page.1,
+            !self.config.ui.low_bandwidth,
+            self.expand_long_lines,
+        );
+        if row < self.diff_scroll || row >= self.diff_scroll + page.0 {
+            self.diff_cursor = self.top_diff_line(page.1);
+        }
+    }
+
+    /// Enter visual mode at the cursor, or on the first changed line in
+    /// view when coming from the file list.
+    fn start_visual_select(&mut self, page: (usize, usize)) {
+        let Some(lines) = self.diff_text_lines() else {
+            self.show_flash_error("Select a file with a text diff first");
+            return;
+        };
+        let is_change =
+            |l: &DiffLine| matches!(l.kind, DiffLineKind::Added | DiffLineKind::Deleted);
+        let top = self.top_diff_line(page.1);
+        let first_change = lines[top..]
+            .iter()
+            .position(is_change)
+            .map(|i| top + i)
+            .or_else(|| lines.iter().position(is_change));
+        match first_change {
+            Some(line) => {
+                self.focus = Focus::Diff;
+                self.diff_cursor = line;
+                self.visual_anchor = Some(line);
+                self.scroll_to_diff_line(line, page);
+            }
+            None => self.show_flash_error("No changed lines to select"),
+        }
+    }
+
+    /// Handle `action` while the diff has focus. Returns false for actions
+    /// that should run as they do in the file list.
+    fn perform_diff_action(&mut self, action: Action, page: (usize, usize)) -> bool {
+        let (page_height, page_width) = page;
+        let Some(lines) = self.diff_text_lines() else {
+            // Nothing to put a cursor on, so navigation scrolls instead.
+            match action {
+                Action::Down => self.scroll_diff(1, page_height, page_width),
+                Action::Up => self.scroll_diff(-1, page_height, page_width),
+                Action::PageDown => self.page_scroll_diff(true, page_height, page_width),
+                Action::PageUp => self.page_scroll_diff(false, page_height, page_width),
+                Action::Top => self.diff_scroll = 0,
+                Action::Bottom => {
+                    self.diff_scroll = crate::ui::diff_panel::max_scroll(
+                        &self.current_diff,
+                        page_height,
+                        page_width,
+                        !self.config.ui.low_bandwidth,
+                        self.expand_long_lines,
+                    )
+                }
+                Action::Back | Action::ToggleFocus => self.focus = Focus::FileList,
+                _ => return false,
+            }
+            return true;
+        };
+        let last = lines.len().saturating_sub(1);
+        let move_to = |line: isize| line.clamp(0, last as isize) as usize;
+        let cursor = self.diff_cursor as isize;
+        let new_cursor = match action {
+            Action::Down => move_to(cursor + 1),
+            Action::Up => move_to(cursor - 1),
+            Action::PageDown => move_to(cursor + page.0 as isize),
+            Action::PageUp => move_to(cursor - page.0 as isize),
+            Action::Top => 0,
+            Action::Bottom => last,
+            Action::Stage | Action::Unstage => {
+                let op = if action == Action::Stage {
+                    git::LineOp::Stage
+                } else {
+                    git::LineOp::Unstage
+                };
+                self.apply_selected_lines(op);
+                return true;
+            }
+            Action::Discard => {
+                self.confirm_discard_lines();
+                return true;
+            }
+            Action::Copy => {
+                self.copy_selected_lines();
+                return true;
+            }
+            Action::VisualSelect => {
+                self.visual_anchor = match self.visual_anchor {
+                    Some(_) => None,
+                    None => Some(self.diff_cursor),
+                };
+                return true;
+            }
+            Action::Select => {
+                self.jump_to_header_file();
+                return true;
+            }
+            Action::Back if self.visual_anchor.is_some() => {
+                self.visual_anchor = None;
+                return true;
+            }
+            Action::Back | Action::ToggleFocus => {
+                self.visual_anchor = None;
+                self.focus = Focus::FileList;
+                return true;
+            }
+            Action::Quit => return false,
+            _ => {
+                self.visual_anchor = None;
+                return false;
+            }
+        };
+        self.diff_cursor = new_cursor;
+        self.scroll_to_diff_line(new_cursor, page);
+        true
+    }
+
+    /// Select the file whose `diff --git` header is under the cursor.
+    fn jump_to_header_file(&mut self) {
+        let header = self
+            .diff_text_lines()
+            .and_then(|lines| lines.get(self.diff_cursor))
+            .filter(|line| line.kind == DiffLineKind::Header)
+            .and_then(|line| git::diff_header_path(&line.content))
+            .map(str::to_string);
+        let Some(path) = header else {
+            return;
+        };
+
+        // Prefer the section being viewed; a rename's old path maps to
+        // the renamed entry.
+        let current = self.selected.as_ref().map(|(section, _)| *section);
+        let sections = [current, Some(Section::Staged), Some(Section::Unstaged)];
+        let target = sections.into_iter().flatten().find_map(|section| {
+            let files = match section {
+                Section::Staged => &self.staged_files,
+                Section::Unstaged => &self.unstaged_files,
+            };
+            files
+                .iter()
+                .find(|f| f.path == path || f.old_path.as_deref() == Some(path.as_str()))
+                .map(|f| (section, f.raw_path.clone()))
+        });
+        let Some((section, path)) = target else {
+            self.show_flash_error(format!("{} is not in the file list", path));
+            return;
+        };
+        if self.selected.as_ref() == Some(&(section, path.clone())) {
+            return;
+        }
+        let row = self
+            .visible_rows
+            .iter()
+            .position(|r| r.section == section && r.raw_path == path);
+        if let Some(idx) = row {
+            self.highlight_index = Some(idx);
+            self.select_current();
+            self.update_scroll_for_highlight();
+        }
+    }
+
+    /// Scroll the diff just enough for `line` to be in view.
+    fn scroll_to_diff_line(&mut self, line: usize, page: (usize, usize)) {
+        let (height, width) = page;
+        let Some(lines) = self.diff_text_lines() else {
+            return;
+        };
+        let row = crate::ui::diff_panel::row_of_line(
+            lines,
+            line,
+            width,
+            !self.config.ui.low_bandwidth,
+            self.expand_long_lines,
+        );
+        if row < self.diff_scroll {
+            self.diff_scroll = row;
+        } else if row >= self.diff_scroll + height {
+            self.diff_scroll = row + 1 - height.max(1);
+        }
+    }
+
+    /// Ordinals of the picked changed lines, as `git::apply_changed_lines`
+    /// counts them.
+    fn selected_changes(&self) -> Vec<usize> {
+        let Some(lines) = self.diff_text_lines() else {
+            return Vec::new();
+        };
+        let range = self.picked_lines();
+        lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| matches!(l.kind, DiffLineKind::Added | DiffLineKind::Deleted))
+            .enumerate()
+            .filter(|(_, (i, _))| range.contains(i))
+            .map(|(ordinal, _)| ordinal)
+            .collect()
+    }
+
+    /// Check that the picked lines can be applied with `op`, returning
+    /// the file's path and the picked changes.
+    fn lines_target(&mut self, op: git::LineOp) -> Option<(Vec<u8>, Vec<usize>)> {
+        let (section, path) = self.selected.clone()?;
+        if section != op.section() {
+            self.show_flash_error(match op {
+                git::LineOp::Unstage => "Select lines in a staged diff to unstage them",
+                _ => "Select lines in an unstaged diff to stage or discard them",
+            });
+            return None;
+        }
+        if self.diff_flags.ignore_eol {
+            self.show_flash_error("Show line-ending changes again before picking lines");
+            return None;
+        }
+        let changes = self.selected_changes();
+        if changes.is_empty() {
+            self.show_flash_error("No changed lines selected");
+            return None;
+        }
+        Some((path, changes))
+    }
+
+    fn apply_selected_lines(&mut self, op: git::LineOp) {
+        let Some((path, changes)) = self.lines_target(op) else {
+            return;
+        };
+        if let Err(e) = self.apply_lines(&path, &changes, op) {
+            let path = git::display_path(&path);
+            self.show_flash_error_detail(format!("Error: {}", e), &e, vec![path]);
+        }
+    }
+
+    fn confirm_discard_lines(&mut self) {
+        let Some((path, changes)) = self.lines_target(git::LineOp::Discard) else {
+            return;
+        };
+        let Some(diff) = self.diff_fingerprint() else {
+            return;
+        };
+        let count = changes.len();
+        self.request_confirm(ConfirmPrompt {
+            message: format!("Discard {} line{}? [y/N]", count, plural_s(count)),
+            action: ConfirmAction::DiscardLines {
+                path,
+                changes,
+                diff,
+            },
+        });
+    }
+
+    fn apply_lines(&mut self, path: &[u8], changes: &[usize], op: git::LineOp) -> Result<()> {
+        let file = self
+            .file_in(op.section(), path)
+            .ok_or_else(|| anyhow::anyhow!("{} is no longer changed", git::display_path(path)))?;
+        if op == git::LineOp::Discard {
+            git::backup_discard(&self.repo, &[&file.raw_path])
+                .context("Failed to back up the file; nothing discarded")?;
+        }
+        let count = git::apply_changed_lines(
+            &self.repo,
+            &file.raw_path,
+            file.raw_old_path.as_deref(),
+            changes,
+            op,
+        )?;
+        self.visual_anchor = None;
+        self.last_action = None;
+        self.refresh()?;
+        let verb = match op {
+            git::LineOp::Stage => "Staged",
+            git::LineOp::Unstage => "Unstaged",
+            git::LineOp::Discard => "Discarded",
+        };
+        self.show_flash_success(format!("{} {} line{}", verb, count, plural_s(count)));
+        let hook = match op {
+            git::LineOp::Stage => Some(Hook::PostStage),
+            git::LineOp::Unstage => None,
+            git::LineOp::Discard => Some(Hook::PostDiscard),
+        };
+        if let Some(hook) = hook {
+            self.run_hook(hook, &[git::display_path(path)]);
+        }
+        Ok(())
+    }
+
+    /// Run the `[hooks]` command for `hook` on `paths`, if one is set, and
+    /// show what it printed: its first line as a flash, and all of it in the
+    /// error details when it fails. Returns false if the hook failed.
+    fn run_hook(&mut self, hook: Hook, paths: &[String]) -> bool {
+        let Some(command) = hook.command(&self.config.hooks) else {
+            return true;
+        };
+        if paths.is_empty() {
+            return true;
+        }
+        let command = command.to_string();
+        match commands::run_hook(&command, hook, self.workdir(), paths) {
+            Ok(out) if out.status.success() => {
+                if let Some(line) = out.output.lines().next() {
+                    self.show_flash_success(line.to_string());
+                }
+                return true;
+            }
+            Ok(out) => {
+                let summary = out.summary();
+                let error = anyhow::anyhow!("{}", out.output).context(format!(
+                    "{} hook failed ({})",
+                    hook.name(),
+                    out.status
+                ));
+                self.show_flash_error_detail(
+                    format!("{} hook: {}", hook.name(), summary),
+                    &error,
+                    paths.to_vec(),
+                );
+            }
+            Err(e) => self.show_flash_error_detail(
+                format!("{} hook failed to run", hook.name()),
+                &e,
+                paths.to_vec(),
+            ),
+        }
+        false
+    }
+
+    /// Put the picked lines' text on the clipboard.
+    fn copy_selected_lines(&mut self) {
+        let Some(lines) = self.diff_text_lines() else {
+            return;
+        };
+        let range = self.picked_lines();
+        let count = range.clone().count();
+        let text = lines[range]
+            .iter()
+            .map(|l| l.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.clipboard = Some(text);
+        self.visual_anchor = None;
+        self.show_flash_success(format!("Copied {} line{}", count, plural_s(count)));
+    }
+
+    /// Show or clip lines longer than `LONG_LINE_CHARS` characters, or
+    /// expand a collapsed generated or minified file.
+    pub fn toggle_expand_lines(&mut self) {
+        let expanded = match self.current_diff {
+            DiffContent::Collapsed { .. } => Some("Showing generated file's diff"),
+            DiffContent::Minified { .. } => Some("Rendering minified diff"),
+            _ => None,
+        };
+        if let Some(message) = expanded {
+            if let Some((_, path)) = &self.selected {
+                self.expanded_diffs.insert(path.clone());
+            }
+            self.update_diff_for_selected();
+            self.show_flash_success(message);
+            return;
+        }
+        let limit = crate::ui::diff_panel::LONG_LINE_CHARS;
+        let is_long = |text: &str| text.chars().nth(limit).is_some();
+        let has_long_lines = match &self.current_diff {
+            DiffContent::Text(lines) => lines.iter().any(|l| is_long(&l.content)),
+            DiffContent::Preview(preview) => preview
+                .lines
+                .iter()
+                .any(|tokens| tokens.iter().map(|(_, t)| t.chars().count()).sum::<usize>() > limit),
+            _ => false,
+        };
+        if !has_long_lines && !self.expand_long_lines {
+            self.show_flash_error("No long lines to expand");
+            return;
+        }
+        self.expand_long_lines = !self.expand_long_lines;
+        self.show_flash_success(if self.expand_long_lines {
+            "Showing long lines in full"
+        } else {
+            "Clipping long lines"
+        });
+    }
+
+    /// Switch binary files between metadata and hexdump views.
+    pub fn toggle_hexdump(&mut self) {
+        match &self.current_diff {
+            DiffContent::Binary(info) if info.content.is_none() => {
+                self.show_flash_error(format!(
+                    "Too large for hexdump (limit {} KiB)",
+                    self.config.preview.hexdump_max_kib
+                ));
+            }
+            DiffContent::Binary(_) | DiffContent::Hexdump(_) => {
+                self.hexdump = !self.hexdump;
+                self.diff_scroll = 0;
+                self.update_diff_for_selected();
+            }
+            _ => self.show_flash_error("Hexdump is only available for binary files"),
+        }
+    }
+
+    /// Start a fetch, push, or pull in the background using the default
+    /// credential sources.
+    pub fn run_network_op(&mut self, op: NetworkOp) {
+        self.run_network_op_with(op, Credentials::new());
+    }
+
+    fn run_network_op_with(&mut self, op: NetworkOp, creds: Credentials) {
+        if let Some(task) = &self.network_task {
+            self.show_flash_error(format!("{} already in progress", task.status.op.name()));
+            return;
+        }
+        if op == NetworkOp::FetchObjects {
+            // The password may unlock blobs that failed without one.
+            self.unfetchable.clear();
+            let objects = self.selected_missing_blobs();
+            self.network_task = Some(NetworkTask::spawn_object_fetch(
+                self.repo.path(),
+                objects,
+                creds,
+            ));
+            self.update_diff_for_selected();
+            return;
+        }
+        self.network_task = Some(NetworkTask::spawn(self.repo.path(), op, creds));
+    }
+
+    /// Ask the running network operation to stop. Returns false if none is running.
+    pub fn cancel_network_op(&mut self) -> bool {
+        match &mut self.network_task {
+            Some(task) if !task.automatic => {
+                task.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Apply user configuration, (re)starting the auto-fetch timer as needed.
+    pub fn apply_config(&mut self, mut config: Config) {
+        if self.options.low_bandwidth {
+            config.ui.low_bandwidth = true;
+        }
+        self.keymap = match Keymap::new(config.keys.preset, &config.keys.overrides) {
+            Ok(keymap) => keymap,
+            Err(e) => {
+                self.show_flash_error(format!("Invalid [keys] config: {:#}", e));
+                Keymap::default()
+            }
+        };
+        let mut with_commands = self.keymap.clone();
+        match with_commands.bind_commands(&config.commands) {
+            Ok(()) => self.keymap = with_commands,
+            Err(e) => self.show_flash_error(format!("Invalid [[commands]] config: {:#}", e)),
+        }
+        self.key_sequence.clear();
+        ui::colors::set_theme(config.ui.theme);
+        ui::symbols::set_glyphs(config.ui.glyphs);
+        ui::icons::set_icons(config.ui.icons);
+        self.auto_fetch = config
+            .auto_fetch
+            .enabled
+            .then(|| AutoFetch::new(config.auto_fetch.interval(), Instant::now()));
+        if config.ui.relative_paths != self.config.ui.relative_paths {
+            self.relative_paths = config.ui.relative_paths;
+        }
+        // Both change what the file list shows.
+        let list_changed =
+            config.generated != self.config.generated || config.preview != self.config.preview;
+        self.config = config;
+        if list_changed {
+            if let Err(e) = self.refresh() {
+                self.show_flash_error_detail(format!("Error: {}", e), &e, Vec::new());
+            }
+        }
+    }
+
+    /// Re-read the config file at `path` and apply it. An invalid file is
+    /// reported and the current settings are kept.
+    pub fn reload_config(&mut self, path: &Path) {
+        match Config::load_from(path) {
+            Ok(config) => {
+                tracing::info!(path = %path.display(), "config reloaded");
+                // apply_config flashes its own error for an invalid keymap.
+                self.clear_flash();
+                self.apply_config(config);
+                if self.flash_message.is_none() {
+                    self.show_flash_success("Config reloaded");
+                }
+            }
+            Err(e) => self.show_flash_error_detail(
+                format!("Config not reloaded: {:#}", e),
+                &e,
+                vec![path.display().to_string()],
+            ),
+        }
+    }
+
+    /// Whether something on screen changes without input: network progress,
+    /// a flash waiting to expire, or a pending key sequence.
+    fn is_animating(&self) -> bool {
+        self.network_task.is_some()
+            || self.pre_commit.is_some()
+            || self.flash_expires()
+            || !self.key_sequence.pending().is_empty()
+    }
+
+    /// Start a background fetch if the auto-fetch timer is due. Skipped while
+    /// another network operation or a modal is open, and for branches
+    /// without an upstream to compare against.
+    pub fn maybe_auto_fetch(&mut self, now: Instant) {
+        let Some(auto) = &self.auto_fetch else {
+            return;
+        };
+        if !auto.is_due(now)
+            || self.network_task.is_some()
+            || self.modal.is_some()
+            || self.upstream.is_none()
+        {
+            return;
+        }
+        self.network_task = Some(NetworkTask::spawn_auto_fetch(self.repo.path()));
+    }
+
+    /// Pick up progress from the running network operation and report its
+    /// outcome once it finishes.
+    pub fn poll_network(&mut self) {
+        let Some(task) = &mut self.network_task else {
+            return;
+        };
+        let Some(outcome) = task.poll() else {
+            return;
+        };
+        let op = task.status.op;
+        let automatic = task.automatic;
+        let objects = std::mem::take(&mut task.objects);
+        self.network_task = None;
+
+        if automatic {
+            self.finish_auto_fetch(outcome);
+        } else if op == NetworkOp::FetchObjects {
+            self.finish_object_fetch(outcome, objects);
+        } else {
+            self.finish_network_op(op, outcome);
+        }
+        // A diff left waiting on blobs while another operation ran.
+        if matches!(self.current_diff, DiffContent::Fetching) && self.network_task.is_none() {
+            self.update_diff_for_selected();
+        }
+    }
+
+    fn finish_network_op(&mut self, op: NetworkOp, outcome: NetworkOutcome) {
+        match outcome {
+            NetworkOutcome::Done(message) => {
+                if op != NetworkOp::Push {
+                    if let Some(auto) = &mut self.auto_fetch {
+                        auto.record_success(Instant::now());
+                    }
+                }
+                if let Err(e) = self.refresh() {
+                    self.show_flash_error_detail(format!("Error: {}", e), &e, Vec::new());
+                } else {
+                    self.show_flash_success(message);
+                }
+            }
+            NetworkOutcome::Cancelled => {
+                self.show_flash_error(format!("{} cancelled", op.name()));
+            }
+            NetworkOutcome::Failed(e) => {
+                if let Some(auth) = e.downcast_ref::<AuthRequired>() {
+                    self.prompt_credentials(op, auth.url.clone());
+                } else {
+                    self.show_flash_error_detail(format!("Error: {:#}", e), &e, Vec::new());
+                }
+            }
+        }
+    }
+
+    /// Show the diff the fetched blobs were for. Blobs that couldn't be
+    /// fetched aren't tried again, so the diff falls back to going without.
+    fn finish_object_fetch(&mut self, outcome: NetworkOutcome, objects: Vec<git2::Oid>) {
+        match outcome {
+            NetworkOutcome::Done(_) => {
+                // Pick up the pack git just wrote.
+                if let Err(e) = self.repo.odb().and_then(|odb| odb.refresh()) {
+                    tracing::warn!(error = %e, "failed to refresh object database");
+                }
+            }
+            NetworkOutcome::Cancelled => self.unfetchable.extend(objects),
+            NetworkOutcome::Failed(e) => {
+                tracing::warn!(error = %format!("{:#}", e), "object fetch failed");
+                self.unfetchable.extend(objects);
+                if let Some(auth) = e.downcast_ref::<AuthRequired>() {
+                    self.prompt_credentials(NetworkOp::FetchObjects, auth.url.clone());
+                } else {
+                    self.show_flash_error_detail(
+                        "Couldn't fetch objects from the promisor remote".to_string(),
+                        &e,
+                        Vec::new(),
+                    );
+                }
+            }
+        }
+        self.update_diff_for_selected();
+    }
+
+    /// Ask for the username and password `url` wants, then retry `op`.
+    fn prompt_credentials(&mut self, op: NetworkOp, url: String) {
+        self.modal = Some(Modal::Credentials(CredentialPrompt {
+            op,
+            url,
+            username: String::new(),
+            password: String::new(),
+            editing_password: false,
+        }));
+    }
+
+    fn finish_auto_fetch(&mut self, outcome: NetworkOutcome) {
+        let now = Instant::now();
+        match outcome {
+            NetworkOutcome::Done(_) | NetworkOutcome::Cancelled => {
+                if let Some(auto) = &mut self.auto_fetch {
+                    auto.record_success(now);
+                }
+                if let Err(e) = self.refresh() {
+                    self.show_flash_error_detail(format!("Error: {}", e), &e, Vec::new());
+                }
+            }
+            NetworkOutcome::Failed(e) => {
+                let Some(auto) = &mut self.auto_fetch else {
+                    return;
+                };
+                let delay = auto.record_failure(now);
+                // Only the first failure in a row is worth interrupting for.
+                if auto.failures() == 1 {
+                    self.show_flash_error(format!(
+                        "Auto-fetch failed: {:#} (retrying in {} min)",
+                        e,
+                        delay.as_secs() / 60
+                    ));
+                }
+            }
+        }
+    }
+
+    fn handle_credential_prompt_key(&mut self, code: KeyCode) {
+        let Some(Modal::Credentials(prompt)) = &mut self.modal else {
+            return;
+        };
+        let field = if prompt.editing_password {
+            &mut prompt.password
+        } else {
+            &mut prompt.username
+        };
+        match code {
+            KeyCode::Esc => self.close_modal(),
+            KeyCode::Tab | KeyCode::BackTab => {
+                prompt.editing_password = !prompt.editing_password;
+            }
+            KeyCode::Backspace => {
+                field.pop();
+            }
+            KeyCode::Char(c) => field.push(c),
+            KeyCode::Enter if !prompt.editing_password => prompt.editing_password = true,
+            KeyCode::Enter => {
+                let Some(Modal::Credentials(prompt)) = self.modal.take() else {
+                    return;
+                };
+                let creds = Credentials::with_userpass(prompt.username, prompt.password);
+                self.run_network_op_with(prompt.op, creds);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn open_remote_panel(&mut self) {
+        match git::list_remotes(&self.repo) {
+            Ok(remotes) => {
+                self.modal = Some(Modal::Remotes(RemotePanel {
+                    remotes,
+                    ..RemotePanel::default()
+                }));
+            }
+            Err(e) => self.show_flash_error_detail(format!("Error: {}", e), &e, Vec::new()),
+        }
+    }
+
+    pub fn open_message_panel(&mut self) {
+        self.modal = Some(Modal::Messages(MessagePanel {
+            messages: self.message_history.iter().rev().cloned().collect(),
+            scroll: 0,
+        }));
+    }
+
+    pub fn open_stash_panel(&mut self) {
+        match git::list_stashes(&self.repo) {
+            Ok(stashes) => {
+                self.modal = Some(Modal::Stashes(StashPanel {
+                    stashes,
+                    ..StashPanel::default()
+                }));
+            }
+            Err(e) => self.show_flash_error_detail(format!("Error: {}", e), &e, Vec::new()),
+        }
+    }
+
+    /// Route a key press to the stash panel: pick a stash with Enter, then
+    /// mark files with Space (or `a` for all) and restore them with Enter.
+    fn handle_stash_panel_key(&mut self, code: KeyCode) {
+        let Some(Modal::Stashes(panel)) = &mut self.modal else {
+            return;
+        };
+        panel.error = None;
+        let file_count = panel.selected_stash().map_or(0, |s| s.files.len());
+        let Some(cursor) = panel.file_cursor else {
+            match code {
+                KeyCode::Esc => self.close_modal(),
+                KeyCode::Down if panel.selected + 1 < panel.stashes.len() => panel.selected += 1,
+                KeyCode::Up => panel.selected = panel.selected.saturating_sub(1),
+                KeyCode::Enter if file_count > 0 => panel.file_cursor = Some(0),
+                _ => {}
+            }
+            return;
+        };
+        match code {
+            KeyCode::Esc => {
+                panel.file_cursor = None;
+                panel.chosen.clear();
+            }
+            KeyCode::Down if cursor + 1 < file_count => panel.file_cursor = Some(cursor + 1),
+            KeyCode::Up => panel.file_cursor = Some(cursor.saturating_sub(1)),
+            KeyCode::Char(' ') if !panel.chosen.remove(&cursor) => {
+                panel.chosen.insert(cursor);
+            }
+            KeyCode::Char('a') if panel.chosen.len() == file_count => panel.chosen.clear(),
+            KeyCode::Char('a') => panel.chosen = (0..file_count).collect(),
+            KeyCode::Enter => {
+                let paths = panel.restore_paths();
+                let index = panel.selected_stash().map_or(0, |s| s.index);
+                self.restore_from_stash(index, &paths);
+            }
+            _ => {}
+        }
+    }
+
+    fn restore_from_stash(&mut self, index: usize, paths: &[String]) {
+        if let Err(e) = git::restore_stash_files(&self.repo, index, paths) {
+            if let Some(Modal::Stashes(panel)) = &mut self.modal {
+                panel.error = Some(format!("{:#}", e));
+            }
+            return;
+        }
+        self.close_modal();
+        if let Err(e) = self.refresh() {
+            self.show_flash_error_detail(format!("Error: {}", e), &e, Vec::new());
             return;
         }
-
-        let current = self.highlight_index.unwrap_or(0) as isize;
-        let new_idx = (current + delta).clamp(0, self.visible_rows.len() as isize - 1) as usize;
-        self.highlight_index = Some(new_idx);
-        self.update_scroll_for_highlight();
+        self.show_flash_success(format!(
+            "Restored {} file{} from stash@{{{}}}",
+            paths.len(),
+            plural_s(paths.len()),
+            index
+        ));
     }
 
-    fn update_scroll_for_highlight(&mut self) {
-        if let Some(idx) = self.highlight_index {
-            let header_offset = self.count_headers_before(idx);
-            let visual_idx = idx + header_offset;
+    pub fn open_recover_panel(&mut self) {
+        match git::list_discard_backups(&self.repo) {
+            Ok(backups) => {
+                self.modal = Some(Modal::Recover(RecoverPanel {
+                    backups,
+                    ..RecoverPanel::default()
+                }));
+            }
+            Err(e) => self.show_flash_error_detail(format!("Error: {}", e), &e, Vec::new()),
+        }
+    }
 
-            if visual_idx < self.file_list_scroll {
-                self.file_list_scroll = visual_idx;
-            } else if self.file_list_height > 0
-                && visual_idx >= self.file_list_scroll + self.file_list_height
-            {
-                self.file_list_scroll = visual_idx - self.file_list_height + 1;
+    /// Route a key press to the recover panel: pick a backup with Enter,
+    /// then restore the highlighted file with Enter or all of them with `a`.
+    fn handle_recover_panel_key(&mut self, code: KeyCode) {
+        let Some(Modal::Recover(panel)) = &mut self.modal else {
+            return;
+        };
+        panel.error = None;
+        let Some(backup) = panel.selected_backup() else {
+            if code == KeyCode::Esc {
+                self.close_modal();
+            }
+            return;
+        };
+        let index = backup.index;
+        let file_count = backup.files.len();
+        let all: Vec<Vec<u8>> = backup.files.iter().map(|f| f.raw_path.clone()).collect();
+        let Some(cursor) = panel.file_cursor else {
+            match code {
+                KeyCode::Esc => self.close_modal(),
+                KeyCode::Down if panel.selected + 1 < panel.backups.len() => panel.selected += 1,
+                KeyCode::Up => panel.selected = panel.selected.saturating_sub(1),
+                KeyCode::Enter if file_count > 0 => panel.file_cursor = Some(0),
+                _ => {}
             }
+            return;
+        };
+        match code {
+            KeyCode::Esc => panel.file_cursor = None,
+            KeyCode::Down if cursor + 1 < file_count => panel.file_cursor = Some(cursor + 1),
+            KeyCode::Up => panel.file_cursor = Some(cursor.saturating_sub(1)),
+            KeyCode::Char('a') => self.recover_files(index, &all),
+            KeyCode::Enter => {
+                let path = all[cursor].clone();
+                self.recover_files(index, &[path]);
+            }
+            _ => {}
         }
     }
 
-    fn count_headers_before(&self, file_idx: usize) -> usize {
-        let mut headers = 0;
-        if !self.staged_files.is_empty() {
-            headers += 1;
+    fn recover_files(&mut self, index: usize, paths: &[Vec<u8>]) {
+        if let Err(e) = git::restore_discard_backup(&self.repo, index, paths) {
+            if let Some(Modal::Recover(panel)) = &mut self.modal {
+                panel.error = Some(format!("{:#}", e));
+            }
+            return;
         }
-        if !self.unstaged_files.is_empty() && file_idx >= self.staged_files.len() {
-            headers += 1;
+        self.close_modal();
+        if let Err(e) = self.refresh() {
+            self.show_flash_error_detail(format!("Error: {}", e), &e, Vec::new());
+            return;
         }
-        headers
+        self.show_flash_success(format!(
+            "Recovered {} file{}",
+            paths.len(),
+            plural_s(paths.len())
+        ));
     }
 
-    fn scroll_diff(&mut self, delta: isize, viewport_height: usize, viewport_width: usize) {
-        let max_scroll =
-            crate::ui::diff_panel::max_scroll(&self.current_diff, viewport_height, viewport_width);
-        let current = self.diff_scroll as isize;
-        self.diff_scroll = (current + delta).clamp(0, max_scroll as isize) as usize;
+    /// Ask for the message of the first commit, on a branch with none yet.
+    pub fn open_initial_commit(&mut self) {
+        if !matches!(self.branch, BranchInfo::Unborn(_)) {
+            self.show_flash_error("Already has commits; commit with git");
+        } else if self.staged_files.is_empty() {
+            self.show_flash_error("Stage files for the initial commit first");
+        } else {
+            let paths: Vec<String> = self.staged_files.iter().map(|f| f.path.clone()).collect();
+            if self.run_hook(Hook::PreCommitPrompt, &paths) {
+                self.modal = Some(Modal::InitialCommit(CommitPrompt::default()));
+            }
+        }
     }
 
-    fn page_scroll_diff(&mut self, down: bool, viewport_height: usize, viewport_width: usize) {
-        let delta = if down {
-            viewport_height as isize
-        } else {
-            -(viewport_height as isize)
+    fn handle_initial_commit_key(&mut self, code: KeyCode) {
+        let Some(Modal::InitialCommit(prompt)) = &mut self.modal else {
+            return;
         };
-        self.scroll_diff(delta, viewport_height, viewport_width);
+        match code {
+            KeyCode::Esc => self.close_modal(),
+            KeyCode::Backspace => {
+                prompt.message.pop();
+            }
+            KeyCode::Char(c) => prompt.message.push(c),
+            KeyCode::Enter if prompt.message.trim().is_empty() => {
+                prompt.error = Some("Enter a commit message".to_string());
+            }
+            KeyCode::Enter => {
+                let message = prompt.message.trim().to_string();
+                match git::create_initial_commit(&self.repo, &message) {
+                    Ok(oid) => {
+                        self.close_modal();
+                        self.last_action = None;
+                        if let Err(e) = self.refresh() {
+                            self.show_flash_error_detail(format!("Error: {}", e), &e, Vec::new());
+                            return;
+                        }
+                        let id = oid.to_string();
+                        self.show_flash_success(format!("Created initial commit {}", &id[..7]));
+                    }
+                    Err(e) => prompt.error = Some(format!("{:#}", e)),
+                }
+            }
+            _ => {}
+        }
     }
 
-    fn click_file_list(&mut self, row: u16) {
-        let inner_row = row.saturating_sub(self.file_list_area.y + 1) as usize;
-        let visual_row = self.file_list_scroll + inner_row;
+    /// Whether HEAD is detached outside a rebase, flashing why not if it
+    /// isn't. The branch helpers only apply then.
+    fn check_detached(&mut self) -> bool {
+        if !matches!(self.branch, BranchInfo::Detached(_)) {
+            self.show_flash_error("HEAD is not detached");
+            false
+        } else if self.rebase_progress.is_some() {
+            self.show_flash_error("A rebase is in progress; finish or abort it first");
+            false
+        } else {
+            true
+        }
+    }
 
-        let staged_count = self.staged_files.len();
-        let unstaged_count = self.unstaged_files.len();
+    /// Ask for the name of a branch to create at the detached HEAD.
+    pub fn open_new_branch(&mut self) {
+        if self.check_detached() {
+            self.modal = Some(Modal::NewBranch(BranchPrompt::default()));
+        }
+    }
 
-        let file_index = if staged_count > 0 && unstaged_count > 0 {
-            let staged_header = 0;
-            let unstaged_header = 1 + staged_count;
+    fn handle_new_branch_key(&mut self, code: KeyCode) {
+        let Some(Modal::NewBranch(prompt)) = &mut self.modal else {
+            return;
+        };
+        match code {
+            KeyCode::Esc => self.close_modal(),
+            KeyCode::Backspace => {
+                prompt.name.pop();
+            }
+            KeyCode::Char(c) if !c.is_whitespace() => prompt.name.push(c),
+            KeyCode::Enter if prompt.name.is_empty() => {
+                prompt.error = Some("Enter a branch name".to_string());
+            }
+            KeyCode::Enter => {
+                let name = prompt.name.clone();
+                match git::create_branch_at_head(&self.repo, &name) {
+                    Ok(()) => {
+                        self.close_modal();
+                        if let Err(e) = self.refresh() {
+                            self.show_flash_error_detail(format!("Error: {}", e), &e, Vec::new());
+                            return;
+                        }
+                        self.show_flash_success(format!("Created and switched to {}", name));
+                    }
+                    Err(e) => prompt.error = Some(format!("{:#}", e)),
+                }
+            }
+            _ => {}
+        }
+    }
 
-            if visual_row == staged_header || visual_row == unstaged_header {
-                return;
-            } else if visual_row < unstaged_header {
-                visual_row - 1
-            } else {
-                visual_row - 2
+    /// Check out the branch HEAD was detached from.
+    pub fn return_to_previous_branch(&mut self) {
+        if !self.check_detached() {
+            return;
+        }
+        let Some(name) = self.previous_branch.clone() else {
+            self.show_flash_error("No previous branch to return to");
+            return;
+        };
+        if let Err(e) = git::switch_branch(&self.repo, &name) {
+            self.show_flash_error_detail(format!("Error: {:#}", e), &e, Vec::new());
+            return;
+        }
+        self.last_action = None;
+        if let Err(e) = self.refresh() {
+            self.show_flash_error_detail(format!("Error: {}", e), &e, Vec::new());
+            return;
+        }
+        self.show_flash_success(format!("Switched to {}", name));
+    }
+
+    /// Offer the first-run setup, which writes its choices to `path`.
+    pub fn open_setup(&mut self, path: PathBuf) {
+        self.modal = Some(Modal::Setup(SetupForm::new(path)));
+    }
+
+    fn handle_setup_key(&mut self, code: KeyCode) {
+        let Some(Modal::Setup(form)) = &mut self.modal else {
+            return;
+        };
+        match code {
+            KeyCode::Down => form.row = (form.row + 1).min(SetupForm::ROWS - 1),
+            KeyCode::Up => form.row = form.row.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char(' ') => form.cycle(true),
+            KeyCode::Left => form.cycle(false),
+            KeyCode::Enter => {
+                let text = form.config_text();
+                self.finish_setup(text, "Saved settings to");
             }
-        } else if staged_count > 0 || unstaged_count > 0 {
-            if visual_row == 0 {
-                return;
+            // Skipping still writes a file so the setup isn't offered again.
+            KeyCode::Esc => {
+                let text = "# better-git-status settings; see the README for options.\n";
+                self.finish_setup(text.to_string(), "Skipped setup; settings file is");
             }
-            visual_row - 1
-        } else {
+            _ => {}
+        }
+    }
+
+    fn finish_setup(&mut self, text: String, message: &str) {
+        let Some(Modal::Setup(form)) = &mut self.modal else {
+            return;
+        };
+        let path = form.path.clone();
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| std::fs::write(&path, &text));
+        if let Err(e) = result {
+            form.error = Some(format!("Failed to write {}: {}", path.display(), e));
+            return;
+        }
+        self.close_modal();
+        match Config::parse(&text) {
+            Ok(config) => self.apply_config(config),
+            Err(e) => self.show_flash_error_detail(format!("Error: {:#}", e), &e, Vec::new()),
+        }
+        self.show_flash_success(format!("{} {}", message, path.display()));
+    }
+
+    /// Open the detail popup for the most recent error that has one.
+    pub fn open_error_detail(&mut self) {
+        let detail = self
+            .message_history
+            .iter()
+            .rev()
+            .find(|flash| flash.is_error)
+            .and_then(|flash| flash.detail.clone());
+        match detail {
+            Some(detail) => self.modal = Some(Modal::ErrorDetail(detail)),
+            None => self.show_flash_error("No error details to show"),
+        }
+    }
+
+    fn handle_message_panel_key(&mut self, code: KeyCode) {
+        let Some(Modal::Messages(panel)) = &mut self.modal else {
             return;
         };
+        match code {
+            KeyCode::Esc => self.close_modal(),
+            KeyCode::Down if panel.scroll + 1 < panel.messages.len() => panel.scroll += 1,
+            KeyCode::Up => panel.scroll = panel.scroll.saturating_sub(1),
+            _ => {}
+        }
+    }
 
-        if file_index < self.visible_rows.len() {
-            self.highlight_index = Some(file_index);
-            self.select_current();
+    /// Scroll or close the pre-commit panel. Closing it leaves the hooks
+    /// running; the result still shows in the status bar.
+    fn handle_pre_commit_panel_key(&mut self, code: KeyCode) {
+        let Some(Modal::PreCommit(panel)) = &mut self.modal else {
+            return;
+        };
+        match code {
+            KeyCode::Esc => self.close_modal(),
+            KeyCode::Up if panel.scroll_back + 1 < panel.lines.len() => panel.scroll_back += 1,
+            KeyCode::Down => panel.scroll_back = panel.scroll_back.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    fn remote_panel_mut(&mut self) -> Option<&mut RemotePanel> {
+        match &mut self.modal {
+            Some(Modal::Remotes(panel)) => Some(panel),
+            _ => None,
+        }
+    }
+
+    /// Route a key press to the remote panel.
+    pub fn handle_remote_panel_key(&mut self, code: KeyCode) {
+        let Some(Modal::Remotes(panel)) = &mut self.modal else {
+            return;
+        };
+
+        if let Some(name) = panel.confirm_remove.take() {
+            if matches!(code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                let result = git::remove_remote(&self.repo, &name);
+                self.finish_remote_change(result, format!("Removed remote {}", name));
+            }
+            return;
+        }
+
+        if let Some(input) = panel.input.as_mut() {
+            match code {
+                KeyCode::Esc => panel.input = None,
+                KeyCode::Backspace => {
+                    input.value.pop();
+                }
+                KeyCode::Char(c) => input.value.push(c),
+                KeyCode::Enter => {
+                    if let Some(input) = panel.input.take() {
+                        self.submit_remote_input(input);
+                    }
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        panel.error = None;
+        match code {
+            KeyCode::Esc => self.close_modal(),
+            KeyCode::Down if panel.selected + 1 < panel.remotes.len() => panel.selected += 1,
+            KeyCode::Up => panel.selected = panel.selected.saturating_sub(1),
+            KeyCode::Char('a') => {
+                panel.input = Some(RemoteInput {
+                    kind: RemoteInputKind::Add,
+                    value: String::new(),
+                });
+            }
+            KeyCode::Char('r') => {
+                if let Some(remote) = panel.selected_remote() {
+                    let name = remote.name.clone();
+                    panel.input = Some(RemoteInput {
+                        kind: RemoteInputKind::Rename(name.clone()),
+                        value: name,
+                    });
+                }
+            }
+            KeyCode::Char('x') => {
+                panel.confirm_remove = panel.selected_remote().map(|r| r.name.clone());
+            }
+            KeyCode::Char('u') => {
+                if let Some(remote) = panel.selected_remote() {
+                    let value = match &self.branch {
+                        BranchInfo::Branch(branch) | BranchInfo::Unborn(branch) => {
+                            format!("{}/{}", remote.name, branch)
+                        }
+                        BranchInfo::Detached(_) => format!("{}/", remote.name),
+                    };
+                    panel.input = Some(RemoteInput {
+                        kind: RemoteInputKind::SetUpstream,
+                        value,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn submit_remote_input(&mut self, input: RemoteInput) {
+        let value = input.value.trim().to_string();
+        match input.kind {
+            RemoteInputKind::Add => {
+                let mut parts = value.split_whitespace();
+                match (parts.next(), parts.next()) {
+                    (Some(name), Some(url)) => {
+                        let result = git::add_remote(&self.repo, name, url);
+                        self.finish_remote_change(result, format!("Added remote {}", name));
+                    }
+                    _ => self.set_remote_panel_error("Expected: <name> <url>"),
+                }
+            }
+            RemoteInputKind::Rename(old) => {
+                if value.is_empty() || value == old {
+                    return;
+                }
+                let result = git::rename_remote(&self.repo, &old, &value).map(|problems| {
+                    if !problems.is_empty() {
+                        self.set_remote_panel_error(format!(
+                            "Refspecs not updated: {}",
+                            problems.join(", ")
+                        ));
+                    }
+                });
+                self.finish_remote_change(result, format!("Renamed remote {} to {}", old, value));
+            }
+            RemoteInputKind::SetUpstream => {
+                let result = git::set_upstream(&self.repo, &value);
+                self.finish_remote_change(result, format!("Upstream set to {}", value));
+            }
+        }
+    }
+
+    fn finish_remote_change(&mut self, result: Result<()>, success: String) {
+        match result {
+            Ok(()) => {
+                self.refresh_upstream();
+                let remotes = git::list_remotes(&self.repo).unwrap_or_default();
+                if let Some(panel) = self.remote_panel_mut() {
+                    panel.selected = panel.selected.min(remotes.len().saturating_sub(1));
+                    panel.remotes = remotes;
+                }
+                self.show_flash_success(success);
+            }
+            Err(e) => self.set_remote_panel_error(format!("{:#}", e)),
+        }
+    }
+
+    fn set_remote_panel_error(&mut self, message: impl Into<String>) {
+        if let Some(panel) = self.remote_panel_mut() {
+            panel.error = Some(message.into());
         }
     }
 }
 
+/// `cwd` relative to `workdir` as a `/`-separated path, if it's a
+/// subdirectory of it.
+fn launch_dir(workdir: &Path, cwd: &Path) -> Option<String> {
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    let relative = canonical(cwd)
+        .strip_prefix(canonical(workdir))
+        .ok()?
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/");
+    (!relative.is_empty()).then_some(relative)
+}
+
 fn plural_s(count: usize) -> &'static str {
-    if count == 1 { "" } else { "s" }
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+/// The notice to show for `upstream`, unless the user already dismissed one
+/// for at least as many commits on the same upstream.
+fn upstream_notice(
+    upstream: Option<&UpstreamInfo>,
+    dismissed: Option<&UpstreamNotice>,
+) -> Option<UpstreamNotice> {
+    let upstream = upstream.filter(|u| u.behind > 0)?;
+    let already_dismissed =
+        dismissed.is_some_and(|d| d.upstream == upstream.name && d.behind >= upstream.behind);
+    (!already_dismissed).then(|| UpstreamNotice {
+        upstream: upstream.name.clone(),
+        behind: upstream.behind,
+    })
 }
 
 pub(crate) fn build_visible_rows(staged: &[FileEntry], unstaged: &[FileEntry]) -> Vec<VisibleRow> {
@@ -615,26 +3227,38 @@ pub(crate) fn build_visible_rows(staged: &[FileEntry], unstaged: &[FileEntry]) -
     for file in staged.iter() {
         rows.push(VisibleRow {
             section: Section::Staged,
-            path: file.path.clone(),
+            raw_path: file.raw_path.clone(),
         });
     }
     for file in unstaged.iter() {
         rows.push(VisibleRow {
             section: Section::Unstaged,
-            path: file.path.clone(),
+            raw_path: file.raw_path.clone(),
         });
     }
     rows
 }
 
-pub fn run(path: &str) -> Result<()> {
+/// Where the row highlighted at `old` index is in the rebuilt `rows`: the
+/// same file if it is still listed, else whatever now sits in its place.
+/// `rows` must not be empty.
+pub(crate) fn relocate_highlight(old: Option<(usize, VisibleRow)>, rows: &[VisibleRow]) -> usize {
+    let Some((idx, row)) = old else {
+        return 0;
+    };
+    rows.iter()
+        .position(|r| r.section == row.section && r.raw_path == row.raw_path)
+        .unwrap_or(idx.min(rows.len() - 1))
+}
+
+pub fn run(path: &str, config: Config, options: RunOptions) -> Result<()> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let result = run_app(&mut terminal, path);
+    let result = run_app(&mut terminal, path, config, options);
 
     disable_raw_mode()?;
     execute!(
@@ -644,89 +3268,161 @@ pub fn run(path: &str) -> Result<()> {
     )?;
     terminal.show_cursor()?;
 
-    result
+    // Reported once the terminal is back, so stderr output isn't lost.
+    if let Some(profile) = result? {
+        profile.emit();
+    }
+    Ok(())
 }
 
-fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, path: &str) -> Result<()> {
-    let mut app = App::new(path)?;
+/// Hand the terminal to `command` until it exits, then restore the UI.
+/// The outer result is a failure to restore the terminal; the inner one is
+/// the command's.
+fn run_suspended(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    command: &PreparedCommand,
+    dir: &Path,
+) -> Result<Result<std::process::ExitStatus>> {
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()?;
+
+    let result = command.run(dir);
+
+    enable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        EnterAlternateScreen,
+        EnableMouseCapture
+    )?;
+    terminal.clear()?;
+    Ok(result)
+}
 
-    let watcher = FileWatcher::new(Path::new(path));
-    let mut use_polling = watcher.is_err();
-    if let Err(ref e) = watcher {
-        eprintln!("Warning: file watcher initialization failed: {e}. Falling back to polling.");
+fn run_app(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    path: &str,
+    config: Config,
+    options: RunOptions,
+) -> Result<Option<StartupProfile>> {
+    let mut app = App::new(path)?;
+    app.options = options;
+    app.apply_config(config);
+    let state_path = state::state_path();
+    if let Some(path) = &state_path {
+        app.restore_ui_state(state::load(path, &app.state_key()));
+    }
+    if app.config.ui.auto_select {
+        app.select_first_file();
+    }
+    let config_path = config::config_path();
+    if let Some(path) = config_path.as_ref().filter(|path| !path.exists()) {
+        app.open_setup(path.clone());
     }
-    let watcher = watcher.ok();
+    let mut config_watcher = config_path.map(ConfigWatcher::new);
+    let mut last_config_check = Instant::now();
+
+    // Watch the whole working tree even when started in a subdirectory.
+    let workdir = app.workdir().to_path_buf();
+    let watcher_start = Instant::now();
+    let watcher = if app.options.no_watch || !app.config.watch.enabled {
+        tracing::info!("file watching disabled; polling");
+        app.polling = Some(PollReason::Disabled);
+        None
+    } else if let Some(fs_type) = watcher::network_filesystem(&workdir) {
+        tracing::info!(fs_type, "network filesystem; polling");
+        app.polling = Some(PollReason::NetworkFs(fs_type));
+        None
+    } else {
+        match FileWatcher::start(&app.repo, &workdir, app.config.watch.fsmonitor) {
+            Ok(watcher) => {
+                if watcher.limited {
+                    app.note_watch_limit();
+                }
+                Some(watcher)
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "file watcher initialization failed; polling");
+                app.show_flash_error(format!(
+                    "File watcher failed to start ({}); polling for changes",
+                    e
+                ));
+                app.polling = Some(PollReason::WatcherFailed);
+                None
+            }
+        }
+    };
+    app.startup.record("watcher setup", watcher_start.elapsed());
+    let mut first_draw = true;
+    let mut watcher_errors = 0;
 
     let mut last_poll = Instant::now();
     let poll_interval = Duration::from_secs(2);
     let debounce_duration = Duration::from_millis(150);
     let mut pending_refresh: Option<Instant> = None;
+    // What the pending refresh covers: these paths, or everything.
+    let mut changed_paths: Option<Vec<PathBuf>> = Some(Vec::new());
+    let mut needs_redraw = true;
 
     loop {
-        terminal.draw(|f| ui::draw(f, &mut app))?;
+        let low_bandwidth = app.config.ui.low_bandwidth;
+        if needs_redraw || !low_bandwidth {
+            let draw_start = Instant::now();
+            terminal.draw(|f| ui::draw(f, &mut app))?;
+            needs_redraw = false;
+            if first_draw {
+                first_draw = false;
+                app.startup.record("first draw", draw_start.elapsed());
+                app.startup.finish();
+            }
+        }
 
         let timeout = if pending_refresh.is_some() {
             Duration::from_millis(10)
+        } else if low_bandwidth {
+            LOW_BANDWIDTH_TICK
         } else {
-            Duration::from_millis(100)
+            TICK
         };
 
         if event::poll(timeout)? {
+            needs_redraw = true;
             match event::read()? {
-                Event::Key(key) => {
-                    if key.kind == KeyEventKind::Press {
-                        if app.confirm_prompt.is_some() {
-                            let confirmed = matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y'));
-                            app.handle_confirm(confirmed)?;
-                        } else {
-                            app.clear_flash();
-                            match key.code {
-                                KeyCode::Char('q') => break,
-                                KeyCode::Esc => {
-                                    if app.multi_selected.is_empty() {
-                                        break;
-                                    } else {
-                                        app.clear_multi_select();
-                                    }
-                                }
-                                KeyCode::Down => app.move_highlight(1),
-                                KeyCode::Up => app.move_highlight(-1),
-                                KeyCode::Char(' ') => app.toggle_multi_select(),
-                                KeyCode::Enter => app.select_current(),
-                                KeyCode::Char('s') => {
-                                    if let Err(e) = app.stage_selected() {
-                                        app.show_flash_error(format!("Error: {}", e));
-                                    }
-                                }
-                                KeyCode::Char('u') => {
-                                    if let Err(e) = app.unstage_selected() {
-                                        app.show_flash_error(format!("Error: {}", e));
-                                    }
-                                }
-                                KeyCode::Char('S') => app.show_stage_all_confirm(),
-                                KeyCode::Char('U') => app.show_unstage_all_confirm(),
-                                KeyCode::Char('d') => app.show_discard_selected_confirm(),
-                                KeyCode::Char('D') => app.show_discard_all_confirm(),
-                                KeyCode::PageDown => {
-                                    let size = terminal.size()?;
-                                    let height = size.height.saturating_sub(10) as usize;
-                                    let width = size.width.saturating_sub(2) as usize;
-                                    app.page_scroll_diff(true, height, width);
-                                }
-                                KeyCode::PageUp => {
-                                    let size = terminal.size()?;
-                                    let height = size.height.saturating_sub(10) as usize;
-                                    let width = size.width.saturating_sub(2) as usize;
-                                    app.page_scroll_diff(false, height, width);
-                                }
-                                KeyCode::Char('z')
-                                    if key.modifiers.contains(KeyModifiers::CONTROL) =>
-                                {
-                                    if let Err(e) = app.undo() {
-                                        app.show_flash_error(format!("Error: {}", e));
-                                    }
-                                }
-                                _ => {}
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    if app.confirm_prompt.is_some() {
+                        let confirmed = matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y'));
+                        app.handle_confirm(confirmed)?;
+                    } else if app.modal.is_some() {
+                        if key.code == KeyCode::Char('q') && !app.modal_has_text_input() {
+                            break;
+                        }
+                        app.clear_flash();
+                        app.handle_modal_key(key.code);
+                    } else {
+                        app.clear_flash();
+                        if let Some(action) = app.resolve_key(Key::from_event(&key), Instant::now())
+                        {
+                            let size = terminal.size()?;
+                            let page = (
+                                size.height.saturating_sub(10) as usize,
+                                size.width.saturating_sub(2) as usize,
+                            );
+                            if !app.perform_action(action, page) {
+                                break;
+                            }
+                            if let Some(text) = app.clipboard.take() {
+                                execute!(
+                                    terminal.backend_mut(),
+                                    CopyToClipboard::to_clipboard_from(text)
+                                )?;
+                            }
+                            if let Some(command) = app.pending_command.take() {
+                                let result = run_suspended(terminal, &command, app.workdir())?;
+                                app.finish_custom_command(&command, result)?;
                             }
                         }
                     }
@@ -755,10 +3451,16 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, path: &str) ->
                                 app.scroll_diff(-3, height, width);
                             }
                         }
-                        MouseEventKind::Down(event::MouseButton::Left) => {
-                            if in_file_list {
-                                app.click_file_list(row);
-                            }
+                        MouseEventKind::Down(event::MouseButton::Left) if in_file_list => {
+                            app.click_file_list(row);
+                        }
+                        MouseEventKind::Down(event::MouseButton::Left) if in_diff => {
+                            app.click_diff(row);
+                        }
+                        MouseEventKind::Down(event::MouseButton::Left)
+                            if app.status_bar_area.contains((col, row).into()) =>
+                        {
+                            app.click_status_bar(col);
                         }
                         _ => {}
                     }
@@ -767,41 +3469,89 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, path: &str) ->
             }
         }
 
-        if let Some(ref w) = watcher {
-            match w.receiver.try_recv() {
-                Ok(WatcherEvent::Changed) => {
-                    pending_refresh = Some(Instant::now());
-                }
-                Err(TryRecvError::Empty) => {}
-                Err(TryRecvError::Disconnected) => {
-                    if !use_polling {
-                        eprintln!("Warning: file watcher disconnected. Falling back to polling.");
+        let watching = matches!(app.polling, None | Some(PollReason::WatchLimit));
+        if let Some(w) = watcher.as_ref().filter(|_| watching) {
+            loop {
+                match w.receiver.try_recv() {
+                    Ok(WatcherEvent::Changed(paths)) => {
+                        pending_refresh = Some(Instant::now());
+                        if paths.is_empty() {
+                            changed_paths = None;
+                        } else if let Some(changed) = changed_paths.as_mut() {
+                            changed.extend(paths);
+                        }
+                    }
+                    Ok(WatcherEvent::Error) => {
+                        // Events may have been dropped; look for ourselves.
+                        pending_refresh = Some(Instant::now());
+                        changed_paths = None;
+                        watcher_errors += 1;
+                        if watcher_errors >= WATCHER_ERROR_LIMIT {
+                            app.fall_back_to_polling(PollReason::WatcherErrors);
+                            needs_redraw = true;
+                            break;
+                        }
+                    }
+                    Ok(WatcherEvent::LimitReached) => {
+                        pending_refresh = Some(Instant::now());
+                        changed_paths = None;
+                        app.note_watch_limit();
+                        needs_redraw = true;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        app.fall_back_to_polling(PollReason::WatcherFailed);
+                        needs_redraw = true;
+                        break;
                     }
-                    use_polling = true;
                 }
             }
-
-            while w.receiver.try_recv().is_ok() {
-                pending_refresh = Some(Instant::now());
-            }
         }
 
         if let Some(pending_time) = pending_refresh {
             if pending_time.elapsed() >= debounce_duration {
-                app.refresh()?;
+                tracing::debug!("refresh after file changes");
+                match changed_paths.replace(Vec::new()) {
+                    Some(paths) => app.refresh_paths(&paths)?,
+                    None => app.refresh()?,
+                }
                 pending_refresh = None;
+                needs_redraw = true;
             }
         }
 
-        if use_polling && last_poll.elapsed() >= poll_interval {
+        if app.polling.is_some() && last_poll.elapsed() >= poll_interval {
+            tracing::debug!("refresh from polling");
             app.refresh()?;
             last_poll = Instant::now();
+            needs_redraw = true;
+        }
+
+        if last_config_check.elapsed() >= CONFIG_POLL_INTERVAL {
+            last_config_check = Instant::now();
+            if let Some(watcher) = config_watcher.as_mut() {
+                if watcher.changed() {
+                    app.reload_config(watcher.path());
+                    needs_redraw = true;
+                }
+            }
         }
 
+        let was_animating = app.is_animating();
+        app.poll_network();
+        app.poll_pre_commit();
+        app.maybe_auto_fetch(Instant::now());
+        app.key_sequence.expire(Instant::now());
         app.check_flash_expiry();
+        needs_redraw |= was_animating || app.is_animating();
     }
 
-    Ok(())
+    if let Some(path) = &state_path {
+        if let Err(e) = state::save(path, &app.state_key(), app.ui_state()) {
+            tracing::warn!(error = %format!("{:#}", e), "failed to save UI state");
+        }
+    }
+    Ok(app.options.profile.then_some(app.startup))
 }
 
 #[cfg(test)]
@@ -813,14 +3563,34 @@ mod tests {
         FileEntry {
             path: path.to_string(),
             old_path: None,
+            raw_path: path.as_bytes().to_vec(),
+            raw_old_path: None,
             status: FileStatus::Modified,
             added_lines: Some(1),
             deleted_lines: Some(0),
             is_binary: false,
             is_submodule: false,
+            eol_change: None,
+            whitespace_errors: false,
+            generated: false,
+            old_mode: 0o100644,
+            mode: 0o100644,
+            size: None,
+            mtime: None,
+            conflict: None,
         }
     }
 
+    #[test]
+    fn launch_dir_is_the_subdirectory_started_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("src/ui");
+        std::fs::create_dir_all(&sub).unwrap();
+        assert_eq!(launch_dir(dir.path(), &sub), Some("src/ui".to_string()));
+        assert_eq!(launch_dir(dir.path(), dir.path()), None);
+        assert_eq!(launch_dir(&sub, dir.path()), None);
+    }
+
     #[test]
     fn build_visible_rows_staged_only() {
         let staged = vec![file_entry("a.rs"), file_entry("b.rs")];
@@ -855,6 +3625,66 @@ mod tests {
         assert!(rows.is_empty());
     }
 
+    #[test]
+    fn relocate_highlight_follows_the_file() {
+        let old_rows = build_visible_rows(&[], &[file_entry("b.rs"), file_entry("c.rs")]);
+        let highlighted = Some((1, old_rows[1].clone()));
+
+        // A file sorting earlier appeared: the highlight moves with c.rs.
+        let rows = build_visible_rows(
+            &[],
+            &[file_entry("a.rs"), file_entry("b.rs"), file_entry("c.rs")],
+        );
+        assert_eq!(relocate_highlight(highlighted.clone(), &rows), 2);
+
+        // c.rs was staged: fall back to the row now in its place.
+        let rows = build_visible_rows(&[file_entry("c.rs")], &[file_entry("b.rs")]);
+        assert_eq!(relocate_highlight(highlighted.clone(), &rows), 1);
+
+        // The list shrank below the old position.
+        let rows = build_visible_rows(&[], &[file_entry("a.rs")]);
+        assert_eq!(relocate_highlight(highlighted, &rows), 0);
+        assert_eq!(relocate_highlight(None, &rows), 0);
+    }
+
+    fn upstream_info(name: &str, behind: usize) -> UpstreamInfo {
+        UpstreamInfo {
+            name: name.to_string(),
+            ahead: 0,
+            behind,
+        }
+    }
+
+    #[test]
+    fn upstream_notice_only_when_behind() {
+        assert_eq!(upstream_notice(None, None), None);
+        assert_eq!(
+            upstream_notice(Some(&upstream_info("origin/main", 0)), None),
+            None
+        );
+        let notice = upstream_notice(Some(&upstream_info("origin/main", 5)), None).unwrap();
+        assert_eq!(notice.to_string(), "↓5 new on origin/main — pull?");
+    }
+
+    #[test]
+    fn upstream_notice_returns_after_dismissal_when_more_arrive() {
+        let dismissed = UpstreamNotice {
+            upstream: "origin/main".to_string(),
+            behind: 3,
+        };
+        let info = upstream_info("origin/main", 3);
+        assert_eq!(upstream_notice(Some(&info), Some(&dismissed)), None);
+
+        let info = upstream_info("origin/main", 4);
+        assert_eq!(
+            upstream_notice(Some(&info), Some(&dismissed)).map(|n| n.behind),
+            Some(4)
+        );
+
+        let info = upstream_info("upstream/main", 3);
+        assert!(upstream_notice(Some(&info), Some(&dismissed)).is_some());
+    }
+
     // Shared helper functions for multi-select operations.
     // These mirror the logic in App but work on raw state, avoiding duplication.
 
@@ -865,7 +3695,7 @@ mod tests {
     ) {
         if let Some(idx) = highlight_index {
             if let Some(row) = visible_rows.get(idx) {
-                let key = (row.section, row.path.clone());
+                let key = (row.section, row.raw_path.clone());
                 if multi_selected.contains(&key) {
                     multi_selected.remove(&key);
                 } else {
@@ -879,7 +3709,7 @@ mod tests {
         multi_selected.retain(|(section, path)| {
             visible_rows
                 .iter()
-                .any(|r| r.section == *section && &r.path == path)
+                .any(|r| r.section == *section && &r.raw_path == path)
         });
     }
 
@@ -887,11 +3717,11 @@ mod tests {
         highlight_index: Option<usize>,
         visible_rows: &[VisibleRow],
         multi_selected: &MultiSelectSet,
-    ) -> Vec<(Section, String)> {
+    ) -> Vec<(Section, Vec<u8>)> {
         if multi_selected.is_empty() {
             if let Some(idx) = highlight_index {
                 if let Some(row) = visible_rows.get(idx) {
-                    return vec![(row.section, row.path.clone())];
+                    return vec![(row.section, row.raw_path.clone())];
                 }
             }
             vec![]
@@ -983,7 +3813,7 @@ mod tests {
             prune_multi_select_helper(&self.visible_rows, &mut self.multi_selected);
         }
 
-        fn get_action_targets(&self) -> Vec<(Section, String)> {
+        fn get_action_targets(&self) -> Vec<(Section, Vec<u8>)> {
             get_action_targets_helper(
                 self.highlight_index,
                 &self.visible_rows,
@@ -1046,7 +3876,7 @@ mod tests {
         assert_eq!(app.multi_selected.len(), 1);
         assert!(app
             .multi_selected
-            .contains(&(Section::Staged, "a.rs".to_string())));
+            .contains(&(Section::Staged, b"a.rs".to_vec())));
     }
 
     #[test]
@@ -1067,10 +3897,10 @@ mod tests {
         assert_eq!(app.multi_selected.len(), 2);
         assert!(app
             .multi_selected
-            .contains(&(Section::Staged, "a.rs".to_string())));
+            .contains(&(Section::Staged, b"a.rs".to_vec())));
         assert!(app
             .multi_selected
-            .contains(&(Section::Staged, "b.rs".to_string())));
+            .contains(&(Section::Staged, b"b.rs".to_vec())));
     }
 
     #[test]
@@ -1102,7 +3932,7 @@ mod tests {
         assert_eq!(app.multi_selected.len(), 1);
         assert!(app
             .multi_selected
-            .contains(&(Section::Staged, "a.rs".to_string())));
+            .contains(&(Section::Staged, b"a.rs".to_vec())));
     }
 
     #[test]
@@ -1110,7 +3940,7 @@ mod tests {
         let app = TestApp::new(vec![file_entry("a.rs")], vec![]);
         let targets = app.get_action_targets();
         assert_eq!(targets.len(), 1);
-        assert_eq!(targets[0], (Section::Staged, "a.rs".to_string()));
+        assert_eq!(targets[0], (Section::Staged, b"a.rs".to_vec()));
     }
 
     #[test]
@@ -0,0 +1,70 @@
+use crate::config::PreviewConfig;
+use crate::git;
+use crate::types::FileStatus;
+use anyhow::Result;
+
+/// Overall state of a working tree, as reported by `--check`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeState {
+    Clean,
+    Dirty,
+    Conflicted,
+}
+
+impl TreeState {
+    /// 0 clean, 1 dirty, 2 conflicted.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            TreeState::Clean => 0,
+            TreeState::Dirty => 1,
+            TreeState::Conflicted => 2,
+        }
+    }
+}
+
+/// Exit code for `--check` when the repository can't be read at all.
+pub const ERROR_EXIT_CODE: i32 = 128;
+
+/// The state of the repository at `path` and a one-line summary such as
+/// `main: 2 staged, 1 unstaged, 3 untracked`.
+pub fn check(path: &str, preview: PreviewConfig) -> Result<(TreeState, String)> {
+    let repo = git::get_repo(path)?;
+    let branch = git::get_branch_info(&repo);
+    let status = git::get_status(&repo, preview)?;
+
+    // Conflicts and untracked files are listed (and counted) with the
+    // unstaged files.
+    let conflicted = status
+        .unstaged_files
+        .iter()
+        .filter(|f| f.status == FileStatus::Conflict)
+        .count();
+    let counts = [
+        (conflicted, "conflicted"),
+        (status.staged_count, "staged"),
+        (
+            status.unstaged_count - conflicted - status.untracked_count,
+            "unstaged",
+        ),
+        (status.untracked_count, "untracked"),
+    ];
+    let parts: Vec<String> = counts
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", count, label))
+        .collect();
+
+    let state = if conflicted > 0 {
+        TreeState::Conflicted
+    } else if parts.is_empty() {
+        TreeState::Clean
+    } else {
+        TreeState::Dirty
+    };
+    let summary = if parts.is_empty() {
+        format!("{}: clean", branch)
+    } else {
+        format!("{}: {}", branch, parts.join(", "))
+    };
+    Ok((state, summary))
+}
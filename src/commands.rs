@@ -0,0 +1,303 @@
+use crate::config::{CustomCommand, HooksConfig};
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+
+/// A `[[commands]]` entry with its placeholders filled in, waiting to run
+/// outside the UI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreparedCommand {
+    pub name: String,
+    pub command: String,
+    pub pause: bool,
+}
+
+impl PreparedCommand {
+    /// Fill in `custom`'s placeholders: `{files}` with the target files,
+    /// `{file}` with the highlighted one, and `{branch}` with the branch.
+    /// File names are shell-quoted. Fails if a file placeholder is used with
+    /// no file to put there.
+    pub fn new(
+        custom: &CustomCommand,
+        files: &[String],
+        file: Option<&str>,
+        branch: &str,
+    ) -> Result<Self> {
+        let template = &custom.command;
+        if template.contains("{files}") && files.is_empty() {
+            bail!("'{}' needs files to act on", custom.name);
+        }
+        if template.contains("{file}") && file.is_none() {
+            bail!("'{}' needs a highlighted file", custom.name);
+        }
+        let command = fill_placeholders(
+            template,
+            &[
+                ("{files}", quote_all(files)),
+                ("{file}", file.map(shell_quote).unwrap_or_default()),
+                ("{branch}", shell_quote(branch)),
+            ],
+        );
+        Ok(Self {
+            name: custom.name.clone(),
+            command,
+            pause: custom.pause,
+        })
+    }
+
+    /// Run the command in `dir` through the shell, attached to the terminal,
+    /// and wait for it. With `pause`, wait for Enter afterwards.
+    pub fn run(&self, dir: &Path) -> Result<ExitStatus> {
+        tracing::info!(name = %self.name, command = %self.command, "run custom command");
+        let status = shell(&self.command)
+            .current_dir(dir)
+            .status()
+            .with_context(|| format!("Failed to run '{}'", self.name))?;
+        if self.pause {
+            print!("\n[{}] Press Enter to return", status);
+            std::io::stdout().flush()?;
+            std::io::stdin().lock().read_line(&mut String::new())?;
+        }
+        Ok(status)
+    }
+}
+
+/// A point where a `[hooks]` command runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    PostStage,
+    PostDiscard,
+    /// Before the initial commit prompt opens; a failure keeps it closed.
+    PreCommitPrompt,
+}
+
+impl Hook {
+    /// Name of the hook's `[hooks]` key, also passed to it as `$0` and
+    /// `BGS_HOOK`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PostStage => "post_stage",
+            Hook::PostDiscard => "post_discard",
+            Hook::PreCommitPrompt => "pre_commit_prompt",
+        }
+    }
+
+    pub fn command(self, hooks: &HooksConfig) -> Option<&str> {
+        match self {
+            Hook::PostStage => hooks.post_stage.as_deref(),
+            Hook::PostDiscard => hooks.post_discard.as_deref(),
+            Hook::PreCommitPrompt => hooks.pre_commit_prompt.as_deref(),
+        }
+    }
+}
+
+/// What a command printed (stdout then stderr, trimmed) and how it exited.
+#[derive(Debug)]
+pub struct CommandOutput {
+    pub status: ExitStatus,
+    pub output: String,
+}
+
+impl CommandOutput {
+    fn capture(mut command: Command) -> std::io::Result<Self> {
+        let output = command.stdin(Stdio::null()).output()?;
+        let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        Ok(Self {
+            status: output.status,
+            output: text.trim().to_string(),
+        })
+    }
+
+    /// The first line of output, or the exit status if there was none.
+    pub fn summary(&self) -> String {
+        self.output
+            .lines()
+            .next()
+            .map_or_else(|| self.status.to_string(), str::to_string)
+    }
+}
+
+/// Run `command` for `hook` in `dir` with `paths` as its arguments (`$1`,
+/// `$2`, ...), capturing its output. The UI waits for it to finish.
+pub fn run_hook(command: &str, hook: Hook, dir: &Path, paths: &[String]) -> Result<CommandOutput> {
+    tracing::info!(hook = hook.name(), command, ?paths, "run hook");
+    let mut shell = shell(command);
+    shell
+        .arg(hook.name())
+        .args(paths)
+        .current_dir(dir)
+        .env("BGS_HOOK", hook.name());
+    CommandOutput::capture(shell).with_context(|| format!("Failed to run the {} hook", hook.name()))
+}
+
+/// Run the `[format]` command in `dir` on `files`, which replace `{files}`
+/// or are appended when the command doesn't mention them.
+pub fn run_formatter(command: &str, dir: &Path, files: &[String]) -> Result<CommandOutput> {
+    let command = if command.contains("{files}") {
+        command.replace("{files}", &quote_all(files))
+    } else {
+        format!("{} {}", command, quote_all(files))
+    };
+    tracing::info!(command, "run formatter");
+    let mut shell = shell(&command);
+    shell.current_dir(dir);
+    CommandOutput::capture(shell).context("Failed to run the formatter")
+}
+
+/// Replace each placeholder in `template` with its value in one pass, so a
+/// value that itself contains a placeholder is left as it is.
+fn fill_placeholders(template: &str, values: &[(&str, String)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match values.iter().find(|(name, _)| rest.starts_with(name)) {
+            Some((name, value)) => {
+                filled.push_str(value);
+                rest = &rest[name.len()..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+fn quote_all(files: &[String]) -> String {
+    files
+        .iter()
+        .map(|f| shell_quote(f))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(unix)]
+pub(crate) fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+pub(crate) fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+/// `text` as one POSIX shell word.
+pub(crate) fn shell_quote(text: &str) -> String {
+    let plain = !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./+,:@%=".contains(c));
+    if plain {
+        text.to_string()
+    } else {
+        format!("'{}'", text.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(command: &str) -> CustomCommand {
+        CustomCommand {
+            name: "test".to_string(),
+            key: "T".to_string(),
+            command: command.to_string(),
+            pause: false,
+        }
+    }
+
+    #[test]
+    fn placeholders_are_filled_and_quoted() {
+        let files = vec!["src/a.rs".to_string(), "it's here.txt".to_string()];
+        let prepared = PreparedCommand::new(
+            &custom("fmt {files}; open {file} on {branch}"),
+            &files,
+            Some("src/a.rs"),
+            "feature/x",
+        )
+        .unwrap();
+        assert_eq!(
+            prepared.command,
+            r"fmt src/a.rs 'it'\''s here.txt'; open src/a.rs on feature/x"
+        );
+    }
+
+    #[test]
+    fn placeholders_inside_values_are_not_filled() {
+        let files = vec!["x{file}y".to_string(), "it's {branch}".to_string()];
+        let prepared = PreparedCommand::new(
+            &custom("rm {files} {file}"),
+            &files,
+            Some("x{file}y"),
+            "main{files}",
+        )
+        .unwrap();
+        assert_eq!(
+            prepared.command,
+            r"rm 'x{file}y' 'it'\''s {branch}' 'x{file}y'"
+        );
+        let prepared =
+            PreparedCommand::new(&custom("echo {{branch}}"), &[], None, "a'$(id)").unwrap();
+        assert_eq!(prepared.command, r"echo {'a'\''$(id)'}");
+    }
+
+    #[test]
+    fn file_placeholders_need_files() {
+        let err = PreparedCommand::new(&custom("fmt {files}"), &[], None, "main").unwrap_err();
+        assert!(err.to_string().contains("needs files"));
+        assert!(PreparedCommand::new(&custom("open {file}"), &[], None, "main").is_err());
+        assert!(PreparedCommand::new(&custom("git log {branch}"), &[], None, "main").is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hook_gets_paths_as_arguments_and_output_is_captured() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = vec!["a b.txt".to_string(), "c.txt".to_string()];
+        let out = run_hook(
+            r#"echo "$BGS_HOOK $0: $1|$2"; echo warn >&2; exit 1"#,
+            Hook::PostStage,
+            dir.path(),
+            &paths,
+        )
+        .unwrap();
+        assert!(!out.status.success());
+        assert_eq!(out.output, "post_stage post_stage: a b.txt|c.txt\nwarn");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn formatter_gets_files_appended_or_substituted() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = vec!["a b.rs".to_string(), "c.rs".to_string()];
+        let out = run_formatter("printf '%s|'", dir.path(), &files).unwrap();
+        assert!(out.status.success());
+        assert_eq!(out.output, "a b.rs|c.rs|");
+
+        let out = run_formatter("echo {files} done; exit 4", dir.path(), &files).unwrap();
+        assert_eq!(out.status.code(), Some(4));
+        assert_eq!(out.summary(), "a b.rs c.rs done");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_reports_exit_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let prepared =
+            PreparedCommand::new(&custom("touch ran; exit 3"), &[], None, "main").unwrap();
+        let status = prepared.run(dir.path()).unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert!(dir.path().join("ran").exists());
+    }
+}
@@ -0,0 +1,661 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// User configuration, read from `$XDG_CONFIG_HOME/better-git-status/config.toml`.
+///
+/// Every field has a default, so a missing file or section is equivalent to
+/// an empty one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub auto_fetch: AutoFetchConfig,
+    pub actions: ActionsConfig,
+    pub flash: FlashConfig,
+    pub confirm: ConfirmConfig,
+    pub ui: UiConfig,
+    pub keys: KeysConfig,
+    pub watch: WatchConfig,
+    /// `[[commands]]`: shell commands bound to keys.
+    pub commands: Vec<CustomCommand>,
+    pub hooks: HooksConfig,
+    pub format: FormatConfig,
+    pub generated: GeneratedConfig,
+    pub preview: PreviewConfig,
+}
+
+/// `[auto_fetch]`: periodically fetch the upstream in the background.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoFetchConfig {
+    pub enabled: bool,
+    pub interval_minutes: u64,
+}
+
+impl Default for AutoFetchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_minutes: 10,
+        }
+    }
+}
+
+impl AutoFetchConfig {
+    /// The fetch interval, never shorter than one minute.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_minutes.max(1) * 60)
+    }
+}
+
+/// `[actions]`: how file actions pick the files they apply to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActionsConfig {
+    pub target: ActionTarget,
+}
+
+/// Which files `s`, `u`, and `d` act on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionTarget {
+    /// The multi-selection if any, otherwise the highlighted row.
+    #[default]
+    Highlighted,
+    /// The multi-selection if any, otherwise the file opened with Enter.
+    Selected,
+    /// Only files marked with Space; nothing happens when none are marked.
+    MultiSelect,
+}
+
+/// `[flash]`: status bar messages shown after an action.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlashConfig {
+    pub duration_seconds: u64,
+    /// Keep error messages until the next key press instead of letting them
+    /// time out.
+    pub sticky_errors: bool,
+}
+
+impl Default for FlashConfig {
+    fn default() -> Self {
+        Self {
+            duration_seconds: 3,
+            sticky_errors: true,
+        }
+    }
+}
+
+impl FlashConfig {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.duration_seconds)
+    }
+}
+
+/// `[watch]`: how file changes are noticed. Read once at startup.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    /// Watch the working tree for changes. When off, status is refreshed by
+    /// polling instead.
+    pub enabled: bool,
+    /// Take changes from the fsmonitor set by `core.fsmonitor`, when there
+    /// is one, instead of watching every directory.
+    pub fsmonitor: bool,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            fsmonitor: true,
+        }
+    }
+}
+
+/// `[format]`: the formatter run by the format-and-stage action.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormatConfig {
+    /// Shell command; the files replace `{files}` or are appended.
+    pub command: Option<String>,
+}
+
+/// `[generated]`: files treated as generated output on top of those marked
+/// `linguist-generated` or `-diff` in `.gitattributes`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeneratedConfig {
+    /// Git pathspec globs matched against repository-relative paths.
+    pub patterns: Vec<String>,
+}
+
+/// `[preview]`: when file content counts as binary or is too big to show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PreviewConfig {
+    /// Leading bytes of an untracked file searched for a NUL byte, which
+    /// marks it binary; 0 searches the whole file.
+    pub binary_check_bytes: usize,
+    /// Largest binary file shown as a hexdump.
+    pub hexdump_max_kib: usize,
+    /// Text files larger than this are summarized like binary files
+    /// instead of diffed or previewed.
+    pub max_text_kib: usize,
+    /// Memory a single diff may take; past it only hunk headers are kept.
+    pub max_diff_kib: usize,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        // 8000 bytes is what git itself checks.
+        Self {
+            binary_check_bytes: 8000,
+            hexdump_max_kib: 16,
+            max_text_kib: 1024,
+            max_diff_kib: 8192,
+        }
+    }
+}
+
+/// `[hooks]`: shell commands run after file actions, with the affected
+/// paths as arguments. Unset hooks don't run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    /// After files or lines are staged.
+    pub post_stage: Option<String>,
+    /// After files or lines are discarded.
+    pub post_discard: Option<String>,
+    /// Before the initial commit prompt opens, with the staged paths.
+    pub pre_commit_prompt: Option<String>,
+}
+
+/// One `[[commands]]` entry. `command` is run by the shell from the
+/// repository root after `{files}`, `{file}`, and `{branch}` are replaced.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomCommand {
+    pub name: String,
+    pub key: String,
+    pub command: String,
+    /// Wait for Enter before returning to the UI, so output can be read.
+    #[serde(default)]
+    pub pause: bool,
+}
+
+/// `[confirm]`: which bulk actions ask before running.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfirmConfig {
+    pub level: ConfirmLevel,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmLevel {
+    /// Stage all, unstage all, and discards.
+    #[default]
+    All,
+    /// Only discards, which can't be undone.
+    Destructive,
+}
+
+impl ConfirmLevel {
+    pub const ALL: [ConfirmLevel; 2] = [ConfirmLevel::All, ConfirmLevel::Destructive];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConfirmLevel::All => "all",
+            ConfirmLevel::Destructive => "destructive",
+        }
+    }
+}
+
+/// `[keys]`: a preset to start from, plus per-action overrides such as
+/// `discard = "d d"`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct KeysConfig {
+    pub preset: KeyPreset,
+    /// Action name to key sequence, applied on top of the preset.
+    #[serde(flatten)]
+    pub overrides: BTreeMap<String, String>,
+}
+
+/// Built-in keymap to start from before `[keys]` overrides.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyPreset {
+    #[default]
+    Default,
+    /// j/k, ctrl+d/ctrl+u.
+    Vim,
+    /// ctrl+n/ctrl+p, ctrl+v/alt+v, alt+</alt+>, ctrl+g.
+    Emacs,
+    /// Space stages, v marks, a stages all, p/P pull and push.
+    Lazygit,
+}
+
+impl KeyPreset {
+    pub const ALL: [KeyPreset; 4] = [
+        KeyPreset::Default,
+        KeyPreset::Vim,
+        KeyPreset::Emacs,
+        KeyPreset::Lazygit,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyPreset::Default => "default",
+            KeyPreset::Vim => "vim",
+            KeyPreset::Emacs => "emacs",
+            KeyPreset::Lazygit => "lazygit",
+        }
+    }
+}
+
+/// `[ui]`: appearance.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConfig {
+    pub theme: Theme,
+    pub glyphs: Glyphs,
+    /// Redraw only after input or state changes, tick less often, and clip
+    /// long diff lines instead of wrapping them. Meant for slow SSH links.
+    pub low_bandwidth: bool,
+    /// Open the first file's diff on launch instead of waiting for Enter.
+    pub auto_select: bool,
+    /// File list columns, in order. The path is added at the end if left
+    /// out.
+    pub columns: Vec<Column>,
+    /// When launched from a subdirectory, show paths relative to it rather
+    /// than to the repository root.
+    pub relative_paths: bool,
+    /// File type icons before file names.
+    pub icons: Icons,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            glyphs: Glyphs::default(),
+            low_bandwidth: false,
+            auto_select: false,
+            columns: Column::DEFAULT.to_vec(),
+            relative_paths: false,
+            icons: Icons::default(),
+        }
+    }
+}
+
+/// A column of the file list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    /// The status letter.
+    Status,
+    /// The path, truncated to fit.
+    Path,
+    /// Lines added and deleted; dropped when the path needs the room.
+    Counts,
+    /// Size of the working tree file.
+    Size,
+    /// How long ago the working tree file was modified.
+    Mtime,
+    /// The file mode, with `x` on executables.
+    Mode,
+}
+
+impl Column {
+    pub const DEFAULT: [Column; 3] = [Column::Status, Column::Path, Column::Counts];
+}
+
+/// Color theme. The accessible themes avoid relying on a red/green
+/// distinction and draw diff markers in bold.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    #[default]
+    Default,
+    /// Blue for additions, orange for deletions.
+    Colorblind,
+    /// Bright ANSI colors for low-contrast displays.
+    HighContrast,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::Default, Theme::Colorblind, Theme::HighContrast];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Default => "default",
+            Theme::Colorblind => "colorblind",
+            Theme::HighContrast => "high_contrast",
+        }
+    }
+}
+
+/// Whether to draw Unicode symbols (`●`, `→`, `│`, ...) or ASCII stand-ins.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Glyphs {
+    /// ASCII unless the locale is UTF-8.
+    #[default]
+    Auto,
+    Unicode,
+    Ascii,
+}
+
+/// File type icons drawn before file names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Icons {
+    #[default]
+    Off,
+    /// Nerd Font glyphs; needs a patched font. ASCII in ASCII glyph mode.
+    Nerd,
+    /// One ASCII character per kind of file.
+    Ascii,
+}
+
+impl Config {
+    /// Load the config from the default location, falling back to defaults
+    /// when no file exists.
+    pub fn load() -> Result<Self> {
+        match config_path() {
+            Some(path) => Self::load_from(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Self::parse(&text).with_context(|| format!("Invalid config in {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+}
+
+/// Notices edits to the config file by polling its modification time, so
+/// editors that replace the file rather than write in place are caught too.
+#[derive(Debug, Clone)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified_time(&path);
+        Self { path, modified }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file was modified, created, or removed since the last call.
+    pub fn changed(&mut self) -> bool {
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// `$XDG_CONFIG_HOME/better-git-status/config.toml`, or `~/.config/...`.
+pub fn config_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("better-git-status").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_config_uses_defaults() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config, Config::default());
+        assert!(!config.auto_fetch.enabled);
+        assert_eq!(config.auto_fetch.interval(), Duration::from_secs(600));
+    }
+
+    #[test]
+    fn parses_auto_fetch_section() {
+        let config = Config::parse("[auto_fetch]\nenabled = true\ninterval_minutes = 3\n").unwrap();
+        assert!(config.auto_fetch.enabled);
+        assert_eq!(config.auto_fetch.interval(), Duration::from_secs(180));
+    }
+
+    #[test]
+    fn interval_is_at_least_one_minute() {
+        let config = Config::parse("[auto_fetch]\ninterval_minutes = 0\n").unwrap();
+        assert_eq!(config.auto_fetch.interval(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn parses_action_target() {
+        assert_eq!(Config::default().actions.target, ActionTarget::Highlighted);
+        let config = Config::parse("[actions]\ntarget = \"multi_select\"\n").unwrap();
+        assert_eq!(config.actions.target, ActionTarget::MultiSelect);
+        assert!(Config::parse("[actions]\ntarget = \"cursor\"\n").is_err());
+    }
+
+    #[test]
+    fn parses_key_overrides() {
+        let config = Config::parse("[keys]\ndiscard = \"d d\"\ntop = \"g g\"\n").unwrap();
+        assert_eq!(config.keys.preset, KeyPreset::Default);
+        assert_eq!(
+            config.keys.overrides.get("discard").map(String::as_str),
+            Some("d d")
+        );
+        assert_eq!(config.keys.overrides.len(), 2);
+    }
+
+    #[test]
+    fn parses_key_preset_with_overrides() {
+        let config = Config::parse("[keys]\npreset = \"vim\"\ndiscard = \"d d\"\n").unwrap();
+        assert_eq!(config.keys.preset, KeyPreset::Vim);
+        assert_eq!(config.keys.overrides.len(), 1);
+        assert!(Config::parse("[keys]\npreset = \"helix\"\n").is_err());
+    }
+
+    #[test]
+    fn parses_flash_section() {
+        assert_eq!(Config::default().flash.duration(), Duration::from_secs(3));
+        assert!(Config::default().flash.sticky_errors);
+        let config =
+            Config::parse("[flash]\nduration_seconds = 8\nsticky_errors = false\n").unwrap();
+        assert_eq!(config.flash.duration(), Duration::from_secs(8));
+        assert!(!config.flash.sticky_errors);
+    }
+
+    #[test]
+    fn parses_confirm_level() {
+        assert_eq!(Config::default().confirm.level, ConfirmLevel::All);
+        let config = Config::parse("[confirm]\nlevel = \"destructive\"\n").unwrap();
+        assert_eq!(config.confirm.level, ConfirmLevel::Destructive);
+    }
+
+    #[test]
+    fn parses_theme() {
+        assert_eq!(Config::default().ui.theme, Theme::Default);
+        let config = Config::parse("[ui]\ntheme = \"high_contrast\"\n").unwrap();
+        assert_eq!(config.ui.theme, Theme::HighContrast);
+        assert!(Config::parse("[ui]\ntheme = \"solarized\"\n").is_err());
+    }
+
+    #[test]
+    fn parses_glyphs() {
+        assert_eq!(Config::default().ui.glyphs, Glyphs::Auto);
+        let config = Config::parse("[ui]\nglyphs = \"ascii\"\n").unwrap();
+        assert_eq!(config.ui.glyphs, Glyphs::Ascii);
+    }
+
+    #[test]
+    fn parses_columns() {
+        assert_eq!(Config::default().ui.columns, Column::DEFAULT);
+        let config = Config::parse("[ui]\ncolumns = [\"mode\", \"status\", \"path\"]\n").unwrap();
+        assert_eq!(
+            config.ui.columns,
+            [Column::Mode, Column::Status, Column::Path]
+        );
+        assert!(Config::parse("[ui]\ncolumns = [\"owner\"]\n").is_err());
+    }
+
+    #[test]
+    fn parses_relative_paths() {
+        assert!(!Config::default().ui.relative_paths);
+        let config = Config::parse("[ui]\nrelative_paths = true\n").unwrap();
+        assert!(config.ui.relative_paths);
+    }
+
+    #[test]
+    fn parses_icons() {
+        assert_eq!(Config::default().ui.icons, Icons::Off);
+        let config = Config::parse("[ui]\nicons = \"nerd\"\n").unwrap();
+        assert_eq!(config.ui.icons, Icons::Nerd);
+        assert!(Config::parse("[ui]\nicons = \"emoji\"\n").is_err());
+    }
+
+    #[test]
+    fn parses_low_bandwidth() {
+        assert!(!Config::default().ui.low_bandwidth);
+        let config = Config::parse("[ui]\nlow_bandwidth = true\n").unwrap();
+        assert!(config.ui.low_bandwidth);
+    }
+
+    #[test]
+    fn parses_watch_enabled() {
+        assert!(Config::default().watch.enabled);
+        let config = Config::parse("[watch]\nenabled = false\n").unwrap();
+        assert!(!config.watch.enabled);
+        assert!(config.watch.fsmonitor);
+        let config = Config::parse("[watch]\nfsmonitor = false\n").unwrap();
+        assert!(!config.watch.fsmonitor);
+    }
+
+    #[test]
+    fn parses_custom_commands() {
+        assert!(Config::default().commands.is_empty());
+        let config = Config::parse(
+            "[[commands]]\nname = \"fmt\"\nkey = \"F\"\ncommand = \"cargo fmt -- {files}\"\n\n\
+             [[commands]]\nname = \"log\"\nkey = \"ctrl+l\"\ncommand = \"git log\"\npause = true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.commands,
+            vec![
+                CustomCommand {
+                    name: "fmt".to_string(),
+                    key: "F".to_string(),
+                    command: "cargo fmt -- {files}".to_string(),
+                    pause: false,
+                },
+                CustomCommand {
+                    name: "log".to_string(),
+                    key: "ctrl+l".to_string(),
+                    command: "git log".to_string(),
+                    pause: true,
+                },
+            ]
+        );
+        assert!(Config::parse("[[commands]]\nname = \"x\"\nkey = \"X\"\n").is_err());
+    }
+
+    #[test]
+    fn parses_hooks() {
+        assert_eq!(Config::default().hooks, HooksConfig::default());
+        let config = Config::parse("[hooks]\npost_stage = \"./check.sh\"\n").unwrap();
+        assert_eq!(config.hooks.post_stage.as_deref(), Some("./check.sh"));
+        assert!(config.hooks.post_discard.is_none());
+        let config = Config::parse("[hooks]\npre_commit_prompt = \"x\"\n").unwrap();
+        assert_eq!(config.hooks.pre_commit_prompt.as_deref(), Some("x"));
+        assert!(Config::parse("[hooks]\npre_commit = \"x\"\n").is_err());
+    }
+
+    #[test]
+    fn parses_generated_patterns() {
+        assert!(Config::default().generated.patterns.is_empty());
+        let config = Config::parse("[generated]\npatterns = [\"*.lock\", \"dist/*\"]\n").unwrap();
+        assert_eq!(config.generated.patterns, ["*.lock", "dist/*"]);
+        assert!(Config::parse("[generated]\npaterns = []\n").is_err());
+    }
+
+    #[test]
+    fn parses_preview_limits() {
+        let config = Config::parse("[preview]\nmax_text_kib = 64\n").unwrap();
+        assert_eq!(config.preview.max_text_kib, 64);
+        assert_eq!(config.preview.binary_check_bytes, 8000);
+        assert_eq!(config.preview.hexdump_max_kib, 16);
+        assert_eq!(config.preview.max_diff_kib, 8192);
+        assert!(Config::parse("[preview]\nmax_kib = 1\n").is_err());
+    }
+
+    #[test]
+    fn parses_format_command() {
+        assert!(Config::default().format.command.is_none());
+        let config = Config::parse("[format]\ncommand = \"cargo fmt --\"\n").unwrap();
+        assert_eq!(config.format.command.as_deref(), Some("cargo fmt --"));
+    }
+
+    #[test]
+    fn parses_auto_select() {
+        assert!(!Config::default().ui.auto_select);
+        let config = Config::parse("[ui]\nauto_select = true\n").unwrap();
+        assert!(config.ui.auto_select);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(Config::parse("[auto_fetch]\nenable = true\n").is_err());
+    }
+
+    #[test]
+    fn config_watcher_reports_each_change_once() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        let mut watcher = ConfigWatcher::new(path.clone());
+        assert!(!watcher.changed());
+
+        std::fs::write(&path, "").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        assert!(watcher.changed());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+    }
+
+    #[test]
+    fn missing_file_uses_defaults() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config::load_from(&dir.path().join("config.toml")).unwrap();
+        assert_eq!(config, Config::default());
+    }
+}
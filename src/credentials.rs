@@ -0,0 +1,248 @@
+use git2::{Cred, CredentialType, RemoteCallbacks};
+use std::path::PathBuf;
+
+/// Upper bound on credential callback invocations for a single operation.
+///
+/// libgit2 keeps asking for credentials as long as the callback returns one,
+/// so a hard cap guarantees a misbehaving server can't spin us forever.
+const MAX_ATTEMPTS: usize = 8;
+
+/// Error returned when a remote needs a username/password we don't have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthRequired {
+    pub url: String,
+}
+
+impl std::fmt::Display for AuthRequired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Authentication required for {}", self.url)
+    }
+}
+
+impl std::error::Error for AuthRequired {}
+
+/// The next credential source to offer libgit2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialAttempt {
+    SshAgent,
+    SshKey(PathBuf),
+    Helper,
+    UserPass,
+    Username(String),
+    Default,
+    GiveUp,
+}
+
+/// Credential state for one network operation.
+///
+/// Each source is tried at most once, in order: SSH agent, SSH key files,
+/// git credential helper, then a username/password supplied by the user.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub(crate) ssh_keys: Vec<PathBuf>,
+    pub(crate) userpass: Option<(String, String)>,
+    tried_agent: bool,
+    tried_keys: usize,
+    tried_helper: bool,
+    tried_userpass: bool,
+    tried_username: bool,
+    tried_default: bool,
+    attempts: usize,
+    /// Set when the remote asked for a password and every source was exhausted.
+    pub needs_userpass: bool,
+}
+
+impl Credentials {
+    pub fn new() -> Self {
+        Self {
+            ssh_keys: default_ssh_keys(),
+            ..Self::default()
+        }
+    }
+
+    /// Credentials that also offer an explicit username and password.
+    pub fn with_userpass(username: String, password: String) -> Self {
+        Self {
+            userpass: Some((username, password)),
+            ..Self::new()
+        }
+    }
+
+    /// Decide which credential source to try next for the allowed types.
+    pub fn next_attempt(
+        &mut self,
+        allowed: CredentialType,
+        username: Option<&str>,
+    ) -> CredentialAttempt {
+        self.attempts += 1;
+        if self.attempts > MAX_ATTEMPTS {
+            return CredentialAttempt::GiveUp;
+        }
+
+        if allowed.contains(CredentialType::USERNAME) && !self.tried_username {
+            self.tried_username = true;
+            return CredentialAttempt::Username(username.unwrap_or("git").to_string());
+        }
+
+        if allowed.contains(CredentialType::SSH_KEY) {
+            if !self.tried_agent {
+                self.tried_agent = true;
+                return CredentialAttempt::SshAgent;
+            }
+            if let Some(key) = self.ssh_keys.get(self.tried_keys) {
+                self.tried_keys += 1;
+                return CredentialAttempt::SshKey(key.clone());
+            }
+        }
+
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            if self.userpass.is_some() && !self.tried_userpass {
+                self.tried_userpass = true;
+                return CredentialAttempt::UserPass;
+            }
+            if !self.tried_helper {
+                self.tried_helper = true;
+                return CredentialAttempt::Helper;
+            }
+            if !self.tried_userpass {
+                self.needs_userpass = true;
+            }
+        }
+
+        if allowed.contains(CredentialType::DEFAULT) && !self.tried_default {
+            self.tried_default = true;
+            return CredentialAttempt::Default;
+        }
+
+        CredentialAttempt::GiveUp
+    }
+
+    /// Build libgit2 callbacks that draw credentials from this state.
+    pub fn callbacks<'a>(&'a mut self, config: &'a git2::Config) -> RemoteCallbacks<'a> {
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(move |url, username, allowed| {
+            let user = username.unwrap_or("git");
+            loop {
+                let result = match self.next_attempt(allowed, username) {
+                    CredentialAttempt::SshAgent => Cred::ssh_key_from_agent(user),
+                    CredentialAttempt::SshKey(path) => Cred::ssh_key(user, None, &path, None),
+                    CredentialAttempt::Helper => Cred::credential_helper(config, url, username),
+                    CredentialAttempt::UserPass => {
+                        let (u, p) = self.userpass.clone().unwrap_or_default();
+                        Cred::userpass_plaintext(&u, &p)
+                    }
+                    CredentialAttempt::Username(name) => Cred::username(&name),
+                    CredentialAttempt::Default => Cred::default(),
+                    CredentialAttempt::GiveUp => {
+                        return Err(git2::Error::from_str("no usable credentials"));
+                    }
+                };
+                // A source that can't even produce a credential (no agent,
+                // no helper configured) falls through to the next one.
+                if result.is_ok() {
+                    return result;
+                }
+            }
+        });
+        callbacks
+    }
+}
+
+fn default_ssh_keys() -> Vec<PathBuf> {
+    let Some(home) = std::env::var_os("HOME") else {
+        return Vec::new();
+    };
+    let ssh_dir = PathBuf::from(home).join(".ssh");
+    ["id_ed25519", "id_ecdsa", "id_rsa"]
+        .iter()
+        .map(|name| ssh_dir.join(name))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn creds_with_keys(keys: &[&str]) -> Credentials {
+        Credentials {
+            ssh_keys: keys.iter().map(PathBuf::from).collect(),
+            ..Credentials::default()
+        }
+    }
+
+    #[test]
+    fn ssh_tries_agent_then_each_key_once() {
+        let mut creds = creds_with_keys(&["/k/id_ed25519", "/k/id_rsa"]);
+        let allowed = CredentialType::SSH_KEY;
+        assert_eq!(
+            creds.next_attempt(allowed, Some("git")),
+            CredentialAttempt::SshAgent
+        );
+        assert_eq!(
+            creds.next_attempt(allowed, Some("git")),
+            CredentialAttempt::SshKey(PathBuf::from("/k/id_ed25519"))
+        );
+        assert_eq!(
+            creds.next_attempt(allowed, Some("git")),
+            CredentialAttempt::SshKey(PathBuf::from("/k/id_rsa"))
+        );
+        assert_eq!(
+            creds.next_attempt(allowed, Some("git")),
+            CredentialAttempt::GiveUp
+        );
+        assert!(!creds.needs_userpass);
+    }
+
+    #[test]
+    fn https_tries_helper_then_requests_userpass() {
+        let mut creds = creds_with_keys(&[]);
+        let allowed = CredentialType::USER_PASS_PLAINTEXT;
+        assert_eq!(creds.next_attempt(allowed, None), CredentialAttempt::Helper);
+        assert_eq!(creds.next_attempt(allowed, None), CredentialAttempt::GiveUp);
+        assert!(creds.needs_userpass);
+    }
+
+    #[test]
+    fn supplied_userpass_is_tried_first_and_once() {
+        let mut creds = creds_with_keys(&[]);
+        creds.userpass = Some(("me".to_string(), "secret".to_string()));
+        let allowed = CredentialType::USER_PASS_PLAINTEXT;
+        assert_eq!(
+            creds.next_attempt(allowed, None),
+            CredentialAttempt::UserPass
+        );
+        assert_eq!(creds.next_attempt(allowed, None), CredentialAttempt::Helper);
+        assert_eq!(creds.next_attempt(allowed, None), CredentialAttempt::GiveUp);
+        assert!(!creds.needs_userpass);
+    }
+
+    #[test]
+    fn username_request_defaults_to_git() {
+        let mut creds = creds_with_keys(&[]);
+        assert_eq!(
+            creds.next_attempt(CredentialType::USERNAME, None),
+            CredentialAttempt::Username("git".to_string())
+        );
+    }
+
+    #[test]
+    fn attempts_are_capped() {
+        let mut creds = creds_with_keys(&["/k/a"; 20]);
+        let attempts: Vec<CredentialAttempt> = (0..MAX_ATTEMPTS + 1)
+            .map(|_| creds.next_attempt(CredentialType::SSH_KEY, None))
+            .collect();
+        assert_eq!(attempts.last(), Some(&CredentialAttempt::GiveUp));
+    }
+
+    #[test]
+    fn auth_required_display() {
+        let err = AuthRequired {
+            url: "https://example.com/repo.git".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Authentication required for https://example.com/repo.git"
+        );
+    }
+}
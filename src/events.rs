@@ -0,0 +1,278 @@
+use crate::app::RunOptions;
+use crate::config::{Config, PreviewConfig};
+use crate::git::{self, StatusCache, StatusResult};
+use crate::types::{FileStatus, Section};
+use crate::watcher::{self, FileWatcher};
+use anyhow::Result;
+use git2::Repository;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// One row of the file list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileState {
+    pub section: Section,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_path: Option<String>,
+    pub status: FileStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub added_lines: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_lines: Option<usize>,
+}
+
+/// The branch and every changed file, as the file list shows them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Snapshot {
+    pub branch: String,
+    pub files: Vec<FileState>,
+}
+
+/// A file whose row differs between two snapshots. `status` is `None` when
+/// the file left the section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileChange {
+    pub section: Section,
+    pub path: String,
+    pub status: Option<FileStatus>,
+}
+
+/// One line of `--json-events` output.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The full state; sent at startup and after every change.
+    Status(Snapshot),
+    /// The files that changed since the previous `status` event.
+    Changed { files: Vec<FileChange> },
+    /// Reading the status failed; the next change is retried.
+    Error { message: String },
+}
+
+pub fn snapshot(repo: &Repository, preview: PreviewConfig) -> Result<Snapshot> {
+    Ok(snapshot_of(repo, git::get_status(repo, preview)?))
+}
+
+fn snapshot_of(repo: &Repository, status: StatusResult) -> Snapshot {
+    let staged = status.staged_files.iter().map(|f| (Section::Staged, f));
+    let unstaged = status.unstaged_files.iter().map(|f| (Section::Unstaged, f));
+    let files = staged
+        .chain(unstaged)
+        .map(|(section, f)| FileState {
+            section,
+            path: f.path.clone(),
+            old_path: f.old_path.clone(),
+            status: f.status,
+            added_lines: f.added_lines,
+            deleted_lines: f.deleted_lines,
+        })
+        .collect();
+    Snapshot {
+        branch: git::get_branch_info(repo).to_string(),
+        files,
+    }
+}
+
+/// Files that were added, removed, or changed status or line counts
+/// between `old` and `new`, in `new`'s order followed by the removed ones.
+pub fn changes(old: &Snapshot, new: &Snapshot) -> Vec<FileChange> {
+    let find = |snapshot: &Snapshot, file: &FileState| {
+        snapshot
+            .files
+            .iter()
+            .find(|f| f.section == file.section && f.path == file.path)
+            .cloned()
+    };
+    let updated = new
+        .files
+        .iter()
+        .filter(|f| find(old, f).as_ref() != Some(*f))
+        .map(|f| FileChange {
+            section: f.section,
+            path: f.path.clone(),
+            status: Some(f.status),
+        });
+    let removed = old
+        .files
+        .iter()
+        .filter(|f| find(new, f).is_none())
+        .map(|f| FileChange {
+            section: f.section,
+            path: f.path.clone(),
+            status: None,
+        });
+    updated.chain(removed).collect()
+}
+
+/// Write `event` as one line. Returns false once the reader has gone away.
+fn emit(out: &mut impl Write, event: &Event) -> Result<bool> {
+    let line = serde_json::to_string(event)?;
+    match writeln!(out, "{}", line).and_then(|()| out.flush()) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Print a `status` event for the repository at `path`, then `changed` and
+/// `status` events whenever the working tree changes, until `out` closes.
+/// Changes are found the same way as in the UI: by the file watcher, or by
+/// polling when watching is off or unreliable.
+pub fn run(path: &str, config: &Config, options: &RunOptions, out: &mut impl Write) -> Result<()> {
+    let repo = git::get_repo(path)?;
+    let mut cache = StatusCache::default();
+    let mut snapshot = || -> Result<Snapshot> {
+        let status = git::get_status_cached(&repo, &mut cache, config.preview)?;
+        Ok(snapshot_of(&repo, status))
+    };
+    let mut last = snapshot()?;
+    if !emit(out, &Event::Status(last.clone()))? {
+        return Ok(());
+    }
+
+    let workdir = repo.workdir().unwrap_or(Path::new(path)).to_path_buf();
+    let mut watcher = if options.no_watch
+        || !config.watch.enabled
+        || watcher::network_filesystem(&workdir).is_some()
+    {
+        None
+    } else {
+        FileWatcher::start(&repo, &workdir, config.watch.fsmonitor)
+            .inspect_err(|e| tracing::warn!(error = %e, "file watcher failed; polling"))
+            .ok()
+    };
+
+    loop {
+        match &watcher {
+            Some(w) => {
+                let timeout = if w.limited {
+                    POLL_INTERVAL
+                } else {
+                    Duration::MAX
+                };
+                match w.receiver.recv_timeout(timeout) {
+                    Ok(_) => {
+                        std::thread::sleep(DEBOUNCE);
+                        while w.receiver.try_recv().is_ok() {}
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => watcher = None,
+                }
+            }
+            None => std::thread::sleep(POLL_INTERVAL),
+        }
+
+        let events = match snapshot() {
+            Ok(next) if next == last => continue,
+            Ok(next) => {
+                let files = changes(&last, &next);
+                last = next;
+                let mut events = Vec::new();
+                if !files.is_empty() {
+                    events.push(Event::Changed { files });
+                }
+                events.push(Event::Status(last.clone()));
+                events
+            }
+            Err(e) => vec![Event::Error {
+                message: format!("{:#}", e),
+            }],
+        };
+        for event in &events {
+            if !emit(out, event)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(section: Section, path: &str, status: FileStatus) -> FileState {
+        FileState {
+            section,
+            path: path.to_string(),
+            old_path: None,
+            status,
+            added_lines: None,
+            deleted_lines: None,
+        }
+    }
+
+    #[test]
+    fn changes_lists_new_changed_and_removed_rows() {
+        let old = Snapshot {
+            branch: "main".to_string(),
+            files: vec![
+                file(Section::Unstaged, "a.rs", FileStatus::Modified),
+                file(Section::Unstaged, "b.rs", FileStatus::Untracked),
+                file(Section::Staged, "c.rs", FileStatus::Added),
+            ],
+        };
+        let new = Snapshot {
+            branch: "main".to_string(),
+            files: vec![
+                file(Section::Staged, "b.rs", FileStatus::Added),
+                file(Section::Unstaged, "a.rs", FileStatus::Modified),
+                file(Section::Staged, "c.rs", FileStatus::Modified),
+            ],
+        };
+
+        let paths: Vec<_> = changes(&old, &new)
+            .into_iter()
+            .map(|c| (c.section, c.path, c.status))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                (Section::Staged, "b.rs".to_string(), Some(FileStatus::Added)),
+                (
+                    Section::Staged,
+                    "c.rs".to_string(),
+                    Some(FileStatus::Modified)
+                ),
+                (Section::Unstaged, "b.rs".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn events_serialize_as_tagged_json_lines() {
+        let status = Event::Status(Snapshot {
+            branch: "main".to_string(),
+            files: vec![file(Section::Unstaged, "a.rs", FileStatus::Untracked)],
+        });
+        let mut out = Vec::new();
+        assert!(emit(&mut out, &status).unwrap());
+        assert!(emit(
+            &mut out,
+            &Event::Changed {
+                files: vec![FileChange {
+                    section: Section::Staged,
+                    path: "a.rs".to_string(),
+                    status: None,
+                }],
+            },
+        )
+        .unwrap());
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                r#"{"event":"status","branch":"main","files":[{"section":"unstaged","path":"a.rs","status":"untracked"}]}"#,
+                "\n",
+                r#"{"event":"changed","files":[{"section":"staged","path":"a.rs","status":null}]}"#,
+                "\n",
+            )
+        );
+    }
+}
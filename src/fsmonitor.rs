@@ -0,0 +1,249 @@
+use anyhow::{bail, Context, Result};
+use git2::Repository;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How long to wait on the daemon before giving up on a query.
+#[cfg(unix)]
+const QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The filesystem monitor `core.fsmonitor` configures, which git keeps
+/// running for the repository already.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fsmonitor {
+    /// `core.fsmonitor = true`: git's builtin daemon, queried over the
+    /// socket in the git directory.
+    Daemon(PathBuf),
+    /// `core.fsmonitor = <command>`: a hook such as the Watchman one,
+    /// queried with protocol version 2.
+    Hook(String),
+}
+
+/// What changed since the previous query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Changes {
+    /// Paths relative to the working tree root; directories end in `/`.
+    Paths(Vec<String>),
+    /// The monitor lost track (it restarted, or didn't know the token), so
+    /// anything may have changed.
+    Everything,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Changes::Paths(paths) if paths.is_empty())
+    }
+}
+
+/// The fsmonitor configured for `repo`, if any.
+pub fn detect(repo: &Repository) -> Option<Fsmonitor> {
+    let config = repo.config().ok()?;
+    let value = config.get_string("core.fsmonitor").ok()?;
+    match git2::Config::parse_bool(value.as_str()) {
+        Ok(true) if cfg!(unix) => {
+            Some(Fsmonitor::Daemon(repo.path().join("fsmonitor--daemon.ipc")))
+        }
+        Ok(_) => None,
+        Err(_) if value.trim().is_empty() => None,
+        Err(_) => Some(Fsmonitor::Hook(value)),
+    }
+}
+
+/// Asks an fsmonitor what changed, keeping the token that marks where the
+/// last answer left off.
+pub struct FsmonitorClient {
+    monitor: Fsmonitor,
+    workdir: PathBuf,
+    token: String,
+}
+
+impl FsmonitorClient {
+    /// Connect to `monitor`, starting git's daemon if it isn't running, and
+    /// take the token to query from. Fails if the monitor doesn't answer.
+    pub fn new(monitor: Fsmonitor, workdir: &Path) -> Result<Self> {
+        let mut client = Self {
+            monitor,
+            workdir: workdir.to_path_buf(),
+            // Hooks take a timestamp in nanoseconds as their first token.
+            token: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+                .to_string(),
+        };
+        if let Fsmonitor::Daemon(socket) = &client.monitor {
+            if !socket.exists() {
+                start_daemon(workdir)?;
+            }
+        }
+        client.query().context("fsmonitor didn't answer")?;
+        Ok(client)
+    }
+
+    /// What changed since the previous query, ignoring the git directory.
+    pub fn query(&mut self) -> Result<Changes> {
+        let response = match &self.monitor {
+            Fsmonitor::Daemon(socket) => query_daemon(socket, &self.token)?,
+            Fsmonitor::Hook(command) => query_hook(command, &self.workdir, &self.token)?,
+        };
+        let (token, changes) = parse_response(&response)?;
+        self.token = token;
+        Ok(changes)
+    }
+}
+
+/// Split a version 2 response: the new token, then changed paths, each
+/// NUL-terminated. A lone `/` means everything.
+fn parse_response(response: &[u8]) -> Result<(String, Changes)> {
+    let mut fields = response.split(|&b| b == 0);
+    let token = fields.next().unwrap_or_default();
+    if token.is_empty() {
+        bail!("fsmonitor response has no token");
+    }
+    let token = String::from_utf8_lossy(token).into_owned();
+    let mut paths = Vec::new();
+    for field in fields.filter(|field| !field.is_empty()) {
+        if field == b"/" {
+            return Ok((token, Changes::Everything));
+        }
+        let path = String::from_utf8_lossy(field).into_owned();
+        if path != ".git" && !path.starts_with(".git/") {
+            paths.push(path);
+        }
+    }
+    Ok((token, Changes::Paths(paths)))
+}
+
+fn start_daemon(workdir: &Path) -> Result<()> {
+    tracing::info!("starting git fsmonitor daemon");
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(workdir)
+        .args(["fsmonitor--daemon", "start"])
+        .output()
+        .context("Failed to run git fsmonitor--daemon")?;
+    if !output.status.success() {
+        bail!(
+            "git fsmonitor--daemon start failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Send `token` to the daemon as git's simple-ipc does: pkt-lines ended by
+/// a flush, answered the same way.
+#[cfg(unix)]
+fn query_daemon(socket: &Path, token: &str) -> Result<Vec<u8>> {
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(socket)
+        .with_context(|| format!("Failed to connect to {}", socket.display()))?;
+    stream.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    stream.set_write_timeout(Some(QUERY_TIMEOUT))?;
+    write_pkt_lines(&mut stream, token.as_bytes())?;
+    stream.write_all(b"0000")?;
+    read_pkt_lines(&mut stream)
+}
+
+#[cfg(not(unix))]
+fn query_daemon(_socket: &Path, _token: &str) -> Result<Vec<u8>> {
+    bail!("The fsmonitor daemon is only supported on Unix")
+}
+
+/// Largest pkt-line payload: the 65520-byte limit less the length prefix.
+const MAX_PKT_PAYLOAD: usize = 65516;
+
+fn write_pkt_lines(out: &mut impl std::io::Write, data: &[u8]) -> Result<()> {
+    for chunk in data.chunks(MAX_PKT_PAYLOAD) {
+        write!(out, "{:04x}", chunk.len() + 4)?;
+        out.write_all(chunk)?;
+    }
+    Ok(())
+}
+
+/// Concatenate pkt-line payloads up to the next flush packet.
+fn read_pkt_lines(input: &mut impl std::io::Read) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    loop {
+        let mut prefix = [0u8; 4];
+        input.read_exact(&mut prefix)?;
+        let len = std::str::from_utf8(&prefix)
+            .ok()
+            .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+            .context("Malformed pkt-line length")?;
+        match len {
+            0 => return Ok(data),
+            1..=4 => bail!("Unexpected pkt-line length {}", len),
+            _ => {
+                let start = data.len();
+                data.resize(start + len - 4, 0);
+                input.read_exact(&mut data[start..])?;
+            }
+        }
+    }
+}
+
+fn query_hook(command: &str, workdir: &Path, token: &str) -> Result<Vec<u8>> {
+    // Relative hook paths are relative to the working tree, as for git.
+    let program = Path::new(command);
+    let program = if program.is_relative() && program.components().count() > 1 {
+        workdir.join(program)
+    } else {
+        program.to_path_buf()
+    };
+    let output = Command::new(&program)
+        .args(["2", token])
+        .current_dir(workdir)
+        .output()
+        .with_context(|| format!("Failed to run fsmonitor hook {}", command))?;
+    if !output.status.success() {
+        bail!(
+            "fsmonitor hook {} failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_response_skips_git_dir() {
+        let (token, changes) =
+            parse_response(b"builtin:1:2\0src/main.rs\0.git/index\0docs/\0").unwrap();
+        assert_eq!(token, "builtin:1:2");
+        assert_eq!(
+            changes,
+            Changes::Paths(vec!["src/main.rs".to_string(), "docs/".to_string()])
+        );
+        assert!(parse_response(b"t\0.git/HEAD\0").unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn parse_response_recognizes_trivial_answer() {
+        let (_, changes) = parse_response(b"builtin:1:0\0/\0").unwrap();
+        assert_eq!(changes, Changes::Everything);
+        assert!(parse_response(b"").is_err());
+    }
+
+    #[test]
+    fn pkt_lines_round_trip() {
+        let mut out = Vec::new();
+        write_pkt_lines(&mut out, b"builtin:1:2").unwrap();
+        assert_eq!(out, b"000fbuiltin:1:2");
+        out.extend_from_slice(b"0000");
+        assert_eq!(read_pkt_lines(&mut &out[..]).unwrap(), b"builtin:1:2");
+
+        let long = vec![b'a'; MAX_PKT_PAYLOAD + 10];
+        let mut out = Vec::new();
+        write_pkt_lines(&mut out, &long).unwrap();
+        out.extend_from_slice(b"0000");
+        assert_eq!(read_pkt_lines(&mut &out[..]).unwrap(), long);
+    }
+}
@@ -1,12 +1,22 @@
+use crate::config::PreviewConfig;
+use crate::credentials::{AuthRequired, Credentials};
 use crate::types::{
-    BranchInfo, DiffContent, DiffLine, DiffLineKind, FileEntry, FileStatus, Section,
+    whitespace_issues, BackupFile, BinaryChange, BinaryInfo, BranchInfo, CommitSummary,
+    ConflictKind, ConflictPreview, DiffContent, DiffLine, DiffLineKind, DiscardBackup,
+    DivergenceGraph, EntryKind, EolChange, FileEntry, FilePreview, FileStatus, RebaseProgress,
+    RemoteInfo, Section, StashInfo, TransferPhase, TransferProgress, UpstreamInfo,
 };
+use crate::untracked::{UntrackedCache, RACY_WINDOW};
 use anyhow::{bail, Context, Result};
+use encoding_rs::Encoding;
 use git2::{DiffOptions, Repository, Status, StatusOptions};
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
 
 pub fn get_repo(path: &str) -> Result<Repository> {
-    let repo = Repository::open(path).context("Not a git repository")?;
+    let repo = Repository::discover(path).context("Not a git repository")?;
     if repo.is_bare() {
         bail!("Repository has no working directory");
     }
@@ -26,20 +36,570 @@ pub fn get_branch_info(repo: &Repository) -> BranchInfo {
             return BranchInfo::Detached(oid_str[..len].to_string());
         }
     }
+    if let Some(name) = unborn_branch(repo) {
+        return BranchInfo::Unborn(name);
+    }
     BranchInfo::Detached("unknown".to_string())
 }
 
+/// The branch HEAD names when it has no commits yet, as in a new
+/// repository.
+pub fn unborn_branch(repo: &Repository) -> Option<String> {
+    match repo.head() {
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => {}
+        _ => return None,
+    }
+    let head = repo.find_reference("HEAD").ok()?;
+    let target = head.symbolic_target()?;
+    Some(
+        target
+            .strip_prefix("refs/heads/")
+            .unwrap_or(target)
+            .to_string(),
+    )
+}
+
+/// HEAD's tree, or `None` on an unborn branch, where staged changes are
+/// diffed against the empty tree.
+fn head_tree(repo: &Repository) -> Result<Option<git2::Tree<'_>>, git2::Error> {
+    match repo.head() {
+        Ok(head) => head.peel_to_tree().map(Some),
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Make the first commit on the unborn current branch from the index.
+pub fn create_initial_commit(repo: &Repository, message: &str) -> Result<git2::Oid> {
+    if unborn_branch(repo).is_none() {
+        bail!("The branch already has commits");
+    }
+    let mut index = repo.index().context("Failed to get repository index")?;
+    if index.is_empty() {
+        bail!("Nothing staged to commit");
+    }
+    let signature = repo
+        .signature()
+        .context("Set user.name and user.email to commit")?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let oid = repo
+        .commit(Some("HEAD"), &signature, &signature, message, &tree, &[])
+        .context("Failed to create commit")?;
+    tracing::info!(%oid, "created initial commit");
+    Ok(oid)
+}
+
+/// The branch HEAD was last checked out from, read from the HEAD reflog,
+/// for returning from a detached HEAD as `git checkout -` would.
+pub fn previous_branch(repo: &Repository) -> Option<String> {
+    let reflog = repo.reflog("HEAD").ok()?;
+    reflog.iter().find_map(|entry| {
+        let message = entry.message()?;
+        let (from, _) = message
+            .strip_prefix("checkout: moving from ")?
+            .split_once(" to ")?;
+        repo.find_branch(from, git2::BranchType::Local)
+            .is_ok()
+            .then(|| from.to_string())
+    })
+}
+
+/// Create branch `name` at the detached HEAD commit and check it out. The
+/// working tree and index are left as they are.
+pub fn create_branch_at_head(repo: &Repository, name: &str) -> Result<()> {
+    let head = repo.head().context("Failed to read HEAD")?;
+    if head.is_branch() {
+        bail!("HEAD is not detached");
+    }
+    let commit = head
+        .peel_to_commit()
+        .context("Failed to read HEAD commit")?;
+    let branch = repo
+        .branch(name, &commit, false)
+        .with_context(|| format!("Failed to create branch '{}'", name))?;
+    let refname = branch
+        .get()
+        .name()
+        .context("Branch name is not valid UTF-8")?;
+    repo.set_head(refname)?;
+    tracing::info!(branch = name, "created branch at detached HEAD");
+    Ok(())
+}
+
+/// Check out local branch `name`. Fails without changing anything if local
+/// changes would be overwritten.
+pub fn switch_branch(repo: &Repository, name: &str) -> Result<()> {
+    let branch = repo
+        .find_branch(name, git2::BranchType::Local)
+        .with_context(|| format!("No branch named '{}'", name))?;
+    let refname = branch
+        .get()
+        .name()
+        .context("Branch name is not valid UTF-8")?
+        .to_string();
+    let commit = branch.get().peel_to_commit()?;
+    let mut checkout = git2::build::CheckoutBuilder::new();
+    checkout.safe();
+    repo.checkout_tree(commit.as_object(), Some(&mut checkout))
+        .context("Local changes would be overwritten; commit or stash them first")?;
+    repo.set_head(&refname)?;
+    tracing::info!(branch = name, "switched branch");
+    Ok(())
+}
+
+/// Whether the repository is a partial clone, whose promisor remote
+/// supplies blobs (or trees) on demand instead of up front.
+pub fn is_partial_clone(repo: &Repository) -> bool {
+    promisor_remote(repo).is_some()
+}
+
+/// The name of the remote a partial clone fetches missing objects from.
+fn promisor_remote(repo: &Repository) -> Option<String> {
+    let config = repo.config().ok()?;
+    if let Ok(name) = config.get_string("extensions.partialclone") {
+        return Some(name);
+    }
+    let remotes = repo.remotes().ok()?;
+    remotes
+        .iter()
+        .flatten()
+        .find(|name| {
+            config
+                .get_bool(&format!("remote.{}.promisor", name))
+                .unwrap_or(false)
+        })
+        .map(str::to_string)
+}
+
+/// Whether `e` is libgit2 failing to find an object, as happens in a
+/// partial clone for blobs that were never fetched.
+fn is_missing_object(e: &git2::Error) -> bool {
+    e.code() == git2::ErrorCode::NotFound && e.class() == git2::ErrorClass::Odb
+}
+
+/// Blobs needed to diff `file` in `section` that aren't in the object
+/// database.
+pub fn missing_blobs(repo: &Repository, file: &FileEntry, section: Section) -> Vec<git2::Oid> {
+    let Ok(odb) = repo.odb() else {
+        return Vec::new();
+    };
+    let head = head_tree(repo).ok().flatten();
+    let index = repo.index().ok();
+    let paths = std::iter::once(&file.raw_path).chain(&file.raw_old_path);
+    let mut oids = Vec::new();
+    for path in paths {
+        let path = fs_path(path);
+        if section == Section::Staged {
+            if let Some(entry) = head.as_ref().and_then(|tree| tree.get_path(&path).ok()) {
+                if entry.kind() == Some(git2::ObjectType::Blob) {
+                    oids.push(entry.id());
+                }
+            }
+        }
+        if let Some(entry) = index.as_ref().and_then(|index| index.get_path(&path, 0)) {
+            if entry.mode != u32::from(git2::FileMode::Commit) {
+                oids.push(entry.id);
+            }
+        }
+    }
+    oids.sort();
+    oids.dedup();
+    oids.retain(|oid| !odb.exists(*oid));
+    oids
+}
+
+/// Fetch `oids` from the promisor remote. libgit2 can't fetch lazily, so
+/// this asks `git`, whose object lookups do. `git` never prompts: a remote
+/// that wants a password fails with [`AuthRequired`] unless `creds` carries
+/// one. `progress` is polled while waiting and stops the fetch when it
+/// returns false.
+pub fn fetch_blobs(
+    repo: &Repository,
+    oids: &[git2::Oid],
+    creds: &Credentials,
+    progress: &mut ProgressFn,
+) -> Result<()> {
+    use crate::types::{TransferPhase, TransferProgress};
+    use std::io::{Read, Write};
+    use std::process::{Command, Stdio};
+
+    let workdir = repo.workdir().unwrap_or_else(|| repo.path());
+    let mut command = Command::new("git");
+    command
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .arg("-C")
+        .arg(workdir);
+    if let Some((username, password)) = &creds.userpass {
+        // Hand git the typed credentials through the environment, ahead of
+        // any configured helper, so they never appear on a command line.
+        command
+            .env("BGS_USERNAME", username)
+            .env("BGS_PASSWORD", password)
+            .args(["-c", "credential.helper="])
+            .args([
+                "-c",
+                "credential.helper=!f() { echo \"username=$BGS_USERNAME\"; echo \"password=$BGS_PASSWORD\"; }; f",
+            ]);
+    }
+    let mut child = command
+        .args(["cat-file", "--batch-check"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run git to fetch missing objects")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        for oid in oids {
+            writeln!(stdin, "{}", oid)?;
+        }
+    }
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let reader = std::thread::spawn(move || {
+        let mut out = String::new();
+        let _ = stderr.read_to_string(&mut out);
+        out
+    });
+    let mut report = TransferProgress {
+        phase: TransferPhase::Receiving,
+        current: 0,
+        total: oids.len(),
+        bytes: 0,
+    };
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if !progress(report) {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("Fetch of missing objects cancelled");
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    let stderr = reader.join().unwrap_or_default();
+    if !status.success() {
+        let stderr = stderr.trim();
+        if stderr.contains("terminal prompts disabled") || stderr.contains("Authentication failed")
+        {
+            let url = promisor_remote(repo)
+                .and_then(|name| repo.find_remote(&name).ok()?.url().map(str::to_string))
+                .unwrap_or_default();
+            return Err(AuthRequired { url }.into());
+        }
+        bail!("git cat-file failed: {}", stderr);
+    }
+
+    let odb = repo.odb()?;
+    // Pick up the pack git just wrote.
+    odb.refresh()?;
+    let missing = oids.iter().filter(|oid| !odb.exists(**oid)).count();
+    if missing > 0 {
+        bail!(
+            "{} of {} objects still missing after fetching from the promisor remote",
+            missing,
+            oids.len()
+        );
+    }
+    report.current = oids.len();
+    progress(report);
+    tracing::info!(count = oids.len(), "fetched missing objects");
+    Ok(())
+}
+
+/// Resolve the local and upstream commit ids for the current branch.
+///
+/// Returns `None` on a detached HEAD or when no upstream is configured.
+fn upstream_oids(repo: &Repository) -> Option<(String, git2::Oid, git2::Oid)> {
+    let head = repo.head().ok()?;
+    if !head.is_branch() {
+        return None;
+    }
+    let local_oid = head.target()?;
+    let branch = git2::Branch::wrap(head);
+    let upstream = branch.upstream().ok()?;
+    let name = upstream.name().ok()??.to_string();
+    let upstream_oid = upstream.get().target()?;
+    Some((name, local_oid, upstream_oid))
+}
+
+/// Get the upstream name and ahead/behind counts for the current branch.
+pub fn get_upstream_info(repo: &Repository) -> Option<UpstreamInfo> {
+    let (name, local_oid, upstream_oid) = upstream_oids(repo)?;
+    let (ahead, behind) = repo.graph_ahead_behind(local_oid, upstream_oid).ok()?;
+    Some(UpstreamInfo {
+        name,
+        ahead,
+        behind,
+    })
+}
+
+/// Collect up to `limit` commits per side describing how HEAD and its
+/// upstream have diverged since their merge base.
+pub fn get_divergence_graph(repo: &Repository, limit: usize) -> Option<DivergenceGraph> {
+    let (name, local_oid, upstream_oid) = upstream_oids(repo)?;
+    let (ahead, behind) = repo.graph_ahead_behind(local_oid, upstream_oid).ok()?;
+
+    let local = walk_exclusive(repo, local_oid, upstream_oid, limit);
+    let remote = walk_exclusive(repo, upstream_oid, local_oid, limit);
+    let merge_base = repo
+        .merge_base(local_oid, upstream_oid)
+        .ok()
+        .and_then(|oid| repo.find_commit(oid).ok())
+        .map(|commit| commit_summary(&commit));
+
+    Some(DivergenceGraph {
+        upstream: UpstreamInfo {
+            name,
+            ahead,
+            behind,
+        },
+        local,
+        remote,
+        merge_base,
+    })
+}
+
+/// Walk commits reachable from `from` but not from `hide`, newest first.
+fn walk_exclusive(
+    repo: &Repository,
+    from: git2::Oid,
+    hide: git2::Oid,
+    limit: usize,
+) -> Vec<CommitSummary> {
+    let Ok(mut walk) = repo.revwalk() else {
+        return Vec::new();
+    };
+    if walk.push(from).is_err() || walk.hide(hide).is_err() {
+        return Vec::new();
+    }
+    let _ = walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME);
+
+    walk.filter_map(|oid| oid.ok())
+        .filter_map(|oid| repo.find_commit(oid).ok())
+        .take(limit)
+        .map(|commit| commit_summary(&commit))
+        .collect()
+}
+
+fn commit_summary(commit: &git2::Commit) -> CommitSummary {
+    let id = commit.id().to_string();
+    CommitSummary {
+        short_id: id[..7.min(id.len())].to_string(),
+        subject: commit.summary().unwrap_or_default().to_string(),
+    }
+}
+
+/// Read the progress of an in-flight rebase from the git directory.
+///
+/// Supports both the merge backend (`rebase-merge/msgnum`, `end`, `done`) and
+/// the apply backend (`rebase-apply/next`, `last`). Returns `None` when no
+/// rebase is in progress or the state files are unreadable.
+pub fn get_rebase_progress(repo: &Repository) -> Option<RebaseProgress> {
+    let git_dir = repo.path();
+
+    let merge_dir = git_dir.join("rebase-merge");
+    if merge_dir.is_dir() {
+        let current = read_state_number(&merge_dir.join("msgnum"))?;
+        let total = read_state_number(&merge_dir.join("end"))?;
+        let subject = std::fs::read_to_string(merge_dir.join("done"))
+            .ok()
+            .and_then(|done| {
+                done.lines()
+                    .rev()
+                    .find(|l| !l.trim().is_empty() && !l.starts_with('#'))
+                    .map(|l| todo_line_subject(repo, l))
+            })
+            .or_else(|| read_first_line(&merge_dir.join("message")))
+            .unwrap_or_default();
+        return Some(RebaseProgress {
+            current,
+            total,
+            subject,
+        });
+    }
+
+    let apply_dir = git_dir.join("rebase-apply");
+    if apply_dir.is_dir() {
+        let current = read_state_number(&apply_dir.join("next"))?;
+        let total = read_state_number(&apply_dir.join("last"))?;
+        let subject = read_first_line(&apply_dir.join("final-commit-summary"))
+            .or_else(|| read_first_line(&apply_dir.join("msg")))
+            .unwrap_or_default();
+        return Some(RebaseProgress {
+            current,
+            total,
+            subject,
+        });
+    }
+
+    None
+}
+
+fn read_state_number(path: &std::path::Path) -> Option<usize> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn read_first_line(path: &std::path::Path) -> Option<String> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let line = content.lines().next()?.trim();
+    if line.is_empty() {
+        None
+    } else {
+        Some(line.to_string())
+    }
+}
+
+/// Extract the commit subject from a rebase todo line such as
+/// `pick abc1234 Fix parser` or `fixup -C abc1234 # Fix parser`.
+///
+/// The commit is looked up in the repository so the real summary is used;
+/// the text after the hash is the fallback when the object can't be found.
+pub(crate) fn todo_line_subject(repo: &Repository, line: &str) -> String {
+    let mut tokens = line
+        .split_whitespace()
+        .skip(1)
+        .skip_while(|t| t.starts_with('-'));
+    let Some(hash) = tokens.next() else {
+        return String::new();
+    };
+
+    let from_commit = repo
+        .revparse_single(hash)
+        .ok()
+        .and_then(|obj| obj.peel_to_commit().ok())
+        .and_then(|commit| commit.summary().map(|s| s.to_string()));
+    if let Some(summary) = from_commit {
+        return summary;
+    }
+
+    let rest: Vec<&str> = tokens.collect();
+    rest.join(" ").trim_start_matches('#').trim().to_string()
+}
+
 pub struct StatusResult {
     pub staged_files: Vec<FileEntry>,
     pub unstaged_files: Vec<FileEntry>,
     pub staged_count: usize,
     pub unstaged_count: usize,
     pub untracked_count: usize,
+    /// Of the time taken, how much went to counting each file's changed
+    /// lines.
+    pub stats_time: Duration,
+}
+
+/// A repository-relative path as shown in the UI. Bytes that aren't
+/// valid UTF-8 become U+FFFD.
+pub fn display_path(raw: &[u8]) -> String {
+    String::from_utf8_lossy(raw).into_owned()
+}
+
+/// Filesystem path for a repository-relative path as git stores it.
+#[cfg(unix)]
+pub(crate) fn fs_path(raw: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(raw))
+}
+
+/// Filesystem path for a repository-relative path as git stores it.
+#[cfg(not(unix))]
+pub(crate) fn fs_path(raw: &[u8]) -> PathBuf {
+    PathBuf::from(display_path(raw))
+}
+
+/// A path's bytes as git stores them; the inverse of [`fs_path`].
+#[cfg(unix)]
+pub(crate) fn raw_path(path: &std::ffi::OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_bytes().to_vec()
+}
+
+/// A path's bytes as git stores them; the inverse of [`fs_path`].
+#[cfg(not(unix))]
+pub(crate) fn raw_path(path: &std::ffi::OsStr) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+/// `repo.statuses`, retried without rename detection if that needs blobs
+/// a partial clone doesn't have.
+fn statuses_without_missing<'r>(
+    repo: &'r Repository,
+    opts: &mut StatusOptions,
+) -> Result<git2::Statuses<'r>> {
+    match repo.statuses(Some(opts)) {
+        Err(e) if is_missing_object(&e) => {
+            tracing::warn!(error = %e, "objects missing; status without rename detection");
+            opts.renames_head_to_index(false)
+                .renames_index_to_workdir(false)
+                .renames_from_rewrites(false);
+            Ok(repo.statuses(Some(opts))?)
+        }
+        result => Ok(result?),
+    }
 }
 
-pub fn get_status(repo: &Repository) -> Result<StatusResult> {
+/// Work kept between refreshes so a large tree isn't rescanned in full
+/// each time.
+#[derive(Debug, Default)]
+pub struct StatusCache {
+    untracked: UntrackedCache,
+    counts: CountsCache,
+    /// See [`nested_repos`]; as of the last full status.
+    nested: Vec<Vec<u8>>,
+}
+
+impl StatusCache {
+    /// Whether `path` is inside a submodule or a nested repository, whose
+    /// changes a status of just that path wouldn't show.
+    pub fn in_nested_repo(&self, path: &[u8]) -> bool {
+        self.nested
+            .iter()
+            .any(|root| path.starts_with(root) || root.strip_suffix(b"/") == Some(path))
+    }
+}
+
+/// Line counts of untracked files, with the size and mtime they were
+/// counted at.
+type CountsCache = HashMap<Vec<u8>, (u64, SystemTime, LineCounts)>;
+
+pub fn get_status(repo: &Repository, preview: PreviewConfig) -> Result<StatusResult> {
+    status(repo, None, None, preview)
+}
+
+/// [`get_status`], reusing what `cache` kept from the previous call for
+/// directories and untracked files that haven't changed since.
+pub fn get_status_cached(
+    repo: &Repository,
+    cache: &mut StatusCache,
+    preview: PreviewConfig,
+) -> Result<StatusResult> {
+    status(repo, Some(cache), None, preview)
+}
+
+/// [`get_status_cached`] for only the files at or under `paths`, given as
+/// repository-relative pathspecs. The counts are of those files.
+pub fn get_status_of_paths(
+    repo: &Repository,
+    paths: &[Vec<u8>],
+    cache: &mut StatusCache,
+    preview: PreviewConfig,
+) -> Result<StatusResult> {
+    status(repo, Some(cache), Some(paths), preview)
+}
+
+fn status(
+    repo: &Repository,
+    mut cache: Option<&mut StatusCache>,
+    paths: Option<&[Vec<u8>]>,
+    preview: PreviewConfig,
+) -> Result<StatusResult> {
+    // The untracked cache covers the whole tree; a few paths are quicker
+    // left to libgit2.
+    let scan_untracked = paths.is_none() && cache.is_some() && UntrackedCache::enabled(repo);
     let mut opts = StatusOptions::new();
-    opts.include_untracked(true)
+    for path in paths.unwrap_or_default() {
+        opts.pathspec(path);
+    }
+    opts.include_untracked(!scan_untracked)
         .recurse_untracked_dirs(true)
         .include_ignored(false)
         .include_unmodified(false)
@@ -48,73 +608,95 @@ pub fn get_status(repo: &Repository) -> Result<StatusResult> {
         .renames_index_to_workdir(true)
         .renames_from_rewrites(true);
 
-    let statuses = repo.statuses(Some(&mut opts))?;
+    let mut statuses = statuses_without_missing(repo, &mut opts)?;
+    // A deleted file may have been renamed to an untracked one, which only
+    // libgit2's own scan can pair up.
+    let scan_untracked = if scan_untracked && statuses.iter().any(|e| e.status().is_wt_deleted()) {
+        opts.include_untracked(true);
+        statuses = statuses_without_missing(repo, &mut opts)?;
+        false
+    } else {
+        scan_untracked
+    };
+    let conflicts = conflict_kinds(repo);
 
     let mut staged_files = Vec::new();
     let mut unstaged_files = Vec::new();
-    let mut staged_paths = HashSet::new();
-    let mut unstaged_paths = HashSet::new();
-    let mut untracked_files = HashSet::new();
+    let mut stats_time = Duration::ZERO;
 
     for entry in statuses.iter() {
-        let Some(raw_path) = entry.path() else {
-            continue;
-        };
+        // `path()` is None for non-UTF-8 names; keep the bytes and show
+        // them lossily instead of dropping the file.
+        let raw_path = entry.path_bytes().to_vec();
         let status = entry.status();
 
-        let (staged_path, staged_old_path) = if status.is_index_renamed() {
-            if let Some(delta) = entry.head_to_index() {
-                let new_path = delta
+        let rename_paths = |delta: Option<git2::DiffDelta>| match delta {
+            Some(delta) => (
+                delta
                     .new_file()
-                    .path()
-                    .map(|p| p.to_string_lossy().to_string())
-                    .unwrap_or_else(|| raw_path.to_string());
-                let old_path = delta
-                    .old_file()
-                    .path()
-                    .map(|p| p.to_string_lossy().to_string());
-                (new_path, old_path)
-            } else {
-                (raw_path.to_string(), None)
-            }
+                    .path_bytes()
+                    .map_or_else(|| raw_path.clone(), <[u8]>::to_vec),
+                delta.old_file().path_bytes().map(<[u8]>::to_vec),
+            ),
+            None => (raw_path.clone(), None),
+        };
+
+        let (staged_path, staged_old_path) = if status.is_index_renamed() {
+            rename_paths(entry.head_to_index())
         } else {
-            (raw_path.to_string(), None)
+            (raw_path.clone(), None)
         };
 
         let (unstaged_path, unstaged_old_path) = if status.is_wt_renamed() {
-            if let Some(delta) = entry.index_to_workdir() {
-                let new_path = delta
-                    .new_file()
-                    .path()
-                    .map(|p| p.to_string_lossy().to_string())
-                    .unwrap_or_else(|| raw_path.to_string());
-                let old_path = delta
-                    .old_file()
-                    .path()
-                    .map(|p| p.to_string_lossy().to_string());
-                (new_path, old_path)
-            } else {
-                (raw_path.to_string(), None)
-            }
+            rename_paths(entry.index_to_workdir())
         } else {
-            (raw_path.to_string(), None)
+            (raw_path.clone(), None)
+        };
+
+        let modes = |delta: Option<git2::DiffDelta>| {
+            delta.map_or((0, 0), |delta| {
+                (
+                    u32::from(delta.old_file().mode()),
+                    u32::from(delta.new_file().mode()),
+                )
+            })
         };
+        let staged_modes = modes(entry.head_to_index());
+        let unstaged_modes = modes(entry.index_to_workdir());
 
-        let path = raw_path.to_string();
+        let path = display_path(&raw_path);
+        let generated = is_generated(repo, &raw_path);
 
         let is_conflict = status.is_conflicted();
-        let is_submodule = status.is_index_typechange() || status.is_wt_typechange();
+        let is_submodule = [entry.head_to_index(), entry.index_to_workdir()]
+            .iter()
+            .flatten()
+            .any(|delta| {
+                delta.old_file().mode() == git2::FileMode::Commit
+                    || delta.new_file().mode() == git2::FileMode::Commit
+            });
 
         if is_conflict {
-            unstaged_paths.insert(path.clone());
+            let conflict = conflicts.get(&raw_path).copied();
+            let meta = workdir_metadata(repo, &raw_path);
             let entry = FileEntry {
                 path,
                 old_path: None,
+                raw_path,
+                raw_old_path: None,
                 status: FileStatus::Conflict,
                 added_lines: None,
                 deleted_lines: None,
                 is_binary: false,
                 is_submodule: false,
+                eol_change: None,
+                whitespace_errors: false,
+                generated,
+                old_mode: 0,
+                mode: 0,
+                size: meta.as_ref().map(|meta| meta.len()),
+                mtime: meta.and_then(|meta| meta.modified().ok()),
+                conflict,
             };
             unstaged_files.push(entry);
             continue;
@@ -125,89 +707,218 @@ pub fn get_status(repo: &Repository) -> Result<StatusResult> {
         let is_untracked = status.is_wt_new();
 
         if is_untracked {
-            untracked_files.insert(path.clone());
-            unstaged_paths.insert(path.clone());
-            let (added, is_binary) = count_lines_in_workdir(repo, &path);
-            let entry = FileEntry {
-                path,
-                old_path: None,
-                status: FileStatus::Untracked,
-                added_lines: Some(added),
-                deleted_lines: Some(0),
-                is_binary,
-                is_submodule: false,
-            };
-            unstaged_files.push(entry);
-            continue;
-        }
-
-        if is_submodule {
-            if has_staged || has_unstaged {
-                staged_paths.insert(path.clone());
-                if has_unstaged {
-                    unstaged_paths.insert(path.clone());
-                }
-                let file_status = get_staged_status(status);
-                let (added, deleted, is_binary) =
-                    get_line_counts_for_section(repo, &path, Section::Staged);
-                staged_files.push(FileEntry {
-                    path,
-                    old_path: staged_old_path,
-                    status: file_status,
-                    added_lines: added,
-                    deleted_lines: deleted,
-                    is_binary,
-                    is_submodule: true,
-                });
-            }
+            let counts = cache.as_deref_mut().map(|cache| &mut cache.counts);
+            let start = Instant::now();
+            unstaged_files.push(untracked_entry(
+                repo,
+                raw_path,
+                unstaged_modes.1,
+                counts,
+                preview,
+            ));
+            stats_time += start.elapsed();
             continue;
         }
 
         if has_staged {
-            staged_paths.insert(staged_path.clone());
             let file_status = get_staged_status(status);
-            let (added, deleted, is_binary) =
-                get_line_counts_for_section(repo, &staged_path, Section::Staged);
+            let start = Instant::now();
+            let counts = get_line_counts_for_section(repo, &staged_path, Section::Staged);
+            stats_time += start.elapsed();
+            let meta = workdir_metadata(repo, &staged_path);
             staged_files.push(FileEntry {
-                path: staged_path,
-                old_path: staged_old_path,
+                path: display_path(&staged_path),
+                old_path: staged_old_path.as_deref().map(display_path),
+                raw_path: staged_path,
+                raw_old_path: staged_old_path,
                 status: file_status,
-                added_lines: added,
-                deleted_lines: deleted,
-                is_binary,
-                is_submodule: false,
+                added_lines: counts.added,
+                deleted_lines: counts.deleted,
+                is_binary: counts.is_binary,
+                is_submodule,
+                eol_change: counts.eol_change,
+                whitespace_errors: counts.whitespace_errors,
+                generated,
+                old_mode: staged_modes.0,
+                mode: staged_modes.1,
+                size: meta.as_ref().map(|meta| meta.len()),
+                mtime: meta.and_then(|meta| meta.modified().ok()),
+                conflict: None,
             });
         }
 
         if has_unstaged {
-            unstaged_paths.insert(unstaged_path.clone());
             let file_status = get_unstaged_status(status);
-            let (added, deleted, is_binary) =
-                get_line_counts_for_section(repo, &unstaged_path, Section::Unstaged);
+            let start = Instant::now();
+            let counts = get_line_counts_for_section(repo, &unstaged_path, Section::Unstaged);
+            stats_time += start.elapsed();
+            if counts.normalized_away && !is_submodule {
+                // Only differs in line endings that `text`/`eol` conversion
+                // undoes, e.g. a CRLF checkout of an LF file.
+                continue;
+            }
+            let meta = workdir_metadata(repo, &unstaged_path);
             unstaged_files.push(FileEntry {
-                path: unstaged_path,
-                old_path: unstaged_old_path,
+                path: display_path(&unstaged_path),
+                old_path: unstaged_old_path.as_deref().map(display_path),
+                raw_path: unstaged_path,
+                raw_old_path: unstaged_old_path,
                 status: file_status,
-                added_lines: added,
-                deleted_lines: deleted,
-                is_binary,
-                is_submodule: false,
+                added_lines: counts.added,
+                deleted_lines: counts.deleted,
+                is_binary: counts.is_binary,
+                is_submodule,
+                eol_change: counts.eol_change,
+                whitespace_errors: counts.whitespace_errors,
+                generated,
+                old_mode: unstaged_modes.0,
+                mode: unstaged_modes.1,
+                size: meta.as_ref().map(|meta| meta.len()),
+                mtime: meta.and_then(|meta| meta.modified().ok()),
+                conflict: None,
             });
         }
     }
 
+    if let Some(cache) = cache.filter(|_| paths.is_none()) {
+        if scan_untracked {
+            for (raw_path, mode) in cache.untracked.scan(repo) {
+                let start = Instant::now();
+                let entry = untracked_entry(repo, raw_path, mode, Some(&mut cache.counts), preview);
+                stats_time += start.elapsed();
+                unstaged_files.push(entry);
+            }
+        }
+        let untracked: HashSet<&[u8]> = unstaged_files
+            .iter()
+            .filter(|f| f.status == FileStatus::Untracked)
+            .map(|f| &f.raw_path[..])
+            .collect();
+        cache.counts.retain(|path, _| untracked.contains(&path[..]));
+        cache.nested = nested_repos(repo, &unstaged_files);
+    }
+
     staged_files.sort_by(|a, b| a.path.cmp(&b.path));
     unstaged_files.sort_by(|a, b| a.path.cmp(&b.path));
 
+    let counts = FileCounts::of(&staged_files, &unstaged_files);
     Ok(StatusResult {
         staged_files,
         unstaged_files,
-        staged_count: staged_paths.len(),
-        unstaged_count: unstaged_paths.len(),
-        untracked_count: untracked_files.len(),
+        staged_count: counts.staged,
+        unstaged_count: counts.unstaged,
+        untracked_count: counts.untracked,
+        stats_time,
     })
 }
 
+/// What the status bar counts in a status's file lists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileCounts {
+    /// Paths with staged changes.
+    pub staged: usize,
+    /// Paths with unstaged changes, untracked and conflicted ones included.
+    pub unstaged: usize,
+    pub untracked: usize,
+}
+
+impl FileCounts {
+    pub fn of(staged: &[FileEntry], unstaged: &[FileEntry]) -> Self {
+        let distinct = |files: &mut dyn Iterator<Item = &FileEntry>| {
+            files.map(|f| &f.raw_path).collect::<HashSet<_>>().len()
+        };
+        Self {
+            staged: distinct(&mut staged.iter()),
+            unstaged: distinct(&mut unstaged.iter()),
+            untracked: distinct(
+                &mut unstaged
+                    .iter()
+                    .filter(|f| f.status == FileStatus::Untracked),
+            ),
+        }
+    }
+}
+
+/// Roots of the submodules and of the untracked nested repositories in
+/// `unstaged`, each ending in `/`.
+fn nested_repos(repo: &Repository, unstaged: &[FileEntry]) -> Vec<Vec<u8>> {
+    let submodules = repo.submodules().unwrap_or_default();
+    let submodules = submodules.iter().map(|submodule| {
+        let mut root = raw_path(submodule.path().as_os_str());
+        root.push(b'/');
+        root
+    });
+    let untracked = unstaged
+        .iter()
+        .filter(|f| f.status == FileStatus::Untracked && f.raw_path.ends_with(b"/"))
+        .map(|f| f.raw_path.clone());
+    submodules.chain(untracked).collect()
+}
+
+/// The file list entry for an untracked file. Its lines are counted unless
+/// `counts` has them for the file's current size and mtime.
+fn untracked_entry(
+    repo: &Repository,
+    raw_path: Vec<u8>,
+    mode: u32,
+    counts: Option<&mut CountsCache>,
+    preview: PreviewConfig,
+) -> FileEntry {
+    let meta = workdir_metadata(repo, &raw_path);
+    let stamp = meta
+        .as_ref()
+        .and_then(|meta| Some((meta.len(), meta.modified().ok()?)));
+    let line_counts = match (counts, stamp) {
+        (Some(counts), Some((size, mtime))) => match counts.get(&raw_path) {
+            Some((s, m, cached)) if *s == size && *m == mtime => cached.clone(),
+            _ => {
+                let fresh = count_lines_in_workdir(repo, &raw_path, preview);
+                // A file written just now may change again within the same
+                // mtime tick.
+                if mtime < SystemTime::now() - RACY_WINDOW {
+                    counts.insert(raw_path.clone(), (size, mtime, fresh.clone()));
+                }
+                fresh
+            }
+        },
+        _ => count_lines_in_workdir(repo, &raw_path, preview),
+    };
+    FileEntry {
+        path: display_path(&raw_path),
+        old_path: None,
+        generated: is_generated(repo, &raw_path),
+        raw_path,
+        raw_old_path: None,
+        status: FileStatus::Untracked,
+        added_lines: line_counts.added,
+        deleted_lines: line_counts.deleted,
+        is_binary: line_counts.is_binary,
+        is_submodule: false,
+        eol_change: None,
+        whitespace_errors: line_counts.whitespace_errors,
+        old_mode: 0,
+        mode,
+        size: meta.as_ref().map(|meta| meta.len()),
+        mtime: meta.and_then(|meta| meta.modified().ok()),
+        conflict: None,
+    }
+}
+
+/// Whether `path` is marked as generated output: `linguist-generated` or
+/// `-diff` in `.gitattributes`.
+fn is_generated(repo: &Repository, path: &[u8]) -> bool {
+    use git2::AttrValue;
+    let attr = |name| {
+        repo.get_attr_bytes(&fs_path(path), name, git2::AttrCheckFlags::FILE_THEN_INDEX)
+            .map(AttrValue::from_bytes)
+            .unwrap_or(AttrValue::Unspecified)
+    };
+    matches!(
+        attr("linguist-generated"),
+        AttrValue::True | AttrValue::String("true")
+    ) || attr("diff") == AttrValue::False
+}
+
 pub(crate) fn has_staged_changes(status: Status) -> bool {
     status.is_index_new()
         || status.is_index_modified()
@@ -230,6 +941,8 @@ pub(crate) fn get_staged_status(status: Status) -> FileStatus {
         FileStatus::Deleted
     } else if status.is_index_renamed() {
         FileStatus::Renamed
+    } else if status.is_index_typechange() {
+        FileStatus::TypeChanged
     } else {
         FileStatus::Modified
     }
@@ -240,124 +953,663 @@ pub(crate) fn get_unstaged_status(status: Status) -> FileStatus {
         FileStatus::Deleted
     } else if status.is_wt_renamed() {
         FileStatus::Renamed
+    } else if status.is_wt_typechange() {
+        FileStatus::TypeChanged
     } else {
         FileStatus::Modified
     }
 }
 
-fn count_lines_in_workdir(repo: &Repository, path: &str) -> (usize, bool) {
+/// How each conflicted path conflicts, from which index stages it has.
+fn conflict_kinds(repo: &Repository) -> HashMap<Vec<u8>, ConflictKind> {
+    let Ok(index) = repo.index() else {
+        return HashMap::new();
+    };
+    let Ok(conflicts) = index.conflicts() else {
+        return HashMap::new();
+    };
+    conflicts
+        .flatten()
+        .filter_map(|conflict| {
+            let path = [&conflict.ancestor, &conflict.our, &conflict.their]
+                .into_iter()
+                .flatten()
+                .next()?
+                .path
+                .clone();
+            let kind = ConflictKind::from_stages(
+                conflict.ancestor.is_some(),
+                conflict.our.is_some(),
+                conflict.their.is_some(),
+            )?;
+            Some((path, kind))
+        })
+        .collect()
+}
+
+/// The working tree file at `path`'s metadata, if it exists.
+fn workdir_metadata(repo: &Repository, path: &[u8]) -> Option<std::fs::Metadata> {
+    let full = repo.workdir()?.join(fs_path(path));
+    std::fs::symlink_metadata(full).ok()
+}
+
+fn count_lines_in_workdir(repo: &Repository, path: &[u8], preview: PreviewConfig) -> LineCounts {
+    let lines = |added| LineCounts {
+        added: Some(added),
+        deleted: Some(0),
+        ..LineCounts::default()
+    };
     let workdir = match repo.workdir() {
         Some(w) => w,
-        None => return (0, false),
+        None => return lines(0),
     };
-    let file_path = workdir.join(path);
+    let file_path = workdir.join(fs_path(path));
+    if std::fs::read_link(&file_path).is_ok() {
+        // A symlink's content is its target path.
+        return lines(1);
+    }
     let content = match std::fs::read(&file_path) {
         Ok(c) => c,
-        Err(_) => return (0, false),
+        Err(_) => return lines(0),
     };
 
-    if content.contains(&0) {
-        return (0, true);
+    if looks_binary(&content, preview) {
+        return LineCounts {
+            is_binary: true,
+            ..lines(0)
+        };
     }
 
     let text = match String::from_utf8(content) {
         Ok(t) => t,
-        Err(_) => return (0, false),
+        Err(_) => return lines(0),
     };
 
-    let line_count = text.lines().count();
-    (line_count, false)
+    LineCounts {
+        whitespace_errors: text.lines().any(|line| !whitespace_issues(line).is_empty()),
+        ..lines(text.lines().count())
+    }
 }
 
-fn get_line_counts_for_section(
-    repo: &Repository,
-    path: &str,
-    section: Section,
-) -> (Option<usize>, Option<usize>, bool) {
+/// Per-file figures shown in the file list.
+#[derive(Debug, Clone, Default)]
+struct LineCounts {
+    added: Option<usize>,
+    deleted: Option<usize>,
+    is_binary: bool,
+    eol_change: Option<EolChange>,
+    whitespace_errors: bool,
+    /// The working tree file matches the index once its line endings are
+    /// converted as `.gitattributes` and `core.autocrlf` say.
+    normalized_away: bool,
+}
+
+fn get_line_counts_for_section(repo: &Repository, path: &[u8], section: Section) -> LineCounts {
     let mut opts = DiffOptions::new();
     opts.pathspec(path);
 
     let diff_result = match section {
-        Section::Staged => {
-            let head_tree = repo.head().ok().and_then(|h| h.peel_to_tree().ok());
-            repo.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut opts))
-        }
+        Section::Staged => head_tree(repo)
+            .and_then(|tree| repo.diff_tree_to_index(tree.as_ref(), None, Some(&mut opts))),
         Section::Unstaged => repo.diff_index_to_workdir(None, Some(&mut opts)),
     };
 
     let diff = match diff_result {
         Ok(d) => d,
-        Err(_) => return (None, None, false),
+        Err(_) => return LineCounts::default(),
     };
 
-    let mut is_binary = false;
-
-    for delta_idx in 0..diff.deltas().len() {
-        if let Some(delta) = diff.get_delta(delta_idx) {
-            if delta.flags().is_binary() {
-                is_binary = true;
-            }
-        }
+    // libgit2 only sets the binary flag once it has loaded the content,
+    // which computing the stats does.
+    let stats = diff.stats();
+    if stats.as_ref().is_err_and(is_missing_object) {
+        // Not fetched in a partial clone; leave the counts blank.
+        return LineCounts::default();
     }
-
-    if is_binary {
-        return (None, None, true);
+    if diff.deltas().any(|delta| delta.flags().is_binary()) {
+        return LineCounts {
+            is_binary: true,
+            ..LineCounts::default()
+        };
     }
 
-    let stats = match diff.stats() {
+    let stats = match stats {
         Ok(s) => s,
-        Err(_) => return (Some(0), Some(0), false),
+        Err(_) => {
+            return LineCounts {
+                added: Some(0),
+                deleted: Some(0),
+                ..LineCounts::default()
+            }
+        }
     };
 
     let added = stats.insertions();
     let deleted = stats.deletions();
+    // libgit2 diffs the working tree through the crlf filter, so a file
+    // flagged modified without any changed lines or mode differs only in
+    // what the filter converts.
+    let normalized_away = section == Section::Unstaged
+        && added == 0
+        && deleted == 0
+        && diff.deltas().all(|delta| {
+            delta.status() == git2::Delta::Modified
+                && delta.old_file().mode() == delta.new_file().mode()
+        });
+    // A line-ending conversion adds and deletes about as many lines; only
+    // then is it worth walking the patch.
+    let balanced = added > 0 && deleted > 0 && added.max(deleted) * 4 <= added.min(deleted) * 5;
+    let (eol_change, whitespace_errors) = if added > 0 {
+        scan_patch(&diff, balanced)
+    } else {
+        (None, false)
+    };
 
-    (Some(added), Some(deleted), false)
+    LineCounts {
+        added: Some(added),
+        deleted: Some(deleted),
+        is_binary: false,
+        eol_change,
+        whitespace_errors,
+        normalized_away,
+    }
 }
 
-pub fn get_diff(
-    repo: &Repository,
-    path: &str,
-    old_path: Option<&str>,
-    section: Section,
-) -> DiffContent {
-    let mut opts = DiffOptions::new();
-    opts.pathspec(path);
-    if let Some(old) = old_path {
-        opts.pathspec(old);
+/// Walk the patch's changed lines for whitespace errors and, if `check_eol`,
+/// a line-ending conversion.
+fn scan_patch(diff: &git2::Diff, check_eol: bool) -> (Option<EolChange>, bool) {
+    let mut deleted = Vec::new();
+    let mut added = Vec::new();
+    let mut whitespace_errors = false;
+    let printed = diff.print(git2::DiffFormat::Patch, |_, _, line| {
+        match line.origin() {
+            '-' if check_eol => deleted.push(line.content().to_vec()),
+            '+' => {
+                let text = String::from_utf8_lossy(line.content());
+                whitespace_errors |= !whitespace_issues(text.trim_end_matches('\n')).is_empty();
+                if check_eol {
+                    added.push(line.content().to_vec());
+                }
+            }
+            _ => {}
+        }
+        true
+    });
+    if printed.is_err() || !check_eol {
+        return (None, whitespace_errors);
     }
+    let deleted: Vec<&[u8]> = deleted.iter().map(Vec::as_slice).collect();
+    let added: Vec<&[u8]> = added.iter().map(Vec::as_slice).collect();
+    (detect_eol_change(&deleted, &added), whitespace_errors)
+}
 
-    let diff_result = match section {
-        Section::Staged => {
-            let head_tree = repo.head().ok().and_then(|h| h.peel_to_tree().ok());
-            repo.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut opts))
+/// Whether at least 80% of the changed lines are deleted/added pairs that
+/// differ only by a trailing CR, and which way they were converted.
+pub fn detect_eol_change(deleted: &[&[u8]], added: &[&[u8]]) -> Option<EolChange> {
+    fn split(line: &[u8]) -> (&[u8], bool) {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        match line.strip_suffix(b"\r") {
+            Some(text) => (text, true),
+            None => (line, false),
         }
-        Section::Unstaged => repo.diff_index_to_workdir(None, Some(&mut opts)),
-    };
-
-    let diff = match diff_result {
-        Ok(d) => d,
-        Err(_) => return DiffContent::Empty,
-    };
+    }
 
-    for delta_idx in 0..diff.deltas().len() {
-        if let Some(delta) = diff.get_delta(delta_idx) {
-            if delta.flags().is_binary() {
-                return DiffContent::Binary;
+    let changed = deleted.len().max(added.len());
+    if changed == 0 {
+        return None;
+    }
+    let mut unmatched: HashMap<&[u8], Vec<bool>> = HashMap::new();
+    for line in deleted {
+        let (text, has_cr) = split(line);
+        unmatched.entry(text).or_default().push(has_cr);
+    }
+    let (mut pairs, mut to_lf) = (0, 0);
+    for line in added {
+        let (text, has_cr) = split(line);
+        let Some(candidates) = unmatched.get_mut(text) else {
+            continue;
+        };
+        if let Some(i) = candidates.iter().position(|&old_cr| old_cr != has_cr) {
+            candidates.swap_remove(i);
+            pairs += 1;
+            if !has_cr {
+                to_lf += 1;
             }
         }
     }
 
-    let mut lines = Vec::new();
-    let mut current_new_line: Option<usize> = None;
-    let mut has_invalid_utf8 = false;
+    if pairs * 5 < changed * 4 {
+        return None;
+    }
+    Some(if to_lf * 2 >= pairs {
+        EolChange::CrlfToLf
+    } else {
+        EolChange::LfToCrlf
+    })
+}
 
-    let result = diff.print(git2::DiffFormat::Patch, |_delta, hunk, line| {
-        let raw_content = match std::str::from_utf8(line.content()) {
-            Ok(s) => s,
-            Err(_) => {
-                has_invalid_utf8 = true;
-                return false;
+/// How a diff is computed for display.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffFlags {
+    /// Hide changes that only touch line endings. Like `git diff
+    /// --ignore-space-at-eol`, this also hides trailing-whitespace changes.
+    pub ignore_eol: bool,
+}
+
+/// The new-side path named by a `diff --git a/<old> b/<new>` header line.
+/// Quoted paths come back without their quotes but still escaped.
+pub fn diff_header_path(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix("diff --git ")?;
+    if let Some(quoted) = rest.strip_suffix('"') {
+        let (_, path) = quoted.rsplit_once(" \"b/")?;
+        return Some(path);
+    }
+    let (_, path) = rest.rsplit_once(" b/")?;
+    Some(path)
+}
+
+/// What the diff panel shows for `file` in `section`: its text diff, or the
+/// binary, type-change, untracked-preview, or conflict view that applies.
+/// Small binary files come back as a hexdump when `hexdump` is set.
+pub fn get_file_diff(
+    repo: &Repository,
+    file: &FileEntry,
+    section: Section,
+    flags: DiffFlags,
+    hexdump: bool,
+    preview: PreviewConfig,
+) -> DiffContent {
+    if file.status == FileStatus::Conflict {
+        DiffContent::Conflict(get_conflict_preview(repo, file, preview))
+    } else if file.is_binary {
+        if !hexdump {
+            if let Some(diff) = get_textconv_diff(repo, file, section, preview) {
+                return diff;
+            }
+        }
+        let info = get_binary_info(
+            repo,
+            &file.raw_path,
+            file.raw_old_path.as_deref(),
+            section,
+            preview,
+        );
+        if hexdump && info.content.is_some() {
+            DiffContent::Hexdump(info)
+        } else {
+            DiffContent::Binary(info)
+        }
+    } else if file.status == FileStatus::TypeChanged {
+        get_type_change(repo, &file.raw_path, section)
+    } else if let Some(info) = too_large_to_preview(repo, file, section, preview) {
+        DiffContent::TooLarge(info)
+    } else if file.status == FileStatus::Untracked {
+        get_untracked_preview(repo, &file.raw_path)
+    } else {
+        get_diff(
+            repo,
+            &file.raw_path,
+            file.raw_old_path.as_deref(),
+            section,
+            flags,
+            preview,
+        )
+    }
+}
+
+/// Changed lines at least this long make a diff count as minified.
+const MINIFIED_LINE_BYTES: usize = 10_000;
+
+/// For a diff dominated by one enormous line (minified JS or CSS, JSON on
+/// one line), the number of changed lines and their size in bytes: the
+/// added lines, or the deleted ones when nothing was added.
+pub fn minified_summary(diff: &DiffContent) -> Option<(usize, u64)> {
+    let sizes: Vec<usize> = match diff {
+        DiffContent::Text(lines) => {
+            let of = |kind| {
+                lines
+                    .iter()
+                    .filter(|l| l.kind == kind)
+                    .map(|l| l.content.len())
+                    .collect::<Vec<_>>()
+            };
+            let added = of(DiffLineKind::Added);
+            if added.is_empty() {
+                of(DiffLineKind::Deleted)
+            } else {
+                added
+            }
+        }
+        DiffContent::Preview(preview) => preview
+            .lines
+            .iter()
+            .map(|tokens| tokens.iter().map(|(_, text)| text.len()).sum())
+            .collect(),
+        _ => return None,
+    };
+    let longest = sizes.iter().copied().max()?;
+    let total: usize = sizes.iter().sum();
+    // "Dominated": the longest line is at least 80% of the change.
+    (longest >= MINIFIED_LINE_BYTES && longest * 5 >= total * 4)
+        .then_some((sizes.len(), total as u64))
+}
+
+/// The `diff.<driver>.textconv` command for `path`, if its `diff`
+/// attribute names a driver that has one, with the driver's name.
+fn textconv_command(repo: &Repository, path: &[u8]) -> Option<(String, String)> {
+    let attr = repo
+        .get_attr(
+            &fs_path(path),
+            "diff",
+            git2::AttrCheckFlags::FILE_THEN_INDEX,
+        )
+        .ok()??;
+    let command = repo
+        .config()
+        .ok()?
+        .get_string(&format!("diff.{}.textconv", attr))
+        .ok()?;
+    Some((attr.to_string(), command))
+}
+
+/// How long a textconv command may run before the preview gives up on it.
+const TEXTCONV_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run `command` on `content`, saved to a temporary file named like `path`
+/// so converters that go by extension work, and return what it printed.
+fn run_textconv(command: &str, path: &[u8], content: &[u8]) -> Result<String> {
+    // A fresh directory only we can open, so the input can't be swapped for
+    // a symlink or read by other users.
+    let dir = tempfile::Builder::new()
+        .prefix("bgs-textconv-")
+        .tempdir()
+        .context("Failed to create textconv directory")?;
+    let mut temp = dir.path().join("input");
+    if let Some(extension) = fs_path(path).extension() {
+        temp.set_extension(extension);
+    }
+    std::fs::write(&temp, content).context("Failed to write textconv input")?;
+    let mut child = crate::commands::shell(&format!(
+        "{} {}",
+        command,
+        crate::commands::shell_quote(&temp.to_string_lossy())
+    ))
+    .stdin(std::process::Stdio::null())
+    .stdout(std::process::Stdio::piped())
+    .stderr(std::process::Stdio::null())
+    .spawn()
+    .with_context(|| format!("Failed to run textconv '{}'", command))?;
+
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = std::thread::spawn(move || {
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut stdout, &mut out).map(|_| out)
+    });
+    let deadline = Instant::now() + TEXTCONV_TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!(
+                "textconv '{}' timed out after {}s",
+                command,
+                TEXTCONV_TIMEOUT.as_secs()
+            );
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    let stdout = reader
+        .join()
+        .map_err(|_| anyhow::anyhow!("textconv reader panicked"))?
+        .with_context(|| format!("Failed to read textconv '{}' output", command))?;
+    if !status.success() {
+        bail!("textconv '{}' failed ({})", command, status);
+    }
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+/// `file`'s diff in `section` with both sides converted to text by its
+/// textconv driver: a text diff for tracked files, or a preview of the
+/// converted text for untracked ones. `None` when no driver applies or the
+/// conversion fails, leaving the binary view.
+pub fn get_textconv_diff(
+    repo: &Repository,
+    file: &FileEntry,
+    section: Section,
+    preview: PreviewConfig,
+) -> Option<DiffContent> {
+    let path = file.raw_path.as_slice();
+    let (driver, command) = textconv_command(repo, path)?;
+    let convert = |side_path: &[u8], side: Side| -> Result<String> {
+        match read_side(repo, side_path, side) {
+            Some((_, content)) => run_textconv(&command, side_path, &content),
+            None => Ok(String::new()),
+        }
+    };
+    let (old_side, new_side) = sides(section);
+    let converted = (|| -> Result<_> {
+        let new = convert(path, new_side)?;
+        if file.status == FileStatus::Untracked {
+            return Ok((None, new));
+        }
+        let old = convert(file.raw_old_path.as_deref().unwrap_or(path), old_side)?;
+        Ok((Some(old), new))
+    })();
+    let (old, new) = match converted {
+        Ok(sides) => sides,
+        Err(e) => {
+            tracing::warn!(error = %format!("{:#}", e), "textconv failed");
+            return None;
+        }
+    };
+
+    let display = display_path(path);
+    let Some(old) = old else {
+        let (_, lines) = crate::highlight::highlight(&display, &new);
+        return Some(DiffContent::Preview(FilePreview {
+            path: display,
+            size: read_side(repo, path, Side::Workdir).map_or(0, |(_, c)| c.len() as u64),
+            file_type: format!("Text via textconv ({})", driver),
+            lines,
+        }));
+    };
+
+    let mut patch = git2::Patch::from_buffers(
+        old.as_bytes(),
+        None,
+        new.as_bytes(),
+        None,
+        Some(&mut DiffOptions::new()),
+    )
+    .ok()?;
+    let mut lines = DiffLines::new(
+        vec![DiffLine {
+            kind: DiffLineKind::Header,
+            content: format!("Converted to text by textconv ({})", driver),
+            new_line_number: None,
+        }],
+        preview,
+    );
+    let mut current_new_line: Option<usize> = None;
+    patch
+        .print(&mut |_delta, hunk, line| {
+            let content = String::from_utf8_lossy(line.content())
+                .trim_end_matches('\n')
+                .to_string();
+            let mut next_line = || {
+                let n = current_new_line;
+                if let Some(ref mut n) = current_new_line {
+                    *n += 1;
+                }
+                n
+            };
+            let (kind, new_line_number) = match line.origin() {
+                'F' => return true,
+                'H' => {
+                    current_new_line = hunk.map(|h| h.new_start() as usize);
+                    (DiffLineKind::Hunk, None)
+                }
+                '+' => (DiffLineKind::Added, next_line()),
+                ' ' => (DiffLineKind::Context, next_line()),
+                '-' => (DiffLineKind::Deleted, None),
+                _ => (DiffLineKind::Header, None),
+            };
+            lines.push(DiffLine {
+                kind,
+                content,
+                new_line_number,
+            });
+            true
+        })
+        .ok()?;
+    let mut lines = lines.finish();
+    if lines.len() == 1 {
+        lines.push(DiffLine {
+            kind: DiffLineKind::Header,
+            content: "No changes in the converted text".to_string(),
+            new_line_number: None,
+        });
+    }
+    Some(DiffContent::Text(lines))
+}
+
+/// Hunk headers kept past the end of a truncated diff.
+const MAX_HUNK_INDEX: usize = 1000;
+
+/// A diff's lines, collected until they take `[preview] max_diff_kib` of
+/// memory. Past that only hunk headers are kept, as an index of the rest,
+/// and the other lines are just counted.
+struct DiffLines {
+    lines: Vec<DiffLine>,
+    bytes: usize,
+    limit: usize,
+    truncated: bool,
+    hunks_kept: usize,
+    hunks_skipped: usize,
+    lines_skipped: usize,
+}
+
+impl DiffLines {
+    fn new(lines: Vec<DiffLine>, preview: PreviewConfig) -> Self {
+        Self {
+            lines,
+            bytes: 0,
+            limit: preview.max_diff_kib * 1024,
+            truncated: false,
+            hunks_kept: 0,
+            hunks_skipped: 0,
+            lines_skipped: 0,
+        }
+    }
+
+    fn push(&mut self, line: DiffLine) {
+        let size = std::mem::size_of::<DiffLine>() + line.content.len();
+        if !self.truncated && self.bytes + size <= self.limit {
+            self.bytes += size;
+            self.lines.push(line);
+            return;
+        }
+        if !self.truncated {
+            self.truncated = true;
+            self.lines.push(DiffLine {
+                kind: DiffLineKind::Header,
+                content: format!(
+                    "Diff truncated at {} KiB; later hunks are listed without their lines",
+                    self.limit / 1024
+                ),
+                new_line_number: None,
+            });
+        }
+        if line.kind != DiffLineKind::Hunk {
+            self.lines_skipped += 1;
+        } else if self.hunks_kept < MAX_HUNK_INDEX {
+            self.hunks_kept += 1;
+            self.lines.push(line);
+        } else {
+            self.hunks_skipped += 1;
+        }
+    }
+
+    fn finish(mut self) -> Vec<DiffLine> {
+        if self.truncated {
+            let mut content = format!("Lines not shown: {}", self.lines_skipped);
+            if self.hunks_skipped > 0 {
+                content.push_str(&format!(", hunks not listed: {}", self.hunks_skipped));
+            }
+            self.lines.push(DiffLine {
+                kind: DiffLineKind::Header,
+                content,
+                new_line_number: None,
+            });
+        }
+        self.lines
+    }
+}
+
+pub fn get_diff(
+    repo: &Repository,
+    path: impl AsRef<[u8]>,
+    old_path: Option<&[u8]>,
+    section: Section,
+    flags: DiffFlags,
+    preview: PreviewConfig,
+) -> DiffContent {
+    let path = path.as_ref();
+    let mut opts = DiffOptions::new();
+    opts.pathspec(path);
+    opts.ignore_whitespace_eol(flags.ignore_eol);
+    if let Some(old) = old_path {
+        opts.pathspec(old);
+    }
+
+    let diff_result = match section {
+        Section::Staged => head_tree(repo)
+            .and_then(|tree| repo.diff_tree_to_index(tree.as_ref(), None, Some(&mut opts))),
+        Section::Unstaged => repo.diff_index_to_workdir(None, Some(&mut opts)),
+    };
+
+    let diff = match diff_result {
+        Ok(d) => d,
+        Err(_) => return DiffContent::Empty,
+    };
+
+    if let Some(delta) = diff.get_delta(0) {
+        let modes = [delta.old_file().mode(), delta.new_file().mode()];
+        let is_link_or_missing = |mode: &git2::FileMode| {
+            matches!(mode, git2::FileMode::Link | git2::FileMode::Unreadable)
+        };
+        if modes.contains(&git2::FileMode::Link) && modes.iter().all(is_link_or_missing) {
+            return get_symlink_change(repo, path, old_path, section);
+        }
+    }
+
+    let mut lines = DiffLines::new(Vec::new(), preview);
+    let mut current_new_line: Option<usize> = None;
+    let mut has_invalid_utf8 = false;
+    // Picked on the first line that isn't UTF-8, then used for the rest.
+    let mut encoding: Option<(&'static Encoding, String)> = None;
+
+    let result = diff.print(git2::DiffFormat::Patch, |_delta, hunk, line| {
+        let raw_content = match std::str::from_utf8(line.content()) {
+            Ok(s) => Cow::Borrowed(s),
+            Err(_) => {
+                let (encoding, _) = encoding.get_or_insert_with(|| {
+                    let (old_side, new_side) = sides(section);
+                    let sample = read_side(repo, path, new_side)
+                        .or_else(|| read_side(repo, old_path.unwrap_or(path), old_side))
+                        .map(|(_, bytes)| bytes)
+                        .unwrap_or_default();
+                    legacy_encoding(repo, path, &sample)
+                });
+                let (text, had_errors) = encoding.decode_without_bom_handling(line.content());
+                if had_errors {
+                    has_invalid_utf8 = true;
+                    return false;
+                }
+                text
             }
         };
 
@@ -431,6 +1683,17 @@ pub fn get_diff(
         true
     });
 
+    // libgit2 only sets the binary flag once it has loaded the content.
+    for delta_idx in 0..diff.deltas().len() {
+        if let Some(delta) = diff.get_delta(delta_idx) {
+            if delta.flags().is_binary() {
+                return DiffContent::Binary(get_binary_info(
+                    repo, path, old_path, section, preview,
+                ));
+            }
+        }
+    }
+
     if has_invalid_utf8 {
         return DiffContent::InvalidUtf8;
     }
@@ -439,6 +1702,59 @@ pub fn get_diff(
         return DiffContent::Empty;
     }
 
+    let mut lines = lines.finish();
+    let has_hunks = lines.iter().any(|l| l.kind == DiffLineKind::Hunk);
+    if flags.ignore_eol && !has_hunks && diff.deltas().len() > 0 {
+        lines.push(DiffLine {
+            kind: DiffLineKind::Header,
+            content: "Only line-ending or trailing-whitespace changes (hidden)".to_string(),
+            new_line_number: None,
+        });
+        return DiffContent::Text(lines);
+    }
+
+    if !has_hunks {
+        if let Some(delta) = diff.get_delta(0) {
+            let old_mode = u32::from(delta.old_file().mode());
+            let new_mode = u32::from(delta.new_file().mode());
+            if old_mode != 0 && new_mode != 0 && old_mode != new_mode {
+                return DiffContent::ModeChange { old_mode, new_mode };
+            }
+        }
+    }
+
+    let changed = |kind| {
+        lines
+            .iter()
+            .filter(|l| l.kind == kind)
+            .map(|l| l.content.as_bytes())
+            .collect::<Vec<_>>()
+    };
+    let eol_change = detect_eol_change(
+        &changed(DiffLineKind::Deleted),
+        &changed(DiffLineKind::Added),
+    );
+    if let Some((_, label)) = encoding {
+        lines.insert(
+            0,
+            DiffLine {
+                kind: DiffLineKind::Header,
+                content: format!("Encoding: {}", label),
+                new_line_number: None,
+            },
+        );
+    }
+    if let Some(change) = eol_change {
+        lines.insert(
+            0,
+            DiffLine {
+                kind: DiffLineKind::Header,
+                content: format!("Line endings changed: {}", change.label()),
+                new_line_number: None,
+            },
+        );
+    }
+
     if lines.is_empty() {
         DiffContent::Empty
     } else {
@@ -454,21 +1770,27 @@ pub fn get_diff(
 /// on individual paths and does not automatically handle the old_path of a rename.
 /// For full rename support, the caller should stage both the removal of the old path
 /// and addition of the new path. See Phase 13 for potential improvements.
-pub fn stage_files(repo: &Repository, paths: &[String]) -> Result<()> {
+pub fn stage_files<P: AsRef<[u8]>>(repo: &Repository, paths: &[P]) -> Result<()> {
+    tracing::debug!(paths = ?display_paths(paths), "stage files");
     let mut index = repo.index().context("Failed to get repository index")?;
-    let workdir = repo.workdir().context("Repository has no working directory")?;
+    let workdir = repo
+        .workdir()
+        .context("Repository has no working directory")?;
 
     for path in paths {
-        let full_path = workdir.join(path);
+        let path = path.as_ref();
+        let rel_path = fs_path(path);
+        let full_path = workdir.join(&rel_path);
 
-        if full_path.exists() {
+        // symlink_metadata so a dangling symlink is staged, not removed.
+        if std::fs::symlink_metadata(&full_path).is_ok() {
             index
-                .add_path(std::path::Path::new(path))
-                .with_context(|| format!("Failed to stage file: {}", path))?;
+                .add_path(&rel_path)
+                .with_context(|| format!("Failed to stage file: {}", display_path(path)))?;
         } else {
             index
-                .remove_path(std::path::Path::new(path))
-                .with_context(|| format!("Failed to stage deleted file: {}", path))?;
+                .remove_path(&rel_path)
+                .with_context(|| format!("Failed to stage deleted file: {}", display_path(path)))?;
         }
     }
 
@@ -484,14 +1806,17 @@ pub fn stage_files(repo: &Repository, paths: &[String]) -> Result<()> {
 /// NOTE: Renamed files are handled on a best-effort basis. This function operates
 /// on individual paths and does not automatically restore the old_path of a rename.
 /// See Phase 13 for potential improvements.
-pub fn unstage_files(repo: &Repository, paths: &[String]) -> Result<()> {
+pub fn unstage_files<P: AsRef<[u8]>>(repo: &Repository, paths: &[P]) -> Result<()> {
+    tracing::debug!(paths = ?display_paths(paths), "unstage files");
     let head = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
     let head_tree = head.as_ref().and_then(|c| c.tree().ok());
 
     let mut index = repo.index().context("Failed to get repository index")?;
 
     for path in paths {
-        let path_obj = std::path::Path::new(path);
+        let path = path.as_ref();
+        let path_obj = fs_path(path);
+        let path_obj = path_obj.as_path();
 
         if let Some(ref tree) = head_tree {
             if let Ok(entry) = tree.get_path(path_obj) {
@@ -510,20 +1835,23 @@ pub fn unstage_files(repo: &Repository, paths: &[String]) -> Result<()> {
                     id: entry.id(),
                     flags: 0,
                     flags_extended: 0,
-                    path: path.as_bytes().to_vec(),
+                    path: path.to_vec(),
                 };
                 index
                     .add(&entry)
-                    .with_context(|| format!("Failed to reset file: {}", path))?;
+                    .with_context(|| format!("Failed to reset file: {}", display_path(path)))?;
             } else {
-                index
-                    .remove_path(path_obj)
-                    .with_context(|| format!("Failed to remove new file from index: {}", path))?;
+                index.remove_path(path_obj).with_context(|| {
+                    format!(
+                        "Failed to remove new file from index: {}",
+                        display_path(path)
+                    )
+                })?;
             }
         } else {
-            index
-                .remove_path(path_obj)
-                .with_context(|| format!("Failed to remove file from index: {}", path))?;
+            index.remove_path(path_obj).with_context(|| {
+                format!("Failed to remove file from index: {}", display_path(path))
+            })?;
         }
     }
 
@@ -531,48 +1859,70 @@ pub fn unstage_files(repo: &Repository, paths: &[String]) -> Result<()> {
     Ok(())
 }
 
-pub fn stage_all(repo: &Repository) -> Result<Vec<String>> {
-    let status = get_status(repo)?;
-    let paths: Vec<String> = status.unstaged_files.into_iter().map(|f| f.path).collect();
-    if !paths.is_empty() {
-        stage_files(repo, &paths)?;
+pub fn stage_all(repo: &Repository) -> Result<Vec<Vec<u8>>> {
+    let status = get_status(repo, PreviewConfig::default())?;
+    let raw_paths: Vec<&[u8]> = status
+        .unstaged_files
+        .iter()
+        .map(|f| &f.raw_path[..])
+        .collect();
+    if !raw_paths.is_empty() {
+        stage_files(repo, &raw_paths)?;
     }
-    Ok(paths)
+    Ok(status
+        .unstaged_files
+        .into_iter()
+        .map(|f| f.raw_path)
+        .collect())
 }
 
-pub fn unstage_all(repo: &Repository) -> Result<Vec<String>> {
-    let status = get_status(repo)?;
-    let paths: Vec<String> = status.staged_files.into_iter().map(|f| f.path).collect();
-    if !paths.is_empty() {
-        unstage_files(repo, &paths)?;
+pub fn unstage_all(repo: &Repository) -> Result<Vec<Vec<u8>>> {
+    let status = get_status(repo, PreviewConfig::default())?;
+    let raw_paths: Vec<&[u8]> = status
+        .staged_files
+        .iter()
+        .map(|f| &f.raw_path[..])
+        .collect();
+    if !raw_paths.is_empty() {
+        unstage_files(repo, &raw_paths)?;
     }
-    Ok(paths)
+    Ok(status
+        .staged_files
+        .into_iter()
+        .map(|f| f.raw_path)
+        .collect())
+}
+
+pub(crate) fn display_paths<P: AsRef<[u8]>>(paths: &[P]) -> Vec<String> {
+    paths.iter().map(|p| display_path(p.as_ref())).collect()
 }
 
 /// Discard unstaged changes to a tracked file by restoring it from the index.
 ///
 /// This is equivalent to `git checkout -- <file>`.
-pub fn discard_unstaged_file(repo: &Repository, path: &str) -> Result<()> {
+pub fn discard_unstaged_file(repo: &Repository, path: impl AsRef<[u8]>) -> Result<()> {
+    let path = path.as_ref();
+    tracing::debug!(path = %display_path(path), "discard unstaged changes");
     repo.checkout_index(
         Some(&mut repo.index()?),
-        Some(
-            git2::build::CheckoutBuilder::new()
-                .force()
-                .path(path),
-        ),
+        Some(git2::build::CheckoutBuilder::new().force().path(path)),
     )
-    .with_context(|| format!("Failed to discard changes: {}", path))?;
+    .with_context(|| format!("Failed to discard changes: {}", display_path(path)))?;
     Ok(())
 }
 
 /// Delete an untracked file from the working directory.
 ///
 /// This is equivalent to `git clean -f <file>`.
-pub fn discard_untracked_file(repo: &Repository, path: &str) -> Result<()> {
-    let workdir = repo.workdir().context("Repository has no working directory")?;
-    let full_path = workdir.join(path);
+pub fn discard_untracked_file(repo: &Repository, path: impl AsRef<[u8]>) -> Result<()> {
+    let path = path.as_ref();
+    tracing::debug!(path = %display_path(path), "delete untracked file");
+    let workdir = repo
+        .workdir()
+        .context("Repository has no working directory")?;
+    let full_path = workdir.join(fs_path(path));
     std::fs::remove_file(&full_path)
-        .with_context(|| format!("Failed to delete untracked file: {}", path))?;
+        .with_context(|| format!("Failed to delete untracked file: {}", display_path(path)))?;
     Ok(())
 }
 
@@ -584,17 +1934,26 @@ pub fn discard_untracked_file(repo: &Repository, path: &str) -> Result<()> {
 ///
 /// Note: Currently unused but kept for potential future use cases.
 #[allow(dead_code)]
-pub fn discard_staged_file(repo: &Repository, path: &str) -> Result<()> {
-    unstage_files(repo, &[path.to_string()])?;
+pub fn discard_staged_file(repo: &Repository, path: impl AsRef<[u8]>) -> Result<()> {
+    unstage_files(repo, &[path])?;
     Ok(())
 }
 
 /// Discard all unstaged changes including untracked files.
 ///
 /// This restores all modified files from the index and deletes all untracked files.
-/// Conflicted files are skipped (they must be resolved separately).
+/// Conflicted files are skipped (they must be resolved separately). The
+/// files are backed up first; see [`backup_discard`].
 pub fn discard_all_unstaged(repo: &Repository) -> Result<(Vec<String>, usize)> {
-    let status = get_status(repo)?;
+    let status = get_status(repo, PreviewConfig::default())?;
+    let to_discard: Vec<&[u8]> = status
+        .unstaged_files
+        .iter()
+        .filter(|f| f.status != FileStatus::Conflict)
+        .map(|f| &f.raw_path[..])
+        .collect();
+    backup_discard(repo, &to_discard).context("Failed to back up files; nothing discarded")?;
+
     let mut discarded = Vec::new();
     let mut skipped_conflicts = 0;
 
@@ -604,9 +1963,9 @@ pub fn discard_all_unstaged(repo: &Repository) -> Result<(Vec<String>, usize)> {
             continue;
         }
         if file.status == FileStatus::Untracked {
-            discard_untracked_file(repo, &file.path)?;
+            discard_untracked_file(repo, &file.raw_path)?;
         } else {
-            discard_unstaged_file(repo, &file.path)?;
+            discard_unstaged_file(repo, &file.raw_path)?;
         }
         discarded.push(file.path.clone());
     }
@@ -614,66 +1973,1282 @@ pub fn discard_all_unstaged(repo: &Repository) -> Result<(Vec<String>, usize)> {
     Ok((discarded, skipped_conflicts))
 }
 
-pub fn get_untracked_diff(repo: &Repository, path: &str) -> DiffContent {
-    let workdir = match repo.workdir() {
-        Some(w) => w,
-        None => return DiffContent::Empty,
+/// What to do with a range of changed lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineOp {
+    /// Copy the changes from the working tree into the index.
+    Stage,
+    /// Take the changes back out of the index.
+    Unstage,
+    /// Revert the changes in the working tree.
+    Discard,
+}
+
+impl LineOp {
+    /// The section whose diff the lines come from.
+    pub fn section(self) -> Section {
+        match self {
+            LineOp::Stage | LineOp::Discard => Section::Unstaged,
+            LineOp::Unstage => Section::Staged,
+        }
+    }
+}
+
+/// One line of a patch, with what is needed to splice it into a file.
+struct PatchLine {
+    origin: char,
+    old_lineno: Option<u32>,
+    new_lineno: Option<u32>,
+    content: Vec<u8>,
+}
+
+/// Stage, unstage, or discard some of a file's changed lines. `selected`
+/// holds ordinals of `+`/`-` lines counted across the whole diff, in the
+/// order [`get_diff`] lists them. Returns how many lines were applied.
+pub fn apply_changed_lines(
+    repo: &Repository,
+    path: &[u8],
+    old_path: Option<&[u8]>,
+    selected: &[usize],
+    op: LineOp,
+) -> Result<usize> {
+    let mut opts = DiffOptions::new();
+    opts.pathspec(path);
+    if let Some(old) = old_path {
+        opts.pathspec(old);
+    }
+    let diff = match op.section() {
+        Section::Staged => {
+            let tree = head_tree(repo)?;
+            repo.diff_tree_to_index(tree.as_ref(), None, Some(&mut opts))?
+        }
+        Section::Unstaged => repo.diff_index_to_workdir(None, Some(&mut opts))?,
     };
 
-    let file_path = workdir.join(path);
-    let content = match std::fs::read(&file_path) {
-        Ok(c) => c,
-        Err(_) => return DiffContent::Empty,
+    let mut index = repo.index().context("Failed to get repository index")?;
+    let mut ordinal = 0;
+    let mut applied = 0;
+    for delta_idx in 0..diff.deltas().len() {
+        let Some(patch) = git2::Patch::from_diff(&diff, delta_idx)? else {
+            continue;
+        };
+        let delta = patch.delta();
+        let mut lines = Vec::new();
+        for hunk_idx in 0..patch.num_hunks() {
+            for line_idx in 0..patch.num_lines_in_hunk(hunk_idx)? {
+                let line = patch.line_in_hunk(hunk_idx, line_idx)?;
+                lines.push(PatchLine {
+                    origin: line.origin(),
+                    old_lineno: line.old_lineno(),
+                    new_lineno: line.new_lineno(),
+                    content: line.content().to_vec(),
+                });
+            }
+        }
+        let picks: Vec<bool> = lines
+            .iter()
+            .filter(|l| matches!(l.origin, '+' | '-'))
+            .map(|_| {
+                ordinal += 1;
+                selected.contains(&(ordinal - 1))
+            })
+            .collect();
+        let count = picks.iter().filter(|&&p| p).count();
+        if count == 0 {
+            continue;
+        }
+        applied += count;
+
+        let file_path = delta
+            .new_file()
+            .path_bytes()
+            .or(delta.old_file().path_bytes())
+            .context("Diff has no path")?
+            .to_vec();
+        if count == picks.len() {
+            // Every change picked: same as acting on the whole file.
+            index.write().context("Failed to write index")?;
+            match op {
+                LineOp::Stage => stage_files(repo, &[&file_path])?,
+                LineOp::Unstage => unstage_files(repo, &[&file_path])?,
+                LineOp::Discard => discard_unstaged_file(repo, &file_path)?,
+            }
+            index.read(true)?;
+            continue;
+        }
+
+        let new_mode = u32::from(delta.new_file().mode());
+        let old_mode = u32::from(delta.old_file().mode());
+        match op {
+            LineOp::Stage => {
+                let (mode, base) =
+                    read_side(repo, &file_path, Side::Index).unwrap_or((new_mode, Vec::new()));
+                let content = splice_changes(&base, &lines, &picks, true);
+                add_to_index(&mut index, &file_path, mode, &content)?;
+            }
+            LineOp::Unstage => {
+                let (mode, base) =
+                    read_side(repo, &file_path, Side::Index).unwrap_or((old_mode, Vec::new()));
+                let content = splice_changes(&base, &lines, &picks, false);
+                add_to_index(&mut index, &file_path, mode, &content)?;
+            }
+            LineOp::Discard => {
+                let base = read_side(repo, &file_path, Side::Workdir)
+                    .map(|(_, bytes)| bytes)
+                    .unwrap_or_default();
+                // The patch holds index-side (LF) text; restored lines take
+                // the line endings the checkout would give them.
+                if checkout_uses_crlf(repo, &file_path) {
+                    for line in &mut lines {
+                        line.content = to_crlf(&line.content);
+                    }
+                }
+                let content = splice_changes(&base, &lines, &picks, false);
+                let workdir = repo
+                    .workdir()
+                    .context("Repository has no working directory")?;
+                std::fs::write(workdir.join(fs_path(&file_path)), content).with_context(|| {
+                    format!("Failed to discard lines: {}", display_path(&file_path))
+                })?;
+            }
+        }
+    }
+
+    if op != LineOp::Discard {
+        index.write().context("Failed to write index")?;
+    }
+    Ok(applied)
+}
+
+/// Whether `path` is checked out with CRLF line endings, going by its
+/// `text` and `eol` attributes, then `core.autocrlf` and `core.eol`.
+fn checkout_uses_crlf(repo: &Repository, path: &[u8]) -> bool {
+    use git2::AttrValue;
+    let attr = |name| {
+        repo.get_attr_bytes(&fs_path(path), name, git2::AttrCheckFlags::FILE_THEN_INDEX)
+            .map(AttrValue::from_bytes)
+            .unwrap_or(AttrValue::Unspecified)
+    };
+    let text = attr("text");
+    if text == AttrValue::False {
+        return false;
+    }
+    match attr("eol") {
+        AttrValue::String("crlf") => return true,
+        AttrValue::String("lf") => return false,
+        _ => {}
+    }
+    let Ok(config) = repo.config() else {
+        return false;
     };
+    let autocrlf = config.get_string("core.autocrlf").unwrap_or_default();
+    let eol = config.get_string("core.eol").unwrap_or_default();
+    match autocrlf.as_str() {
+        "true" => true,
+        "input" => false,
+        _ => text != AttrValue::Unspecified && eol == "crlf",
+    }
+}
 
-    let text = match std::str::from_utf8(&content) {
-        Ok(t) => t,
-        Err(_) => return DiffContent::InvalidUtf8,
+/// `line` with an LF ending turned into CRLF.
+fn to_crlf(line: &[u8]) -> Vec<u8> {
+    match line.strip_suffix(b"\n") {
+        Some(body) if !body.ends_with(b"\r") => [body, b"\r\n"].concat(),
+        _ => line.to_vec(),
+    }
+}
+
+/// Rebuild a file from `base` with the `picks`ed changes of `lines`
+/// applied (`forward`, `base` is the old side) or reverted (`base` is the
+/// new side). Unpicked changes leave `base` as it was.
+fn splice_changes(base: &[u8], lines: &[PatchLine], picks: &[bool], forward: bool) -> Vec<u8> {
+    fn push_line(out: &mut Vec<u8>, line: &[u8]) {
+        if !out.is_empty() && !out.ends_with(b"\n") {
+            out.push(b'\n');
+        }
+        out.extend_from_slice(line);
+    }
+
+    let base_lines: Vec<&[u8]> = base.split_inclusive(|&b| b == b'\n').collect();
+    let (kept, inserted) = if forward { ('-', '+') } else { ('+', '-') };
+    let mut out = Vec::with_capacity(base.len());
+    let mut next = 0;
+    let copy_until = |out: &mut Vec<u8>, next: &mut usize, end: usize| {
+        while *next < end.min(base_lines.len()) {
+            push_line(out, base_lines[*next]);
+            *next += 1;
+        }
     };
 
-    let mut lines = Vec::new();
+    let mut picks = picks.iter();
+    for line in lines {
+        // 1-based line number in `base`, if the line exists there.
+        let base_lineno = if forward {
+            line.old_lineno
+        } else {
+            line.new_lineno
+        };
+        if line.origin == ' ' {
+            if let Some(n) = base_lineno {
+                copy_until(&mut out, &mut next, n as usize);
+            }
+        } else if line.origin == kept {
+            let picked = *picks.next().unwrap_or(&false);
+            if let Some(n) = base_lineno {
+                copy_until(&mut out, &mut next, n as usize - 1);
+                if !picked {
+                    copy_until(&mut out, &mut next, n as usize);
+                }
+                next = next.max(n as usize);
+            }
+        } else if line.origin == inserted && *picks.next().unwrap_or(&false) {
+            push_line(&mut out, &line.content);
+        }
+    }
+    copy_until(&mut out, &mut next, usize::MAX);
+    out
+}
 
-    lines.push(DiffLine {
-        kind: DiffLineKind::Header,
-        content: format!("diff --git a/{} b/{}", path, path),
-        new_line_number: None,
-    });
-    lines.push(DiffLine {
-        kind: DiffLineKind::Header,
-        content: "new file".to_string(),
-        new_line_number: None,
-    });
-    lines.push(DiffLine {
-        kind: DiffLineKind::Header,
-        content: "--- /dev/null".to_string(),
-        new_line_number: None,
-    });
-    lines.push(DiffLine {
-        kind: DiffLineKind::Header,
-        content: format!("+++ b/{}", path),
-        new_line_number: None,
-    });
+fn add_to_index(index: &mut git2::Index, path: &[u8], mode: u32, content: &[u8]) -> Result<()> {
+    let entry = git2::IndexEntry {
+        ctime: git2::IndexTime::new(0, 0),
+        mtime: git2::IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode,
+        uid: 0,
+        gid: 0,
+        file_size: content.len() as u32,
+        id: git2::Oid::zero(),
+        flags: 0,
+        flags_extended: 0,
+        path: path.to_vec(),
+    };
+    index
+        .add_frombuffer(&entry, content)
+        .with_context(|| format!("Failed to update index: {}", display_path(path)))
+}
 
-    let text_lines: Vec<&str> = text.lines().collect();
-    let line_count = text_lines.len();
+/// List configured remotes with their URLs and tracking state.
+pub fn list_remotes(repo: &Repository) -> Result<Vec<RemoteInfo>> {
+    let upstream_remote = current_upstream_remote(repo);
+    let names = repo.remotes().context("Failed to list remotes")?;
+
+    let mut remotes = Vec::new();
+    for name in names.iter().flatten() {
+        let remote = repo
+            .find_remote(name)
+            .with_context(|| format!("Failed to read remote: {}", name))?;
+        let fetch_url = remote.url().map(|u| u.to_string());
+        let push_url = remote
+            .pushurl()
+            .map(|u| u.to_string())
+            .filter(|u| Some(u) != fetch_url.as_ref());
+        let prefix = format!("refs/remotes/{}/", name);
+        let tracking_branches = repo
+            .references_glob(&format!("{}*", prefix))
+            .map(|refs| {
+                refs.flatten()
+                    .filter(|r| r.name().is_some_and(|n| !n.ends_with("/HEAD")))
+                    .count()
+            })
+            .unwrap_or(0);
+        remotes.push(RemoteInfo {
+            name: name.to_string(),
+            fetch_url,
+            push_url,
+            tracking_branches,
+            is_upstream: upstream_remote.as_deref() == Some(name),
+        });
+    }
+    Ok(remotes)
+}
 
-    if line_count > 0 {
-        lines.push(DiffLine {
-            kind: DiffLineKind::Hunk,
-            content: format!("@@ -0,0 +1,{} @@", line_count),
-            new_line_number: None,
+fn current_upstream_remote(repo: &Repository) -> Option<String> {
+    let head = repo.head().ok()?;
+    let refname = head.name()?;
+    let buf = repo.branch_upstream_remote(refname).ok()?;
+    buf.as_str().map(|s| s.to_string())
+}
+
+/// Add a remote with the default fetch refspec.
+pub fn add_remote(repo: &Repository, name: &str, url: &str) -> Result<()> {
+    repo.remote(name, url)
+        .with_context(|| format!("Failed to add remote: {}", name))?;
+    Ok(())
+}
+
+/// Remove a remote along with its remote-tracking branches and config.
+pub fn remove_remote(repo: &Repository, name: &str) -> Result<()> {
+    repo.remote_delete(name)
+        .with_context(|| format!("Failed to remove remote: {}", name))?;
+    Ok(())
+}
+
+/// Rename a remote, updating its refspecs and remote-tracking branches.
+///
+/// Returns any non-default refspecs git could not rewrite automatically.
+pub fn rename_remote(repo: &Repository, old: &str, new: &str) -> Result<Vec<String>> {
+    let problems = repo
+        .remote_rename(old, new)
+        .with_context(|| format!("Failed to rename remote {} to {}", old, new))?;
+    Ok(problems.iter().flatten().map(|s| s.to_string()).collect())
+}
+
+/// Set the current branch's upstream to `<remote>/<branch>`.
+///
+/// The configuration is written directly so the upstream can be set before
+/// the remote branch has been fetched. Remote names containing `/` are
+/// matched against the configured remotes, longest first.
+pub fn set_upstream(repo: &Repository, upstream: &str) -> Result<()> {
+    let head = repo.head().context("Failed to read HEAD")?;
+    if !head.is_branch() {
+        bail!("Cannot set upstream on a detached HEAD");
+    }
+    let branch = head
+        .shorthand()
+        .context("Current branch name is not valid UTF-8")?
+        .to_string();
+
+    let remotes = repo.remotes().context("Failed to list remotes")?;
+    let mut names: Vec<&str> = remotes.iter().flatten().collect();
+    names.sort_by_key(|n| std::cmp::Reverse(n.len()));
+    let (remote, remote_branch) = names
+        .iter()
+        .find_map(|name| {
+            upstream
+                .strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('/'))
+                .filter(|rest| !rest.is_empty())
+                .map(|rest| (*name, rest))
+        })
+        .with_context(|| format!("No remote matches upstream: {}", upstream))?;
+
+    let mut config = repo.config().context("Failed to open config")?;
+    config
+        .set_str(&format!("branch.{}.remote", branch), remote)
+        .context("Failed to set branch remote")?;
+    config
+        .set_str(
+            &format!("branch.{}.merge", branch),
+            &format!("refs/heads/{}", remote_branch),
+        )
+        .context("Failed to set branch merge ref")?;
+    Ok(())
+}
+
+/// Result of a pull that completed without error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PullOutcome {
+    UpToDate,
+    FastForwarded,
+}
+
+/// The remote used for network operations: the upstream's remote if set,
+/// otherwise `origin`.
+fn network_remote(repo: &Repository) -> Result<String> {
+    if let Some(remote) = current_upstream_remote(repo) {
+        return Ok(remote);
+    }
+    if repo.find_remote("origin").is_ok() {
+        return Ok("origin".to_string());
+    }
+    bail!("No remote named 'origin'; configure one with R")
+}
+
+/// Convert a libgit2 network error, surfacing `AuthRequired` when the
+/// credential callbacks ran out of sources and need a username/password.
+fn check_auth<T>(
+    result: std::result::Result<T, git2::Error>,
+    creds: &Credentials,
+    url: &str,
+) -> Result<T> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(_) if creds.needs_userpass => Err(AuthRequired {
+            url: url.to_string(),
+        }
+        .into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Receives transfer progress during a network operation. Returning `false`
+/// asks the operation to stop as soon as libgit2 gives us the chance.
+pub type ProgressFn<'a> = dyn FnMut(TransferProgress) -> bool + 'a;
+
+/// Shares one progress callback between several libgit2 callbacks and
+/// remembers whether it asked to cancel.
+struct ProgressRelay<'p, 'f> {
+    progress: std::cell::RefCell<&'p mut ProgressFn<'f>>,
+    cancelled: std::cell::Cell<bool>,
+}
+
+impl<'p, 'f> ProgressRelay<'p, 'f> {
+    fn new(progress: &'p mut ProgressFn<'f>) -> Self {
+        Self {
+            progress: std::cell::RefCell::new(progress),
+            cancelled: std::cell::Cell::new(false),
+        }
+    }
+
+    fn report(&self, progress: TransferProgress) -> bool {
+        if !self.cancelled.get() && !(self.progress.borrow_mut())(progress) {
+            self.cancelled.set(true);
+        }
+        !self.cancelled.get()
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.get()
+    }
+}
+
+fn receive_progress(stats: &git2::Progress) -> TransferProgress {
+    if stats.received_objects() < stats.total_objects() {
+        TransferProgress {
+            phase: TransferPhase::Receiving,
+            current: stats.received_objects(),
+            total: stats.total_objects(),
+            bytes: stats.received_bytes(),
+        }
+    } else {
+        TransferProgress {
+            phase: TransferPhase::Resolving,
+            current: stats.indexed_deltas(),
+            total: stats.total_deltas(),
+            bytes: 0,
+        }
+    }
+}
+
+/// Fetch from the current branch's remote. Returns the remote name.
+pub fn fetch(
+    repo: &Repository,
+    creds: &mut Credentials,
+    progress: &mut ProgressFn,
+) -> Result<String> {
+    let remote_name = network_remote(repo)?;
+    let mut remote = repo
+        .find_remote(&remote_name)
+        .with_context(|| format!("Failed to find remote: {}", remote_name))?;
+    let url = remote.url().unwrap_or_default().to_string();
+    let config = repo.config().context("Failed to open config")?;
+
+    let relay = ProgressRelay::new(progress);
+    let result = {
+        let mut callbacks = creds.callbacks(&config);
+        callbacks.transfer_progress(|stats| relay.report(receive_progress(&stats)));
+        callbacks.sideband_progress(|_| !relay.is_cancelled());
+        let mut opts = git2::FetchOptions::new();
+        opts.remote_callbacks(callbacks);
+        remote.fetch::<&str>(&[], Some(&mut opts), None)
+    };
+    check_auth(result, creds, &url).with_context(|| format!("Failed to fetch {}", remote_name))?;
+    Ok(remote_name)
+}
+
+/// Push the current branch to its upstream, setting `origin/<branch>` as the
+/// upstream when none is configured. Returns the destination (e.g. "origin/main").
+///
+/// Cancellation is honored until the upload starts; git2 offers no way to
+/// abort a pack that is already being sent.
+pub fn push(
+    repo: &Repository,
+    creds: &mut Credentials,
+    progress: &mut ProgressFn,
+) -> Result<String> {
+    let head = repo.head().context("Failed to read HEAD")?;
+    if !head.is_branch() {
+        bail!("Cannot push from detached HEAD; create a branch first");
+    }
+    let branch = head
+        .shorthand()
+        .context("Current branch name is not valid UTF-8")?
+        .to_string();
+    let head_name = head.name().unwrap_or_default().to_string();
+
+    let upstream_merge = repo
+        .branch_upstream_merge(&head_name)
+        .ok()
+        .and_then(|buf| buf.as_str().map(|s| s.to_string()));
+    let (remote_name, remote_branch, has_upstream) =
+        match (current_upstream_remote(repo), upstream_merge) {
+            (Some(remote), Some(merge)) => {
+                let remote_branch = merge.trim_start_matches("refs/heads/").to_string();
+                (remote, remote_branch, true)
+            }
+            _ => (network_remote(repo)?, branch.clone(), false),
+        };
+
+    let mut remote = repo
+        .find_remote(&remote_name)
+        .with_context(|| format!("Failed to find remote: {}", remote_name))?;
+    let url = remote.url().unwrap_or_default().to_string();
+    let config = repo.config().context("Failed to open config")?;
+    let refspec = format!("refs/heads/{}:refs/heads/{}", branch, remote_branch);
+
+    let relay = ProgressRelay::new(progress);
+    let rejection = std::cell::RefCell::new(None);
+    let result = {
+        let mut callbacks = creds.callbacks(&config);
+        callbacks.pack_progress(|_, current, total| {
+            relay.report(TransferProgress {
+                phase: TransferPhase::Packing,
+                current,
+                total,
+                bytes: 0,
+            });
+        });
+        callbacks.push_transfer_progress(|current, total, bytes| {
+            relay.report(TransferProgress {
+                phase: TransferPhase::Uploading,
+                current,
+                total,
+                bytes,
+            });
+        });
+        callbacks.sideband_progress(|_| !relay.is_cancelled());
+        callbacks.push_negotiation(|_| {
+            if relay.is_cancelled() {
+                Err(git2::Error::from_str("push cancelled"))
+            } else {
+                Ok(())
+            }
         });
+        callbacks.push_update_reference(|refname, status| {
+            if let Some(message) = status {
+                *rejection.borrow_mut() = Some(format!("{} rejected: {}", refname, message));
+            }
+            Ok(())
+        });
+        let mut opts = git2::PushOptions::new();
+        opts.remote_callbacks(callbacks);
+        remote.push(&[refspec.as_str()], Some(&mut opts))
+    };
+    check_auth(result, creds, &url)
+        .with_context(|| format!("Failed to push to {}", remote_name))?;
+    if let Some(message) = rejection.into_inner() {
+        bail!(message);
+    }
 
-        for (i, line) in text_lines.iter().enumerate() {
-            lines.push(DiffLine {
-                kind: DiffLineKind::Added,
-                content: line.to_string(),
-                new_line_number: Some(i + 1),
+    let destination = format!("{}/{}", remote_name, remote_branch);
+    if !has_upstream {
+        set_upstream(repo, &destination)?;
+    }
+    Ok(destination)
+}
+
+/// Fetch the upstream and fast-forward the current branch to it.
+///
+/// Diverged branches are refused rather than merged; the working tree is
+/// updated with a safe checkout so local modifications are never clobbered.
+pub fn pull(
+    repo: &Repository,
+    creds: &mut Credentials,
+    progress: &mut ProgressFn,
+) -> Result<PullOutcome> {
+    if current_upstream_remote(repo).is_none() {
+        bail!("No upstream configured; set one with R or push first");
+    }
+    fetch(repo, creds, progress)?;
+
+    let (name, _, upstream_oid) =
+        upstream_oids(repo).context("Upstream branch has not been fetched")?;
+    let annotated = repo
+        .find_annotated_commit(upstream_oid)
+        .context("Failed to read upstream commit")?;
+    let (analysis, _) = repo
+        .merge_analysis(&[&annotated])
+        .context("Failed to analyze merge")?;
+
+    if analysis.is_up_to_date() {
+        return Ok(PullOutcome::UpToDate);
+    }
+    if !analysis.is_fast_forward() {
+        bail!(
+            "Branch has diverged from {}; merge or rebase with git",
+            name
+        );
+    }
+
+    let target = repo
+        .find_commit(upstream_oid)
+        .context("Failed to read upstream commit")?;
+    repo.checkout_tree(
+        target.as_object(),
+        Some(git2::build::CheckoutBuilder::new().safe()),
+    )
+    .context("Failed to update working tree; commit or stash local changes")?;
+    repo.head()?
+        .set_target(upstream_oid, &format!("pull: fast-forward to {}", name))
+        .context("Failed to update branch")?;
+    Ok(PullOutcome::FastForwarded)
+}
+
+/// Stashes, newest first, with the files each one changed.
+pub fn list_stashes(repo: &Repository) -> Result<Vec<StashInfo>> {
+    if repo.find_reference("refs/stash").is_err() {
+        return Ok(Vec::new());
+    }
+    let reflog = repo
+        .reflog("refs/stash")
+        .context("Failed to read stash list")?;
+    reflog
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let commit = repo
+                .find_commit(entry.id_new())
+                .with_context(|| format!("Failed to read stash@{{{}}}", index))?;
+            Ok(StashInfo {
+                index,
+                message: entry.message().unwrap_or_default().to_string(),
+                files: stash_files(repo, &commit)?,
+            })
+        })
+        .collect()
+}
+
+/// The tree of a stash's untracked files, from `git stash -u`.
+fn stash_untracked_tree<'r>(commit: &git2::Commit<'r>) -> Option<git2::Tree<'r>> {
+    commit.parent(2).ok()?.tree().ok()
+}
+
+/// Paths a stash commit changed from the commit it was made on, plus any
+/// untracked files it saved, sorted.
+fn stash_files(repo: &Repository, commit: &git2::Commit) -> Result<Vec<String>> {
+    let base = commit.parent(0)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&base), Some(&commit.tree()?), None)?;
+    let mut files: Vec<String> = diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().or(delta.old_file().path()))
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    if let Some(untracked) = stash_untracked_tree(commit) {
+        untracked.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(git2::ObjectType::Blob) {
+                files.push(format!("{}{}", dir, entry.name().unwrap_or_default()));
+            }
+            git2::TreeWalkResult::Ok
+        })?;
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Restore `paths` to how stash `index` left them, like `git checkout
+/// stash@{n} -- <paths>` but leaving the index alone. Untracked files the
+/// stash saved are restored too. Nothing is overwritten: a path with local
+/// changes fails the restore.
+pub fn restore_stash_files(repo: &Repository, index: usize, paths: &[String]) -> Result<()> {
+    let commit = repo
+        .revparse_single(&format!("stash@{{{}}}", index))
+        .and_then(|object| object.peel_to_commit())
+        .with_context(|| format!("Failed to read stash@{{{}}}", index))?;
+    let untracked = stash_untracked_tree(&commit);
+    let (untracked_paths, tracked_paths): (Vec<&String>, Vec<&String>) =
+        paths.iter().partition(|path| {
+            untracked
+                .as_ref()
+                .is_some_and(|tree| tree.get_path(std::path::Path::new(path)).is_ok())
+        });
+    let trees = [(commit.tree()?, tracked_paths)]
+        .into_iter()
+        .chain(untracked.map(|tree| (tree, untracked_paths)));
+    for (tree, paths) in trees {
+        if paths.is_empty() {
+            continue;
+        }
+        let mut checkout = git2::build::CheckoutBuilder::new();
+        checkout.safe().update_index(false).recreate_missing(true);
+        for path in paths {
+            checkout.path(path);
+        }
+        repo.checkout_tree(tree.as_object(), Some(&mut checkout))
+            .context("Failed to restore from stash; commit or stash local changes")?;
+    }
+    Ok(())
+}
+
+/// Starts the message of the stash entries made by [`backup_discard`], so
+/// the recover panel can tell them from the user's own stashes.
+const DISCARD_STASH_MESSAGE: &str = "better-git-status discard: ";
+
+/// Lines of each backed up file shown in the recover panel.
+const BACKUP_PREVIEW_LINES: usize = 8;
+
+/// Save the working tree content of `paths` as a stash entry before they
+/// are discarded, so they can be restored from the recover panel or with
+/// `git stash apply`. Unlike `git stash` only `paths` are saved and the
+/// working tree is left alone: tracked files go in the stash's working
+/// tree commit and untracked ones in its third parent, as `git stash -u`
+/// lays them out. Files missing from the working tree, and submodules,
+/// have nothing to lose and are left out. Returns whether anything was
+/// saved.
+pub fn backup_discard<P: AsRef<[u8]>>(repo: &Repository, paths: &[P]) -> Result<bool> {
+    let signature = repo
+        .signature()
+        .or_else(|_| git2::Signature::now("better-git-status", "better-git-status@localhost"))?;
+    let base = match repo.head().and_then(|head| head.peel_to_commit()) {
+        Ok(commit) => commit,
+        // A stash needs a commit to sit on; an unborn branch gets an empty one.
+        Err(_) => {
+            let empty = repo.find_tree(repo.treebuilder(None)?.write()?)?;
+            let oid = repo.commit(None, &signature, &signature, "empty", &empty, &[])?;
+            repo.find_commit(oid)?
+        }
+    };
+    // A conflicted index can't be written as a tree, so the stash's index
+    // commit falls back to HEAD's.
+    let index_tree = match repo.index()?.write_tree() {
+        Ok(oid) => repo.find_tree(oid)?,
+        Err(_) => base.tree()?,
+    };
+
+    let mut tracked = git2::Index::new()?;
+    tracked.read_tree(&index_tree)?;
+    let mut untracked = git2::Index::new()?;
+    let mut saved = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        let Some((mode, content)) = read_side(repo, path, Side::Workdir) else {
+            continue;
+        };
+        if mode == u32::from(git2::FileMode::Commit) {
+            continue;
+        }
+        let entry = git2::IndexEntry {
+            ctime: git2::IndexTime::new(0, 0),
+            mtime: git2::IndexTime::new(0, 0),
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            file_size: content.len() as u32,
+            id: repo.blob(&content)?,
+            flags: 0,
+            flags_extended: 0,
+            path: path.to_vec(),
+        };
+        let target = if index_tree.get_path(&fs_path(path)).is_ok() {
+            &mut tracked
+        } else {
+            &mut untracked
+        };
+        target
+            .add(&entry)
+            .with_context(|| format!("Failed to back up {}", display_path(path)))?;
+        saved.push(path);
+    }
+    if saved.is_empty() {
+        return Ok(false);
+    }
+
+    let branch = match repo.head() {
+        Ok(head) if head.is_branch() => head.shorthand().unwrap_or_default().to_string(),
+        Ok(_) => "(no branch)".to_string(),
+        Err(_) => unborn_branch(repo).unwrap_or_default(),
+    };
+    let on = format!(
+        "{}: {} {}",
+        branch,
+        &base.id().to_string()[..7],
+        base.summary().unwrap_or_default()
+    );
+    let index_commit = repo.commit(
+        None,
+        &signature,
+        &signature,
+        &format!("index on {}", on),
+        &index_tree,
+        &[&base],
+    )?;
+    let mut parents = vec![base, repo.find_commit(index_commit)?];
+    if !untracked.is_empty() {
+        let tree = repo.find_tree(untracked.write_tree_to(repo)?)?;
+        let oid = repo.commit(
+            None,
+            &signature,
+            &signature,
+            &format!("untracked files on {}", on),
+            &tree,
+            &[],
+        )?;
+        parents.push(repo.find_commit(oid)?);
+    }
+
+    let message = format!(
+        "On {}: {}{}",
+        branch,
+        DISCARD_STASH_MESSAGE,
+        display_paths(&saved).join(", ")
+    );
+    let tree = repo.find_tree(tracked.write_tree_to(repo)?)?;
+    let parents: Vec<&git2::Commit> = parents.iter().collect();
+    let oid = repo.commit(None, &signature, &signature, &message, &tree, &parents)?;
+    repo.reference_ensure_log("refs/stash")?;
+    repo.reference("refs/stash", oid, true, &message)
+        .context("Failed to record discard backup")?;
+    tracing::debug!(%oid, files = saved.len(), "stashed files before discarding");
+    Ok(true)
+}
+
+/// The stash entries made by [`backup_discard`], newest first, with a
+/// preview of each saved file.
+pub fn list_discard_backups(repo: &Repository) -> Result<Vec<DiscardBackup>> {
+    if repo.find_reference("refs/stash").is_err() {
+        return Ok(Vec::new());
+    }
+    let reflog = repo
+        .reflog("refs/stash")
+        .context("Failed to read stash list")?;
+    reflog
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| {
+            entry
+                .message()
+                .is_some_and(|m| m.contains(DISCARD_STASH_MESSAGE))
+        })
+        .map(|(index, entry)| {
+            let commit = repo
+                .find_commit(entry.id_new())
+                .with_context(|| format!("Failed to read stash@{{{}}}", index))?;
+            let seconds = u64::try_from(commit.time().seconds()).unwrap_or(0);
+            Ok(DiscardBackup {
+                index,
+                time: SystemTime::UNIX_EPOCH + Duration::from_secs(seconds),
+                files: backup_files(repo, &commit)?,
+            })
+        })
+        .collect()
+}
+
+/// The trees a discard stash saved files in: its working tree commit's,
+/// with the files the index commit already had taken out, and its
+/// untracked files' tree.
+fn backup_trees<'r>(
+    repo: &'r Repository,
+    commit: &git2::Commit<'r>,
+) -> Result<Vec<(git2::Tree<'r>, Vec<Vec<u8>>)>> {
+    let tree = commit.tree()?;
+    let index_tree = commit.parent(1)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&index_tree), Some(&tree), None)?;
+    let changed = diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path_bytes().map(<[u8]>::to_vec))
+        .collect();
+    let mut trees = vec![(tree, changed)];
+    if let Some(untracked) = stash_untracked_tree(commit) {
+        let mut paths = Vec::new();
+        untracked.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(git2::ObjectType::Blob) {
+                let mut path = dir.as_bytes().to_vec();
+                path.extend_from_slice(entry.name_bytes());
+                paths.push(path);
+            }
+            git2::TreeWalkResult::Ok
+        })?;
+        trees.push((untracked, paths));
+    }
+    Ok(trees)
+}
+
+/// The files a discard stash saved, in path order.
+fn backup_files(repo: &Repository, commit: &git2::Commit) -> Result<Vec<BackupFile>> {
+    let mut files = Vec::new();
+    for (tree, paths) in backup_trees(repo, commit)? {
+        for raw_path in paths {
+            let entry = tree.get_path(&fs_path(&raw_path))?;
+            let preview = repo.find_blob(entry.id()).ok().and_then(|blob| {
+                let content = blob.content();
+                (!looks_binary(content, PreviewConfig::default())).then(|| {
+                    String::from_utf8_lossy(content)
+                        .lines()
+                        .take(BACKUP_PREVIEW_LINES)
+                        .map(str::to_string)
+                        .collect()
+                })
+            });
+            files.push(BackupFile {
+                path: display_path(&raw_path),
+                raw_path,
+                preview,
             });
         }
     }
+    files.sort_by(|a, b| a.raw_path.cmp(&b.raw_path));
+    Ok(files)
+}
+
+/// Write `paths` back to the working tree as the discard stash
+/// `stash@{index}` saved them, leaving the index and the stash alone. A
+/// file that has changed since it was discarded is not overwritten.
+pub fn restore_discard_backup<P: AsRef<[u8]>>(
+    repo: &Repository,
+    index: usize,
+    paths: &[P],
+) -> Result<()> {
+    let commit = repo
+        .revparse_single(&format!("stash@{{{}}}", index))
+        .and_then(|object| object.peel_to_commit())
+        .with_context(|| format!("Failed to read stash@{{{}}}", index))?;
+    let trees = backup_trees(repo, &commit)?;
+    let workdir = repo
+        .workdir()
+        .context("Repository has no working directory")?;
+
+    for path in paths {
+        let path = path.as_ref();
+        let entry = trees
+            .iter()
+            .filter(|(_, saved)| saved.iter().any(|p| p == path))
+            .find_map(|(tree, _)| tree.get_path(&fs_path(path)).ok())
+            .with_context(|| format!("{} is not in this backup", display_path(path)))?;
+        let blob = repo.find_blob(entry.id())?;
+        let mode = entry.filemode() as u32;
+        if read_side(repo, path, Side::Workdir).is_some_and(|current| {
+            current != (mode, blob.content().to_vec()) && has_local_changes(repo, path)
+        }) {
+            bail!(
+                "{} has changed since it was discarded; discard or stash that first",
+                display_path(path)
+            );
+        }
+        let full_path = workdir.join(fs_path(path));
+        write_workdir_file(&full_path, mode, blob.content())
+            .with_context(|| format!("Failed to recover {}", display_path(path)))?;
+    }
+    Ok(())
+}
+
+/// Whether the working tree file at `path` differs from the index, or
+/// isn't tracked at all.
+fn has_local_changes(repo: &Repository, path: &[u8]) -> bool {
+    repo.status_file(&fs_path(path)).map_or(true, |status| {
+        status.intersects(
+            Status::WT_NEW
+                | Status::WT_MODIFIED
+                | Status::WT_TYPECHANGE
+                | Status::WT_RENAMED
+                | Status::CONFLICTED,
+        )
+    })
+}
+
+/// Replace the file at `full_path` with `content`, as a symlink or an
+/// executable when `mode` says so.
+fn write_workdir_file(full_path: &std::path::Path, mode: u32, content: &[u8]) -> Result<()> {
+    if let Some(parent) = full_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if std::fs::symlink_metadata(full_path).is_ok() {
+        std::fs::remove_file(full_path)?;
+    }
+    #[cfg(unix)]
+    if mode == u32::from(git2::FileMode::Link) {
+        std::os::unix::fs::symlink(fs_path(content), full_path)?;
+        return Ok(());
+    }
+    std::fs::write(full_path, content)?;
+    #[cfg(unix)]
+    if mode == u32::from(git2::FileMode::BlobExecutable) {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(full_path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// Where to read one side of a change from.
+#[derive(Debug, Clone, Copy)]
+enum Side {
+    Head,
+    Index,
+    Workdir,
+}
+
+/// The two sides compared by `section`'s diff.
+fn sides(section: Section) -> (Side, Side) {
+    match section {
+        Section::Staged => (Side::Head, Side::Index),
+        Section::Unstaged => (Side::Index, Side::Workdir),
+    }
+}
+
+/// Mode and raw content of `path` on one side, or `None` if it doesn't
+/// exist there. A symlink's content is its target; a submodule's is empty.
+fn read_side(repo: &Repository, path: &[u8], side: Side) -> Option<(u32, Vec<u8>)> {
+    let blob = |mode: u32, oid: git2::Oid| {
+        if mode == u32::from(git2::FileMode::Commit) {
+            return Some((mode, Vec::new()));
+        }
+        let blob = repo.find_blob(oid).ok()?;
+        Some((mode, blob.content().to_vec()))
+    };
+    match side {
+        Side::Head => {
+            let tree = head_tree(repo).ok()??;
+            let entry = tree.get_path(&fs_path(path)).ok()?;
+            blob(entry.filemode() as u32, entry.id())
+        }
+        Side::Index => {
+            let index = repo.index().ok()?;
+            let entry = index.get_path(&fs_path(path), 0)?;
+            blob(entry.mode, entry.id)
+        }
+        Side::Workdir => {
+            let full = repo.workdir()?.join(fs_path(path));
+            let meta = std::fs::symlink_metadata(&full).ok()?;
+            if meta.file_type().is_symlink() {
+                let target = std::fs::read_link(&full).ok()?;
+                let target = target.to_string_lossy().into_owned();
+                Some((u32::from(git2::FileMode::Link), target.into_bytes()))
+            } else if meta.is_dir() {
+                Some((u32::from(git2::FileMode::Commit), Vec::new()))
+            } else {
+                let mode = if is_executable(&meta) {
+                    git2::FileMode::BlobExecutable
+                } else {
+                    git2::FileMode::Blob
+                };
+                Some((u32::from(mode), std::fs::read(&full).ok()?))
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn is_executable(meta: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_executable(_meta: &std::fs::Metadata) -> bool {
+    false
+}
+
+fn entry_kind(mode: u32, content: &[u8]) -> EntryKind {
+    let target = (mode == u32::from(git2::FileMode::Link))
+        .then(|| String::from_utf8_lossy(content).into_owned());
+    EntryKind { mode, target }
+}
+
+/// Old and new kinds of a path whose type changed, e.g. file to symlink.
+pub fn get_type_change(repo: &Repository, path: impl AsRef<[u8]>, section: Section) -> DiffContent {
+    let path = path.as_ref();
+    let (old_side, new_side) = sides(section);
+    match (
+        read_side(repo, path, old_side),
+        read_side(repo, path, new_side),
+    ) {
+        (Some((old_mode, old)), Some((new_mode, new))) => DiffContent::TypeChange {
+            old: entry_kind(old_mode, &old),
+            new: entry_kind(new_mode, &new),
+        },
+        _ => DiffContent::Empty,
+    }
+}
+
+/// Old and new targets of a symlink changed in `section`.
+fn get_symlink_change(
+    repo: &Repository,
+    path: &[u8],
+    old_path: Option<&[u8]>,
+    section: Section,
+) -> DiffContent {
+    let (old_side, new_side) = sides(section);
+    let target = |path: &[u8], side| {
+        read_side(repo, path, side).and_then(|(mode, content)| entry_kind(mode, &content).target)
+    };
+    DiffContent::Symlink {
+        old_target: target(old_path.unwrap_or(path), old_side),
+        new_target: target(path, new_side),
+    }
+}
+
+/// Whether `content` looks binary: a NUL byte within the leading
+/// `[preview] binary_check_bytes`.
+pub fn looks_binary(content: &[u8], preview: PreviewConfig) -> bool {
+    let limit = preview.binary_check_bytes;
+    let checked = if limit == 0 {
+        content
+    } else {
+        &content[..content.len().min(limit)]
+    };
+    checked.contains(&0)
+}
+
+/// Size in bytes of `path` on one side, where that's cheap to find.
+fn side_size(repo: &Repository, path: &[u8], side: Side) -> Option<u64> {
+    match side {
+        Side::Head => None,
+        Side::Index => {
+            let index = repo.index().ok()?;
+            let entry = index.get_path(&fs_path(path), 0)?;
+            Some(u64::from(entry.file_size))
+        }
+        Side::Workdir => {
+            let full = repo.workdir()?.join(fs_path(path));
+            std::fs::metadata(full).ok().map(|meta| meta.len())
+        }
+    }
+}
+
+/// Sizes of a text file whose new side is over `[preview] max_text_kib`,
+/// to show instead of its diff.
+fn too_large_to_preview(
+    repo: &Repository,
+    file: &FileEntry,
+    section: Section,
+    preview: PreviewConfig,
+) -> Option<BinaryInfo> {
+    let limit = preview.max_text_kib as u64 * 1024;
+    let (_, new_side) = sides(section);
+    let size = side_size(repo, &file.raw_path, new_side)?;
+    (size > limit).then(|| {
+        get_binary_info(
+            repo,
+            &file.raw_path,
+            file.raw_old_path.as_deref(),
+            section,
+            preview,
+        )
+    })
+}
+
+/// Sizes and detected type of a binary file on both sides of `section`'s
+/// change. Untracked files have no old side.
+pub fn get_binary_info(
+    repo: &Repository,
+    path: impl AsRef<[u8]>,
+    old_path: Option<&[u8]>,
+    section: Section,
+    preview: PreviewConfig,
+) -> BinaryInfo {
+    let path = path.as_ref();
+    let (old_side, new_side) = sides(section);
+    let old = read_side(repo, old_path.unwrap_or(path), old_side).map(|(_, bytes)| bytes);
+    let new = read_side(repo, path, new_side).map(|(_, bytes)| bytes);
+
+    let change = match (&old, &new) {
+        (None, Some(_)) => BinaryChange::Added,
+        (Some(_), None) => BinaryChange::Deleted,
+        _ => BinaryChange::Modified,
+    };
+    let shown = new.as_deref().or(old.as_deref());
+    let mime_type = shown
+        .and_then(infer::get)
+        .map(|kind| kind.mime_type())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let content = shown
+        .filter(|bytes| bytes.len() <= preview.hexdump_max_kib * 1024)
+        .map(<[u8]>::to_vec);
+
+    BinaryInfo {
+        change,
+        old_size: old.map(|bytes| bytes.len() as u64),
+        new_size: new.map(|bytes| bytes.len() as u64),
+        mime_type,
+        content,
+    }
+}
+
+/// The encoding to show `path`'s non-UTF-8 text in, with a label saying
+/// where it came from: the `working-tree-encoding` attribute if set,
+/// otherwise a guess from `content`.
+fn legacy_encoding(repo: &Repository, path: &[u8], content: &[u8]) -> (&'static Encoding, String) {
+    let attribute = repo
+        .get_attr(
+            &fs_path(path),
+            "working-tree-encoding",
+            git2::AttrCheckFlags::FILE_THEN_INDEX,
+        )
+        .ok()
+        .flatten()
+        .and_then(|label| Encoding::for_label(label.as_bytes()));
+    if let Some(encoding) = attribute {
+        return (
+            encoding,
+            format!("{} (working-tree-encoding)", encoding.name()),
+        );
+    }
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(content, true);
+    let encoding = detector.guess(None, false);
+    (encoding, format!("{} (detected)", encoding.name()))
+}
+
+/// A conflicted file's working tree content, for resolving it in place.
+/// The lines are left empty for a missing, binary, non-UTF-8, or
+/// over-limit file.
+pub fn get_conflict_preview(
+    repo: &Repository,
+    file: &FileEntry,
+    preview: PreviewConfig,
+) -> ConflictPreview {
+    let limit = preview.max_text_kib as u64 * 1024;
+    let lines = repo
+        .workdir()
+        .map(|workdir| workdir.join(fs_path(&file.raw_path)))
+        .filter(|full| std::fs::metadata(full).is_ok_and(|meta| meta.len() <= limit))
+        .and_then(|full| std::fs::read(full).ok())
+        .filter(|content| !looks_binary(content, preview))
+        .and_then(|content| String::from_utf8(content).ok())
+        .map(|text| text.lines().map(str::to_string).collect())
+        .unwrap_or_default();
+    ConflictPreview {
+        kind: file.conflict,
+        lines,
+    }
+}
+
+/// Content of an untracked file, highlighted, instead of an all-added diff.
+pub fn get_untracked_preview(repo: &Repository, path: impl AsRef<[u8]>) -> DiffContent {
+    let path = path.as_ref();
+    let workdir = match repo.workdir() {
+        Some(w) => w,
+        None => return DiffContent::Empty,
+    };
+
+    let file_path = workdir.join(fs_path(path));
+    if let Ok(target) = std::fs::read_link(&file_path) {
+        return DiffContent::Symlink {
+            old_target: None,
+            new_target: Some(target.to_string_lossy().into_owned()),
+        };
+    }
+    let content = match std::fs::read(&file_path) {
+        Ok(c) => c,
+        Err(_) => return DiffContent::Empty,
+    };
 
-    DiffContent::Text(lines)
+    let (text, label) = match std::str::from_utf8(&content) {
+        Ok(t) => (Cow::Borrowed(t), None),
+        Err(_) => {
+            let (encoding, label) = legacy_encoding(repo, path, &content);
+            match encoding.decode_without_bom_handling_and_without_replacement(&content) {
+                Some(text) => (text, Some(label)),
+                None => return DiffContent::InvalidUtf8,
+            }
+        }
+    };
+
+    let path = display_path(path);
+    let (mut file_type, lines) = crate::highlight::highlight(&path, &text);
+    if let Some(label) = label {
+        file_type = format!("{} · {}", file_type, label);
+    }
+    DiffContent::Preview(FilePreview {
+        path,
+        size: content.len() as u64,
+        file_type,
+        lines,
+    })
 }
 
 #[cfg(test)]
@@ -763,4 +3338,110 @@ mod tests {
         assert!(has_staged_changes(status));
         assert!(has_unstaged_changes(status));
     }
+
+    #[test]
+    fn looks_binary_checks_only_the_leading_bytes() {
+        let mut content = vec![b'a'; 9000];
+        content[8500] = 0;
+        assert!(!looks_binary(&content, PreviewConfig::default()));
+        let whole_file = PreviewConfig {
+            binary_check_bytes: 0,
+            ..PreviewConfig::default()
+        };
+        assert!(looks_binary(&content, whole_file));
+    }
+
+    #[test]
+    fn minified_summary_needs_one_dominant_long_line() {
+        let line = |kind, content: String| DiffLine {
+            kind,
+            content,
+            new_line_number: None,
+        };
+        let long = "x".repeat(MINIFIED_LINE_BYTES);
+        let diff = DiffContent::Text(vec![
+            line(DiffLineKind::Hunk, "@@ -1 +1 @@".to_string()),
+            line(DiffLineKind::Deleted, long.clone()),
+            line(DiffLineKind::Added, format!("{}y", long)),
+        ]);
+        assert_eq!(
+            minified_summary(&diff),
+            Some((1, MINIFIED_LINE_BYTES as u64 + 1))
+        );
+
+        // Plenty of ordinary lines alongside it: not minified.
+        let mut lines = vec![line(DiffLineKind::Added, long.clone())];
+        lines.extend((0..100).map(|_| line(DiffLineKind::Added, "y".repeat(80))));
+        assert_eq!(minified_summary(&DiffContent::Text(lines)), None);
+
+        let short = DiffContent::Text(vec![line(DiffLineKind::Added, "x".repeat(500))]);
+        assert_eq!(minified_summary(&short), None);
+        assert_eq!(
+            minified_summary(&DiffContent::Conflict(ConflictPreview::default())),
+            None
+        );
+    }
+
+    #[test]
+    fn detect_eol_change_direction_and_threshold() {
+        let crlf: [&[u8]; 2] = [b"a\r\n", b"b\r\n"];
+        let lf: [&[u8]; 2] = [b"a\n", b"b\n"];
+        assert_eq!(detect_eol_change(&crlf, &lf), Some(EolChange::CrlfToLf));
+        assert_eq!(detect_eol_change(&lf, &crlf), Some(EolChange::LfToCrlf));
+        assert_eq!(detect_eol_change(&[], &[]), None);
+
+        let edited: [&[u8]; 2] = [b"a\n", b"c\n"];
+        assert_eq!(detect_eol_change(&crlf, &edited), None);
+    }
+
+    #[test]
+    fn splice_changes_applies_only_picked_lines() {
+        let line = |origin, old_lineno, new_lineno, content: &str| PatchLine {
+            origin,
+            old_lineno,
+            new_lineno,
+            content: content.as_bytes().to_vec(),
+        };
+        // a,b,c -> a,B,c,d
+        let lines = [
+            line(' ', Some(1), Some(1), "a\n"),
+            line('-', Some(2), None, "b\n"),
+            line('+', None, Some(2), "B\n"),
+            line(' ', Some(3), Some(3), "c\n"),
+            line('+', None, Some(4), "d\n"),
+        ];
+        let old = b"a\nb\nc\n";
+        let new = b"a\nB\nc\nd\n";
+
+        assert_eq!(
+            splice_changes(old, &lines, &[false, true, false], true),
+            b"a\nb\nB\nc\n"
+        );
+        assert_eq!(
+            splice_changes(old, &lines, &[true, false, true], true),
+            b"a\nc\nd\n"
+        );
+        assert_eq!(
+            splice_changes(new, &lines, &[false, false, true], false),
+            b"a\nB\nc\n"
+        );
+        assert_eq!(
+            splice_changes(new, &lines, &[true, true, false], false),
+            b"a\nb\nc\nd\n"
+        );
+    }
+
+    #[test]
+    fn diff_header_path_takes_new_side() {
+        assert_eq!(
+            diff_header_path("diff --git a/src/old.rs b/src/new.rs"),
+            Some("src/new.rs")
+        );
+        assert_eq!(
+            diff_header_path("diff --git \"a/my file\" \"b/my file\""),
+            Some("my file")
+        );
+        assert_eq!(diff_header_path("index 123..456 100644"), None);
+        assert_eq!(diff_header_path("diff --git nonsense"), None);
+    }
 }
@@ -0,0 +1,158 @@
+use crate::types::TokenKind;
+use std::sync::OnceLock;
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// Files larger than this are shown without highlighting; parsing them
+/// would stall the UI.
+const MAX_HIGHLIGHT_BYTES: usize = 256 * 1024;
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// Pick a syntax from the file name or extension, falling back to the first
+/// line (shebangs, modelines) and then plain text.
+fn detect<'a>(set: &'a SyntaxSet, path: &str, text: &str) -> &'a SyntaxReference {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let extension = name.rsplit_once('.').map(|(_, ext)| ext);
+    set.find_syntax_by_extension(name)
+        .or_else(|| extension.and_then(|ext| set.find_syntax_by_extension(ext)))
+        .or_else(|| set.find_syntax_by_first_line(text.lines().next().unwrap_or("")))
+        .unwrap_or_else(|| set.find_syntax_plain_text())
+}
+
+/// Split `text` into lines of highlighted tokens. Returns the detected file
+/// type alongside.
+pub fn highlight(path: &str, text: &str) -> (String, Vec<Vec<(TokenKind, String)>>) {
+    let set = syntaxes();
+    let syntax = detect(set, path, text);
+    let file_type = syntax.name.clone();
+    let plain = || {
+        text.lines()
+            .map(|line| vec![(TokenKind::Plain, line.to_string())])
+            .collect()
+    };
+    if text.len() > MAX_HIGHLIGHT_BYTES {
+        return (file_type, plain());
+    }
+
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut lines = Vec::new();
+    for line in LinesWithEndings::from(text) {
+        let Ok(ops) = state.parse_line(line, set) else {
+            return (file_type, plain());
+        };
+        let line = line.trim_end_matches(['\n', '\r']);
+        let mut tokens: Vec<(TokenKind, String)> = Vec::new();
+        let mut start = 0;
+        for (end, op) in ops {
+            push_token(&mut tokens, classify(&stack), line, start, end);
+            start = start.max(end);
+            if stack.apply(&op).is_err() {
+                return (file_type, plain());
+            }
+        }
+        push_token(&mut tokens, classify(&stack), line, start, line.len());
+        lines.push(tokens);
+    }
+    (file_type, lines)
+}
+
+/// Append `line[start..end]`, merging with the previous token when the kind
+/// matches. Offsets past the trimmed line end are clamped.
+fn push_token(
+    tokens: &mut Vec<(TokenKind, String)>,
+    kind: TokenKind,
+    line: &str,
+    start: usize,
+    end: usize,
+) {
+    let end = end.min(line.len());
+    if start >= end {
+        return;
+    }
+    let text = &line[start..end];
+    match tokens.last_mut() {
+        Some((last, content)) if *last == kind => content.push_str(text),
+        _ => tokens.push((kind, text.to_string())),
+    }
+}
+
+/// The token kind for the innermost scope that maps to one.
+fn classify(stack: &ScopeStack) -> TokenKind {
+    stack
+        .as_slice()
+        .iter()
+        .rev()
+        .find_map(scope_kind)
+        .unwrap_or(TokenKind::Plain)
+}
+
+fn scope_kind(scope: &Scope) -> Option<TokenKind> {
+    let name = scope.build_string();
+    let is = |prefix: &str| name == prefix || name.starts_with(&format!("{}.", prefix));
+    if is("comment") {
+        Some(TokenKind::Comment)
+    } else if is("string") {
+        Some(TokenKind::String)
+    } else if is("constant.numeric") || is("constant.language") || is("constant.character") {
+        Some(TokenKind::Constant)
+    } else if is("entity.name.function") || is("support.function") {
+        Some(TokenKind::Function)
+    } else if is("entity.name.type")
+        || is("entity.name.class")
+        || is("entity.name.struct")
+        || is("support.type")
+        || is("support.class")
+    {
+        Some(TokenKind::Type)
+    } else if is("keyword") || is("storage") {
+        Some(TokenKind::Keyword)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_rust_tokens() {
+        let (file_type, lines) = highlight(
+            "src/main.rs",
+            "fn main() {\n    // hi\n    let s = \"x\";\n}\n",
+        );
+        assert_eq!(file_type, "Rust");
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0][0], (TokenKind::Keyword, "fn".to_string()));
+        assert!(lines[1].contains(&(TokenKind::Comment, "// hi".to_string())));
+        assert!(lines[2]
+            .iter()
+            .any(|(kind, text)| *kind == TokenKind::String && text.contains('x')));
+        let rebuilt: String = lines[2].iter().map(|(_, text)| text.as_str()).collect();
+        assert_eq!(rebuilt, "    let s = \"x\";");
+    }
+
+    #[test]
+    fn unknown_files_are_plain_text() {
+        let (file_type, lines) = highlight("notes.unknownext", "hello\nworld\n");
+        assert_eq!(file_type, "Plain Text");
+        assert_eq!(
+            lines,
+            vec![
+                vec![(TokenKind::Plain, "hello".to_string())],
+                vec![(TokenKind::Plain, "world".to_string())],
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_shebang() {
+        let (file_type, _) = highlight("script", "#!/bin/bash\necho hi\n");
+        assert_eq!(file_type, "Bourne Again Shell (bash)");
+    }
+}
@@ -0,0 +1,727 @@
+use crate::config::{CustomCommand, KeyPreset};
+use anyhow::{anyhow, bail, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// How long a partial key sequence waits for its next key.
+pub const SEQUENCE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// A single key press: code plus modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub const fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self { code, modifiers }
+    }
+
+    pub const fn plain(code: KeyCode) -> Self {
+        Self::new(code, KeyModifiers::NONE)
+    }
+
+    pub const fn char(c: char) -> Self {
+        Self::plain(KeyCode::Char(c))
+    }
+
+    /// Normalize a terminal key event. Shift is dropped for characters since
+    /// it is already reflected in the character itself (`G`, not `shift+g`).
+    pub fn from_event(event: &KeyEvent) -> Self {
+        let mut modifiers = event.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if !matches!(event.code, KeyCode::Char(_)) {
+            modifiers |= event.modifiers & KeyModifiers::SHIFT;
+        }
+        Self::new(event.code, modifiers)
+    }
+
+    /// Parse a key such as `g`, `G`, `ctrl+z`, `space`, or `pgdn`.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        loop {
+            let lower = rest.to_ascii_lowercase();
+            if let Some(stripped) = lower.strip_prefix("ctrl+") {
+                modifiers |= KeyModifiers::CONTROL;
+                rest = &rest[rest.len() - stripped.len()..];
+            } else if let Some(stripped) = lower.strip_prefix("alt+") {
+                modifiers |= KeyModifiers::ALT;
+                rest = &rest[rest.len() - stripped.len()..];
+            } else {
+                break;
+            }
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pgup" | "pageup" => KeyCode::PageUp,
+                "pgdn" | "pagedown" => KeyCode::PageDown,
+                _ => bail!("Unknown key '{}'", text),
+            },
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Enter => write!(f, "Enter"),
+            KeyCode::Esc => write!(f, "Esc"),
+            KeyCode::Tab => write!(f, "Tab"),
+            KeyCode::Backspace => write!(f, "Backspace"),
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::Home => write!(f, "Home"),
+            KeyCode::End => write!(f, "End"),
+            KeyCode::PageUp => write!(f, "PgUp"),
+            KeyCode::PageDown => write!(f, "PgDn"),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+/// Parse a space-separated key sequence such as `g g` or `ctrl+z`.
+pub fn parse_sequence(text: &str) -> Result<Vec<Key>> {
+    let keys = text
+        .split_whitespace()
+        .map(Key::parse)
+        .collect::<Result<Vec<_>>>()?;
+    if keys.is_empty() {
+        bail!("Empty key sequence");
+    }
+    Ok(keys)
+}
+
+/// Render a key sequence for display (e.g., "g g").
+pub fn format_sequence(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|k| k.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Something a key binding can do in the file list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    /// Esc: cancel a network op, clear marks, dismiss a notice, or quit.
+    Back,
+    Down,
+    Up,
+    Top,
+    Bottom,
+    PageDown,
+    PageUp,
+    ToggleMark,
+    Select,
+    Stage,
+    Unstage,
+    /// Run the `[format]` command on the targets, then stage them.
+    FormatStage,
+    /// Run `pre-commit` on the targets.
+    PreCommit,
+    StageAll,
+    UnstageAll,
+    Discard,
+    DiscardAll,
+    Undo,
+    ToggleGraph,
+    Remotes,
+    /// Open the stash panel to restore files from a stash.
+    Stashes,
+    /// Open the recover panel to bring back discarded files.
+    Recover,
+    /// Make the first commit in a repository with no commits yet.
+    InitialCommit,
+    /// On a detached HEAD, create a branch at the current commit.
+    CreateBranch,
+    /// On a detached HEAD, check out the branch it was detached from.
+    PreviousBranch,
+    Messages,
+    ErrorDetails,
+    Hexdump,
+    IgnoreEol,
+    ExpandLines,
+    /// Show paths relative to the launch directory or the repository root.
+    RelativePaths,
+    /// Highlight and show the next conflicted file, wrapping around.
+    NextConflict,
+    /// Scroll a conflicted file to its next or previous conflict region.
+    NextRegion,
+    PrevRegion,
+    /// Move key focus between the file list and the diff.
+    ToggleFocus,
+    /// Show the next or previous file's diff, keeping focus where it is.
+    NextFile,
+    PrevFile,
+    /// Start or leave selecting a range of diff lines.
+    VisualSelect,
+    Copy,
+    Fetch,
+    Push,
+    Pull,
+    /// Run the `[[commands]]` entry at this index.
+    Custom(usize),
+}
+
+impl Action {
+    pub const ALL: [Action; 43] = [
+        Action::Quit,
+        Action::Back,
+        Action::Down,
+        Action::Up,
+        Action::Top,
+        Action::Bottom,
+        Action::PageDown,
+        Action::PageUp,
+        Action::ToggleMark,
+        Action::Select,
+        Action::Stage,
+        Action::Unstage,
+        Action::FormatStage,
+        Action::PreCommit,
+        Action::StageAll,
+        Action::UnstageAll,
+        Action::Discard,
+        Action::DiscardAll,
+        Action::Undo,
+        Action::ToggleGraph,
+        Action::Remotes,
+        Action::Stashes,
+        Action::Recover,
+        Action::InitialCommit,
+        Action::CreateBranch,
+        Action::PreviousBranch,
+        Action::Messages,
+        Action::ErrorDetails,
+        Action::Hexdump,
+        Action::IgnoreEol,
+        Action::ExpandLines,
+        Action::RelativePaths,
+        Action::NextConflict,
+        Action::NextRegion,
+        Action::PrevRegion,
+        Action::ToggleFocus,
+        Action::NextFile,
+        Action::PrevFile,
+        Action::VisualSelect,
+        Action::Copy,
+        Action::Fetch,
+        Action::Push,
+        Action::Pull,
+    ];
+
+    /// Name used in the `[keys]` config table.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Back => "back",
+            Action::Down => "down",
+            Action::Up => "up",
+            Action::Top => "top",
+            Action::Bottom => "bottom",
+            Action::PageDown => "page_down",
+            Action::PageUp => "page_up",
+            Action::ToggleMark => "toggle_mark",
+            Action::Select => "select",
+            Action::Stage => "stage",
+            Action::Unstage => "unstage",
+            Action::FormatStage => "format_stage",
+            Action::PreCommit => "pre_commit",
+            Action::StageAll => "stage_all",
+            Action::UnstageAll => "unstage_all",
+            Action::Discard => "discard",
+            Action::DiscardAll => "discard_all",
+            Action::Undo => "undo",
+            Action::ToggleGraph => "toggle_graph",
+            Action::Remotes => "remotes",
+            Action::Stashes => "stashes",
+            Action::Recover => "recover",
+            Action::InitialCommit => "initial_commit",
+            Action::CreateBranch => "create_branch",
+            Action::PreviousBranch => "previous_branch",
+            Action::Messages => "messages",
+            Action::ErrorDetails => "error_details",
+            Action::Hexdump => "hexdump",
+            Action::IgnoreEol => "ignore_eol",
+            Action::ExpandLines => "expand_lines",
+            Action::RelativePaths => "relative_paths",
+            Action::NextConflict => "next_conflict",
+            Action::NextRegion => "next_region",
+            Action::PrevRegion => "prev_region",
+            Action::ToggleFocus => "toggle_focus",
+            Action::NextFile => "next_file",
+            Action::PrevFile => "prev_file",
+            Action::VisualSelect => "visual_select",
+            Action::Copy => "copy",
+            Action::Fetch => "fetch",
+            Action::Push => "push",
+            Action::Pull => "pull",
+            Action::Custom(_) => "command",
+        }
+    }
+
+    /// How an action is named in error messages.
+    fn label(&self) -> String {
+        match self {
+            Action::Custom(i) => format!("command #{}", i + 1),
+            action => action.name().to_string(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|a| a.name() == name)
+    }
+}
+
+/// Bindings a preset puts in place of the defaults, per action. Actions not
+/// listed keep their default binding.
+fn preset_bindings(preset: KeyPreset) -> &'static [(Action, &'static [&'static str])] {
+    match preset {
+        KeyPreset::Default => &[],
+        KeyPreset::Vim => &[
+            (Action::Down, &["down", "j"]),
+            (Action::Up, &["up", "k"]),
+            (Action::PageDown, &["pgdn", "ctrl+d"]),
+            (Action::PageUp, &["pgup", "ctrl+u"]),
+        ],
+        KeyPreset::Emacs => &[
+            (Action::Back, &["esc", "ctrl+g"]),
+            (Action::Down, &["down", "ctrl+n"]),
+            (Action::Up, &["up", "ctrl+p"]),
+            (Action::Top, &["g g", "alt+<"]),
+            (Action::Bottom, &["G", "alt+>"]),
+            (Action::PageDown, &["pgdn", "ctrl+v"]),
+            (Action::PageUp, &["pgup", "alt+v"]),
+            (Action::Undo, &["ctrl+z", "ctrl+_"]),
+        ],
+        KeyPreset::Lazygit => &[
+            (Action::Down, &["down", "j"]),
+            (Action::Up, &["up", "k"]),
+            (Action::Top, &["g g", "<"]),
+            (Action::Bottom, &["G", ">"]),
+            (Action::PageDown, &["pgdn", "."]),
+            (Action::PageUp, &["pgup", ","]),
+            (Action::ToggleMark, &["v"]),
+            (Action::VisualSelect, &["V"]),
+            (Action::Stage, &["space"]),
+            (Action::StageAll, &["a"]),
+            (Action::Undo, &["ctrl+z", "z"]),
+            (Action::Pull, &["p"]),
+            (Action::Push, &["P"]),
+        ],
+    }
+}
+
+/// Result of looking up a (possibly partial) key sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lookup {
+    Exact(Action),
+    Prefix,
+    None,
+}
+
+/// Key sequences bound to actions. Bindings are kept prefix-free so a
+/// sequence is never both complete and the start of a longer one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<(Vec<Key>, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let ctrl = |c| Key::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        let bindings = vec![
+            (vec![Key::char('q')], Action::Quit),
+            (vec![Key::plain(KeyCode::Esc)], Action::Back),
+            (vec![Key::plain(KeyCode::Down)], Action::Down),
+            (vec![Key::plain(KeyCode::Up)], Action::Up),
+            (vec![Key::char('g'), Key::char('g')], Action::Top),
+            (vec![Key::char('G')], Action::Bottom),
+            (vec![Key::plain(KeyCode::PageDown)], Action::PageDown),
+            (vec![Key::plain(KeyCode::PageUp)], Action::PageUp),
+            (vec![Key::char(' ')], Action::ToggleMark),
+            (vec![Key::plain(KeyCode::Enter)], Action::Select),
+            (vec![Key::char('s')], Action::Stage),
+            (vec![Key::char('u')], Action::Unstage),
+            (vec![Key::char('F')], Action::FormatStage),
+            (vec![Key::char('c')], Action::PreCommit),
+            (vec![Key::char('S')], Action::StageAll),
+            (vec![Key::char('U')], Action::UnstageAll),
+            (vec![Key::char('d')], Action::Discard),
+            (vec![Key::char('D')], Action::DiscardAll),
+            (vec![ctrl('z')], Action::Undo),
+            (vec![Key::char('L')], Action::ToggleGraph),
+            (vec![Key::char('R')], Action::Remotes),
+            (vec![Key::char('Z')], Action::Stashes),
+            (vec![Key::char('X')], Action::Recover),
+            (vec![Key::char('I')], Action::InitialCommit),
+            (vec![Key::char('B')], Action::CreateBranch),
+            (vec![Key::char('-')], Action::PreviousBranch),
+            (vec![Key::char('m')], Action::Messages),
+            (vec![Key::char('e')], Action::ErrorDetails),
+            (vec![Key::char('x')], Action::Hexdump),
+            (vec![Key::char('E')], Action::IgnoreEol),
+            (vec![Key::char('o')], Action::ExpandLines),
+            (vec![Key::char('r')], Action::RelativePaths),
+            (vec![Key::char('n')], Action::NextConflict),
+            (vec![Key::char('}')], Action::NextRegion),
+            (vec![Key::char('{')], Action::PrevRegion),
+            (vec![Key::plain(KeyCode::Tab)], Action::ToggleFocus),
+            (vec![Key::char(']')], Action::NextFile),
+            (vec![Key::char('[')], Action::PrevFile),
+            (vec![Key::char('v')], Action::VisualSelect),
+            (vec![Key::char('y')], Action::Copy),
+            (vec![Key::char('f')], Action::Fetch),
+            (vec![Key::char('p')], Action::Push),
+            (vec![Key::char('l')], Action::Pull),
+        ];
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// The keymap for `preset` with `[keys]` overrides applied. Each preset
+    /// entry and each override replaces every earlier binding of its action.
+    pub fn new(preset: KeyPreset, overrides: &BTreeMap<String, String>) -> Result<Self> {
+        let mut keymap = Self::default();
+        for &(action, sequences) in preset_bindings(preset) {
+            keymap.bindings.retain(|(_, a)| *a != action);
+            for sequence in sequences {
+                keymap.bindings.push((parse_sequence(sequence)?, action));
+            }
+        }
+        for (name, sequence) in overrides {
+            let action =
+                Action::from_name(name).ok_or_else(|| anyhow!("Unknown action '{}'", name))?;
+            let keys = parse_sequence(sequence)
+                .map_err(|e| anyhow!("Invalid keys for '{}': {}", name, e))?;
+            keymap.bindings.retain(|(_, a)| *a != action);
+            keymap.bindings.push((keys, action));
+        }
+        keymap.validate()?;
+        Ok(keymap)
+    }
+
+    /// Bind each of `commands` to its key as [`Action::Custom`]. Keys must
+    /// not clash with the existing bindings or each other.
+    pub fn bind_commands(&mut self, commands: &[CustomCommand]) -> Result<()> {
+        for (i, command) in commands.iter().enumerate() {
+            let keys = parse_sequence(&command.key)
+                .map_err(|e| anyhow!("Invalid key for '{}': {}", command.name, e))?;
+            self.bindings.push((keys, Action::Custom(i)));
+        }
+        self.validate()
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (i, (keys, action)) in self.bindings.iter().enumerate() {
+            for (other_keys, other_action) in &self.bindings[i + 1..] {
+                let shorter = keys.len().min(other_keys.len());
+                if keys[..shorter] == other_keys[..shorter] {
+                    bail!(
+                        "'{}' ({}) conflicts with '{}' ({})",
+                        format_sequence(keys),
+                        action.label(),
+                        format_sequence(other_keys),
+                        other_action.label()
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, sequence: &[Key]) -> Lookup {
+        let mut result = Lookup::None;
+        for (keys, action) in &self.bindings {
+            if keys.as_slice() == sequence {
+                return Lookup::Exact(*action);
+            }
+            if keys.starts_with(sequence) {
+                result = Lookup::Prefix;
+            }
+        }
+        result
+    }
+
+    /// The key sequence bound to `action`, if any.
+    pub fn keys_for(&self, action: Action) -> Option<&[Key]> {
+        self.bindings
+            .iter()
+            .find(|(_, a)| *a == action)
+            .map(|(keys, _)| keys.as_slice())
+    }
+}
+
+/// What a key press resolved to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyInput {
+    Action(Action),
+    /// The key started or continued a sequence; wait for more.
+    Pending,
+    Unbound,
+}
+
+/// Keys typed so far toward a multi-key binding.
+#[derive(Debug, Clone, Default)]
+pub struct KeySequence {
+    pending: Vec<Key>,
+    last_key_at: Option<Instant>,
+}
+
+impl KeySequence {
+    pub fn feed(&mut self, keymap: &Keymap, key: Key, now: Instant) -> KeyInput {
+        self.expire(now);
+        if !self.pending.is_empty() && key == Key::plain(KeyCode::Esc) {
+            self.clear();
+            return KeyInput::Unbound;
+        }
+
+        self.pending.push(key);
+        match keymap.lookup(&self.pending) {
+            Lookup::Exact(action) => {
+                self.clear();
+                KeyInput::Action(action)
+            }
+            Lookup::Prefix => {
+                self.last_key_at = Some(now);
+                KeyInput::Pending
+            }
+            Lookup::None => {
+                self.clear();
+                KeyInput::Unbound
+            }
+        }
+    }
+
+    /// Drop a partial sequence that has waited longer than [`SEQUENCE_TIMEOUT`].
+    pub fn expire(&mut self, now: Instant) {
+        if self
+            .last_key_at
+            .is_some_and(|at| now.duration_since(at) >= SEQUENCE_TIMEOUT)
+        {
+            self.clear();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.last_key_at = None;
+    }
+
+    pub fn pending(&self) -> &[Key] {
+        &self.pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_keys() {
+        assert_eq!(Key::parse("g").unwrap(), Key::char('g'));
+        assert_eq!(Key::parse("G").unwrap(), Key::char('G'));
+        assert_eq!(
+            Key::parse("ctrl+z").unwrap(),
+            Key::new(KeyCode::Char('z'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(Key::parse("Space").unwrap(), Key::char(' '));
+        assert_eq!(Key::parse("pgdn").unwrap(), Key::plain(KeyCode::PageDown));
+        assert!(Key::parse("hyper").is_err());
+        assert_eq!(
+            parse_sequence("d d").unwrap(),
+            vec![Key::char('d'), Key::char('d')]
+        );
+        assert!(parse_sequence("  ").is_err());
+    }
+
+    #[test]
+    fn from_event_drops_shift_for_chars() {
+        let event = KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT);
+        assert_eq!(Key::from_event(&event), Key::char('G'));
+    }
+
+    #[test]
+    fn key_display() {
+        assert_eq!(
+            format_sequence(&parse_sequence("ctrl+z space g").unwrap()),
+            "Ctrl+z Space g"
+        );
+    }
+
+    #[test]
+    fn default_keymap_is_prefix_free() {
+        Keymap::default().validate().unwrap();
+        for action in Action::ALL {
+            assert!(Keymap::default().keys_for(action).is_some());
+            assert_eq!(Action::from_name(action.name()), Some(action));
+        }
+    }
+
+    #[test]
+    fn sequence_resolves_two_key_binding() {
+        let keymap = Keymap::default();
+        let mut seq = KeySequence::default();
+        let now = Instant::now();
+        assert_eq!(seq.feed(&keymap, Key::char('g'), now), KeyInput::Pending);
+        assert_eq!(seq.pending(), &[Key::char('g')]);
+        assert_eq!(
+            seq.feed(&keymap, Key::char('g'), now),
+            KeyInput::Action(Action::Top)
+        );
+        assert!(seq.pending().is_empty());
+    }
+
+    #[test]
+    fn sequence_mismatch_and_escape_reset() {
+        let keymap = Keymap::default();
+        let mut seq = KeySequence::default();
+        let now = Instant::now();
+        seq.feed(&keymap, Key::char('g'), now);
+        assert_eq!(seq.feed(&keymap, Key::char('x'), now), KeyInput::Unbound);
+        assert!(seq.pending().is_empty());
+
+        seq.feed(&keymap, Key::char('g'), now);
+        assert_eq!(
+            seq.feed(&keymap, Key::plain(KeyCode::Esc), now),
+            KeyInput::Unbound
+        );
+        assert!(seq.pending().is_empty());
+    }
+
+    #[test]
+    fn sequence_times_out() {
+        let keymap = Keymap::default();
+        let mut seq = KeySequence::default();
+        let now = Instant::now();
+        seq.feed(&keymap, Key::char('g'), now);
+        seq.expire(now + SEQUENCE_TIMEOUT);
+        assert!(seq.pending().is_empty());
+        assert_eq!(
+            seq.feed(&keymap, Key::char('s'), now + SEQUENCE_TIMEOUT),
+            KeyInput::Action(Action::Stage)
+        );
+    }
+
+    #[test]
+    fn overrides_replace_default_binding() {
+        let overrides = BTreeMap::from([("discard".to_string(), "d d".to_string())]);
+        let keymap = Keymap::new(KeyPreset::Default, &overrides).unwrap();
+        assert_eq!(
+            keymap.keys_for(Action::Discard),
+            Some(&[Key::char('d'), Key::char('d')][..])
+        );
+        let mut seq = KeySequence::default();
+        let now = Instant::now();
+        assert_eq!(seq.feed(&keymap, Key::char('d'), now), KeyInput::Pending);
+        assert_eq!(
+            seq.feed(&keymap, Key::char('d'), now),
+            KeyInput::Action(Action::Discard)
+        );
+    }
+
+    #[test]
+    fn presets_are_conflict_free() {
+        for preset in KeyPreset::ALL {
+            assert!(
+                Keymap::new(preset, &BTreeMap::new()).is_ok(),
+                "{:?}",
+                preset
+            );
+        }
+    }
+
+    #[test]
+    fn preset_adds_bindings_and_overrides_apply_on_top() {
+        let overrides = BTreeMap::from([("up".to_string(), "i".to_string())]);
+        let keymap = Keymap::new(KeyPreset::Vim, &overrides).unwrap();
+        let mut seq = KeySequence::default();
+        let now = Instant::now();
+        assert_eq!(
+            seq.feed(&keymap, Key::char('j'), now),
+            KeyInput::Action(Action::Down)
+        );
+        assert_eq!(
+            seq.feed(&keymap, Key::plain(KeyCode::Down), now),
+            KeyInput::Action(Action::Down)
+        );
+        assert_eq!(keymap.keys_for(Action::Up), Some(&[Key::char('i')][..]));
+        assert_eq!(seq.feed(&keymap, Key::char('k'), now), KeyInput::Unbound);
+
+        let lazygit = Keymap::new(KeyPreset::Lazygit, &BTreeMap::new()).unwrap();
+        assert_eq!(
+            seq.feed(&lazygit, Key::char(' '), now),
+            KeyInput::Action(Action::Stage)
+        );
+        assert_eq!(
+            seq.feed(&lazygit, Key::char('P'), now),
+            KeyInput::Action(Action::Push)
+        );
+    }
+
+    #[test]
+    fn overrides_reject_conflicts_and_unknown_actions() {
+        let conflict = BTreeMap::from([("top".to_string(), "s s".to_string())]);
+        let err = Keymap::new(KeyPreset::Default, &conflict).unwrap_err();
+        assert!(err.to_string().contains("conflicts"));
+
+        let unknown = BTreeMap::from([("teleport".to_string(), "t".to_string())]);
+        assert!(Keymap::new(KeyPreset::Default, &unknown).is_err());
+    }
+
+    #[test]
+    fn commands_bind_to_keys_without_clashing() {
+        let command = |key: &str| CustomCommand {
+            name: "fmt".to_string(),
+            key: key.to_string(),
+            command: "cargo fmt".to_string(),
+            pause: false,
+        };
+        let mut keymap = Keymap::default();
+        keymap
+            .bind_commands(&[command("C"), command("ctrl+f")])
+            .unwrap();
+        let mut seq = KeySequence::default();
+        assert_eq!(
+            seq.feed(&keymap, Key::char('C'), Instant::now()),
+            KeyInput::Action(Action::Custom(0))
+        );
+        assert_eq!(keymap.keys_for(Action::Custom(1)).unwrap().len(), 1);
+
+        let err = Keymap::default()
+            .bind_commands(&[command("s")])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "'s' (stage) conflicts with 's' (command #1)"
+        );
+    }
+}
@@ -1,5 +1,21 @@
 pub mod app;
+pub mod check;
+pub mod commands;
+pub mod config;
+pub mod credentials;
+pub mod events;
+pub mod fsmonitor;
 pub mod git;
+pub mod highlight;
+pub mod keymap;
+pub mod logging;
+pub mod network;
+pub mod precommit;
+pub mod print;
+pub mod profile;
+pub mod server;
+pub mod state;
 pub mod types;
 pub mod ui;
+pub mod untracked;
 pub mod watcher;
@@ -0,0 +1,38 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::Level;
+
+/// Write `tracing` events to `path`, appending to an existing log. Without
+/// this no subscriber is installed and events are discarded.
+pub fn init(path: &Path) -> Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open log file {}", path.display()))?;
+    tracing_subscriber::fmt()
+        .with_writer(Mutex::new(file))
+        .with_ansi(false)
+        .with_max_level(Level::DEBUG)
+        .try_init()
+        .map_err(|e| anyhow!("Failed to start logging: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_events_to_log_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("bgs.log");
+        init(&path).unwrap();
+        tracing::info!(paths = ?["a.txt"], "stage files");
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert!(log.contains("stage files"));
+        assert!(log.contains("a.txt"));
+    }
+}
@@ -1,22 +1,172 @@
 mod app;
+mod check;
+mod commands;
+mod config;
+mod credentials;
+mod events;
+mod fsmonitor;
 mod git;
+mod highlight;
+mod keymap;
+mod logging;
+mod network;
+mod precommit;
+mod print;
+mod profile;
+mod server;
+mod state;
 mod types;
 mod ui;
+mod untracked;
 mod watcher;
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "better-git-status")]
 #[command(about = "Interactive git status with tree view and diff preview")]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to the git repository (default: current directory)
     #[arg(default_value = ".")]
     path: String,
+
+    /// Reduce redraws for slow connections (overrides `ui.low_bandwidth`)
+    #[arg(long)]
+    low_bandwidth: bool,
+
+    /// Don't watch the working tree; poll for changes instead (overrides
+    /// `watch.enabled`)
+    #[arg(long)]
+    no_watch: bool,
+
+    /// Append a debug log of git operations, file watcher events, and
+    /// refresh timings to this file
+    #[arg(long, value_name = "FILE")]
+    log: Option<PathBuf>,
+
+    /// Report how long startup took in each phase (opening the repository,
+    /// status, line counts, watcher setup, first draw) on exit, to the --log
+    /// file if there is one and otherwise to stderr
+    #[arg(long, conflicts_with_all = ["check", "json_events", "serve"])]
+    profile: bool,
+
+    /// Don't start the UI; exit 0 if the working tree is clean, 1 if it
+    /// has changes, 2 if it has conflicts (128 on error)
+    #[arg(long)]
+    check: bool,
+
+    /// With --check, print a one-line summary such as
+    /// `main: 2 staged, 1 untracked`
+    #[arg(long, requires = "check")]
+    summary: bool,
+
+    /// Don't start the UI; print newline-delimited JSON status snapshots
+    /// and change events to stdout as the working tree changes
+    #[arg(long, conflicts_with = "check")]
+    json_events: bool,
+
+    /// Don't start the UI; answer JSON status, stage, and unstage requests
+    /// on a Unix socket at this path
+    #[arg(long, value_name = "SOCKET", conflicts_with_all = ["check", "json_events"])]
+    serve: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print a file's diff the way the diff panel shows it, then exit
+    Diff {
+        /// The file, relative to the current directory
+        path: String,
+
+        /// Show the staged changes instead of the unstaged ones
+        #[arg(long)]
+        staged: bool,
+
+        /// Color the output: "auto" colors only when writing to a terminal
+        /// and NO_COLOR is unset
+        #[arg(long, value_enum, default_value_t = ColorWhen::Auto)]
+        color: ColorWhen,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorWhen {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorWhen {
+    fn enabled(self) -> bool {
+        match self {
+            ColorWhen::Always => true,
+            ColorWhen::Never => false,
+            ColorWhen::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+        }
+    }
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    app::run(&cli.path)
+    if let Some(log) = &cli.log {
+        logging::init(log)?;
+        tracing::info!(path = %cli.path, "starting");
+    }
+    let config = config::Config::load().unwrap_or_else(|e| {
+        eprintln!("Warning: {:#}. Using default settings.", e);
+        config::Config::default()
+    });
+    if cli.check {
+        let code = match check::check(&cli.path, config.preview) {
+            Ok((state, summary)) => {
+                if cli.summary {
+                    println!("{}", summary);
+                }
+                state.exit_code()
+            }
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                check::ERROR_EXIT_CODE
+            }
+        };
+        std::process::exit(code);
+    }
+    if let Some(Command::Diff {
+        path,
+        staged,
+        color,
+    }) = &cli.command
+    {
+        ui::colors::set_theme(config.ui.theme);
+        ui::symbols::set_glyphs(config.ui.glyphs);
+        let cwd = std::env::current_dir()?;
+        let diff = print::render_diff(&cwd, path, *staged, color.enabled(), config.preview)?;
+        let mut out = std::io::stdout().lock();
+        // A reader that stops early, like `| head`, isn't an error.
+        return match out.write_all(diff.as_bytes()).and_then(|()| out.flush()) {
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+            result => Ok(result?),
+        };
+    }
+    let options = app::RunOptions {
+        low_bandwidth: cli.low_bandwidth,
+        no_watch: cli.no_watch,
+        profile: cli.profile,
+    };
+    if let Some(socket) = &cli.serve {
+        return server::serve(&cli.path, socket, config.preview);
+    }
+    if cli.json_events {
+        return events::run(&cli.path, &config, &options, &mut std::io::stdout().lock());
+    }
+    app::run(&cli.path, config, options)
 }
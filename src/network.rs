@@ -0,0 +1,245 @@
+use crate::credentials::Credentials;
+use crate::git::{self, ProgressFn, PullOutcome};
+use crate::types::{NetworkOp, NetworkStatus, TransferProgress};
+use anyhow::{anyhow, Context, Result};
+use git2::{Oid, Repository};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Minimum gap between progress reports sent to the UI thread.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Longest wait between auto-fetch attempts after repeated failures.
+const MAX_AUTO_FETCH_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+enum TaskEvent {
+    Progress(TransferProgress),
+    Finished(Result<String>),
+}
+
+/// How a background network operation ended.
+pub enum NetworkOutcome {
+    /// Completed, with a message for the status bar.
+    Done(String),
+    Failed(anyhow::Error),
+    Cancelled,
+}
+
+/// A fetch, push, or pull running on a background thread so the UI can keep
+/// drawing progress and accept a cancel request.
+pub struct NetworkTask {
+    pub status: NetworkStatus,
+    /// Started by the auto-fetch timer rather than the user; runs silently
+    /// and can't be cancelled with Esc.
+    pub automatic: bool,
+    /// What a [`NetworkOp::FetchObjects`] task is fetching.
+    pub objects: Vec<Oid>,
+    cancel: Arc<AtomicBool>,
+    receiver: Receiver<TaskEvent>,
+}
+
+impl NetworkTask {
+    /// Start `op` against the repository at `git_dir`.
+    pub fn spawn(git_dir: &Path, op: NetworkOp, creds: Credentials) -> Self {
+        Self::start(git_dir, op, Vec::new(), creds)
+    }
+
+    /// Start fetching `objects` from the promisor remote of the partial
+    /// clone at `git_dir`.
+    pub fn spawn_object_fetch(git_dir: &Path, objects: Vec<Oid>, creds: Credentials) -> Self {
+        Self::start(git_dir, NetworkOp::FetchObjects, objects, creds)
+    }
+
+    fn start(git_dir: &Path, op: NetworkOp, objects: Vec<Oid>, creds: Credentials) -> Self {
+        let (tx, rx) = channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let thread_cancel = Arc::clone(&cancel);
+        let git_dir = git_dir.to_path_buf();
+        let thread_objects = objects.clone();
+
+        thread::spawn(move || {
+            let progress_tx = tx.clone();
+            let mut last_report: Option<Instant> = None;
+            let mut progress = |progress: TransferProgress| {
+                if last_report.is_none_or(|at| at.elapsed() >= PROGRESS_INTERVAL) {
+                    let _ = progress_tx.send(TaskEvent::Progress(progress));
+                    last_report = Some(Instant::now());
+                }
+                !thread_cancel.load(Ordering::Relaxed)
+            };
+            let result = run(&git_dir, op, &thread_objects, creds, &mut progress);
+            let _ = tx.send(TaskEvent::Finished(result));
+        });
+
+        Self {
+            status: NetworkStatus {
+                op,
+                progress: None,
+                cancelling: false,
+            },
+            automatic: false,
+            objects,
+            cancel,
+            receiver: rx,
+        }
+    }
+
+    /// Start a silent background fetch for the auto-fetch timer.
+    pub fn spawn_auto_fetch(git_dir: &Path) -> Self {
+        Self {
+            automatic: true,
+            ..Self::spawn(git_dir, NetworkOp::Fetch, Credentials::new())
+        }
+    }
+
+    /// Ask the operation to stop at its next progress callback.
+    pub fn cancel(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.status.cancelling = true;
+    }
+
+    /// Apply pending progress reports and return the outcome once finished.
+    pub fn poll(&mut self) -> Option<NetworkOutcome> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(TaskEvent::Progress(progress)) => self.status.progress = Some(progress),
+                Ok(TaskEvent::Finished(Ok(message))) => return Some(NetworkOutcome::Done(message)),
+                Ok(TaskEvent::Finished(Err(_))) if self.cancel.load(Ordering::Relaxed) => {
+                    return Some(NetworkOutcome::Cancelled);
+                }
+                Ok(TaskEvent::Finished(Err(e))) => return Some(NetworkOutcome::Failed(e)),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    return Some(NetworkOutcome::Failed(anyhow!(
+                        "{} stopped unexpectedly",
+                        self.status.op.name()
+                    )));
+                }
+            }
+        }
+    }
+}
+
+fn run(
+    git_dir: &Path,
+    op: NetworkOp,
+    objects: &[Oid],
+    mut creds: Credentials,
+    progress: &mut ProgressFn,
+) -> Result<String> {
+    tracing::info!(op = op.name(), "network operation started");
+    let repo = Repository::open(git_dir).context("Failed to open repository")?;
+    let result = match op {
+        NetworkOp::Fetch => {
+            git::fetch(&repo, &mut creds, progress).map(|remote| format!("Fetched {}", remote))
+        }
+        NetworkOp::Push => {
+            git::push(&repo, &mut creds, progress).map(|dest| format!("Pushed to {}", dest))
+        }
+        NetworkOp::Pull => git::pull(&repo, &mut creds, progress).map(|outcome| match outcome {
+            PullOutcome::UpToDate => "Already up to date".to_string(),
+            PullOutcome::FastForwarded => "Pulled (fast-forward)".to_string(),
+        }),
+        NetworkOp::FetchObjects => git::fetch_blobs(&repo, objects, &creds, progress)
+            .map(|()| format!("Fetched {} objects", objects.len())),
+    };
+    match &result {
+        Ok(message) => tracing::info!(op = op.name(), %message, "network operation finished"),
+        Err(e) => {
+            tracing::warn!(op = op.name(), error = %format!("{:#}", e), "network operation failed")
+        }
+    }
+    result
+}
+
+/// Schedules periodic background fetches, doubling the wait after each
+/// consecutive failure so an unreachable remote isn't hammered.
+#[derive(Debug, Clone)]
+pub struct AutoFetch {
+    interval: Duration,
+    next_at: Instant,
+    failures: u32,
+}
+
+impl AutoFetch {
+    /// First fetch is due one interval from `now`.
+    pub fn new(interval: Duration, now: Instant) -> Self {
+        Self {
+            interval,
+            next_at: now + interval,
+            failures: 0,
+        }
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.next_at
+    }
+
+    /// Number of consecutive failed attempts.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Reset the backoff and wait a full interval; also used after a manual fetch.
+    pub fn record_success(&mut self, now: Instant) {
+        self.failures = 0;
+        self.next_at = now + self.interval;
+    }
+
+    /// Back off and return how long until the next attempt.
+    pub fn record_failure(&mut self, now: Instant) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        let delay = self
+            .interval
+            .saturating_mul(2u32.saturating_pow(self.failures.min(16)))
+            .min(MAX_AUTO_FETCH_BACKOFF.max(self.interval));
+        self.next_at = now + delay;
+        delay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn auto_fetch_is_due_after_interval() {
+        let start = Instant::now();
+        let auto = AutoFetch::new(5 * MINUTE, start);
+        assert!(!auto.is_due(start));
+        assert!(!auto.is_due(start + 4 * MINUTE));
+        assert!(auto.is_due(start + 5 * MINUTE));
+    }
+
+    #[test]
+    fn auto_fetch_backs_off_and_resets() {
+        let start = Instant::now();
+        let mut auto = AutoFetch::new(5 * MINUTE, start);
+
+        assert_eq!(auto.record_failure(start), 10 * MINUTE);
+        assert_eq!(auto.record_failure(start), 20 * MINUTE);
+        assert_eq!(auto.failures(), 2);
+        assert!(!auto.is_due(start + 19 * MINUTE));
+        assert!(auto.is_due(start + 20 * MINUTE));
+
+        auto.record_success(start);
+        assert_eq!(auto.failures(), 0);
+        assert!(auto.is_due(start + 5 * MINUTE));
+    }
+
+    #[test]
+    fn auto_fetch_backoff_is_capped() {
+        let start = Instant::now();
+        let mut auto = AutoFetch::new(5 * MINUTE, start);
+        for _ in 0..40 {
+            auto.record_failure(start);
+        }
+        assert_eq!(auto.record_failure(start), MAX_AUTO_FETCH_BACKOFF);
+    }
+}
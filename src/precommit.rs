@@ -0,0 +1,188 @@
+use anyhow::{Context, Result};
+use git2::{ObjectType, Oid};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+
+/// The file that marks a repository as using the pre-commit framework.
+pub const CONFIG_FILE: &str = ".pre-commit-config.yaml";
+
+/// The program run for the pre-commit action.
+pub const PROGRAM: &str = "pre-commit";
+
+/// Whether the working tree at `workdir` has a pre-commit config.
+pub fn detect(workdir: &Path) -> bool {
+    workdir.join(CONFIG_FILE).is_file()
+}
+
+enum RunEvent {
+    Line(String),
+    Finished(std::io::Result<ExitStatus>),
+}
+
+/// What polling a running [`PreCommitRun`] produced since the last poll.
+#[derive(Debug, Default)]
+pub struct RunProgress {
+    pub lines: Vec<String>,
+    /// Set once the hooks have finished.
+    pub finished: Option<Result<ExitStatus>>,
+}
+
+/// `pre-commit run --files ...` running on a background thread, with its
+/// output streamed back a line at a time.
+pub struct PreCommitRun {
+    workdir: PathBuf,
+    /// Content hash of each file before the hooks ran; `None` if missing.
+    before: Vec<(String, Option<Oid>)>,
+    receiver: Receiver<RunEvent>,
+}
+
+impl PreCommitRun {
+    /// Start `program run --files <files>` in `workdir`.
+    pub fn spawn(program: &str, workdir: &Path, files: &[String]) -> Result<Self> {
+        let before = fingerprints(workdir, files);
+        tracing::info!(program, ?files, "run pre-commit");
+        let mut child = Command::new(program)
+            .args(["run", "--color", "never", "--files"])
+            .args(files)
+            .current_dir(workdir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", program))?;
+
+        let (tx, rx) = channel();
+        let readers = [
+            child
+                .stdout
+                .take()
+                .map(|out| forward_lines(out, tx.clone())),
+            child
+                .stderr
+                .take()
+                .map(|err| forward_lines(err, tx.clone())),
+        ];
+        thread::spawn(move || {
+            for reader in readers.into_iter().flatten() {
+                let _ = reader.join();
+            }
+            let _ = tx.send(RunEvent::Finished(child.wait()));
+        });
+
+        Ok(Self {
+            workdir: workdir.to_path_buf(),
+            before,
+            receiver: rx,
+        })
+    }
+
+    /// Output received since the last poll, and the exit status once done.
+    pub fn poll(&mut self) -> RunProgress {
+        let mut progress = RunProgress::default();
+        loop {
+            match self.receiver.try_recv() {
+                Ok(RunEvent::Line(line)) => progress.lines.push(line),
+                Ok(RunEvent::Finished(status)) => {
+                    progress.finished = Some(status.context("pre-commit did not finish"));
+                    return progress;
+                }
+                Err(TryRecvError::Empty) => return progress,
+                Err(TryRecvError::Disconnected) => {
+                    progress.finished = Some(Err(anyhow::anyhow!("pre-commit stopped reporting")));
+                    return progress;
+                }
+            }
+        }
+    }
+
+    /// The files whose content differs from before the hooks ran.
+    pub fn modified_files(&self) -> Vec<String> {
+        let paths: Vec<String> = self.before.iter().map(|(path, _)| path.clone()).collect();
+        fingerprints(&self.workdir, &paths)
+            .into_iter()
+            .zip(&self.before)
+            .filter(|(after, before)| after.1 != before.1)
+            .map(|(after, _)| after.0)
+            .collect()
+    }
+}
+
+fn forward_lines(
+    stream: impl Read + Send + 'static,
+    tx: Sender<RunEvent>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            if tx.send(RunEvent::Line(line)).is_err() {
+                break;
+            }
+        }
+    })
+}
+
+fn fingerprints(workdir: &Path, files: &[String]) -> Vec<(String, Option<Oid>)> {
+    files
+        .iter()
+        .map(|path| {
+            let hash = Oid::hash_file(ObjectType::Blob, workdir.join(path)).ok();
+            (path.clone(), hash)
+        })
+        .collect()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, Instant};
+
+    fn wait(run: &mut PreCommitRun) -> (Vec<String>, ExitStatus) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut lines = Vec::new();
+        loop {
+            let progress = run.poll();
+            lines.extend(progress.lines);
+            if let Some(status) = progress.finished {
+                return (lines, status.unwrap());
+            }
+            assert!(Instant::now() < deadline, "pre-commit run timed out");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn streams_output_and_reports_modified_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "a \n").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "b\n").unwrap();
+        let script = dir.path().join("fake-pre-commit");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\necho \"args: $*\"\necho 'fixing a.txt' >&2\nprintf 'a\\n' > a.txt\nexit 1\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let files = vec!["a.txt".to_string(), "b.txt".to_string()];
+        let mut run = PreCommitRun::spawn(script.to_str().unwrap(), dir.path(), &files).unwrap();
+        let (lines, status) = wait(&mut run);
+
+        assert_eq!(status.code(), Some(1));
+        assert!(lines.contains(&"args: run --color never --files a.txt b.txt".to_string()));
+        assert!(lines.contains(&"fixing a.txt".to_string()));
+        assert_eq!(run.modified_files(), vec!["a.txt".to_string()]);
+    }
+
+    #[test]
+    fn missing_program_fails_to_spawn() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(PreCommitRun::spawn("bgs-no-such-program", dir.path(), &[]).is_err());
+        assert!(!detect(dir.path()));
+        std::fs::write(dir.path().join(CONFIG_FILE), "repos: []\n").unwrap();
+        assert!(detect(dir.path()));
+    }
+}
@@ -0,0 +1,174 @@
+use crate::config::PreviewConfig;
+use crate::git::{self, DiffFlags};
+use crate::types::Section;
+use crate::ui::diff_panel;
+use anyhow::{bail, Context, Result};
+use git2::Repository;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use std::path::{Component, Path, PathBuf};
+
+/// The diff panel's rendering of `file`, one terminal line per diff line.
+/// `file` is relative to `cwd`, which may be anywhere inside the working
+/// tree. The unstaged diff is shown unless `staged`; untracked files are
+/// shown as a preview. With `color` off the output is plain text.
+pub fn render_diff(
+    cwd: &Path,
+    file: &str,
+    staged: bool,
+    color: bool,
+    preview: PreviewConfig,
+) -> Result<String> {
+    let repo = Repository::discover(cwd).context("Not a git repository")?;
+    let workdir = repo
+        .workdir()
+        .context("Repository has no working directory")?
+        .to_path_buf();
+    let path = repo_relative(&workdir, cwd, file);
+    let status = git::get_status(&repo, preview)?;
+    let (section, files) = if staged {
+        (Section::Staged, &status.staged_files)
+    } else {
+        (Section::Unstaged, &status.unstaged_files)
+    };
+    let Some(entry) = files.iter().find(|f| f.path == path) else {
+        let which = if staged { "staged" } else { "unstaged" };
+        bail!("No {} changes to {}", which, path);
+    };
+
+    let diff = git::get_file_diff(&repo, entry, section, DiffFlags::default(), false, preview);
+    let mut out = String::new();
+    for line in diff_panel::full_lines(&diff) {
+        write_line(&mut out, &line, color);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// `file` (relative to `cwd`) as a path relative to `workdir`, with `.` and
+/// `..` resolved. Paths outside the working tree are returned as given.
+fn repo_relative(workdir: &Path, cwd: &Path, file: &str) -> String {
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    let mut absolute = PathBuf::new();
+    for component in canonical(cwd).join(file).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            other => absolute.push(other),
+        }
+    }
+    match absolute.strip_prefix(canonical(workdir)) {
+        Ok(relative) => relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        Err(_) => file.to_string(),
+    }
+}
+
+/// Append `line`'s text to `out`, with SGR escapes for its styles when
+/// `color` is set.
+fn write_line(out: &mut String, line: &Line, color: bool) {
+    for span in &line.spans {
+        let style = line.style.patch(span.style);
+        let sgr = if color { sgr_codes(style) } else { Vec::new() };
+        if sgr.is_empty() || span.content.is_empty() {
+            out.push_str(&span.content);
+        } else {
+            out.push_str(&format!("\x1b[{}m{}\x1b[0m", sgr.join(";"), span.content));
+        }
+    }
+}
+
+fn sgr_codes(style: Style) -> Vec<String> {
+    let mut codes = Vec::new();
+    for (modifier, code) in [
+        (Modifier::BOLD, "1"),
+        (Modifier::DIM, "2"),
+        (Modifier::ITALIC, "3"),
+        (Modifier::UNDERLINED, "4"),
+        (Modifier::REVERSED, "7"),
+    ] {
+        if style.add_modifier.contains(modifier) {
+            codes.push(code.to_string());
+        }
+    }
+    if let Some(fg) = style.fg.and_then(|c| color_code(c, 30)) {
+        codes.push(fg);
+    }
+    if let Some(bg) = style.bg.and_then(|c| color_code(c, 40)) {
+        codes.push(bg);
+    }
+    codes
+}
+
+/// The SGR parameter for `color`, where `base` is 30 for foreground and 40
+/// for background.
+fn color_code(color: Color, base: u8) -> Option<String> {
+    let named = |n: u8| Some((base + n).to_string());
+    let bright = |n: u8| Some((base + 60 + n).to_string());
+    match color {
+        Color::Reset => None,
+        Color::Black => named(0),
+        Color::Red => named(1),
+        Color::Green => named(2),
+        Color::Yellow => named(3),
+        Color::Blue => named(4),
+        Color::Magenta => named(5),
+        Color::Cyan => named(6),
+        Color::Gray => named(7),
+        Color::DarkGray => bright(0),
+        Color::LightRed => bright(1),
+        Color::LightGreen => bright(2),
+        Color::LightYellow => bright(3),
+        Color::LightBlue => bright(4),
+        Color::LightMagenta => bright(5),
+        Color::LightCyan => bright(6),
+        Color::White => bright(7),
+        Color::Indexed(i) => Some(format!("{};5;{}", base + 8, i)),
+        Color::Rgb(r, g, b) => Some(format!("{};2;{};{};{}", base + 8, r, g, b)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::text::Span;
+
+    #[test]
+    fn write_line_emits_sgr_only_with_color() {
+        let line = Line::from(vec![
+            Span::raw("  1 │"),
+            Span::styled("+", Style::default().fg(Color::Rgb(1, 2, 3))),
+            Span::styled(
+                "x",
+                Style::default()
+                    .fg(Color::LightRed)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ]);
+
+        let mut plain = String::new();
+        write_line(&mut plain, &line, false);
+        assert_eq!(plain, "  1 │+x");
+
+        let mut colored = String::new();
+        write_line(&mut colored, &line, true);
+        assert_eq!(colored, "  1 │\x1b[38;2;1;2;3m+\x1b[0m\x1b[1;91mx\x1b[0m");
+    }
+
+    #[test]
+    fn repo_relative_resolves_against_cwd() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("src");
+        std::fs::create_dir(&sub).unwrap();
+
+        assert_eq!(repo_relative(dir.path(), &sub, "main.rs"), "src/main.rs");
+        assert_eq!(repo_relative(dir.path(), &sub, "../README.md"), "README.md");
+        assert_eq!(repo_relative(dir.path(), dir.path(), "./a/b.rs"), "a/b.rs");
+        assert_eq!(repo_relative(&sub, dir.path(), "other.rs"), "other.rs");
+    }
+}
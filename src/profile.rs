@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+/// Time spent in each startup phase, collected for `--profile`.
+#[derive(Debug)]
+pub struct StartupProfile {
+    started: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Default for StartupProfile {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            phases: Vec::new(),
+        }
+    }
+}
+
+impl StartupProfile {
+    pub fn record(&mut self, phase: &'static str, duration: Duration) {
+        self.phases.push((phase, duration));
+    }
+
+    /// Run `f`, recording how long it took as `phase`.
+    pub fn time<T>(&mut self, phase: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(phase, start.elapsed());
+        result
+    }
+
+    /// Record the time since the profile was created as the total.
+    pub fn finish(&mut self) {
+        self.record("total", self.started.elapsed());
+    }
+
+    /// One line per phase, in the order recorded.
+    pub fn report(&self) -> Vec<String> {
+        self.phases
+            .iter()
+            .map(|(phase, duration)| {
+                format!("{:<16} {:>9.1} ms", phase, duration.as_secs_f64() * 1000.0)
+            })
+            .collect()
+    }
+
+    /// Send the report to the log if `--log` set one up, else to stderr.
+    pub fn emit(&self) {
+        let report = self.report();
+        if tracing::dispatcher::has_been_set() {
+            for line in &report {
+                tracing::info!("profile: {}", line);
+            }
+        } else {
+            eprintln!("Startup profile:");
+            for line in &report {
+                eprintln!("  {}", line);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_lists_phases_in_order() {
+        let mut profile = StartupProfile::default();
+        profile.record("repo open", Duration::from_micros(1500));
+        let value = profile.time("status", || 7);
+        assert_eq!(value, 7);
+        profile.finish();
+
+        let report = profile.report();
+        assert_eq!(report.len(), 3);
+        assert_eq!(report[0], "repo open              1.5 ms");
+        assert!(report[1].starts_with("status "));
+        assert!(report[2].starts_with("total "));
+    }
+}
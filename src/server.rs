@@ -0,0 +1,122 @@
+use crate::config::PreviewConfig;
+use crate::events::{self, Snapshot};
+use crate::git;
+use anyhow::Result;
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+
+/// One line of input on the `--serve` socket.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Request {
+    Status,
+    Stage { paths: Vec<String> },
+    Unstage { paths: Vec<String> },
+    StageAll,
+    UnstageAll,
+}
+
+/// The reply to a [`Request`]: the status after the command ran, or why it
+/// failed.
+#[derive(Debug, Serialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<Snapshot>,
+}
+
+/// Run one JSON request line against `repo`.
+pub fn handle(repo: &Repository, line: &str, preview: PreviewConfig) -> Response {
+    let result = serde_json::from_str::<Request>(line)
+        .map_err(anyhow::Error::from)
+        .and_then(|request| run(repo, request, preview));
+    match result {
+        Ok(status) => Response {
+            ok: true,
+            error: None,
+            status: Some(status),
+        },
+        Err(e) => Response {
+            ok: false,
+            error: Some(format!("{:#}", e)),
+            status: None,
+        },
+    }
+}
+
+fn run(repo: &Repository, request: Request, preview: PreviewConfig) -> Result<Snapshot> {
+    tracing::debug!(?request, "server request");
+    match request {
+        Request::Status => {}
+        Request::Stage { paths } => git::stage_files(repo, &paths)?,
+        Request::Unstage { paths } => git::unstage_files(repo, &paths)?,
+        Request::StageAll => {
+            git::stage_all(repo)?;
+        }
+        Request::UnstageAll => {
+            git::unstage_all(repo)?;
+        }
+    }
+    events::snapshot(repo, preview)
+}
+
+/// Answer requests for the repository at `path` on a Unix socket at
+/// `socket` until killed. Each connection is served on its own thread, one
+/// JSON response line per request line. A socket file left behind by an
+/// earlier server is replaced.
+#[cfg(unix)]
+pub fn serve(path: &str, socket: &std::path::Path, preview: PreviewConfig) -> Result<()> {
+    use anyhow::{bail, Context};
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    // Fail before binding if the repository can't be opened.
+    git::get_repo(path)?;
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            bail!("{} is already in use", socket.display());
+        }
+        std::fs::remove_file(socket)
+            .with_context(|| format!("Failed to remove stale {}", socket.display()))?;
+    }
+    let listener = UnixListener::bind(socket)
+        .with_context(|| format!("Failed to listen on {}", socket.display()))?;
+    tracing::info!(socket = %socket.display(), "serving");
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to accept connection");
+                continue;
+            }
+        };
+        let path = path.to_string();
+        std::thread::spawn(move || {
+            let result = (|| -> Result<()> {
+                let repo = git::get_repo(&path)?;
+                let mut writer = stream.try_clone()?;
+                for line in BufReader::new(stream).lines() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let response = serde_json::to_string(&handle(&repo, &line, preview))?;
+                    writeln!(writer, "{}", response)?;
+                }
+                Ok(())
+            })();
+            if let Err(e) = result {
+                tracing::warn!(error = %format!("{:#}", e), "connection closed");
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve(_path: &str, _socket: &std::path::Path, _preview: PreviewConfig) -> Result<()> {
+    anyhow::bail!("--serve needs Unix domain sockets, which this platform lacks")
+}
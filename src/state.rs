@@ -0,0 +1,162 @@
+use crate::types::Section;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// What the UI looked like when a repository was last closed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepoState {
+    /// The file the highlight was on.
+    pub highlighted: Option<FileRef>,
+    /// The file whose diff was open.
+    pub selected: Option<FileRef>,
+    pub show_graph: bool,
+    pub ignore_eol: bool,
+}
+
+/// A row of the file list, by identity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRef {
+    pub section: Section,
+    /// The path as git stores it; see [`FileEntry::raw_path`].
+    ///
+    /// [`FileEntry::raw_path`]: crate::types::FileEntry::raw_path
+    #[serde(with = "hex_path")]
+    pub path: Vec<u8>,
+}
+
+/// Paths are saved as hex so names that aren't valid UTF-8 come back
+/// byte for byte.
+mod hex_path {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(path: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let hex: String = path.iter().map(|b| format!("{:02x}", b)).collect();
+        serializer.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or_else(|| D::Error::custom(format!("invalid path {:?}", hex)))
+            })
+            .collect()
+    }
+}
+
+/// Every repository's state, keyed by working directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct StateFile {
+    repos: BTreeMap<String, RepoState>,
+}
+
+impl StateFile {
+    fn read(path: &Path) -> Result<Self> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        toml::from_str(&text).with_context(|| format!("Invalid state in {}", path.display()))
+    }
+}
+
+/// The saved state for the repository at `repo_key`. A missing or
+/// unreadable state file gives the defaults.
+pub fn load(path: &Path, repo_key: &str) -> RepoState {
+    match StateFile::read(path) {
+        Ok(mut file) => file.repos.remove(repo_key).unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!(error = %format!("{:#}", e), "ignoring UI state");
+            RepoState::default()
+        }
+    }
+}
+
+/// Record `state` for `repo_key`, keeping other repositories' entries.
+pub fn save(path: &Path, repo_key: &str, state: RepoState) -> Result<()> {
+    // A corrupt file is replaced rather than blocking saves forever.
+    let mut file = StateFile::read(path).unwrap_or_default();
+    file.repos.insert(repo_key.to_string(), state);
+    let text = toml::to_string(&file).context("Failed to serialize UI state")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// `$XDG_STATE_HOME/better-git-status/state.toml`, or `~/.local/state/...`.
+pub fn state_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        })?;
+    Some(base.join("better-git-status").join("state.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load_round_trip_per_repo() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("state.toml");
+        let state = RepoState {
+            highlighted: Some(FileRef {
+                section: Section::Unstaged,
+                path: b"src/main.rs".to_vec(),
+            }),
+            selected: None,
+            show_graph: true,
+            ignore_eol: false,
+        };
+
+        save(&path, "/repos/a", state.clone()).unwrap();
+        save(&path, "/repos/b", RepoState::default()).unwrap();
+
+        assert_eq!(load(&path, "/repos/a"), state);
+        assert_eq!(load(&path, "/repos/b"), RepoState::default());
+        assert_eq!(load(&path, "/repos/missing"), RepoState::default());
+    }
+
+    #[test]
+    fn non_utf8_path_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.toml");
+        let state = RepoState {
+            selected: Some(FileRef {
+                section: Section::Staged,
+                path: b"caf\xe9.txt".to_vec(),
+            }),
+            ..RepoState::default()
+        };
+
+        save(&path, "/repo", state.clone()).unwrap();
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("path = \"636166e92e747874\""));
+        assert_eq!(load(&path, "/repo"), state);
+    }
+
+    #[test]
+    fn corrupt_state_file_is_ignored_and_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.toml");
+        std::fs::write(&path, "not = [valid").unwrap();
+
+        assert_eq!(load(&path, "/repo"), RepoState::default());
+        save(&path, "/repo", RepoState::default()).unwrap();
+        assert_eq!(load(&path, "/repo"), RepoState::default());
+    }
+}
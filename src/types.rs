@@ -1,7 +1,12 @@
-use std::collections::HashSet;
+use crate::config::{ConfirmLevel, KeyPreset, Theme};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::SystemTime;
 
-/// Type alias for multi-select set containing (Section, path) pairs.
-pub type MultiSelectSet = HashSet<(Section, String)>;
+/// Type alias for multi-select set containing (Section, raw path) pairs.
+pub type MultiSelectSet = HashSet<(Section, Vec<u8>)>;
 
 /// A file entry representing a changed file in the git repository.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -10,6 +15,11 @@ pub struct FileEntry {
     pub path: String,
     /// The original path for renamed files (None if not a rename).
     pub old_path: Option<String>,
+    /// `path` as git stores it. Differs from `path` only when the name
+    /// isn't valid UTF-8; use this for index and filesystem operations.
+    pub raw_path: Vec<u8>,
+    /// `old_path` as git stores it.
+    pub raw_old_path: Option<Vec<u8>>,
     /// The type of change (added, modified, deleted, etc.).
     pub status: FileStatus,
     /// Number of lines added (None if not computable).
@@ -20,15 +30,77 @@ pub struct FileEntry {
     pub is_binary: bool,
     /// Whether the file is a submodule.
     pub is_submodule: bool,
+    /// Set when most of the change is line endings flipping.
+    pub eol_change: Option<EolChange>,
+    /// Whether added lines have whitespace errors (see [`whitespace_issues`]).
+    pub whitespace_errors: bool,
+    /// Generated output such as a lockfile, whose diff starts collapsed.
+    pub generated: bool,
+    /// Git file mode before the change (e.g. `0o100644`); 0 if it didn't
+    /// exist or isn't known.
+    pub old_mode: u32,
+    /// Git file mode after the change; 0 if it was deleted or isn't known.
+    pub mode: u32,
+    /// Size in bytes of the working tree file, if it exists.
+    pub size: Option<u64>,
+    /// When the working tree file was last modified, if it exists.
+    pub mtime: Option<SystemTime>,
+    /// How a conflicted file conflicts.
+    pub conflict: Option<ConflictKind>,
 }
 
-/// The type of change for a file.
+impl FileEntry {
+    /// The mode of whichever side exists, preferring the new one.
+    pub fn current_mode(&self) -> u32 {
+        if self.mode != 0 {
+            self.mode
+        } else {
+            self.old_mode
+        }
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.current_mode() == u32::from(git2::FileMode::BlobExecutable)
+    }
+
+    /// Whether only the mode changed, e.g. a file made executable.
+    pub fn mode_only(&self) -> bool {
+        self.status == FileStatus::Modified
+            && self.old_mode != 0
+            && self.mode != 0
+            && self.old_mode != self.mode
+            && !self.is_binary
+            && self.added_lines == Some(0)
+            && self.deleted_lines == Some(0)
+    }
+}
+
+/// Direction of a line-ending conversion.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EolChange {
+    CrlfToLf,
+    LfToCrlf,
+}
+
+impl EolChange {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EolChange::CrlfToLf => "CRLF->LF",
+            EolChange::LfToCrlf => "LF->CRLF",
+        }
+    }
+}
+
+/// The type of change for a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FileStatus {
     Added,
     Modified,
     Deleted,
     Renamed,
+    /// Changed between regular file, symlink, and submodule.
+    TypeChanged,
     Untracked,
     Conflict,
 }
@@ -41,19 +113,96 @@ impl FileStatus {
             FileStatus::Modified => "M",
             FileStatus::Deleted => "D",
             FileStatus::Renamed => "R",
+            FileStatus::TypeChanged => "T",
             FileStatus::Untracked => "?",
             FileStatus::Conflict => "C",
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileStatus::Added => "added",
+            FileStatus::Modified => "modified",
+            FileStatus::Deleted => "deleted",
+            FileStatus::Renamed => "renamed",
+            FileStatus::TypeChanged => "type changed",
+            FileStatus::Untracked => "untracked",
+            FileStatus::Conflict => "conflict",
+        }
+    }
+}
+
+/// How a path conflicts, named as `git status` names it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    BothModified,
+    BothAdded,
+    BothDeleted,
+    AddedByUs,
+    AddedByThem,
+    DeletedByUs,
+    DeletedByThem,
+}
+
+impl ConflictKind {
+    /// The kind of conflict with a base (stage 1), our (stage 2), and
+    /// their (stage 3) version present as given.
+    pub fn from_stages(base: bool, ours: bool, theirs: bool) -> Option<Self> {
+        match (base, ours, theirs) {
+            (true, true, true) => Some(ConflictKind::BothModified),
+            (false, true, true) => Some(ConflictKind::BothAdded),
+            (true, false, false) => Some(ConflictKind::BothDeleted),
+            (false, true, false) => Some(ConflictKind::AddedByUs),
+            (false, false, true) => Some(ConflictKind::AddedByThem),
+            (true, false, true) => Some(ConflictKind::DeletedByUs),
+            (true, true, false) => Some(ConflictKind::DeletedByThem),
+            (false, false, false) => None,
+        }
+    }
+
+    /// The two-letter code `git status --short` shows.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ConflictKind::BothModified => "UU",
+            ConflictKind::BothAdded => "AA",
+            ConflictKind::BothDeleted => "DD",
+            ConflictKind::AddedByUs => "AU",
+            ConflictKind::AddedByThem => "UA",
+            ConflictKind::DeletedByUs => "DU",
+            ConflictKind::DeletedByThem => "UD",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConflictKind::BothModified => "both modified",
+            ConflictKind::BothAdded => "both added",
+            ConflictKind::BothDeleted => "both deleted",
+            ConflictKind::AddedByUs => "added by us",
+            ConflictKind::AddedByThem => "added by them",
+            ConflictKind::DeletedByUs => "deleted by us",
+            ConflictKind::DeletedByThem => "deleted by them",
+        }
+    }
 }
 
 /// Which section a file belongs to (staged or unstaged).
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Section {
     Staged,
     Unstaged,
 }
 
+impl Section {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Section::Staged => "staged",
+            Section::Unstaged => "unstaged",
+        }
+    }
+}
+
 /// Information about the current branch or detached HEAD.
 #[derive(Debug, Clone)]
 pub enum BranchInfo {
@@ -61,6 +210,8 @@ pub enum BranchInfo {
     Branch(String),
     /// Detached HEAD at a specific commit (short hash).
     Detached(String),
+    /// On a branch with no commits yet, as in a new repository.
+    Unborn(String),
 }
 
 impl std::fmt::Display for BranchInfo {
@@ -68,10 +219,106 @@ impl std::fmt::Display for BranchInfo {
         match self {
             BranchInfo::Branch(name) => write!(f, "{}", name),
             BranchInfo::Detached(hash) => write!(f, "HEAD@{}", hash),
+            BranchInfo::Unborn(name) => write!(f, "{} (no commits yet)", name),
+        }
+    }
+}
+
+/// Persistent hint that the upstream has commits the local branch lacks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamNotice {
+    /// Short name of the upstream branch (e.g., "origin/main").
+    pub upstream: String,
+    /// Number of upstream commits not in the local branch.
+    pub behind: usize,
+}
+
+impl std::fmt::Display for UpstreamNotice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "↓{} new on {} — pull?", self.behind, self.upstream)
+    }
+}
+
+/// Tracking information for the current branch's upstream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamInfo {
+    /// Short name of the upstream branch (e.g., "origin/main").
+    pub name: String,
+    /// Number of local commits not on the upstream.
+    pub ahead: usize,
+    /// Number of upstream commits not in the local branch.
+    pub behind: usize,
+}
+
+/// A one-line summary of a commit for compact displays.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitSummary {
+    /// Abbreviated commit hash.
+    pub short_id: String,
+    /// First line of the commit message.
+    pub subject: String,
+}
+
+/// The shape of the divergence between HEAD and its upstream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DivergenceGraph {
+    pub upstream: UpstreamInfo,
+    /// Most recent commits only on HEAD, newest first.
+    pub local: Vec<CommitSummary>,
+    /// Most recent commits only on the upstream, newest first.
+    pub remote: Vec<CommitSummary>,
+    /// The common ancestor both sides fork from.
+    pub merge_base: Option<CommitSummary>,
+}
+
+/// Progress of an in-flight rebase, read from the rebase state directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebaseProgress {
+    /// 1-based index of the commit currently being applied.
+    pub current: usize,
+    /// Total number of commits in the rebase.
+    pub total: usize,
+    /// Subject line of the commit being applied (empty if unknown).
+    pub subject: String,
+}
+
+impl std::fmt::Display for RebaseProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.subject.is_empty() {
+            write!(f, "applying {}/{}", self.current, self.total)
+        } else {
+            write!(
+                f,
+                "applying {}/{}: {}",
+                self.current, self.total, self.subject
+            )
         }
     }
 }
 
+/// Which panel navigation keys act on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Focus {
+    #[default]
+    FileList,
+    /// The diff, with a cursor on one of its lines.
+    Diff,
+}
+
+/// Lines picked in the diff's visual mode, as indices into
+/// `DiffContent::Text`. `anchor` stays put while `cursor` moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffSelection {
+    pub anchor: usize,
+    pub cursor: usize,
+}
+
+impl DiffSelection {
+    pub fn range(&self) -> RangeInclusive<usize> {
+        self.anchor.min(self.cursor)..=self.anchor.max(self.cursor)
+    }
+}
+
 /// The content of a diff to display in the diff panel.
 #[derive(Debug, Clone)]
 pub enum DiffContent {
@@ -81,16 +328,230 @@ pub enum DiffContent {
     Clean,
     /// Diff text with line-by-line content.
     Text(Vec<DiffLine>),
+    /// Content of an untracked file.
+    Preview(FilePreview),
     /// File is binary.
-    Binary,
-    /// File contains invalid UTF-8.
+    Binary(BinaryInfo),
+    /// A small binary file shown as a hexdump.
+    Hexdump(BinaryInfo),
+    /// A text file over the preview size limit, shown by its sizes.
+    TooLarge(BinaryInfo),
+    /// Only the file mode changed, e.g. `chmod +x`.
+    ModeChange { old_mode: u32, new_mode: u32 },
+    /// The entry changed kind, e.g. from a regular file to a symlink.
+    TypeChange { old: EntryKind, new: EntryKind },
+    /// A symlink was added, removed, or retargeted. `None` is a missing side.
+    Symlink {
+        old_target: Option<String>,
+        new_target: Option<String>,
+    },
+    /// The diff of a generated file, hidden until expanded.
+    Collapsed {
+        added: Option<usize>,
+        deleted: Option<usize>,
+    },
+    /// A diff dominated by one enormous line, such as minified JS, shown as
+    /// a summary until expanded: how many lines changed and their size.
+    Minified { lines: usize, bytes: u64 },
+    /// Blobs the diff needs are being fetched from a partial clone's
+    /// promisor remote.
+    Fetching,
+    /// File is neither UTF-8 nor valid in its detected encoding.
     InvalidUtf8,
-    /// File has merge conflicts.
-    Conflict,
+    /// File has merge conflicts; shown as its working tree content with
+    /// the conflict regions marked.
+    Conflict(ConflictPreview),
 }
 
-/// A single line in a diff.
+/// A conflicted file's working tree content, markers and all.
+#[derive(Debug, Clone, Default)]
+pub struct ConflictPreview {
+    pub kind: Option<ConflictKind>,
+    /// The file's lines; empty when it is missing, binary, or too large.
+    pub lines: Vec<String>,
+}
+
+/// The part a line plays in a file's conflict regions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictRole {
+    /// Outside any conflict region.
+    Outside,
+    /// A `<<<<<<<`, `|||||||`, `=======`, or `>>>>>>>` line.
+    Marker,
+    Ours,
+    /// The common ancestor's lines, in `diff3` style conflicts.
+    Base,
+    Theirs,
+}
+
+/// Whether `line` is a conflict marker made of seven `c`s.
+fn is_conflict_marker(line: &str, c: u8) -> bool {
+    let bytes = line.as_bytes();
+    bytes.len() >= 7
+        && bytes[..7].iter().all(|&b| b == c)
+        && bytes.get(7).is_none_or(|&b| b == b' ')
+}
+
+impl ConflictPreview {
+    /// Each line's part in the conflict regions.
+    pub fn roles(&self) -> Vec<ConflictRole> {
+        let mut state = ConflictRole::Outside;
+        self.lines
+            .iter()
+            .map(|line| {
+                let next = match state {
+                    ConflictRole::Outside if is_conflict_marker(line, b'<') => ConflictRole::Ours,
+                    ConflictRole::Ours if is_conflict_marker(line, b'|') => ConflictRole::Base,
+                    ConflictRole::Ours | ConflictRole::Base if is_conflict_marker(line, b'=') => {
+                        ConflictRole::Theirs
+                    }
+                    ConflictRole::Theirs if is_conflict_marker(line, b'>') => ConflictRole::Outside,
+                    _ => return state,
+                };
+                state = next;
+                ConflictRole::Marker
+            })
+            .collect()
+    }
+
+    /// Whether any `<<<<<<<` or `>>>>>>>` marker is left in the file.
+    pub fn has_markers(&self) -> bool {
+        self.lines
+            .iter()
+            .any(|line| is_conflict_marker(line, b'<') || is_conflict_marker(line, b'>'))
+    }
+
+    /// The indices of the `<<<<<<<` lines that open each conflict region.
+    pub fn region_starts(&self) -> Vec<usize> {
+        self.roles()
+            .into_iter()
+            .zip(&self.lines)
+            .enumerate()
+            .filter(|(_, (role, line))| {
+                *role == ConflictRole::Marker && is_conflict_marker(line, b'<')
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+/// What a git file mode means, e.g. "executable" for `100755`.
+pub fn describe_mode(mode: u32) -> &'static str {
+    match mode {
+        0o100644 => "regular file",
+        0o100755 => "executable",
+        0o120000 => "symlink",
+        0o160000 => "submodule",
+        0o040000 => "directory",
+        _ => "unknown",
+    }
+}
+
+/// What a path is on one side of a change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryKind {
+    pub mode: u32,
+    /// Where the link points, for symlinks.
+    pub target: Option<String>,
+}
+
+impl std::fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.target {
+            Some(target) => write!(f, "symlink to {}", target),
+            None => write!(f, "{}", describe_mode(self.mode)),
+        }
+    }
+}
+
+/// What is known about a binary file's change, shown in place of a diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryInfo {
+    pub change: BinaryChange,
+    /// Size in bytes before the change, if the file existed.
+    pub old_size: Option<u64>,
+    /// Size in bytes after the change, if the file still exists.
+    pub new_size: Option<u64>,
+    /// MIME type detected from the content, e.g. "image/png".
+    pub mime_type: String,
+    /// Content of the newer side (the older one for deletions), kept only
+    /// when small enough for the hexdump view.
+    pub content: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryChange {
+    Added,
+    Deleted,
+    Modified,
+}
+
+impl BinaryChange {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BinaryChange::Added => "added",
+            BinaryChange::Deleted => "deleted",
+            BinaryChange::Modified => "modified",
+        }
+    }
+}
+
+/// An untracked file's content, highlighted for display.
 #[derive(Debug, Clone)]
+pub struct FilePreview {
+    pub path: String,
+    /// Size on disk in bytes.
+    pub size: u64,
+    /// Detected file type, e.g. "Rust" or "Plain Text".
+    pub file_type: String,
+    /// One entry per line, split into highlighted tokens.
+    pub lines: Vec<Vec<(TokenKind, String)>>,
+}
+
+/// Syntax class of a highlighted token. Colors come from the active theme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Plain,
+    Comment,
+    String,
+    Keyword,
+    Constant,
+    Function,
+    Type,
+}
+
+/// Byte ranges of whitespace errors in `line`, as `git diff --check` reports
+/// them: trailing whitespace, and spaces directly before a tab in the
+/// indentation. A final CR is treated as part of the line ending.
+pub fn whitespace_issues(line: &str) -> Vec<std::ops::Range<usize>> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let content_end = line.trim_end_matches([' ', '\t']).len();
+    let mut issues = Vec::new();
+
+    let indent_end = line.len() - line.trim_start_matches([' ', '\t']).len();
+    if content_end > 0 {
+        let mut spaces_start = None;
+        for (i, c) in line[..indent_end].char_indices() {
+            match c {
+                ' ' => {
+                    spaces_start.get_or_insert(i);
+                }
+                _ => {
+                    if let Some(start) = spaces_start.take() {
+                        issues.push(start..i);
+                    }
+                }
+            }
+        }
+    }
+    if content_end < line.len() {
+        issues.push(content_end..line.len());
+    }
+    issues
+}
+
+/// A single line in a diff.
+#[derive(Debug, Clone, Hash)]
 pub struct DiffLine {
     /// The type of line (header, hunk, context, added, deleted).
     pub kind: DiffLineKind,
@@ -101,7 +562,7 @@ pub struct DiffLine {
 }
 
 /// The type of a diff line.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DiffLineKind {
     /// Diff header line (e.g., "diff --git a/... b/...").
     Header,
@@ -120,8 +581,8 @@ pub enum DiffLineKind {
 pub struct VisibleRow {
     /// Which section this row belongs to.
     pub section: Section,
-    /// The file path.
-    pub path: String,
+    /// The file's path as git stores it; see [`FileEntry::raw_path`].
+    pub raw_path: Vec<u8>,
 }
 
 /// Action to perform after confirmation.
@@ -129,15 +590,37 @@ pub struct VisibleRow {
 pub enum ConfirmAction {
     StageAll,
     UnstageAll,
-    DiscardSelected { paths: Vec<(Section, String)> },
+    /// Stage files, some of them conflicted files with markers left in.
+    StageWithMarkers {
+        paths: Vec<Vec<u8>>,
+    },
+    DiscardSelected {
+        paths: Vec<(Section, Vec<u8>)>,
+    },
     DiscardAll,
+    /// Discard some changed lines of one file; see `git::apply_changed_lines`.
+    /// `diff` fingerprints the diff they were picked from, since `changes`
+    /// only hold positions in it.
+    DiscardLines {
+        path: Vec<u8>,
+        changes: Vec<usize>,
+        diff: u64,
+    },
 }
 
 /// Undo action for reverting stage/unstage operations.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum UndoAction {
-    Stage { paths: Vec<String> },
-    Unstage { paths: Vec<String> },
+    Stage { paths: Vec<Vec<u8>> },
+    Unstage { paths: Vec<Vec<u8>> },
+}
+
+impl UndoAction {
+    pub fn paths(&self) -> &[Vec<u8>] {
+        match self {
+            UndoAction::Stage { paths } | UndoAction::Unstage { paths } => paths,
+        }
+    }
 }
 
 /// Confirmation prompt state.
@@ -147,12 +630,424 @@ pub struct ConfirmPrompt {
     pub action: ConfirmAction,
 }
 
+/// An overlay panel drawn on top of the main UI that captures input.
+#[derive(Debug, Clone)]
+pub enum Modal {
+    Remotes(RemotePanel),
+    Credentials(CredentialPrompt),
+    Messages(MessagePanel),
+    Stashes(StashPanel),
+    Recover(RecoverPanel),
+    InitialCommit(CommitPrompt),
+    NewBranch(BranchPrompt),
+    ErrorDetail(ErrorDetail),
+    Setup(SetupForm),
+    PreCommit(PreCommitPanel),
+}
+
+/// A network operation against the current branch's remote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkOp {
+    Fetch,
+    Push,
+    Pull,
+    /// Fetching blobs a partial clone needs for a diff.
+    FetchObjects,
+}
+
+impl NetworkOp {
+    /// Present participle for progress and prompt text (e.g., "Fetching").
+    pub fn verb(&self) -> &'static str {
+        match self {
+            NetworkOp::Fetch => "Fetching",
+            NetworkOp::Push => "Pushing",
+            NetworkOp::Pull => "Pulling",
+            NetworkOp::FetchObjects => "Fetching objects",
+        }
+    }
+
+    /// Noun form for result messages (e.g., "Fetch cancelled").
+    pub fn name(&self) -> &'static str {
+        match self {
+            NetworkOp::Fetch => "Fetch",
+            NetworkOp::Push => "Push",
+            NetworkOp::Pull => "Pull",
+            NetworkOp::FetchObjects => "Object fetch",
+        }
+    }
+}
+
+/// Which stage of a transfer a progress report describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferPhase {
+    /// Downloading objects from the remote.
+    Receiving,
+    /// Resolving deltas in the received pack.
+    Resolving,
+    /// Building the pack to send.
+    Packing,
+    /// Sending objects to the remote.
+    Uploading,
+}
+
+/// A snapshot of git2's transfer progress callbacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferProgress {
+    pub phase: TransferPhase,
+    pub current: usize,
+    pub total: usize,
+    /// Bytes transferred so far; zero for phases that don't report bytes.
+    pub bytes: usize,
+}
+
+impl std::fmt::Display for TransferProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self.phase {
+            TransferPhase::Receiving => "receiving objects",
+            TransferPhase::Resolving => "resolving deltas",
+            TransferPhase::Packing => "packing objects",
+            TransferPhase::Uploading => "uploading objects",
+        };
+        write!(f, "{} {}/{}", label, self.current, self.total)?;
+        if let Some(percent) = (self.current * 100).checked_div(self.total) {
+            write!(f, " ({}%)", percent)?;
+        }
+        if self.bytes > 0 {
+            write!(f, ", {}", format_bytes(self.bytes))?;
+        }
+        Ok(())
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Why status is refreshed on a timer instead of by the file watcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PollReason {
+    /// `--no-watch` or `watch.enabled = false`.
+    Disabled,
+    /// The working tree is on a network filesystem of this type, where
+    /// change notifications miss edits made on other machines.
+    NetworkFs(String),
+    /// The watcher could not start or stopped.
+    WatcherFailed,
+    /// The watcher kept reporting errors.
+    WatcherErrors,
+    /// The OS limit on watches was hit, so only part of the tree is
+    /// watched; polling covers the rest.
+    WatchLimit,
+}
+
+impl std::fmt::Display for PollReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PollReason::Disabled => write!(f, "watching off"),
+            PollReason::NetworkFs(fs_type) => write!(f, "{} mount", fs_type),
+            PollReason::WatcherFailed => write!(f, "watcher unavailable"),
+            PollReason::WatcherErrors => write!(f, "watcher errors"),
+            PollReason::WatchLimit => write!(f, "watch limit"),
+        }
+    }
+}
+
+/// State of a network operation running in the background.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkStatus {
+    pub op: NetworkOp,
+    pub progress: Option<TransferProgress>,
+    /// Set once the user asked to cancel; the operation stops at the next
+    /// progress callback.
+    pub cancelling: bool,
+}
+
+impl std::fmt::Display for NetworkStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.cancelling {
+            return write!(f, "Cancelling {}…", self.op.name().to_lowercase());
+        }
+        write!(f, "{}…", self.op.verb())?;
+        if let Some(progress) = &self.progress {
+            write!(f, " {}", progress)?;
+        }
+        Ok(())
+    }
+}
+
+/// Message input for the first commit on an unborn branch.
+#[derive(Debug, Clone, Default)]
+pub struct CommitPrompt {
+    pub message: String,
+    pub error: Option<String>,
+}
+
+/// Name input for a branch created at a detached HEAD.
+#[derive(Debug, Clone, Default)]
+pub struct BranchPrompt {
+    pub name: String,
+    pub error: Option<String>,
+}
+
+/// Username/password prompt shown when a remote rejects every other
+/// credential source. The operation is retried on submit.
+#[derive(Debug, Clone)]
+pub struct CredentialPrompt {
+    pub op: NetworkOp,
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    /// Whether input currently goes to the password field.
+    pub editing_password: bool,
+}
+
+/// A configured remote as shown in the remote management panel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteInfo {
+    pub name: String,
+    pub fetch_url: Option<String>,
+    /// Explicit push URL, if it differs from the fetch URL.
+    pub push_url: Option<String>,
+    /// Number of remote-tracking branches (zero if never fetched).
+    pub tracking_branches: usize,
+    /// Whether the current branch's upstream lives on this remote.
+    pub is_upstream: bool,
+}
+
+/// A stash entry as shown in the stash panel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StashInfo {
+    /// Position in the stash list, as in `stash@{n}`.
+    pub index: usize,
+    pub message: String,
+    /// Files the stash changed, untracked ones included.
+    pub files: Vec<String>,
+}
+
+/// State of the stash panel.
+#[derive(Debug, Clone, Default)]
+pub struct StashPanel {
+    pub stashes: Vec<StashInfo>,
+    pub selected: usize,
+    /// The highlighted file while choosing files from the selected stash.
+    pub file_cursor: Option<usize>,
+    /// Files chosen for restoring, by position in the stash's files.
+    pub chosen: BTreeSet<usize>,
+    pub error: Option<String>,
+}
+
+impl StashPanel {
+    pub fn selected_stash(&self) -> Option<&StashInfo> {
+        self.stashes.get(self.selected)
+    }
+
+    /// The files to restore: the chosen ones, or else the highlighted one.
+    pub fn restore_paths(&self) -> Vec<String> {
+        let (Some(stash), Some(cursor)) = (self.selected_stash(), self.file_cursor) else {
+            return Vec::new();
+        };
+        if self.chosen.is_empty() {
+            return stash.files.get(cursor).cloned().into_iter().collect();
+        }
+        self.chosen
+            .iter()
+            .filter_map(|&i| stash.files.get(i).cloned())
+            .collect()
+    }
+}
+
+/// Files saved before a discard, as shown in the recover panel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscardBackup {
+    /// The stash entry holding the files, as in `stash@{index}`.
+    pub index: usize,
+    pub time: SystemTime,
+    pub files: Vec<BackupFile>,
+}
+
+/// One file in a discard backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupFile {
+    pub path: String,
+    /// `path` as git stores it; see [`FileEntry::raw_path`].
+    pub raw_path: Vec<u8>,
+    /// The first lines of the saved content, or `None` if it is binary.
+    pub preview: Option<Vec<String>>,
+}
+
+/// State of the recover panel.
+#[derive(Debug, Clone, Default)]
+pub struct RecoverPanel {
+    pub backups: Vec<DiscardBackup>,
+    pub selected: usize,
+    /// The highlighted file once the selected backup is opened.
+    pub file_cursor: Option<usize>,
+    pub error: Option<String>,
+}
+
+impl RecoverPanel {
+    pub fn selected_backup(&self) -> Option<&DiscardBackup> {
+        self.backups.get(self.selected)
+    }
+
+    /// The file whose preview is shown: the highlighted one, or the first
+    /// file of the selected backup before it is opened.
+    pub fn previewed_file(&self) -> Option<&BackupFile> {
+        self.selected_backup()?
+            .files
+            .get(self.file_cursor.unwrap_or(0))
+    }
+}
+
+/// What the remote panel's text input will be used for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteInputKind {
+    /// Add a remote; input is "<name> <url>".
+    Add,
+    /// Rename the named remote; input is the new name.
+    Rename(String),
+    /// Set the current branch's upstream; input is "<remote>/<branch>".
+    SetUpstream,
+}
+
+/// A pending text input inside the remote panel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteInput {
+    pub kind: RemoteInputKind,
+    pub value: String,
+}
+
+/// State of the remote management panel.
+#[derive(Debug, Clone, Default)]
+pub struct RemotePanel {
+    pub remotes: Vec<RemoteInfo>,
+    pub selected: usize,
+    pub input: Option<RemoteInput>,
+    /// Remote awaiting removal confirmation.
+    pub confirm_remove: Option<String>,
+    pub error: Option<String>,
+}
+
+impl RemotePanel {
+    pub fn selected_remote(&self) -> Option<&RemoteInfo> {
+        self.remotes.get(self.selected)
+    }
+}
+
+/// The first-run setup overlay: a few common choices written out as a
+/// config file.
+#[derive(Debug, Clone)]
+pub struct SetupForm {
+    /// Where the config file will be written.
+    pub path: PathBuf,
+    /// Highlighted row: 0 theme, 1 keys, 2 confirmations.
+    pub row: usize,
+    pub theme: Theme,
+    pub keys: KeyPreset,
+    pub confirm: ConfirmLevel,
+    pub error: Option<String>,
+}
+
+impl SetupForm {
+    pub const ROWS: usize = 3;
+
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            row: 0,
+            theme: Theme::default(),
+            keys: KeyPreset::default(),
+            confirm: ConfirmLevel::default(),
+            error: None,
+        }
+    }
+
+    /// Step the highlighted row's value forward or back, wrapping around.
+    pub fn cycle(&mut self, forward: bool) {
+        fn step<T: Copy + PartialEq>(all: &[T], current: T, forward: bool) -> T {
+            let i = all.iter().position(|v| *v == current).unwrap_or(0);
+            let next = if forward {
+                (i + 1) % all.len()
+            } else {
+                (i + all.len() - 1) % all.len()
+            };
+            all[next]
+        }
+        match self.row {
+            0 => self.theme = step(&Theme::ALL, self.theme, forward),
+            1 => self.keys = step(&KeyPreset::ALL, self.keys, forward),
+            _ => self.confirm = step(&ConfirmLevel::ALL, self.confirm, forward),
+        }
+    }
+
+    /// The config file for the current choices.
+    pub fn config_text(&self) -> String {
+        let mut text = String::from("# Created by better-git-status first-run setup.\n");
+        text.push_str(&format!("\n[ui]\ntheme = \"{}\"\n", self.theme.name()));
+        text.push_str(&format!(
+            "\n[confirm]\nlevel = \"{}\"\n",
+            self.confirm.name()
+        ));
+        text.push_str(&format!("\n[keys]\npreset = \"{}\"\n", self.keys.name()));
+        text
+    }
+}
+
+/// State of the message history panel.
+#[derive(Debug, Clone, Default)]
+pub struct MessagePanel {
+    /// Past flash messages, newest first.
+    pub messages: Vec<FlashMessage>,
+    pub scroll: usize,
+}
+
+/// Output of a pre-commit run, streamed in while the hooks work.
+#[derive(Debug, Clone, Default)]
+pub struct PreCommitPanel {
+    pub lines: Vec<String>,
+    /// Lines scrolled up from the end; 0 follows new output.
+    pub scroll_back: usize,
+    /// How the run ended; `None` while the hooks are still running.
+    pub result: Option<String>,
+    pub failed: bool,
+}
+
+/// Full context for a failed action, shown in the error detail popup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorDetail {
+    /// The error message followed by each of its causes.
+    pub chain: Vec<String>,
+    /// Files the failed action was applied to.
+    pub paths: Vec<String>,
+}
+
+impl ErrorDetail {
+    pub fn new(error: &anyhow::Error, paths: Vec<String>) -> Self {
+        Self {
+            chain: error.chain().map(|cause| cause.to_string()).collect(),
+            paths,
+        }
+    }
+}
+
 /// Flash message for temporary feedback.
 #[derive(Debug, Clone)]
 pub struct FlashMessage {
     pub text: String,
     pub is_error: bool,
     pub shown_at: std::time::Instant,
+    pub detail: Option<ErrorDetail>,
 }
 
 impl FlashMessage {
@@ -161,6 +1056,7 @@ impl FlashMessage {
             text: text.into(),
             is_error: false,
             shown_at: std::time::Instant::now(),
+            detail: None,
         }
     }
 
@@ -169,6 +1065,14 @@ impl FlashMessage {
             text: text.into(),
             is_error: true,
             shown_at: std::time::Instant::now(),
+            detail: None,
+        }
+    }
+
+    pub fn with_detail(self, detail: ErrorDetail) -> Self {
+        Self {
+            detail: Some(detail),
+            ..self
         }
     }
 
@@ -181,34 +1085,184 @@ impl FlashMessage {
 mod tests {
     use super::*;
 
+    #[test]
+    fn whitespace_issues_match_diff_check() {
+        assert!(whitespace_issues("clean line").is_empty());
+        assert!(whitespace_issues("\tindented with tab").is_empty());
+        assert!(whitespace_issues("crlf line\r").is_empty());
+        assert_eq!(whitespace_issues("trailing  "), vec![8..10]);
+        assert_eq!(whitespace_issues("trailing \r"), vec![8..9]);
+        assert_eq!(whitespace_issues("  \tcode"), vec![0..2]);
+        assert_eq!(whitespace_issues("\t \tcode \t"), vec![1..2, 7..9]);
+        assert_eq!(whitespace_issues("   "), vec![0..3]);
+    }
+
+    #[test]
+    fn setup_form_writes_parseable_config() {
+        let mut form = SetupForm::new(PathBuf::from("config.toml"));
+        form.cycle(true);
+        form.row = 1;
+        form.cycle(true);
+        form.row = 2;
+        form.cycle(false);
+
+        let config = crate::config::Config::parse(&form.config_text()).unwrap();
+        assert_eq!(config.ui.theme, Theme::Colorblind);
+        assert_eq!(config.confirm.level, ConfirmLevel::Destructive);
+        assert_eq!(config.keys.preset, KeyPreset::Vim);
+        assert!(config.keys.overrides.is_empty());
+    }
+
+    #[test]
+    fn transfer_progress_display() {
+        let progress = TransferProgress {
+            phase: TransferPhase::Receiving,
+            current: 120,
+            total: 480,
+            bytes: 3 * 1024 * 1024 / 2,
+        };
+        assert_eq!(
+            progress.to_string(),
+            "receiving objects 120/480 (25%), 1.5 MiB"
+        );
+
+        let progress = TransferProgress {
+            phase: TransferPhase::Resolving,
+            current: 0,
+            total: 0,
+            bytes: 0,
+        };
+        assert_eq!(progress.to_string(), "resolving deltas 0/0");
+    }
+
+    #[test]
+    fn format_bytes_units() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(2048), "2.0 KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+
+    #[test]
+    fn network_status_display() {
+        let mut status = NetworkStatus {
+            op: NetworkOp::Fetch,
+            progress: None,
+            cancelling: false,
+        };
+        assert_eq!(status.to_string(), "Fetching…");
+        status.progress = Some(TransferProgress {
+            phase: TransferPhase::Uploading,
+            current: 1,
+            total: 2,
+            bytes: 100,
+        });
+        assert_eq!(
+            status.to_string(),
+            "Fetching… uploading objects 1/2 (50%), 100 B"
+        );
+        status.cancelling = true;
+        assert_eq!(status.to_string(), "Cancelling fetch…");
+    }
+
     #[test]
     fn file_status_symbol() {
         assert_eq!(FileStatus::Added.symbol(), "A");
         assert_eq!(FileStatus::Modified.symbol(), "M");
         assert_eq!(FileStatus::Deleted.symbol(), "D");
         assert_eq!(FileStatus::Renamed.symbol(), "R");
+        assert_eq!(FileStatus::TypeChanged.symbol(), "T");
         assert_eq!(FileStatus::Untracked.symbol(), "?");
         assert_eq!(FileStatus::Conflict.symbol(), "C");
     }
 
+    #[test]
+    fn conflict_roles_follow_the_markers() {
+        let preview = ConflictPreview {
+            kind: None,
+            lines: [
+                "a",
+                "<<<<<<< HEAD",
+                "ours",
+                "||||||| base",
+                "base",
+                "=======",
+                "======== not a marker",
+                ">>>>>>> topic",
+                "<<<<<<< HEAD",
+                "=======",
+                "theirs",
+                ">>>>>>>",
+            ]
+            .map(String::from)
+            .to_vec(),
+        };
+        use ConflictRole::*;
+        assert_eq!(
+            preview.roles(),
+            vec![
+                Outside, Marker, Ours, Marker, Base, Marker, Theirs, Marker, Marker, Marker,
+                Theirs, Marker
+            ]
+        );
+        assert_eq!(preview.region_starts(), vec![1, 8]);
+        assert!(preview.has_markers());
+        let resolved = ConflictPreview {
+            kind: None,
+            lines: vec!["ours".to_string(), "======= heading".to_string()],
+        };
+        assert!(!resolved.has_markers());
+    }
+
+    #[test]
+    fn conflict_kind_from_stages() {
+        let kind = |base, ours, theirs| ConflictKind::from_stages(base, ours, theirs);
+        assert_eq!(kind(true, true, true), Some(ConflictKind::BothModified));
+        assert_eq!(kind(false, true, true), Some(ConflictKind::BothAdded));
+        assert_eq!(kind(true, false, true), Some(ConflictKind::DeletedByUs));
+        assert_eq!(kind(true, true, false), Some(ConflictKind::DeletedByThem));
+        assert_eq!(kind(false, false, false), None);
+        assert_eq!(ConflictKind::DeletedByUs.code(), "DU");
+        assert_eq!(ConflictKind::AddedByThem.label(), "added by them");
+    }
+
     #[test]
     fn branch_info_display() {
         let branch = BranchInfo::Branch("main".to_string());
         assert_eq!(branch.to_string(), "main");
 
+        let unborn = BranchInfo::Unborn("main".to_string());
+        assert_eq!(unborn.to_string(), "main (no commits yet)");
+
         let detached = BranchInfo::Detached("abc1234".to_string());
         assert_eq!(detached.to_string(), "HEAD@abc1234");
     }
 
+    #[test]
+    fn rebase_progress_display() {
+        let progress = RebaseProgress {
+            current: 4,
+            total: 9,
+            subject: "Fix parser".to_string(),
+        };
+        assert_eq!(progress.to_string(), "applying 4/9: Fix parser");
+
+        let no_subject = RebaseProgress {
+            current: 1,
+            total: 2,
+            subject: String::new(),
+        };
+        assert_eq!(no_subject.to_string(), "applying 1/2");
+    }
+
     #[test]
     fn undo_action_stage_variant() {
         let action = UndoAction::Stage {
-            paths: vec!["a.rs".to_string(), "b.rs".to_string()],
+            paths: vec![b"a.rs".to_vec(), b"b.rs".to_vec()],
         };
         if let UndoAction::Stage { paths } = action {
             assert_eq!(paths.len(), 2);
-            assert_eq!(paths[0], "a.rs");
-            assert_eq!(paths[1], "b.rs");
+            assert_eq!(paths[0], b"a.rs");
+            assert_eq!(paths[1], b"b.rs");
         } else {
             panic!("Expected Stage variant");
         }
@@ -217,11 +1271,11 @@ mod tests {
     #[test]
     fn undo_action_unstage_variant() {
         let action = UndoAction::Unstage {
-            paths: vec!["c.rs".to_string()],
+            paths: vec![b"c.rs".to_vec()],
         };
         if let UndoAction::Unstage { paths } = action {
             assert_eq!(paths.len(), 1);
-            assert_eq!(paths[0], "c.rs");
+            assert_eq!(paths[0], b"c.rs");
         } else {
             panic!("Expected Unstage variant");
         }
@@ -230,13 +1284,13 @@ mod tests {
     #[test]
     fn undo_action_equality() {
         let a1 = UndoAction::Stage {
-            paths: vec!["a.rs".to_string()],
+            paths: vec![b"a.rs".to_vec()],
         };
         let a2 = UndoAction::Stage {
-            paths: vec!["a.rs".to_string()],
+            paths: vec![b"a.rs".to_vec()],
         };
         let a3 = UndoAction::Stage {
-            paths: vec!["b.rs".to_string()],
+            paths: vec![b"b.rs".to_vec()],
         };
         assert_eq!(a1, a2);
         assert_ne!(a1, a3);
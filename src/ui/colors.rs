@@ -1,13 +1,163 @@
-use ratatui::style::Color;
-
-pub const GREEN: Color = Color::Rgb(166, 227, 161);
-pub const RED: Color = Color::Rgb(243, 139, 168);
-pub const YELLOW: Color = Color::Rgb(249, 226, 175);
-pub const BLUE: Color = Color::Rgb(137, 180, 250);
-pub const GRAY: Color = Color::Rgb(147, 153, 178);
-pub const MAGENTA: Color = Color::Rgb(245, 194, 231);
-
-pub const CYAN: Color = Color::Rgb(148, 226, 213);
-pub const TEXT: Color = Color::Rgb(205, 214, 244);
-pub const SURFACE: Color = Color::Rgb(49, 50, 68);
-pub const OVERLAY: Color = Color::Rgb(108, 112, 134);
+use crate::config::Theme;
+use ratatui::style::{Color, Modifier, Style};
+use std::cell::Cell;
+
+/// The colors every panel draws with. Slots keep the names of the default
+/// Catppuccin palette; other themes remap them, so `green` (additions) is
+/// blue in the colorblind theme and `red` (deletions) is orange.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub green: Color,
+    pub red: Color,
+    pub yellow: Color,
+    pub blue: Color,
+    pub gray: Color,
+    pub magenta: Color,
+    pub cyan: Color,
+    pub text: Color,
+    pub surface: Color,
+    pub overlay: Color,
+    /// Draw `+`/`-` diff markers in bold so additions and deletions can be
+    /// told apart without relying on color.
+    pub bold_markers: bool,
+}
+
+impl Palette {
+    pub const DEFAULT: Palette = Palette {
+        green: Color::Rgb(166, 227, 161),
+        red: Color::Rgb(243, 139, 168),
+        yellow: Color::Rgb(249, 226, 175),
+        blue: Color::Rgb(137, 180, 250),
+        gray: Color::Rgb(147, 153, 178),
+        magenta: Color::Rgb(245, 194, 231),
+        cyan: Color::Rgb(148, 226, 213),
+        text: Color::Rgb(205, 214, 244),
+        surface: Color::Rgb(49, 50, 68),
+        overlay: Color::Rgb(108, 112, 134),
+        bold_markers: false,
+    };
+
+    /// Blue/orange instead of green/red, based on the Okabe-Ito palette.
+    pub const COLORBLIND: Palette = Palette {
+        green: Color::Rgb(86, 180, 233),
+        red: Color::Rgb(230, 159, 0),
+        yellow: Color::Rgb(240, 228, 66),
+        blue: Color::Rgb(180, 190, 254),
+        gray: Color::Rgb(147, 153, 178),
+        magenta: Color::Rgb(204, 121, 167),
+        cyan: Color::Rgb(148, 226, 213),
+        text: Color::Rgb(205, 214, 244),
+        surface: Color::Rgb(49, 50, 68),
+        overlay: Color::Rgb(108, 112, 134),
+        bold_markers: true,
+    };
+
+    /// Saturated ANSI colors on the terminal's own background.
+    pub const HIGH_CONTRAST: Palette = Palette {
+        green: Color::LightCyan,
+        red: Color::LightYellow,
+        yellow: Color::Yellow,
+        blue: Color::LightBlue,
+        gray: Color::Gray,
+        magenta: Color::LightMagenta,
+        cyan: Color::Cyan,
+        text: Color::White,
+        surface: Color::Black,
+        overlay: Color::White,
+        bold_markers: true,
+    };
+
+    pub fn for_theme(theme: Theme) -> Palette {
+        match theme {
+            Theme::Default => Palette::DEFAULT,
+            Theme::Colorblind => Palette::COLORBLIND,
+            Theme::HighContrast => Palette::HIGH_CONTRAST,
+        }
+    }
+}
+
+thread_local! {
+    static ACTIVE: Cell<Palette> = const { Cell::new(Palette::DEFAULT) };
+}
+
+/// Switch the palette used by subsequent draws on this thread.
+pub fn set_theme(theme: Theme) {
+    ACTIVE.with(|active| active.set(Palette::for_theme(theme)));
+}
+
+pub fn palette() -> Palette {
+    ACTIVE.with(Cell::get)
+}
+
+pub fn green() -> Color {
+    palette().green
+}
+
+pub fn red() -> Color {
+    palette().red
+}
+
+pub fn yellow() -> Color {
+    palette().yellow
+}
+
+pub fn blue() -> Color {
+    palette().blue
+}
+
+pub fn gray() -> Color {
+    palette().gray
+}
+
+pub fn magenta() -> Color {
+    palette().magenta
+}
+
+pub fn cyan() -> Color {
+    palette().cyan
+}
+
+pub fn text() -> Color {
+    palette().text
+}
+
+pub fn surface() -> Color {
+    palette().surface
+}
+
+pub fn overlay() -> Color {
+    palette().overlay
+}
+
+/// Style for a diff's `+`/`-` marker, given the style of its line.
+pub fn marker_style(line_style: Style) -> Style {
+    if palette().bold_markers {
+        line_style.add_modifier(Modifier::BOLD)
+    } else {
+        line_style
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_theme_switches_palette() {
+        assert_eq!(green(), Palette::DEFAULT.green);
+        set_theme(Theme::Colorblind);
+        assert_eq!(green(), Palette::COLORBLIND.green);
+        assert_eq!(red(), Palette::COLORBLIND.red);
+        set_theme(Theme::Default);
+        assert_eq!(palette(), Palette::DEFAULT);
+    }
+
+    #[test]
+    fn accessible_themes_embolden_markers() {
+        let style = Style::default();
+        assert_eq!(marker_style(style), style);
+        set_theme(Theme::HighContrast);
+        assert!(marker_style(style).add_modifier.contains(Modifier::BOLD));
+        set_theme(Theme::Default);
+    }
+}
@@ -1,213 +1,1004 @@
-use crate::types::{DiffContent, DiffLine, DiffLineKind};
-use crate::ui::colors;
+use crate::types::{
+    describe_mode, whitespace_issues, BinaryChange, BinaryInfo, ConflictPreview, ConflictRole,
+    DiffContent, DiffLine, DiffLineKind, EntryKind, FileEntry, FilePreview, Section, TokenKind,
+};
+use crate::ui::file_list::{format_path_with_priority, relative_path};
+use crate::ui::{colors, symbols};
 use ratatui::{
     layout::Rect,
-    style::Style,
+    style::{Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, Paragraph},
     Frame,
 };
+use std::ops::RangeInclusive;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-pub fn draw(frame: &mut Frame, area: Rect, diff: &DiffContent, scroll: usize) {
+/// Draw the diff. With `wrap` off, long lines are clipped at the panel edge.
+/// A text diff's `cursor` line is highlighted when the diff has focus, and
+/// lines in `selection` are drawn reversed.
+#[allow(clippy::too_many_arguments)]
+pub fn draw(
+    frame: &mut Frame,
+    area: Rect,
+    diff: &DiffContent,
+    scroll: usize,
+    wrap: bool,
+    expand: bool,
+    cursor: Option<usize>,
+    selection: Option<RangeInclusive<usize>>,
+    breadcrumb: Option<&Breadcrumb>,
+) {
     let inner_height = area.height.saturating_sub(2) as usize;
+    let inner_width = area.width.saturating_sub(2) as usize;
 
-    let (lines, total_lines) = match diff {
-        DiffContent::Empty => {
-            let placeholder = vec![
-                Line::from(""),
-                Line::from(Span::styled(
-                    "↑/↓ navigate, Space to view diff",
-                    Style::default().fg(colors::GRAY),
-                )),
-            ];
-            (placeholder, 2)
-        }
-        DiffContent::Clean => {
-            let placeholder = vec![
-                Line::from(""),
-                Line::from(Span::styled(
-                    "No changes (q to quit)",
-                    Style::default().fg(colors::GRAY),
-                )),
-            ];
-            (placeholder, 2)
-        }
-        DiffContent::Binary => {
-            let placeholder = vec![
-                Line::from(""),
-                Line::from(Span::styled(
-                    "Binary file",
-                    Style::default().fg(colors::GRAY),
-                )),
-            ];
-            (placeholder, 2)
-        }
-        DiffContent::InvalidUtf8 => {
-            let placeholder = vec![
-                Line::from(""),
-                Line::from(Span::styled(
-                    "File contains invalid UTF-8 encoding",
-                    Style::default().fg(colors::GRAY),
-                )),
-            ];
-            (placeholder, 2)
-        }
-        DiffContent::Conflict => {
-            let placeholder = vec![
-                Line::from(""),
-                Line::from(Span::styled(
-                    "Conflict - resolve before viewing diff",
-                    Style::default().fg(colors::MAGENTA),
-                )),
-            ];
-            (placeholder, 2)
-        }
+    let (lines, scroll_offset) = match diff {
         DiffContent::Text(diff_lines) => {
-            let lines = render_diff_lines(diff_lines, area.width.saturating_sub(2) as usize);
-            let len = lines.len();
-            (lines, len)
+            let layout = DiffLayout::new(diff_lines, inner_width, wrap, expand);
+            let marks = LineMarks { cursor, selection };
+            render_diff_window(diff_lines, &layout, scroll, inner_height, &marks)
+        }
+        _ => {
+            let lines = content_lines(diff, inner_width, wrap, expand);
+            let scroll_offset = scroll.min(lines.len().saturating_sub(inner_height));
+            (lines, scroll_offset)
         }
     };
+    let kind = match diff {
+        DiffContent::Preview(_) => "Preview",
+        DiffContent::Hexdump(_) => "Hexdump",
+        DiffContent::Conflict(_) => "Conflict",
+        _ => "Diff",
+    };
+    let title = match breadcrumb {
+        Some(crumb) => crumb.title(kind, area.width.saturating_sub(2) as usize),
+        None => Line::from(kind),
+    };
 
-    let scroll_offset = scroll.min(total_lines.saturating_sub(inner_height));
+    let border = if cursor.is_some() {
+        colors::blue()
+    } else {
+        colors::overlay()
+    };
 
     let paragraph = Paragraph::new(lines)
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(colors::OVERLAY))
-                .title("Diff"),
+                .border_set(symbols::border_set())
+                .border_style(Style::default().fg(border))
+                .title(title),
         )
         .scroll((scroll_offset as u16, 0));
 
     frame.render_widget(paragraph, area);
 }
 
-fn render_diff_lines(diff_lines: &[DiffLine], width: usize) -> Vec<Line<'static>> {
-    let max_line_num = diff_lines
-        .iter()
-        .filter_map(|l| l.new_line_number)
-        .max()
-        .unwrap_or(0);
-    let line_num_width = max_line_num.to_string().len().max(3);
-    let gutter_width = line_num_width + 3; // " │" + prefix char
+/// The file a diff belongs to, named in the panel's title so the panel
+/// identifies itself when the file list has scrolled it away.
+pub struct Breadcrumb<'a> {
+    pub file: &'a FileEntry,
+    pub section: Section,
+    /// Directory the path is shown relative to, if not the root.
+    pub path_base: Option<&'a str>,
+}
+
+impl Breadcrumb<'_> {
+    /// " path · section · status · +a/-d " in at most `width` columns. The
+    /// path is shortened from the left, then the details are dropped.
+    /// `kind` is added unless the diff is a plain one.
+    fn title(&self, kind: &str, width: usize) -> Line<'static> {
+        let file = self.file;
+        let gray = Style::default().fg(colors::gray());
+        let mut details = vec![
+            (
+                self.section.name().to_string(),
+                Style::default().fg(colors::cyan()),
+            ),
+            (
+                file.conflict
+                    .map_or(file.status.name(), |kind| kind.label())
+                    .to_string(),
+                gray,
+            ),
+        ];
+        if file.is_binary {
+            details.push(("binary".to_string(), gray));
+        } else if let (Some(added), Some(deleted)) = (file.added_lines, file.deleted_lines) {
+            details.push((format!("+{}/-{}", added, deleted), gray));
+        }
+        if kind != "Diff" {
+            details.push((kind.to_lowercase(), gray));
+        }
+        let separator = symbols::text(" · ").into_owned();
+        let details_text = details
+            .iter()
+            .map(|(text, _)| text.as_str())
+            .collect::<Vec<_>>()
+            .join(&separator);
+
+        let path = relative_path(&file.path, self.path_base);
+        // Room for the padding on either side and the first separator,
+        // which replaces the single space the path fitting allows for.
+        let available = width.saturating_sub(2 + separator.width() - 1);
+        let (path, show_details) = format_path_with_priority(&path, &details_text, available);
+
+        let mut spans = vec![
+            Span::raw(" "),
+            Span::styled(
+                path,
+                Style::default()
+                    .fg(colors::text())
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ];
+        if show_details {
+            for (text, style) in details {
+                spans.push(Span::styled(separator.clone(), gray));
+                spans.push(Span::styled(text, style));
+            }
+        }
+        spans.push(Span::raw(" "));
+        Line::from(spans)
+    }
+}
+
+/// Every row of `diff`, for content that isn't windowed while drawing.
+fn content_lines(diff: &DiffContent, width: usize, wrap: bool, expand: bool) -> Vec<Line<'static>> {
+    match diff {
+        DiffContent::Empty => vec![
+            Line::from(""),
+            Line::from(Span::styled(
+                symbols::text("↑/↓ navigate, Space to view diff"),
+                Style::default().fg(colors::gray()),
+            )),
+        ],
+        DiffContent::Clean => vec![
+            Line::from(""),
+            Line::from(Span::styled(
+                "No changes (q to quit)",
+                Style::default().fg(colors::gray()),
+            )),
+        ],
+        DiffContent::Binary(info) => binary_lines(info),
+        DiffContent::TooLarge(info) => too_large_lines(info),
+        DiffContent::ModeChange { old_mode, new_mode } => mode_change_lines(*old_mode, *new_mode),
+        DiffContent::Collapsed { added, deleted } => collapsed_lines(*added, *deleted),
+        DiffContent::Minified { lines, bytes } => minified_lines(*lines, *bytes),
+        DiffContent::TypeChange { old, new } => type_change_lines(old, new),
+        DiffContent::Symlink {
+            old_target,
+            new_target,
+        } => symlink_lines(old_target.as_deref(), new_target.as_deref()),
+        DiffContent::Hexdump(info) => hexdump_lines(info, width),
+        DiffContent::Fetching => vec![
+            Line::from(""),
+            Line::from(Span::styled(
+                symbols::text("Fetching objects from the promisor remote…"),
+                Style::default().fg(colors::gray()),
+            )),
+        ],
+        DiffContent::InvalidUtf8 => vec![
+            Line::from(""),
+            Line::from(Span::styled(
+                "File contains invalid UTF-8 and no encoding decodes it",
+                Style::default().fg(colors::gray()),
+            )),
+        ],
+        DiffContent::Conflict(preview) => conflict_lines(preview, width, wrap, expand).0,
+        DiffContent::Text(diff_lines) => {
+            let layout = DiffLayout::new(diff_lines, width, wrap, expand);
+            render_diff_window(diff_lines, &layout, 0, usize::MAX, &LineMarks::default()).0
+        }
+        DiffContent::Preview(preview) => render_preview_lines(preview, width, wrap, expand),
+    }
+}
+
+/// Every line of `diff` at full length, unwrapped, for printing outside the
+/// TUI.
+pub fn full_lines(diff: &DiffContent) -> Vec<Line<'static>> {
+    content_lines(diff, usize::MAX, false, true)
+}
+
+/// Lines longer than this many characters are cut short until expanded.
+pub const LONG_LINE_CHARS: usize = 1000;
+
+/// Column layout shared by every row of a text diff.
+struct DiffLayout {
+    line_num_width: usize,
+    content_width: usize,
+    wrap: bool,
+    expand: bool,
+}
+
+impl DiffLayout {
+    fn new(diff_lines: &[DiffLine], width: usize, wrap: bool, expand: bool) -> Self {
+        let max_line_num = diff_lines
+            .iter()
+            .filter_map(|l| l.new_line_number)
+            .max()
+            .unwrap_or(0);
+        let line_num_width = max_line_num.to_string().len().max(3);
+        let gutter_width = line_num_width + 3; // " │" + prefix char
+        Self {
+            line_num_width,
+            content_width: width.saturating_sub(gutter_width),
+            wrap,
+            expand,
+        }
+    }
+
+    fn is_single_row(&self, line: &DiffLine) -> bool {
+        !self.wrap || self.content_width == 0 || line.content.is_empty()
+    }
+
+    /// How many rows `render` produces for `line`, without building them.
+    fn rows(&self, line: &DiffLine) -> usize {
+        if self.is_single_row(line) {
+            return 1;
+        }
+        let (text, suffix) = visible_content(&line.content, self.expand);
+        let widths = text
+            .chars()
+            .chain(suffix.iter().flat_map(|s| s.chars()))
+            .map(|c| c.width().unwrap_or(0));
+        // The first row loses a column to the +/- prefix.
+        count_rows(
+            widths,
+            self.content_width.saturating_sub(1),
+            self.content_width,
+        )
+    }
+
+    fn render(&self, line: &DiffLine) -> Vec<Line<'static>> {
+        let line_num_width = self.line_num_width;
+        let bar = symbols::text("│");
+        let (line_num_str, content_style) = match line.kind {
+            DiffLineKind::Header => (
+                format!("{:>width$} {}", "", bar, width = line_num_width),
+                Style::default().fg(colors::cyan()),
+            ),
+            DiffLineKind::Hunk => (
+                format!("{:>width$} {}", "", bar, width = line_num_width),
+                Style::default().fg(colors::cyan()),
+            ),
+            DiffLineKind::Context => {
+                let num = line
+                    .new_line_number
+                    .map(|n| n.to_string())
+                    .unwrap_or_default();
+                (
+                    format!("{:>width$} {}", num, bar, width = line_num_width),
+                    Style::default().fg(colors::text()),
+                )
+            }
+            DiffLineKind::Added => {
+                let num = line
+                    .new_line_number
+                    .map(|n| n.to_string())
+                    .unwrap_or_default();
+                (
+                    format!("{:>width$} {}", num, bar, width = line_num_width),
+                    Style::default().fg(colors::green()),
+                )
+            }
+            DiffLineKind::Deleted => (
+                format!("{:>width$} {}", "-", bar, width = line_num_width),
+                Style::default().fg(colors::red()),
+            ),
+        };
+
+        let prefix = match line.kind {
+            DiffLineKind::Added => "+",
+            DiffLineKind::Deleted => "-",
+            DiffLineKind::Context => " ",
+            _ => "",
+        };
+
+        let spans = content_spans(line, content_style, self.expand);
+        let continuation_gutter = format!("{:>width$} {} ", "", bar, width = line_num_width);
+
+        if self.is_single_row(line) {
+            let mut row = vec![
+                Span::styled(line_num_str, Style::default().fg(colors::gray())),
+                Span::styled(prefix, colors::marker_style(content_style)),
+            ];
+            row.extend(spans);
+            return vec![Line::from(row)];
+        }
+
+        // The first row loses a column to the +/- prefix.
+        split_spans(
+            spans,
+            self.content_width.saturating_sub(1),
+            self.content_width,
+        )
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut row = if i == 0 {
+                vec![
+                    Span::styled(line_num_str.clone(), Style::default().fg(colors::gray())),
+                    Span::styled(prefix, colors::marker_style(content_style)),
+                ]
+            } else {
+                vec![Span::styled(
+                    continuation_gutter.clone(),
+                    Style::default().fg(colors::gray()),
+                )]
+            };
+            row.extend(chunk);
+            Line::from(row)
+        })
+        .collect()
+    }
+}
+
+/// Lines of a text diff drawn highlighted.
+#[derive(Default)]
+struct LineMarks {
+    cursor: Option<usize>,
+    selection: Option<RangeInclusive<usize>>,
+}
+
+impl LineMarks {
+    fn style(&self, line: usize) -> Option<Style> {
+        if self
+            .selection
+            .as_ref()
+            .is_some_and(|range| range.contains(&line))
+        {
+            Some(Style::default().add_modifier(Modifier::REVERSED))
+        } else if self.cursor == Some(line) {
+            Some(Style::default().bg(colors::surface()))
+        } else {
+            None
+        }
+    }
+}
+
+/// The rows of a text diff that fill a `height`-row viewport scrolled by
+/// `scroll` (clamped), plus how many rows of the first returned line sit
+/// above the viewport. Lines outside the viewport are counted, not rendered.
+fn render_diff_window(
+    diff_lines: &[DiffLine],
+    layout: &DiffLayout,
+    scroll: usize,
+    height: usize,
+    marks: &LineMarks,
+) -> (Vec<Line<'static>>, usize) {
+    let rows: Vec<usize> = diff_lines.iter().map(|l| layout.rows(l)).collect();
+    let total: usize = rows.iter().sum();
+    let scroll = scroll.min(total.saturating_sub(height));
+
+    let mut first = 0;
+    let mut above = 0;
+    while first < rows.len() && above + rows[first] <= scroll {
+        above += rows[first];
+        first += 1;
+    }
+    let skip = scroll - above;
+
+    let mut lines = Vec::new();
+    for (i, line) in diff_lines.iter().enumerate().skip(first) {
+        if lines.len() >= skip + height {
+            break;
+        }
+        let rendered = layout.render(line);
+        match marks.style(i) {
+            Some(style) => lines.extend(rendered.into_iter().map(|l| l.patch_style(style))),
+            None => lines.extend(rendered),
+        }
+    }
+    (lines, skip)
+}
+
+/// The drawn part of a line's text: without a trailing CR and clipped to
+/// [`LONG_LINE_CHARS`] unless `expand`. The second value is what is drawn
+/// after it, a "(+N chars)" marker for a clipped line or a glyph for the CR.
+fn visible_content(content: &str, expand: bool) -> (&str, Option<String>) {
+    let (text, cr) = match content.strip_suffix('\r') {
+        Some(text) => (text, true),
+        None => (content, false),
+    };
+    if !expand {
+        if let Some((end, _)) = text.char_indices().nth(LONG_LINE_CHARS) {
+            let hidden = text[end..].chars().count();
+            return (&text[..end], Some(hidden_marker(hidden)));
+        }
+    }
+    (text, cr.then(|| symbols::text("␍").into_owned()))
+}
 
-    let content_width = width.saturating_sub(gutter_width);
+fn hidden_marker(hidden: usize) -> String {
+    format!("{} (+{} chars)", symbols::ellipsis(), hidden)
+}
 
-    diff_lines
+/// A diff line's text as spans. Whitespace errors in added lines are
+/// highlighted, and a trailing CR is drawn as a glyph instead of being sent
+/// to the terminal.
+fn content_spans(line: &DiffLine, style: Style, expand: bool) -> Vec<Span<'static>> {
+    let (text, suffix) = visible_content(&line.content, expand);
+    let issues = if line.kind == DiffLineKind::Added {
+        whitespace_issues(text)
+    } else {
+        Vec::new()
+    };
+
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    for range in issues {
+        if range.start > pos {
+            spans.push(Span::styled(text[pos..range.start].to_string(), style));
+        }
+        spans.push(Span::styled(
+            text[range.clone()].to_string(),
+            Style::default().bg(colors::red()),
+        ));
+        pos = range.end;
+    }
+    if pos < text.len() || spans.is_empty() {
+        spans.push(Span::styled(text[pos..].to_string(), style));
+    }
+    if let Some(suffix) = suffix {
+        spans.push(Span::styled(suffix, Style::default().fg(colors::gray())));
+    }
+    spans
+}
+
+/// Render an untracked file: a summary line, then numbered, highlighted
+/// content.
+fn render_preview_lines(
+    preview: &FilePreview,
+    width: usize,
+    wrap: bool,
+    expand: bool,
+) -> Vec<Line<'static>> {
+    let mut lines = vec![Line::from(Span::styled(
+        preview_header(preview),
+        Style::default().fg(colors::cyan()),
+    ))];
+    let rows = preview
+        .lines
         .iter()
-        .flat_map(|line| {
-            let (line_num_str, content_style) = match line.kind {
-                DiffLineKind::Header => (
-                    format!("{:>width$} │", "", width = line_num_width),
-                    Style::default().fg(colors::CYAN),
-                ),
-                DiffLineKind::Hunk => (
-                    format!("{:>width$} │", "", width = line_num_width),
-                    Style::default().fg(colors::CYAN),
-                ),
-                DiffLineKind::Context => {
-                    let num = line
-                        .new_line_number
-                        .map(|n| n.to_string())
-                        .unwrap_or_default();
-                    (
-                        format!("{:>width$} │", num, width = line_num_width),
-                        Style::default().fg(colors::TEXT),
-                    )
-                }
-                DiffLineKind::Added => {
-                    let num = line
-                        .new_line_number
-                        .map(|n| n.to_string())
-                        .unwrap_or_default();
-                    (
-                        format!("{:>width$} │", num, width = line_num_width),
-                        Style::default().fg(colors::GREEN),
-                    )
-                }
-                DiffLineKind::Deleted => (
-                    format!("{:>width$} │", "-", width = line_num_width),
-                    Style::default().fg(colors::RED),
-                ),
+        .map(|tokens| token_spans(tokens, expand))
+        .collect();
+    lines.extend(numbered_lines(rows, width, wrap).0);
+    lines
+}
+
+/// Render a conflicted file: how it conflicts, then its numbered content
+/// with the markers and each side of every region colored. Also returns
+/// the row each region's `<<<<<<<` line starts on.
+pub fn conflict_lines(
+    preview: &ConflictPreview,
+    width: usize,
+    wrap: bool,
+    expand: bool,
+) -> (Vec<Line<'static>>, Vec<usize>) {
+    let kind = preview
+        .kind
+        .map(|kind| format!(" ({}: {})", kind.code(), kind.label()))
+        .unwrap_or_default();
+    if preview.lines.is_empty() {
+        let message = format!("Conflict{} - resolve before viewing diff", kind);
+        let lines = vec![
+            Line::from(""),
+            Line::from(Span::styled(
+                message,
+                Style::default().fg(colors::magenta()),
+            )),
+        ];
+        return (lines, Vec::new());
+    }
+
+    let starts = preview.region_starts();
+    let header = format!(
+        "Conflict{} {} {} region{}",
+        kind,
+        symbols::text("·"),
+        starts.len(),
+        if starts.len() == 1 { "" } else { "s" }
+    );
+    let rows = preview
+        .roles()
+        .into_iter()
+        .zip(&preview.lines)
+        .map(|(role, text)| {
+            let style = match role {
+                ConflictRole::Outside => Style::default().fg(colors::text()),
+                ConflictRole::Marker => Style::default()
+                    .fg(colors::magenta())
+                    .add_modifier(Modifier::BOLD),
+                ConflictRole::Ours => Style::default().fg(colors::green()),
+                ConflictRole::Base => Style::default().fg(colors::gray()),
+                ConflictRole::Theirs => Style::default().fg(colors::blue()),
             };
+            clipped_spans([(style, text.as_str())], expand)
+        })
+        .collect();
+    let (body, line_rows) = numbered_lines(rows, width, wrap);
 
-            let prefix = match line.kind {
-                DiffLineKind::Added => "+",
-                DiffLineKind::Deleted => "-",
-                DiffLineKind::Context => " ",
-                _ => "",
+    let mut lines = vec![Line::from(Span::styled(
+        header,
+        Style::default().fg(colors::magenta()),
+    ))];
+    lines.extend(body);
+    let region_rows = starts.iter().map(|&i| line_rows[i] + 1).collect();
+    (lines, region_rows)
+}
+
+/// `rows` behind a line-number gutter, wrapped to `width` when `wrap` is
+/// set. Also returns the row each input row starts on.
+fn numbered_lines(
+    rows: Vec<Vec<Span<'static>>>,
+    width: usize,
+    wrap: bool,
+) -> (Vec<Line<'static>>, Vec<usize>) {
+    let line_num_width = rows.len().to_string().len().max(3);
+    let content_width = width.saturating_sub(line_num_width + 3);
+    let bar = symbols::text("│");
+    let continuation_gutter = format!("{:>width$} {} ", "", bar, width = line_num_width);
+
+    let mut lines = Vec::new();
+    let mut starts = Vec::with_capacity(rows.len());
+    for (i, spans) in rows.into_iter().enumerate() {
+        starts.push(lines.len());
+        let gutter = format!("{:>width$} {} ", i + 1, bar, width = line_num_width);
+        if !wrap || content_width == 0 {
+            let mut row = vec![Span::styled(gutter, Style::default().fg(colors::gray()))];
+            row.extend(spans);
+            lines.push(Line::from(row));
+            continue;
+        }
+        for (j, chunk) in split_spans(spans, content_width, content_width)
+            .into_iter()
+            .enumerate()
+        {
+            let gutter = if j == 0 {
+                gutter.clone()
+            } else {
+                continuation_gutter.clone()
             };
+            let mut row = vec![Span::styled(gutter, Style::default().fg(colors::gray()))];
+            row.extend(chunk);
+            lines.push(Line::from(row));
+        }
+    }
+    (lines, starts)
+}
 
-            let content = &line.content;
-            let continuation_gutter = format!("{:>width$} │ ", "", width = line_num_width);
+/// Highlighted tokens as spans, clipped like diff lines unless `expand`.
+fn token_spans(tokens: &[(TokenKind, String)], expand: bool) -> Vec<Span<'static>> {
+    clipped_spans(
+        tokens
+            .iter()
+            .map(|(kind, text)| (token_style(*kind), text.as_str())),
+        expand,
+    )
+}
 
-            if content_width == 0 || content.is_empty() {
-                return vec![Line::from(vec![
-                    Span::styled(line_num_str, Style::default().fg(colors::GRAY)),
-                    Span::styled(prefix, content_style),
-                    Span::styled(content.clone(), content_style),
-                ])];
+/// Styled text as spans, clipped like diff lines unless `expand`.
+fn clipped_spans<'a>(
+    parts: impl IntoIterator<Item = (Style, &'a str)>,
+    expand: bool,
+) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut left = if expand { usize::MAX } else { LONG_LINE_CHARS };
+    let mut hidden = 0;
+    for (style, text) in parts {
+        if left == 0 {
+            hidden += text.chars().count();
+            continue;
+        }
+        match text.char_indices().nth(left) {
+            Some((end, _)) => {
+                spans.push(Span::styled(text[..end].to_string(), style));
+                hidden += text[end..].chars().count();
+                left = 0;
             }
+            None => {
+                spans.push(Span::styled(text.to_string(), style));
+                left -= text.chars().count();
+            }
+        }
+    }
+    if hidden > 0 {
+        spans.push(Span::styled(
+            hidden_marker(hidden),
+            Style::default().fg(colors::gray()),
+        ));
+    }
+    spans
+}
 
-            let mut result_lines = Vec::new();
-            let mut chars: Vec<char> = content.chars().collect();
-            let mut first = true;
+fn preview_header(preview: &FilePreview) -> String {
+    let dot = symbols::text("·");
+    format!(
+        "{} {} {} {} {} {} {} lines",
+        preview.path,
+        dot,
+        format_size(preview.size),
+        dot,
+        preview.file_type,
+        dot,
+        preview.lines.len()
+    )
+}
 
-            while !chars.is_empty() {
-                let take = if first {
-                    content_width.saturating_sub(1) // account for prefix
-                } else {
-                    content_width
-                };
-                let chunk: String = chars.drain(..take.min(chars.len())).collect();
-
-                if first {
-                    result_lines.push(Line::from(vec![
-                        Span::styled(line_num_str.clone(), Style::default().fg(colors::GRAY)),
-                        Span::styled(prefix, content_style),
-                        Span::styled(chunk, content_style),
-                    ]));
-                    first = false;
+/// The old and new mode of a file whose content is unchanged.
+fn mode_change_lines(old_mode: u32, new_mode: u32) -> Vec<Line<'static>> {
+    let mode_line = |prefix: &'static str, label: &str, mode: u32, color| {
+        Line::from(Span::styled(
+            format!("{}{} {:06o} ({})", prefix, label, mode, describe_mode(mode)),
+            Style::default().fg(color),
+        ))
+    };
+    vec![
+        Line::from(""),
+        Line::from(Span::styled(
+            "Mode change only, content is unchanged",
+            Style::default().fg(colors::text()),
+        )),
+        mode_line("-", "old mode", old_mode, colors::red()),
+        mode_line("+", "new mode", new_mode, colors::green()),
+    ]
+}
+
+fn collapsed_lines(added: Option<usize>, deleted: Option<usize>) -> Vec<Line<'static>> {
+    let counts = match (added, deleted) {
+        (Some(added), Some(deleted)) => format!(" (+{} -{})", added, deleted),
+        _ => String::new(),
+    };
+    vec![
+        Line::from(""),
+        Line::from(Span::styled(
+            format!("Generated file, diff collapsed{}", counts),
+            Style::default().fg(colors::text()),
+        )),
+        Line::from(Span::styled(
+            "Expand to review it anyway",
+            Style::default().fg(colors::gray()),
+        )),
+    ]
+}
+
+fn minified_lines(lines: usize, bytes: u64) -> Vec<Line<'static>> {
+    vec![
+        Line::from(""),
+        Line::from(Span::styled(
+            format!(
+                "{} line{} changed, {}",
+                lines,
+                if lines == 1 { "" } else { "s" },
+                format_size(bytes)
+            ),
+            Style::default().fg(colors::text()),
+        )),
+        Line::from(Span::styled(
+            "Looks minified; expand to render it",
+            Style::default().fg(colors::gray()),
+        )),
+    ]
+}
+
+fn type_change_lines(old: &EntryKind, new: &EntryKind) -> Vec<Line<'static>> {
+    vec![
+        Line::from(""),
+        Line::from(Span::styled(
+            "Type change",
+            Style::default().fg(colors::text()),
+        )),
+        Line::from(vec![
+            Span::styled(old.to_string(), Style::default().fg(colors::red())),
+            Span::styled(
+                format!(" {} ", symbols::text("→")),
+                Style::default().fg(colors::gray()),
+            ),
+            Span::styled(new.to_string(), Style::default().fg(colors::green())),
+        ]),
+    ]
+}
+
+fn symlink_lines(old_target: Option<&str>, new_target: Option<&str>) -> Vec<Line<'static>> {
+    let change = match (old_target, new_target) {
+        (None, Some(_)) => "Symlink added",
+        (Some(_), None) => "Symlink deleted",
+        _ => "Symlink retargeted",
+    };
+    let mut lines = vec![
+        Line::from(""),
+        Line::from(Span::styled(change, Style::default().fg(colors::text()))),
+    ];
+    if let Some(target) = old_target {
+        lines.push(Line::from(Span::styled(
+            format!("-{} {}", symbols::text("→"), target),
+            Style::default().fg(colors::red()),
+        )));
+    }
+    if let Some(target) = new_target {
+        lines.push(Line::from(Span::styled(
+            format!("+{} {}", symbols::text("→"), target),
+            Style::default().fg(colors::green()),
+        )));
+    }
+    lines
+}
+
+/// Metadata shown for a binary file instead of a diff.
+fn binary_lines(info: &BinaryInfo) -> Vec<Line<'static>> {
+    let mut lines = size_summary_lines("Binary file ", info);
+    lines.insert(
+        2,
+        Line::from(vec![
+            Span::styled("Type  ", Style::default().fg(colors::gray())),
+            Span::styled(info.mime_type.clone(), Style::default().fg(colors::text())),
+        ]),
+    );
+    lines
+}
+
+fn too_large_lines(info: &BinaryInfo) -> Vec<Line<'static>> {
+    let mut lines = size_summary_lines("Large file ", info);
+    lines.push(Line::from(Span::styled(
+        "Too large to preview; raise [preview] max_text_kib to show it",
+        Style::default().fg(colors::gray()),
+    )));
+    lines
+}
+
+/// `title` with the kind of change, then the sizes before and after.
+fn size_summary_lines(title: &'static str, info: &BinaryInfo) -> Vec<Line<'static>> {
+    let change_color = match info.change {
+        BinaryChange::Added => colors::green(),
+        BinaryChange::Deleted => colors::red(),
+        BinaryChange::Modified => colors::yellow(),
+    };
+    let size = |size: Option<u64>| match size {
+        Some(bytes) => format_size(bytes),
+        None => symbols::text("—").into_owned(),
+    };
+    let mut size_text = format!(
+        "{} {} {}",
+        size(info.old_size),
+        symbols::text("→"),
+        size(info.new_size)
+    );
+    if let (Some(old), Some(new)) = (info.old_size, info.new_size) {
+        let sign = if new >= old { "+" } else { "-" };
+        size_text.push_str(&format!(" ({}{})", sign, format_size(new.abs_diff(old))));
+    }
+    let label = |text: &'static str| Span::styled(text, Style::default().fg(colors::gray()));
+
+    vec![
+        Line::from(""),
+        Line::from(vec![
+            Span::styled(title, Style::default().fg(colors::text())),
+            Span::styled(info.change.name(), Style::default().fg(change_color)),
+        ]),
+        Line::from(vec![
+            label("Size  "),
+            Span::styled(size_text, Style::default().fg(colors::text())),
+        ]),
+    ]
+}
+
+/// Binary metadata followed by an `offset  hex  |ascii|` dump, 16 bytes per
+/// row when the panel is wide enough and 8 otherwise.
+fn hexdump_lines(info: &BinaryInfo, width: usize) -> Vec<Line<'static>> {
+    let mut lines = binary_lines(info);
+    lines.push(Line::from(""));
+    let Some(content) = &info.content else {
+        return lines;
+    };
+    let per_row = if width >= hexdump_row_width(16) {
+        16
+    } else {
+        8
+    };
+    for (i, row) in content.chunks(per_row).enumerate() {
+        let mut hex = String::new();
+        for j in 0..per_row {
+            if j > 0 && j % 8 == 0 {
+                hex.push(' ');
+            }
+            match row.get(j) {
+                Some(byte) => hex.push_str(&format!("{:02x} ", byte)),
+                None => hex.push_str("   "),
+            }
+        }
+        let ascii: String = row
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
                 } else {
-                    result_lines.push(Line::from(vec![
-                        Span::styled(
-                            continuation_gutter.clone(),
-                            Style::default().fg(colors::GRAY),
-                        ),
-                        Span::styled(chunk, content_style),
-                    ]));
+                    '.'
                 }
+            })
+            .collect();
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!("{:08x}  ", i * per_row),
+                Style::default().fg(colors::gray()),
+            ),
+            Span::styled(hex, Style::default().fg(colors::text())),
+            Span::styled(format!(" |{}|", ascii), Style::default().fg(colors::cyan())),
+        ]));
+    }
+    lines
+}
+
+/// Columns taken by a hexdump row of `per_row` bytes.
+fn hexdump_row_width(per_row: usize) -> usize {
+    10 + per_row * 3 + (per_row - 1) / 8 + 2 + per_row + 1
+}
+
+/// Human-readable byte count, e.g. "512 B" or "1.5 KiB".
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+fn token_style(kind: TokenKind) -> Style {
+    let color = match kind {
+        TokenKind::Plain => colors::text(),
+        TokenKind::Comment => colors::gray(),
+        TokenKind::String => colors::green(),
+        TokenKind::Keyword => colors::magenta(),
+        TokenKind::Constant => colors::yellow(),
+        TokenKind::Function => colors::blue(),
+        TokenKind::Type => colors::cyan(),
+    };
+    Style::default().fg(color)
+}
+
+/// Break styled spans into rows: `first_width` columns for the first
+/// row and `width` for the rest. Wide characters never straddle a row. Always returns at least one (possibly
+/// empty) row.
+fn split_spans(
+    spans: Vec<Span<'static>>,
+    first_width: usize,
+    width: usize,
+) -> Vec<Vec<Span<'static>>> {
+    let mut rows = vec![Vec::new()];
+    let mut used = 0;
+    for span in spans {
+        let mut chunk = String::new();
+        for c in span.content.chars() {
+            let limit = if rows.len() == 1 { first_width } else { width };
+            let char_width = c.width().unwrap_or(0);
+            if used + char_width > limit && used > 0 {
+                if !chunk.is_empty() {
+                    rows.last_mut()
+                        .unwrap()
+                        .push(Span::styled(std::mem::take(&mut chunk), span.style));
+                }
+                rows.push(Vec::new());
+                used = 0;
             }
+            chunk.push(c);
+            used += char_width;
+        }
+        if !chunk.is_empty() {
+            rows.last_mut()
+                .unwrap()
+                .push(Span::styled(chunk, span.style));
+        }
+    }
+    rows
+}
 
-            result_lines
-        })
-        .collect()
+/// How many rows [`split_spans`] breaks characters of these display
+/// widths into.
+fn count_rows(widths: impl Iterator<Item = usize>, first_width: usize, width: usize) -> usize {
+    let mut rows = 1;
+    let mut used = 0;
+    for char_width in widths {
+        let limit = if rows == 1 { first_width } else { width };
+        if used + char_width > limit && used > 0 {
+            rows += 1;
+            used = 0;
+        }
+        used += char_width;
+    }
+    rows
 }
 
 /// Calculate the maximum scroll offset for the diff content.
-pub fn max_scroll(diff: &DiffContent, viewport_height: usize, viewport_width: usize) -> usize {
+pub fn max_scroll(
+    diff: &DiffContent,
+    viewport_height: usize,
+    viewport_width: usize,
+    wrap: bool,
+    expand: bool,
+) -> usize {
     let total = match diff {
+        DiffContent::Text(lines) if !wrap => lines.len(),
         DiffContent::Text(lines) => {
-            let rendered = render_diff_lines(lines, viewport_width);
-            rendered.len()
+            let layout = DiffLayout::new(lines, viewport_width, true, expand);
+            lines.iter().map(|l| layout.rows(l)).sum()
+        }
+        DiffContent::Hexdump(info) => hexdump_lines(info, viewport_width).len(),
+        DiffContent::Preview(preview) if !wrap => preview.lines.len() + 1,
+        DiffContent::Preview(preview) => {
+            render_preview_lines(preview, viewport_width, true, expand).len()
         }
+        DiffContent::Conflict(preview) if preview.lines.is_empty() => 0,
+        DiffContent::Conflict(preview) => conflict_lines(preview, viewport_width, wrap, expand)
+            .0
+            .len(),
         _ => 0,
     };
     total.saturating_sub(viewport_height)
 }
 
+/// The row a text diff's `line` starts on.
+pub fn row_of_line(
+    diff_lines: &[DiffLine],
+    line: usize,
+    viewport_width: usize,
+    wrap: bool,
+    expand: bool,
+) -> usize {
+    let layout = DiffLayout::new(diff_lines, viewport_width, wrap, expand);
+    diff_lines[..line.min(diff_lines.len())]
+        .iter()
+        .map(|l| layout.rows(l))
+        .sum()
+}
+
+/// The index of the text diff line drawn on `row`, or the last line past
+/// the end.
+pub fn line_at_row(
+    diff_lines: &[DiffLine],
+    row: usize,
+    viewport_width: usize,
+    wrap: bool,
+    expand: bool,
+) -> usize {
+    let layout = DiffLayout::new(diff_lines, viewport_width, wrap, expand);
+    let mut end = 0;
+    for (i, line) in diff_lines.iter().enumerate() {
+        end += layout.rows(line);
+        if row < end {
+            return i;
+        }
+    }
+    diff_lines.len().saturating_sub(1)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_max_scroll_empty() {
-        assert_eq!(max_scroll(&DiffContent::Empty, 10, 80), 0);
-        assert_eq!(max_scroll(&DiffContent::Clean, 10, 80), 0);
-        assert_eq!(max_scroll(&DiffContent::Binary, 10, 80), 0);
-        assert_eq!(max_scroll(&DiffContent::InvalidUtf8, 10, 80), 0);
-        assert_eq!(max_scroll(&DiffContent::Conflict, 10, 80), 0);
+        assert_eq!(max_scroll(&DiffContent::Empty, 10, 80, true, false), 0);
+        assert_eq!(max_scroll(&DiffContent::Clean, 10, 80, true, false), 0);
+        assert_eq!(
+            max_scroll(&DiffContent::InvalidUtf8, 10, 80, true, false),
+            0
+        );
+        assert_eq!(
+            max_scroll(
+                &DiffContent::Conflict(Default::default()),
+                10,
+                80,
+                true,
+                false
+            ),
+            0
+        );
     }
 
     #[test]
@@ -223,12 +1014,327 @@ mod tests {
         let diff = DiffContent::Text(lines);
 
         // 20 lines, viewport 10, wide enough: can scroll 10
-        assert_eq!(max_scroll(&diff, 10, 80), 10);
+        assert_eq!(max_scroll(&diff, 10, 80, true, false), 10);
 
         // 20 lines, viewport 20: no scroll
-        assert_eq!(max_scroll(&diff, 20, 80), 0);
+        assert_eq!(max_scroll(&diff, 20, 80, true, false), 0);
 
         // 20 lines, viewport 30: no scroll
-        assert_eq!(max_scroll(&diff, 30, 80), 0);
+        assert_eq!(max_scroll(&diff, 30, 80, true, false), 0);
+    }
+
+    #[test]
+    fn test_split_spans_counts_display_width() {
+        let spans = vec![Span::raw("中文字符"), Span::raw("ab")];
+        let rows: Vec<String> = split_spans(spans, 5, 5)
+            .iter()
+            .map(|row| row.iter().map(|s| s.content.as_ref()).collect())
+            .collect();
+        // A wide character that would cross the edge moves to the next row
+        assert_eq!(rows, vec!["中文", "字符a", "b"]);
+
+        let rows = split_spans(vec![Span::raw("🎉🎉🎉")], 3, 4);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][0].content, "🎉");
+        assert_eq!(rows[1][0].content, "🎉🎉");
+    }
+
+    fn line_text(line: &Line) -> String {
+        line.spans.iter().map(|s| s.content.as_ref()).collect()
+    }
+
+    fn preview(lines: &[&str]) -> FilePreview {
+        FilePreview {
+            path: "src/new.rs".to_string(),
+            size: 2048,
+            file_type: "Rust".to_string(),
+            lines: lines
+                .iter()
+                .map(|l| {
+                    vec![
+                        (TokenKind::Keyword, "fn".to_string()),
+                        (TokenKind::Plain, l.to_string()),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_preview_lines_have_header_and_real_line_numbers() {
+        let text: Vec<String> = render_preview_lines(&preview(&[" a()", " b()"]), 80, true, false)
+            .iter()
+            .map(line_text)
+            .collect();
+        assert_eq!(
+            text,
+            vec![
+                "src/new.rs · 2.0 KiB · Rust · 2 lines",
+                "  1 │ fn a()",
+                "  2 │ fn b()",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_preview_wraps_across_tokens() {
+        // Content width is 10 - 6 = 4 columns.
+        let text: Vec<String> = render_preview_lines(&preview(&[" abcde"]), 10, true, false)
+            .iter()
+            .map(line_text)
+            .collect();
+        assert_eq!(text[1..], ["  1 │ fn a", "    │ bcde"]);
+        let diff = DiffContent::Preview(preview(&[" abcde"]));
+        assert_eq!(max_scroll(&diff, 1, 10, true, false), 2);
+        assert_eq!(max_scroll(&diff, 1, 10, false, false), 1);
+    }
+
+    #[test]
+    fn test_binary_lines_show_change_type_and_sizes() {
+        let info = BinaryInfo {
+            change: BinaryChange::Modified,
+            old_size: Some(2048),
+            new_size: Some(1024),
+            mime_type: "image/png".to_string(),
+            content: None,
+        };
+        let text: Vec<String> = binary_lines(&info).iter().map(line_text).collect();
+        assert_eq!(
+            text,
+            vec![
+                "",
+                "Binary file modified",
+                "Type  image/png",
+                "Size  2.0 KiB → 1.0 KiB (-1.0 KiB)",
+            ]
+        );
+
+        let added = BinaryInfo {
+            change: BinaryChange::Added,
+            old_size: None,
+            new_size: Some(10),
+            ..info
+        };
+        assert_eq!(line_text(&binary_lines(&added)[3]), "Size  — → 10 B");
+    }
+
+    #[test]
+    fn test_hexdump_lines() {
+        let info = BinaryInfo {
+            change: BinaryChange::Added,
+            old_size: None,
+            new_size: Some(18),
+            mime_type: "application/octet-stream".to_string(),
+            content: Some(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR!!".to_vec()),
+        };
+        let text: Vec<String> = hexdump_lines(&info, 80).iter().map(line_text).collect();
+        assert_eq!(
+            text[5..],
+            [
+                "00000000  89 50 4e 47 0d 0a 1a 0a  00 00 00 0d 49 48 44 52  |.PNG........IHDR|",
+                "00000010  21 21                                             |!!|",
+            ]
+        );
+        assert_eq!(hexdump_row_width(16), text[5].chars().count());
+
+        let narrow: Vec<String> = hexdump_lines(&info, 60).iter().map(line_text).collect();
+        assert_eq!(narrow[5], "00000000  89 50 4e 47 0d 0a 1a 0a  |.PNG....|");
+        assert_eq!(narrow.len(), 8);
+    }
+
+    #[test]
+    fn test_mode_change_lines() {
+        let text: Vec<String> = mode_change_lines(0o100644, 0o100755)
+            .iter()
+            .map(line_text)
+            .collect();
+        assert_eq!(
+            text[2..],
+            [
+                "-old mode 100644 (regular file)",
+                "+new mode 100755 (executable)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_change_lines() {
+        let old = EntryKind {
+            mode: 0o100644,
+            target: None,
+        };
+        let new = EntryKind {
+            mode: 0o120000,
+            target: Some("../shared/config".to_string()),
+        };
+        let text: Vec<String> = type_change_lines(&old, &new)
+            .iter()
+            .map(line_text)
+            .collect();
+        assert_eq!(text[2], "regular file → symlink to ../shared/config");
+    }
+
+    #[test]
+    fn test_symlink_lines() {
+        let text: Vec<String> = symlink_lines(Some("a/old"), Some("b/new"))
+            .iter()
+            .map(line_text)
+            .collect();
+        assert_eq!(text[1..], ["Symlink retargeted", "-→ a/old", "+→ b/new"]);
+        let added: Vec<String> = symlink_lines(None, Some("x"))
+            .iter()
+            .map(line_text)
+            .collect();
+        assert_eq!(added[1..], ["Symlink added", "+→ x"]);
+    }
+
+    #[test]
+    fn test_content_spans_highlight_whitespace_in_added_lines() {
+        let line = |kind, content: &str| DiffLine {
+            kind,
+            content: content.to_string(),
+            new_line_number: Some(1),
+        };
+        let style = Style::default().fg(colors::green());
+        let spans = content_spans(&line(DiffLineKind::Added, "  \tx = 1;  \r"), style, false);
+        let parts: Vec<(&str, bool)> = spans
+            .iter()
+            .map(|s| (s.content.as_ref(), s.style.bg == Some(colors::red())))
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                ("  ", true),
+                ("\tx = 1;", false),
+                ("  ", true),
+                ("␍", false)
+            ]
+        );
+
+        let context = content_spans(&line(DiffLineKind::Context, "x  "), style, false);
+        assert_eq!(context.len(), 1);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn test_max_scroll_without_wrap_ignores_width() {
+        let diff = DiffContent::Text(vec![DiffLine {
+            kind: DiffLineKind::Added,
+            content: "x".repeat(100),
+            new_line_number: Some(1),
+        }]);
+        assert_eq!(max_scroll(&diff, 1, 20, true, false), 7);
+        assert_eq!(max_scroll(&diff, 1, 20, false, false), 0);
+    }
+
+    fn context(content: String, n: usize) -> DiffLine {
+        DiffLine {
+            kind: DiffLineKind::Context,
+            content,
+            new_line_number: Some(n),
+        }
+    }
+
+    #[test]
+    fn test_long_lines_are_clipped_with_marker_until_expanded() {
+        let line = context("a".repeat(LONG_LINE_CHARS + 250), 1);
+        let spans = content_spans(&line, Style::default(), false);
+        let text: String = spans.iter().map(|s| s.content.as_ref()).collect();
+        assert!(text.starts_with(&"a".repeat(LONG_LINE_CHARS)));
+        assert!(text.ends_with("(+250 chars)"));
+
+        let spans = content_spans(&line, Style::default(), true);
+        let text: String = spans.iter().map(|s| s.content.as_ref()).collect();
+        assert_eq!(text.len(), LONG_LINE_CHARS + 250);
+
+        let tokens = vec![
+            (TokenKind::Keyword, "x".repeat(LONG_LINE_CHARS - 1)),
+            (TokenKind::Plain, "yz".to_string()),
+            (TokenKind::Comment, "abc".to_string()),
+        ];
+        let clipped = token_spans(&tokens, false);
+        assert_eq!(clipped[1].content, "y");
+        assert!(clipped.last().unwrap().content.ends_with("(+4 chars)"));
+    }
+
+    #[test]
+    fn test_row_counts_match_rendered_rows() {
+        let lines = vec![
+            context("short".to_string(), 1),
+            context("中文字符".repeat(30), 2),
+            context(format!("{}\r", "x".repeat(37)), 3),
+            context("y".repeat(LONG_LINE_CHARS * 3), 4),
+            context(String::new(), 5),
+        ];
+        for width in [10, 17, 40, 80] {
+            for expand in [false, true] {
+                let layout = DiffLayout::new(&lines, width, true, expand);
+                for line in &lines {
+                    assert_eq!(
+                        layout.rows(line),
+                        layout.render(line).len(),
+                        "width {width}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_diff_window_renders_only_visible_lines() {
+        let lines: Vec<DiffLine> = (0..1000).map(|i| context("z".repeat(30), i + 1)).collect();
+        let layout = DiffLayout::new(&lines, 20, true, false);
+        let all: Vec<String> = lines
+            .iter()
+            .flat_map(|l| layout.render(l))
+            .map(|l| line_text(&l))
+            .collect();
+
+        // Each line wraps to three rows; start partway through line 168.
+        let (window, skip) = render_diff_window(&lines, &layout, 502, 10, &LineMarks::default());
+        assert_eq!(skip, 1);
+        assert!(window.len() <= 12);
+        let shown: Vec<String> = window.iter().skip(skip).take(10).map(line_text).collect();
+        assert_eq!(shown, all[502..512]);
+
+        // Scrolling past the end clamps to the last full page.
+        let (window, skip) =
+            render_diff_window(&lines, &layout, usize::MAX, 10, &LineMarks::default());
+        let shown: Vec<String> = window.iter().skip(skip).map(line_text).collect();
+        assert_eq!(shown, all[all.len() - 10..]);
+    }
+
+    #[test]
+    fn test_line_rows_round_trip_and_selection_is_reversed() {
+        let lines: Vec<DiffLine> = (0..5)
+            .map(|i| context("w".repeat(if i == 2 { 40 } else { 5 }), i + 1))
+            .collect();
+        // Line 2 wraps to three rows at width 20.
+        assert_eq!(row_of_line(&lines, 3, 20, true, false), 5);
+        assert_eq!(row_of_line(&lines, 3, 20, false, false), 3);
+        assert_eq!(line_at_row(&lines, 4, 20, true, false), 2);
+        assert_eq!(line_at_row(&lines, 5, 20, true, false), 3);
+        assert_eq!(line_at_row(&lines, 99, 20, true, false), 4);
+
+        let layout = DiffLayout::new(&lines, 20, true, false);
+        let marks = LineMarks {
+            cursor: Some(4),
+            selection: Some(1..=2),
+        };
+        let (window, _) = render_diff_window(&lines, &layout, 0, 10, &marks);
+        let reversed: Vec<bool> = window
+            .iter()
+            .map(|l| l.style.add_modifier.contains(Modifier::REVERSED))
+            .collect();
+        assert_eq!(reversed, [false, true, true, true, true, false, false]);
+        assert_eq!(window[6].style.bg, Some(colors::surface()));
+        assert_eq!(window[5].style.bg, None);
     }
 }
@@ -1,5 +1,8 @@
+use crate::config::Column;
 use crate::types::{FileEntry, FileStatus, MultiSelectSet, Section};
-use crate::ui::colors;
+use crate::ui::diff_panel::format_size;
+use crate::ui::modal::format_age;
+use crate::ui::{colors, icons, symbols};
 use ratatui::{
     layout::Rect,
     style::{Modifier, Style},
@@ -7,6 +10,10 @@ use ratatui::{
     widgets::{Block, Borders, List, ListItem},
     Frame,
 };
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::time::SystemTime;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 #[allow(clippy::too_many_arguments)]
 pub fn draw(
@@ -15,59 +22,71 @@ pub fn draw(
     staged_files: &[FileEntry],
     unstaged_files: &[FileEntry],
     highlight_index: Option<usize>,
-    selected: Option<&(Section, String)>,
+    selected: Option<&(Section, Vec<u8>)>,
     multi_selected: &MultiSelectSet,
+    needs_review: &HashSet<String>,
+    columns: &[Column],
+    path_base: Option<&str>,
     scroll_offset: usize,
 ) {
+    let layout = RowLayout::new(columns, path_base, area.width);
     let mut items: Vec<ListItem> = Vec::new();
     let mut current_index = 0usize;
 
     if !staged_files.is_empty() {
-        items.push(ListItem::new(Line::from(Span::styled(
-            "[STAGED]",
-            Style::default()
-                .fg(colors::CYAN)
-                .add_modifier(Modifier::BOLD),
-        ))));
+        let count = format!(" ({})", staged_files.len());
+        items.push(section_header("[STAGED]", count));
 
         for file in staged_files {
             let is_highlighted = highlight_index == Some(current_index);
             let is_selected = selected
-                .map(|(s, p)| *s == Section::Staged && p == &file.path)
+                .map(|(s, p)| *s == Section::Staged && p == &file.raw_path)
                 .unwrap_or(false);
-            let is_multi_selected = multi_selected.contains(&(Section::Staged, file.path.clone()));
+            let is_multi_selected =
+                multi_selected.contains(&(Section::Staged, file.raw_path.clone()));
             items.push(create_file_item(
                 file,
                 is_highlighted,
                 is_selected,
                 is_multi_selected,
-                area.width,
+                false,
+                &layout,
             ));
             current_index += 1;
         }
     }
 
     if !unstaged_files.is_empty() {
-        items.push(ListItem::new(Line::from(Span::styled(
-            "[UNSTAGED]",
-            Style::default()
-                .fg(colors::CYAN)
-                .add_modifier(Modifier::BOLD),
-        ))));
+        let untracked = unstaged_files
+            .iter()
+            .filter(|f| f.status == FileStatus::Untracked)
+            .count();
+        let count = if untracked > 0 {
+            symbols::text(format!(
+                " ({} · {} untracked)",
+                unstaged_files.len(),
+                untracked
+            ))
+            .into_owned()
+        } else {
+            format!(" ({})", unstaged_files.len())
+        };
+        items.push(section_header("[UNSTAGED]", count));
 
         for file in unstaged_files {
             let is_highlighted = highlight_index == Some(current_index);
             let is_selected = selected
-                .map(|(s, p)| *s == Section::Unstaged && p == &file.path)
+                .map(|(s, p)| *s == Section::Unstaged && p == &file.raw_path)
                 .unwrap_or(false);
             let is_multi_selected =
-                multi_selected.contains(&(Section::Unstaged, file.path.clone()));
+                multi_selected.contains(&(Section::Unstaged, file.raw_path.clone()));
             items.push(create_file_item(
                 file,
                 is_highlighted,
                 is_selected,
                 is_multi_selected,
-                area.width,
+                needs_review.contains(&file.path),
+                &layout,
             ));
             current_index += 1;
         }
@@ -78,21 +97,37 @@ pub fn draw(
     let end = (start + visible_height).min(items.len());
     let visible_items: Vec<ListItem> = items.into_iter().skip(start).take(end - start).collect();
 
-    let list = List::new(visible_items).block(
-        Block::default()
-            .borders(Borders::ALL)
-            .border_style(Style::default().fg(colors::OVERLAY)),
-    );
+    let mut block = Block::default()
+        .borders(Borders::ALL)
+        .border_set(symbols::border_set())
+        .border_style(Style::default().fg(colors::overlay()));
+    if let Some(totals) = totals_line(staged_files, unstaged_files, area.width.saturating_sub(2)) {
+        block = block.title_bottom(totals);
+    }
+    let list = List::new(visible_items).block(block);
 
     frame.render_widget(list, area);
 }
 
+fn section_header(title: &'static str, count: String) -> ListItem<'static> {
+    ListItem::new(Line::from(vec![
+        Span::styled(
+            title,
+            Style::default()
+                .fg(colors::cyan())
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(count, Style::default().fg(colors::gray())),
+    ]))
+}
+
 fn create_file_item(
     file: &FileEntry,
     is_highlighted: bool,
     is_selected: bool,
     is_multi_selected: bool,
-    width: u16,
+    needs_review: bool,
+    layout: &RowLayout,
 ) -> ListItem<'static> {
     let prefix = match (is_highlighted, is_selected, is_multi_selected) {
         (true, true, true) => ">●◆",
@@ -104,103 +139,276 @@ fn create_file_item(
         (false, false, true) => "  ◆",
         (false, false, false) => "   ",
     };
+    let prefix = symbols::text(prefix);
 
-    let status_color = get_status_color(file.status);
-    let status_symbol = file.status.symbol();
+    let base_style = if is_highlighted {
+        Style::default().add_modifier(Modifier::BOLD)
+    } else {
+        Style::default()
+    };
 
-    let counts = format_line_counts(file.added_lines, file.deleted_lines, file.is_binary);
+    let counts = if layout.columns.contains(&Column::Counts) {
+        format_counts(file)
+    } else {
+        String::new()
+    };
 
+    let path = relative_path(&file.path, layout.path_base.as_deref());
     let display_path = if let Some(ref old) = file.old_path {
-        format!("{} → {}", old, file.path)
+        let old = relative_path(old, layout.path_base.as_deref());
+        symbols::text(format!("{} → {}", old, path)).into_owned()
     } else {
-        file.path.clone()
+        path.into_owned()
     };
-
-    let fixed_width = prefix.len() + 2 + counts.len() + 2;
-    let available_width = (width as usize).saturating_sub(fixed_width);
+    // Kept apart from the path so truncation never cuts it off.
+    let icon = icons::icon(file).map(|icon| format!("{} ", icon));
+
+    // Rewritten by pre-commit hooks since it was last looked at.
+    let review = if needs_review { " !hooks" } else { "" };
+    let badge = if file.whitespace_errors { " !ws" } else { "" };
+    let generated = if file.generated { " gen" } else { "" };
+
+    // Every column but the path and counts keeps its width; each is
+    // followed by a one-column gap.
+    let cells: Vec<(Column, Option<Span<'static>>)> = layout
+        .columns
+        .iter()
+        .map(|&column| (column, fixed_cell(column, file, base_style, layout.now)))
+        .collect();
+    let fixed_width = prefix.width()
+        + cells
+            .iter()
+            .flat_map(|(_, cell)| cell)
+            .map(|cell| cell.width() + 1)
+            .sum::<usize>()
+        + icon.as_ref().map_or(0, |icon| icon.width())
+        + review.width()
+        + badge.width()
+        + generated.width()
+        + 2;
+    let available_width = (layout.width as usize).saturating_sub(fixed_width);
 
     let (path_display, show_counts) =
         format_path_with_priority(&display_path, &counts, available_width);
 
-    let base_style = if is_highlighted {
-        Style::default().add_modifier(Modifier::BOLD)
-    } else {
-        Style::default()
-    };
-
-    let mut spans = vec![
-        Span::styled(prefix, base_style.fg(colors::TEXT)),
-        Span::styled(status_symbol, base_style.fg(status_color)),
-        Span::styled(" ", base_style),
-        Span::styled(path_display, base_style.fg(colors::TEXT)),
-    ];
+    let mut spans = vec![Span::styled(prefix, base_style.fg(colors::text()))];
+    for (column, cell) in cells {
+        let cell = match column {
+            Column::Path => Span::styled(path_display.clone(), base_style.fg(colors::text())),
+            Column::Counts if !show_counts || counts.is_empty() => continue,
+            Column::Counts => {
+                let color = if file.conflict.is_some() {
+                    colors::magenta()
+                } else if file.mode_only() {
+                    colors::cyan()
+                } else {
+                    colors::gray()
+                };
+                Span::styled(counts.clone(), Style::default().fg(color))
+            }
+            _ => match cell {
+                Some(cell) => cell,
+                None => continue,
+            },
+        };
+        if spans.len() > 1 {
+            spans.push(Span::styled(" ", base_style));
+        }
+        if let (Column::Path, Some(icon)) = (column, &icon) {
+            spans.push(Span::styled(
+                icon.clone(),
+                Style::default().fg(colors::blue()),
+            ));
+        }
+        spans.push(cell);
+    }
 
-    if show_counts && !counts.is_empty() {
+    if !generated.is_empty() {
+        spans.push(Span::styled(generated, Style::default().fg(colors::gray())));
+    }
+    if !review.is_empty() {
         spans.push(Span::styled(
-            format!(" {}", counts),
-            Style::default().fg(colors::GRAY),
+            review,
+            Style::default()
+                .fg(colors::yellow())
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+    if !badge.is_empty() {
+        spans.push(Span::styled(
+            badge,
+            Style::default()
+                .fg(colors::yellow())
+                .add_modifier(Modifier::BOLD),
         ));
     }
 
     ListItem::new(Line::from(spans))
 }
 
-fn format_path_with_priority(path: &str, counts: &str, available_width: usize) -> (String, bool) {
+/// What every row is laid out with.
+struct RowLayout {
+    /// The configured columns, with the path added at the end if missing.
+    columns: Vec<Column>,
+    /// Directory the paths are shown relative to, if not the root.
+    path_base: Option<String>,
+    now: SystemTime,
+    width: u16,
+}
+
+impl RowLayout {
+    fn new(columns: &[Column], path_base: Option<&str>, width: u16) -> Self {
+        let mut columns = columns.to_vec();
+        if !columns.contains(&Column::Path) {
+            columns.push(Column::Path);
+        }
+        Self {
+            columns,
+            path_base: path_base.map(str::to_string),
+            now: SystemTime::now(),
+            width,
+        }
+    }
+}
+
+/// The content of a fixed-width `column`; `None` for the path and counts,
+/// which are fitted to the space left over.
+fn fixed_cell(
+    column: Column,
+    file: &FileEntry,
+    base_style: Style,
+    now: SystemTime,
+) -> Option<Span<'static>> {
+    let gray = Style::default().fg(colors::gray());
+    match column {
+        Column::Path | Column::Counts => None,
+        Column::Status => Some(Span::styled(
+            file.status.symbol(),
+            base_style.fg(get_status_color(file.status)),
+        )),
+        Column::Mode => {
+            let exec = if file.is_executable() { "x" } else { " " };
+            let text = format!("{:>3} {}", format_mode(file.current_mode()), exec);
+            let color = if file.is_executable() {
+                colors::green()
+            } else {
+                colors::gray()
+            };
+            Some(Span::styled(text, Style::default().fg(color)))
+        }
+        // Right-aligned so the values line up; blank for deleted files.
+        Column::Mtime => {
+            let age = file
+                .mtime
+                .map(|mtime| format_age(now.duration_since(mtime).unwrap_or_default()))
+                .unwrap_or_default();
+            Some(Span::styled(format!("{:>7}", age), gray))
+        }
+        Column::Size => {
+            let size = file.size.map(format_size).unwrap_or_default();
+            Some(Span::styled(format!("{:>10}", size), gray))
+        }
+    }
+}
+
+/// The line counts, or the mode change when that's all that changed, with
+/// any line-ending conversion after them. Conflicts show how they conflict.
+fn format_counts(file: &FileEntry) -> String {
+    if let Some(kind) = file.conflict {
+        return kind.label().to_string();
+    }
+    let mut counts = if file.mode_only() {
+        // Permission churn stands in for the empty +0/-0.
+        symbols::text(format!(
+            "{}→{}",
+            format_mode(file.old_mode),
+            format_mode(file.mode)
+        ))
+        .into_owned()
+    } else {
+        format_line_counts(file.added_lines, file.deleted_lines, file.is_binary)
+    };
+    if let Some(change) = file.eol_change {
+        counts = format!("{} {}", counts, change.label()).trim().to_string();
+    }
+    counts
+}
+
+pub(crate) fn format_path_with_priority(
+    path: &str,
+    counts: &str,
+    available_width: usize,
+) -> (String, bool) {
     let counts_len = if counts.is_empty() {
         0
     } else {
-        counts.len() + 1
+        counts.width() + 1
     };
 
-    let path_char_count = path.chars().count();
+    let path_width = path.width();
 
-    if path_char_count + counts_len <= available_width {
+    if path_width + counts_len <= available_width {
         return (path.to_string(), true);
     }
 
-    if path_char_count <= available_width {
+    if path_width <= available_width {
         return (path.to_string(), false);
     }
 
     let filename = path.rsplit('/').next().unwrap_or(path);
-    let filename_char_count = filename.chars().count();
+    let filename_width = filename.width();
+
+    if filename_width < available_width {
+        let ellipsis = symbols::ellipsis();
+        let remaining = available_width.saturating_sub(ellipsis.width());
+        return (
+            format!("{}{}", ellipsis, tail_within(path, remaining)),
+            false,
+        );
+    }
 
-    if filename_char_count < available_width {
-        let remaining = available_width.saturating_sub(1);
-        if path_char_count <= remaining {
-            return (path.to_string(), false);
-        }
-        // Use chars() to avoid slicing at invalid UTF-8 boundaries
-        let tail: String = path
-            .chars()
-            .rev()
-            .take(remaining)
-            .collect::<String>()
-            .chars()
-            .rev()
-            .collect();
-        return (format!("…{}", tail), false);
-    }
-
-    if filename_char_count <= available_width {
+    if filename_width <= available_width {
         return (filename.to_string(), false);
     }
 
-    if available_width > 0 {
-        return (filename.chars().take(available_width).collect(), false);
-    }
+    (head_within(filename, available_width), false)
+}
+
+/// Longest prefix of `s` whose display width fits in `width` columns.
+fn head_within(s: &str, width: usize) -> String {
+    let mut used = 0;
+    s.chars()
+        .take_while(|c| {
+            used += c.width().unwrap_or(0);
+            used <= width
+        })
+        .collect()
+}
 
-    (String::new(), false)
+/// Longest suffix of `s` whose display width fits in `width` columns.
+fn tail_within(s: &str, width: usize) -> String {
+    let mut used = 0;
+    let mut tail: Vec<char> = s
+        .chars()
+        .rev()
+        .take_while(|c| {
+            used += c.width().unwrap_or(0);
+            used <= width
+        })
+        .collect();
+    tail.reverse();
+    tail.into_iter().collect()
 }
 
 fn get_status_color(status: FileStatus) -> ratatui::style::Color {
     match status {
-        FileStatus::Added => colors::GREEN,
-        FileStatus::Modified => colors::YELLOW,
-        FileStatus::Deleted => colors::RED,
-        FileStatus::Renamed => colors::BLUE,
-        FileStatus::Untracked => colors::GRAY,
-        FileStatus::Conflict => colors::MAGENTA,
+        FileStatus::Added => colors::green(),
+        FileStatus::Modified => colors::yellow(),
+        FileStatus::Deleted => colors::red(),
+        FileStatus::Renamed => colors::blue(),
+        FileStatus::TypeChanged => colors::cyan(),
+        FileStatus::Untracked => colors::gray(),
+        FileStatus::Conflict => colors::magenta(),
     }
 }
 
@@ -214,6 +422,133 @@ fn format_line_counts(added: Option<usize>, deleted: Option<usize>, is_binary: b
     }
 }
 
+/// Files, insertions, and deletions in a set of changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Totals {
+    files: usize,
+    insertions: usize,
+    deletions: usize,
+}
+
+impl Totals {
+    fn of(files: &[FileEntry]) -> Self {
+        Self {
+            files: files.len(),
+            insertions: files.iter().filter_map(|f| f.added_lines).sum(),
+            deletions: files.iter().filter_map(|f| f.deleted_lines).sum(),
+        }
+    }
+
+    fn counts(&self) -> String {
+        format!("+{}/-{}", self.insertions, self.deletions)
+    }
+}
+
+/// A `git diff --stat` style summary for the bottom border, split into
+/// staged and unstaged, shortened to fit `width` or left out if it can't.
+fn totals_line(staged: &[FileEntry], unstaged: &[FileEntry], width: u16) -> Option<Line<'static>> {
+    if staged.is_empty() && unstaged.is_empty() {
+        return None;
+    }
+    // A file with both staged and unstaged changes is one changed file.
+    let changed: HashSet<&str> = staged
+        .iter()
+        .chain(unstaged)
+        .map(|f| f.path.as_str())
+        .collect();
+    let (staged, unstaged) = (Totals::of(staged), Totals::of(unstaged));
+    let insertions = staged.insertions + unstaged.insertions;
+    let deletions = staged.deletions + unstaged.deletions;
+
+    let gray = Style::default().fg(colors::gray());
+    let files = format!(
+        "{} file{}",
+        changed.len(),
+        if changed.len() == 1 { "" } else { "s" }
+    );
+    let summary = vec![
+        Span::styled(format!(" {} changed, ", files), gray),
+        Span::styled(
+            format!(
+                "{} insertion{}(+)",
+                insertions,
+                if insertions == 1 { "" } else { "s" }
+            ),
+            Style::default().fg(colors::green()),
+        ),
+        Span::styled(", ", gray),
+        Span::styled(
+            format!(
+                "{} deletion{}(-) ",
+                deletions,
+                if deletions == 1 { "" } else { "s" }
+            ),
+            Style::default().fg(colors::red()),
+        ),
+    ];
+    let split = Span::styled(
+        symbols::text(format!(
+            "· staged {} {} · unstaged {} {} ",
+            staged.files,
+            staged.counts(),
+            unstaged.files,
+            unstaged.counts()
+        ))
+        .into_owned(),
+        gray,
+    );
+    let short = vec![
+        Span::styled(format!(" {} ", files), gray),
+        Span::styled(
+            format!("+{}", insertions),
+            Style::default().fg(colors::green()),
+        ),
+        Span::styled(" ", gray),
+        Span::styled(
+            format!("-{} ", deletions),
+            Style::default().fg(colors::red()),
+        ),
+    ];
+
+    let mut full = summary.clone();
+    full.push(split);
+    [full, summary, short]
+        .into_iter()
+        .map(Line::from)
+        .find(|line| line.width() <= width as usize)
+}
+
+/// Repository-relative `path` as seen from the directory `base`, going up
+/// with `../` where needed.
+pub fn relative_path<'a>(path: &'a str, base: Option<&str>) -> Cow<'a, str> {
+    let Some(base) = base else {
+        return Cow::Borrowed(path);
+    };
+    let mut path_parts: Vec<&str> = path.split('/').collect();
+    let base_parts: Vec<&str> = base.split('/').collect();
+    // Never share the file name itself with the base.
+    let shared = base_parts
+        .iter()
+        .zip(&path_parts[..path_parts.len() - 1])
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut parts = vec![".."; base_parts.len() - shared];
+    parts.extend(path_parts.drain(shared..));
+    Cow::Owned(parts.join("/"))
+}
+
+/// A git file mode the way a listing shows it: the permission bits for
+/// regular files, otherwise the kind of entry.
+fn format_mode(mode: u32) -> String {
+    match mode {
+        0 => String::new(),
+        0o120000 => "lnk".to_string(),
+        0o160000 => "sub".to_string(),
+        0o040000 => "dir".to_string(),
+        mode => format!("{:o}", mode & 0o777),
+    }
+}
+
 /// Calculate the height of the file list widget.
 pub fn calculate_height(staged_count: usize, unstaged_count: usize, max_height: u16) -> u16 {
     let mut total = 0;
@@ -240,6 +575,56 @@ mod tests {
         assert_eq!(format_line_counts(Some(10), Some(5), true), "-/-");
     }
 
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(relative_path("src/ui/a.rs", None), "src/ui/a.rs");
+        assert_eq!(relative_path("src/ui/a.rs", Some("src/ui")), "a.rs");
+        assert_eq!(relative_path("src/main.rs", Some("src/ui")), "../main.rs");
+        assert_eq!(
+            relative_path("README.md", Some("src/ui")),
+            "../../README.md"
+        );
+        assert_eq!(relative_path("docs/x.md", Some("src")), "../docs/x.md");
+        assert_eq!(relative_path("src", Some("src")), "../src");
+    }
+
+    #[test]
+    fn test_totals_line() {
+        let file = |path: &str, added, deleted| FileEntry {
+            added_lines: Some(added),
+            deleted_lines: Some(deleted),
+            ..crate::ui::tests::test_file_entry(path, FileStatus::Modified)
+        };
+        let staged = [file("a.rs", 3, 1)];
+        let unstaged = [file("a.rs", 1, 0), file("b.rs", 2, 2)];
+        let text = |width| {
+            totals_line(&staged, &unstaged, width)
+                .map(|line| line.to_string())
+                .unwrap_or_default()
+        };
+
+        assert_eq!(
+            text(100),
+            " 2 files changed, 6 insertions(+), 3 deletions(-) · staged 1 +3/-1 · unstaged 2 +3/-2 "
+        );
+        assert_eq!(
+            text(60),
+            " 2 files changed, 6 insertions(+), 3 deletions(-) "
+        );
+        assert_eq!(text(20), " 2 files +6 -3 ");
+        assert_eq!(text(10), "");
+        assert!(totals_line(&[], &[], 100).is_none());
+    }
+
+    #[test]
+    fn test_format_mode() {
+        assert_eq!(format_mode(0o100644), "644");
+        assert_eq!(format_mode(0o100755), "755");
+        assert_eq!(format_mode(0o120000), "lnk");
+        assert_eq!(format_mode(0o160000), "sub");
+        assert_eq!(format_mode(0), "");
+    }
+
     #[test]
     fn test_calculate_height() {
         // No files: 2 for borders
@@ -272,7 +657,7 @@ mod tests {
 
         // Path needs truncation - function may use ellipsis which is multi-byte
         let (path, show_counts) = format_path_with_priority("very/long/path/to/file.txt", "", 15);
-        assert!(path.chars().count() <= 15);
+        assert!(path.width() <= 15);
         assert!(!show_counts);
 
         // Zero width
@@ -284,11 +669,27 @@ mod tests {
     fn test_format_path_with_priority_unicode() {
         // Unicode paths should not panic when truncated
         let (path, _) = format_path_with_priority("src/über/файл.rs", "", 10);
-        assert!(path.chars().count() <= 10);
+        assert!(path.width() <= 10);
 
         // Full Unicode path that fits
         let (path, show_counts) = format_path_with_priority("über.txt", "+1/-1", 20);
         assert_eq!(path, "über.txt");
         assert!(show_counts);
     }
+
+    #[test]
+    fn test_format_path_with_priority_wide_chars() {
+        // CJK characters occupy two columns each
+        let (path, show_counts) = format_path_with_priority("文档/说明.md", "+1/-1", 12);
+        assert_eq!(path, "文档/说明.md");
+        assert!(!show_counts);
+
+        let (path, _) = format_path_with_priority("src/中文目录/文件.rs", "", 12);
+        assert!(path.width() <= 12, "{path:?} is {} wide", path.width());
+        assert!(path.ends_with("文件.rs"));
+
+        // A wide filename is cut on a character boundary without overflowing
+        let (path, _) = format_path_with_priority("🎉🎉🎉🎉.txt", "", 5);
+        assert_eq!(path, "🎉🎉");
+    }
 }
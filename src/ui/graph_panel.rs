@@ -0,0 +1,194 @@
+use crate::types::{CommitSummary, DivergenceGraph};
+use crate::ui::{colors, symbols};
+use ratatui::{
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+pub fn draw(frame: &mut Frame, area: Rect, graph: Option<&DivergenceGraph>) {
+    let title = match graph {
+        Some(g) => format!(
+            "HEAD {}{} {} {} {}{}",
+            symbols::text("↑"),
+            g.upstream.ahead,
+            symbols::text("·"),
+            g.upstream.name,
+            symbols::text("↓"),
+            g.upstream.behind
+        ),
+        None => "Divergence".to_string(),
+    };
+
+    let paragraph = Paragraph::new(build_lines(graph)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_set(symbols::border_set())
+            .border_style(Style::default().fg(colors::overlay()))
+            .title(title),
+    );
+
+    frame.render_widget(paragraph, area);
+}
+
+/// Calculate the height of the graph panel, including borders.
+pub fn calculate_height(graph: Option<&DivergenceGraph>, max_height: u16) -> u16 {
+    let content_height = build_lines(graph).len() as u16;
+    content_height.saturating_add(2).min(max_height)
+}
+
+fn build_lines(graph: Option<&DivergenceGraph>) -> Vec<Line<'static>> {
+    let Some(graph) = graph else {
+        return vec![Line::from(Span::styled(
+            "No upstream configured for this branch",
+            Style::default().fg(colors::gray()),
+        ))];
+    };
+
+    let mut lines = Vec::new();
+    let has_local = !graph.local.is_empty();
+    let has_remote = !graph.remote.is_empty();
+
+    for commit in &graph.local {
+        lines.push(commit_line("", "● ", colors::green(), commit));
+    }
+    let hidden_local = graph.upstream.ahead.saturating_sub(graph.local.len());
+    if hidden_local > 0 {
+        lines.push(more_line("", hidden_local));
+    }
+
+    let remote_indent = if has_local { "│ " } else { "" };
+    for commit in &graph.remote {
+        lines.push(commit_line(remote_indent, "○ ", colors::blue(), commit));
+    }
+    let hidden_remote = graph.upstream.behind.saturating_sub(graph.remote.len());
+    if hidden_remote > 0 {
+        lines.push(more_line(remote_indent, hidden_remote));
+    }
+
+    if has_local && has_remote {
+        lines.push(Line::from(Span::styled(
+            symbols::text("├─┘"),
+            Style::default().fg(colors::overlay()),
+        )));
+    }
+
+    match &graph.merge_base {
+        Some(base) => lines.push(commit_line("", "◆ ", colors::cyan(), base)),
+        None => lines.push(Line::from(Span::styled(
+            "(no common ancestor)",
+            Style::default().fg(colors::gray()),
+        ))),
+    }
+
+    lines
+}
+
+fn commit_line(
+    indent: &'static str,
+    marker: &'static str,
+    color: Color,
+    commit: &CommitSummary,
+) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(
+            symbols::text(indent),
+            Style::default().fg(colors::overlay()),
+        ),
+        Span::styled(symbols::text(marker), Style::default().fg(color)),
+        Span::styled(
+            format!("{} ", commit.short_id),
+            Style::default().fg(colors::gray()),
+        ),
+        Span::styled(commit.subject.clone(), Style::default().fg(colors::text())),
+    ])
+}
+
+fn more_line(indent: &'static str, count: usize) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(
+            symbols::text(indent),
+            Style::default().fg(colors::overlay()),
+        ),
+        Span::styled(
+            symbols::text(format!("┆ … {} more", count)),
+            Style::default().fg(colors::gray()),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::UpstreamInfo;
+
+    fn commit(id: &str) -> CommitSummary {
+        CommitSummary {
+            short_id: id.to_string(),
+            subject: format!("subject {}", id),
+        }
+    }
+
+    fn graph(local: usize, remote: usize, ahead: usize, behind: usize) -> DivergenceGraph {
+        DivergenceGraph {
+            upstream: UpstreamInfo {
+                name: "origin/main".to_string(),
+                ahead,
+                behind,
+            },
+            local: (0..local).map(|i| commit(&format!("l{}", i))).collect(),
+            remote: (0..remote).map(|i| commit(&format!("r{}", i))).collect(),
+            merge_base: Some(commit("base")),
+        }
+    }
+
+    fn line_text(line: &Line) -> String {
+        line.spans.iter().map(|s| s.content.as_ref()).collect()
+    }
+
+    #[test]
+    fn test_build_lines_no_upstream() {
+        let lines = build_lines(None);
+        assert_eq!(lines.len(), 1);
+        assert!(line_text(&lines[0]).contains("No upstream"));
+    }
+
+    #[test]
+    fn test_build_lines_diverged() {
+        let g = graph(2, 1, 2, 1);
+        let text: Vec<String> = build_lines(Some(&g)).iter().map(line_text).collect();
+        assert_eq!(
+            text,
+            vec![
+                "● l0 subject l0",
+                "● l1 subject l1",
+                "│ ○ r0 subject r0",
+                "├─┘",
+                "◆ base subject base",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_lines_only_behind_has_no_join() {
+        let g = graph(0, 1, 0, 1);
+        let text: Vec<String> = build_lines(Some(&g)).iter().map(line_text).collect();
+        assert_eq!(text, vec!["○ r0 subject r0", "◆ base subject base"]);
+    }
+
+    #[test]
+    fn test_build_lines_reports_hidden_commits() {
+        let g = graph(1, 0, 15, 0);
+        let text: Vec<String> = build_lines(Some(&g)).iter().map(line_text).collect();
+        assert!(text.contains(&"┆ … 14 more".to_string()));
+    }
+
+    #[test]
+    fn test_calculate_height() {
+        assert_eq!(calculate_height(None, 20), 3);
+        assert_eq!(calculate_height(Some(&graph(2, 1, 2, 1)), 20), 7);
+        assert_eq!(calculate_height(Some(&graph(2, 1, 2, 1)), 5), 5);
+    }
+}
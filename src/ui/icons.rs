@@ -0,0 +1,174 @@
+use crate::config::Icons;
+use crate::types::FileEntry;
+use crate::ui::symbols;
+use std::cell::Cell;
+
+/// A kind of file with its Nerd Font glyph and single-character ASCII
+/// stand-in.
+struct FileKind {
+    nerd: &'static str,
+    ascii: &'static str,
+}
+
+const fn kind(nerd: &'static str, ascii: &'static str) -> FileKind {
+    FileKind { nerd, ascii }
+}
+
+// ASCII stand-ins group kinds: `@` source, `>` script, `~` document,
+// `#` config or data, `%` markup and style, `*` media, `$` archive.
+const RUST: FileKind = kind("\u{e7a8}", "@");
+const PYTHON: FileKind = kind("\u{e73c}", "@");
+const JAVASCRIPT: FileKind = kind("\u{e74e}", "@");
+const TYPESCRIPT: FileKind = kind("\u{e628}", "@");
+const GO: FileKind = kind("\u{e627}", "@");
+const C: FileKind = kind("\u{e61e}", "@");
+const CPP: FileKind = kind("\u{e61d}", "@");
+const JAVA: FileKind = kind("\u{e738}", "@");
+const RUBY: FileKind = kind("\u{e739}", "@");
+const SHELL: FileKind = kind("\u{f489}", ">");
+const MARKDOWN: FileKind = kind("\u{e73e}", "~");
+const TEXT: FileKind = kind("\u{f15c}", "~");
+const JSON: FileKind = kind("\u{e60b}", "#");
+const CONFIG: FileKind = kind("\u{e615}", "#");
+const LOCK: FileKind = kind("\u{f023}", "#");
+const HTML: FileKind = kind("\u{e736}", "%");
+const CSS: FileKind = kind("\u{e749}", "%");
+const IMAGE: FileKind = kind("\u{f1c5}", "*");
+const ARCHIVE: FileKind = kind("\u{f410}", "$");
+const GIT: FileKind = kind("\u{e702}", "#");
+const DOCKER: FileKind = kind("\u{f308}", "#");
+const MAKE: FileKind = kind("\u{e779}", ">");
+const SUBMODULE: FileKind = kind("\u{f1d3}", "+");
+const SYMLINK: FileKind = kind("\u{f0c1}", "&");
+const DEFAULT: FileKind = kind("\u{f15b}", "-");
+
+/// Kinds recognised by whole file name, checked before the extension.
+const BY_NAME: &[(&str, &FileKind)] = &[
+    ("Dockerfile", &DOCKER),
+    ("Makefile", &MAKE),
+    (".gitignore", &GIT),
+    (".gitattributes", &GIT),
+    (".gitmodules", &GIT),
+];
+
+const BY_EXTENSION: &[(&str, &FileKind)] = &[
+    ("rs", &RUST),
+    ("py", &PYTHON),
+    ("js", &JAVASCRIPT),
+    ("mjs", &JAVASCRIPT),
+    ("jsx", &JAVASCRIPT),
+    ("ts", &TYPESCRIPT),
+    ("tsx", &TYPESCRIPT),
+    ("go", &GO),
+    ("c", &C),
+    ("h", &C),
+    ("cc", &CPP),
+    ("cpp", &CPP),
+    ("hpp", &CPP),
+    ("java", &JAVA),
+    ("rb", &RUBY),
+    ("sh", &SHELL),
+    ("bash", &SHELL),
+    ("zsh", &SHELL),
+    ("fish", &SHELL),
+    ("md", &MARKDOWN),
+    ("txt", &TEXT),
+    ("rst", &TEXT),
+    ("json", &JSON),
+    ("toml", &CONFIG),
+    ("yaml", &CONFIG),
+    ("yml", &CONFIG),
+    ("ini", &CONFIG),
+    ("lock", &LOCK),
+    ("html", &HTML),
+    ("css", &CSS),
+    ("scss", &CSS),
+    ("png", &IMAGE),
+    ("jpg", &IMAGE),
+    ("jpeg", &IMAGE),
+    ("gif", &IMAGE),
+    ("svg", &IMAGE),
+    ("webp", &IMAGE),
+    ("zip", &ARCHIVE),
+    ("gz", &ARCHIVE),
+    ("tar", &ARCHIVE),
+];
+
+thread_local! {
+    static ICONS: Cell<Icons> = const { Cell::new(Icons::Off) };
+}
+
+/// Switch file icons for subsequent draws on this thread.
+pub fn set_icons(icons: Icons) {
+    ICONS.with(|cell| cell.set(icons));
+}
+
+/// The icon to draw before `file`'s name, if icons are on. Nerd Font
+/// glyphs fall back to ASCII when the UI is in ASCII mode.
+pub fn icon(file: &FileEntry) -> Option<&'static str> {
+    let ascii = match ICONS.with(Cell::get) {
+        Icons::Off => return None,
+        Icons::Nerd => symbols::is_ascii(),
+        Icons::Ascii => true,
+    };
+    let kind = file_kind(file);
+    Some(if ascii { kind.ascii } else { kind.nerd })
+}
+
+fn file_kind(file: &FileEntry) -> &'static FileKind {
+    if file.is_submodule {
+        return &SUBMODULE;
+    }
+    if file.current_mode() == u32::from(git2::FileMode::Link) {
+        return &SYMLINK;
+    }
+    let name = file.path.rsplit('/').next().unwrap_or(&file.path);
+    if let Some((_, kind)) = BY_NAME.iter().find(|(n, _)| *n == name) {
+        return kind;
+    }
+    let extension = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => extension.to_ascii_lowercase(),
+        _ => return &DEFAULT,
+    };
+    BY_EXTENSION
+        .iter()
+        .find(|(e, _)| *e == extension)
+        .map_or(&DEFAULT, |(_, kind)| kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Glyphs;
+    use crate::types::FileStatus;
+
+    fn entry(path: &str) -> FileEntry {
+        crate::ui::tests::test_file_entry(path, FileStatus::Modified)
+    }
+
+    #[test]
+    fn icons_follow_name_then_extension() {
+        set_icons(Icons::Ascii);
+        assert_eq!(icon(&entry("src/main.rs")), Some("@"));
+        assert_eq!(icon(&entry("README.MD")), Some("~"));
+        assert_eq!(icon(&entry("Cargo.lock")), Some("#"));
+        assert_eq!(icon(&entry("docker/Dockerfile")), Some("#"));
+        assert_eq!(icon(&entry(".env")), Some("-"));
+        assert_eq!(icon(&entry("LICENSE")), Some("-"));
+        let mut link = entry("current");
+        link.mode = 0o120000;
+        assert_eq!(icon(&link), Some("&"));
+
+        set_icons(Icons::Off);
+        assert_eq!(icon(&entry("src/main.rs")), None);
+    }
+
+    #[test]
+    fn nerd_icons_fall_back_to_ascii() {
+        set_icons(Icons::Nerd);
+        symbols::set_glyphs(Glyphs::Unicode);
+        assert_eq!(icon(&entry("main.rs")), Some("\u{e7a8}"));
+        symbols::set_glyphs(Glyphs::Ascii);
+        assert_eq!(icon(&entry("main.rs")), Some("@"));
+    }
+}
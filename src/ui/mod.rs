@@ -1,9 +1,14 @@
 pub mod colors;
 pub mod diff_panel;
 pub mod file_list;
+pub mod graph_panel;
+pub mod icons;
+pub mod modal;
 pub mod status_bar;
+pub mod symbols;
 
 use crate::app::App;
+use crate::types::Focus;
 
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
@@ -33,30 +38,48 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
 
     app.file_list_height = file_list_height.saturating_sub(2) as usize;
 
+    let graph_height = if app.show_graph {
+        graph_panel::calculate_height(app.divergence.as_ref(), max_file_list_height)
+    } else {
+        0
+    };
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Length(1),
             Constraint::Length(file_list_height),
+            Constraint::Length(graph_height),
             Constraint::Min(5),
         ])
         .split(area);
 
-    status_bar::draw(
+    let hints = app.context_hints();
+    app.status_bar_area = chunks[0];
+    app.count_links = status_bar::draw(
         frame,
         chunks[0],
         status_bar::StatusBarState {
             branch: &app.branch,
+            upstream: app.upstream.as_ref(),
+            upstream_notice: app.upstream_notice.as_ref(),
+            rebase_progress: app.rebase_progress.as_ref(),
             staged_count: app.staged_count,
             unstaged_count: app.unstaged_count,
             untracked_count: app.untracked_count,
+            conflict_count: app.conflict_count(),
             confirm_prompt: app.confirm_prompt.as_ref(),
             flash_message: app.flash_message.as_ref(),
+            network: app.network_task.as_ref().map(|task| &task.status),
+            polling: app.polling.as_ref(),
+            keymap: &app.keymap,
+            hints: &hints,
+            pending_keys: app.key_sequence.pending(),
         },
     );
 
     app.file_list_area = chunks[1];
-    app.diff_area = chunks[2];
+    app.diff_area = chunks[3];
 
     file_list::draw(
         frame,
@@ -66,34 +89,83 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
         app.highlight_index,
         app.selected.as_ref(),
         &app.multi_selected,
+        &app.needs_review,
+        &app.config.ui.columns,
+        app.path_base(),
         app.file_list_scroll,
     );
 
-    diff_panel::draw(frame, chunks[2], &app.current_diff, app.diff_scroll);
+    if app.show_graph {
+        graph_panel::draw(frame, chunks[2], app.divergence.as_ref());
+    }
+
+    let breadcrumb = app
+        .selected_file()
+        .map(|(section, file)| diff_panel::Breadcrumb {
+            file,
+            section,
+            path_base: app.path_base(),
+        });
+    diff_panel::draw(
+        frame,
+        chunks[3],
+        &app.current_diff,
+        app.diff_scroll,
+        !app.config.ui.low_bandwidth,
+        app.expand_long_lines,
+        (app.focus == Focus::Diff).then_some(app.diff_cursor),
+        app.diff_selection().map(|selection| selection.range()),
+        breadcrumb.as_ref(),
+    );
+
+    if let Some(ref modal) = app.modal {
+        modal::draw(frame, area, modal);
+    }
 }
 
 fn draw_too_small(frame: &mut Frame, area: Rect) {
     let message = Paragraph::new(Line::from(Span::raw("Terminal too small")))
         .block(Block::default().borders(Borders::NONE))
-        .style(Style::default().fg(colors::GRAY));
+        .style(Style::default().fg(colors::gray()));
     frame.render_widget(message, area);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::{DiffContent, FileEntry, FileStatus, MultiSelectSet, Section};
+    use crate::keymap::{Action, Keymap};
+
+    const DEFAULT_HINTS: &[(Action, &str)] = &[
+        (Action::Stage, "stage"),
+        (Action::Unstage, "unstage"),
+        (Action::Quit, "quit"),
+    ];
+    use crate::config::Column;
+    use crate::types::{
+        ConflictKind, ConflictPreview, DiffContent, FileEntry, FileStatus, MultiSelectSet, Section,
+    };
     use ratatui::{backend::TestBackend, buffer::Buffer, Terminal};
+    use std::collections::HashSet;
 
-    fn test_file_entry(path: &str, status: FileStatus) -> FileEntry {
+    pub(super) fn test_file_entry(path: &str, status: FileStatus) -> FileEntry {
         FileEntry {
             path: path.to_string(),
             old_path: None,
+            raw_path: path.as_bytes().to_vec(),
+            raw_old_path: None,
             status,
             added_lines: Some(5),
             deleted_lines: Some(3),
             is_binary: false,
             is_submodule: false,
+            eol_change: None,
+            whitespace_errors: false,
+            generated: false,
+            old_mode: 0o100644,
+            mode: 0o100644,
+            size: None,
+            mtime: None,
+            conflict: None,
         }
     }
 
@@ -143,19 +215,25 @@ mod tests {
                     None,
                     None,
                     &multi_selected,
+                    &HashSet::new(),
+                    &Column::DEFAULT,
+                    None,
                     0,
                 );
             })
             .unwrap();
         let buffer = terminal.backend().buffer().clone();
-        assert!(buffer_contains(&buffer, "[STAGED]"));
+        assert!(buffer_contains(&buffer, "[STAGED] (1)"));
     }
 
     #[test]
     fn file_list_shows_unstaged_header() {
         let backend = TestBackend::new(80, 10);
         let mut terminal = Terminal::new(backend).unwrap();
-        let unstaged = vec![test_file_entry("file.rs", FileStatus::Modified)];
+        let unstaged = vec![
+            test_file_entry("file.rs", FileStatus::Modified),
+            test_file_entry("new.rs", FileStatus::Untracked),
+        ];
         let multi_selected = MultiSelectSet::new();
         terminal
             .draw(|frame| {
@@ -167,12 +245,15 @@ mod tests {
                     None,
                     None,
                     &multi_selected,
+                    &HashSet::new(),
+                    &Column::DEFAULT,
+                    None,
                     0,
                 );
             })
             .unwrap();
         let buffer = terminal.backend().buffer().clone();
-        assert!(buffer_contains(&buffer, "[UNSTAGED]"));
+        assert!(buffer_contains(&buffer, "[UNSTAGED] (2 · 1 untracked)"));
     }
 
     #[test]
@@ -192,6 +273,9 @@ mod tests {
                     None,
                     None,
                     &multi_selected,
+                    &HashSet::new(),
+                    &Column::DEFAULT,
+                    None,
                     0,
                 );
             })
@@ -217,6 +301,9 @@ mod tests {
                     Some(0),
                     None,
                     &multi_selected,
+                    &HashSet::new(),
+                    &Column::DEFAULT,
+                    None,
                     0,
                 );
             })
@@ -230,7 +317,7 @@ mod tests {
         let backend = TestBackend::new(80, 10);
         let mut terminal = Terminal::new(backend).unwrap();
         let staged = vec![test_file_entry("file.rs", FileStatus::Modified)];
-        let selected = (Section::Staged, "file.rs".to_string());
+        let selected = (Section::Staged, b"file.rs".to_vec());
         let multi_selected = MultiSelectSet::new();
         terminal
             .draw(|frame| {
@@ -242,6 +329,9 @@ mod tests {
                     None,
                     Some(&selected),
                     &multi_selected,
+                    &HashSet::new(),
+                    &Column::DEFAULT,
+                    None,
                     0,
                 );
             })
@@ -268,6 +358,9 @@ mod tests {
                     None,
                     None,
                     &multi_selected,
+                    &HashSet::new(),
+                    &Column::DEFAULT,
+                    None,
                     0,
                 );
             })
@@ -282,7 +375,7 @@ mod tests {
         let mut terminal = Terminal::new(backend).unwrap();
         let staged = vec![test_file_entry("file.rs", FileStatus::Modified)];
         let mut multi_selected = MultiSelectSet::new();
-        multi_selected.insert((Section::Staged, "file.rs".to_string()));
+        multi_selected.insert((Section::Staged, b"file.rs".to_vec()));
         terminal
             .draw(|frame| {
                 file_list::draw(
@@ -293,6 +386,9 @@ mod tests {
                     None,
                     None,
                     &multi_selected,
+                    &HashSet::new(),
+                    &Column::DEFAULT,
+                    None,
                     0,
                 );
             })
@@ -301,26 +397,245 @@ mod tests {
         assert!(buffer_contains(&buffer, "◆"));
     }
 
+    #[test]
+    fn file_list_flags_files_rewritten_by_hooks() {
+        let backend = TestBackend::new(80, 10);
+        let mut terminal = Terminal::new(backend).unwrap();
+        let unstaged = vec![
+            test_file_entry("fixed.rs", FileStatus::Modified),
+            test_file_entry("other.rs", FileStatus::Modified),
+        ];
+        let needs_review = HashSet::from(["fixed.rs".to_string()]);
+        terminal
+            .draw(|frame| {
+                file_list::draw(
+                    frame,
+                    frame.area(),
+                    &[],
+                    &unstaged,
+                    None,
+                    None,
+                    &MultiSelectSet::new(),
+                    &needs_review,
+                    &Column::DEFAULT,
+                    None,
+                    0,
+                );
+            })
+            .unwrap();
+        let buffer = buffer_to_string(terminal.backend().buffer());
+        let fixed = buffer.lines().find(|l| l.contains("fixed.rs")).unwrap();
+        let other = buffer.lines().find(|l| l.contains("other.rs")).unwrap();
+        assert!(fixed.contains("!hooks"));
+        assert!(!other.contains("!hooks"));
+    }
+
+    #[test]
+    fn file_list_shows_modes_and_mode_only_changes() {
+        let backend = TestBackend::new(80, 10);
+        let mut terminal = Terminal::new(backend).unwrap();
+        let mut script = test_file_entry("run.sh", FileStatus::Modified);
+        script.mode = 0o100755;
+        script.added_lines = Some(0);
+        script.deleted_lines = Some(0);
+        let unstaged = vec![script, test_file_entry("lib.rs", FileStatus::Modified)];
+        terminal
+            .draw(|frame| {
+                file_list::draw(
+                    frame,
+                    frame.area(),
+                    &[],
+                    &unstaged,
+                    None,
+                    None,
+                    &MultiSelectSet::new(),
+                    &HashSet::new(),
+                    &[Column::Status, Column::Mode, Column::Path, Column::Counts],
+                    None,
+                    0,
+                );
+            })
+            .unwrap();
+        let buffer = buffer_to_string(terminal.backend().buffer());
+        let script = buffer.lines().find(|l| l.contains("run.sh")).unwrap();
+        let lib = buffer.lines().find(|l| l.contains("lib.rs")).unwrap();
+        assert!(script.contains("755 x run.sh 644→755"), "{script}");
+        assert!(lib.contains("644   lib.rs +5/-3"), "{lib}");
+    }
+
+    #[test]
+    fn file_list_columns_follow_the_configured_order() {
+        let backend = TestBackend::new(80, 10);
+        let mut terminal = Terminal::new(backend).unwrap();
+        let mut file = test_file_entry("big.bin", FileStatus::Modified);
+        file.size = Some(2048);
+        let unstaged = vec![file];
+        terminal
+            .draw(|frame| {
+                file_list::draw(
+                    frame,
+                    frame.area(),
+                    &[],
+                    &unstaged,
+                    None,
+                    None,
+                    &MultiSelectSet::new(),
+                    &HashSet::new(),
+                    &[Column::Counts, Column::Size, Column::Status],
+                    None,
+                    0,
+                );
+            })
+            .unwrap();
+        let buffer = buffer_to_string(terminal.backend().buffer());
+        let row = buffer.lines().find(|l| l.contains("big.bin")).unwrap();
+        assert!(row.contains("+5/-3    2.0 KiB M big.bin"), "{row}");
+    }
+
+    #[test]
+    fn file_list_draws_icons_before_names() {
+        let backend = TestBackend::new(80, 10);
+        let mut terminal = Terminal::new(backend).unwrap();
+        let unstaged = vec![test_file_entry("src/main.rs", FileStatus::Modified)];
+        icons::set_icons(crate::config::Icons::Ascii);
+        terminal
+            .draw(|frame| {
+                file_list::draw(
+                    frame,
+                    frame.area(),
+                    &[],
+                    &unstaged,
+                    None,
+                    None,
+                    &MultiSelectSet::new(),
+                    &HashSet::new(),
+                    &Column::DEFAULT,
+                    None,
+                    0,
+                );
+            })
+            .unwrap();
+        icons::set_icons(crate::config::Icons::Off);
+        let buffer = buffer_to_string(terminal.backend().buffer());
+        assert!(buffer.contains("M @ src/main.rs +5/-3"), "{buffer}");
+    }
+
+    #[test]
+    fn file_list_shows_modification_age() {
+        let backend = TestBackend::new(80, 10);
+        let mut terminal = Terminal::new(backend).unwrap();
+        let mut recent = test_file_entry("recent.rs", FileStatus::Modified);
+        recent.mtime = Some(std::time::SystemTime::now() - std::time::Duration::from_secs(330));
+        let unstaged = vec![recent, test_file_entry("gone.rs", FileStatus::Deleted)];
+        terminal
+            .draw(|frame| {
+                file_list::draw(
+                    frame,
+                    frame.area(),
+                    &[],
+                    &unstaged,
+                    None,
+                    None,
+                    &MultiSelectSet::new(),
+                    &HashSet::new(),
+                    &[Column::Status, Column::Mtime, Column::Path],
+                    None,
+                    0,
+                );
+            })
+            .unwrap();
+        let buffer = buffer_to_string(terminal.backend().buffer());
+        let recent = buffer.lines().find(|l| l.contains("recent.rs")).unwrap();
+        let gone = buffer.lines().find(|l| l.contains("gone.rs")).unwrap();
+        assert!(recent.contains("M  5m ago recent.rs"), "{recent}");
+        assert!(gone.contains("D         gone.rs"), "{gone}");
+    }
+
     #[test]
     fn diff_panel_empty_shows_hint() {
         let backend = TestBackend::new(80, 20);
         let mut terminal = Terminal::new(backend).unwrap();
         terminal
             .draw(|frame| {
-                diff_panel::draw(frame, frame.area(), &DiffContent::Empty, 0);
+                diff_panel::draw(
+                    frame,
+                    frame.area(),
+                    &DiffContent::Empty,
+                    0,
+                    true,
+                    false,
+                    None,
+                    None,
+                    None,
+                );
             })
             .unwrap();
         let buffer = terminal.backend().buffer().clone();
         assert!(buffer_contains(&buffer, "navigate"));
     }
 
+    #[test]
+    fn diff_panel_title_names_the_file() {
+        let file = test_file_entry("src/ui/diff_panel.rs", FileStatus::Modified);
+        let title_at = |width: u16, path_base: Option<&str>| {
+            let backend = TestBackend::new(width, 5);
+            let mut terminal = Terminal::new(backend).unwrap();
+            let crumb = diff_panel::Breadcrumb {
+                file: &file,
+                section: Section::Unstaged,
+                path_base,
+            };
+            terminal
+                .draw(|frame| {
+                    diff_panel::draw(
+                        frame,
+                        frame.area(),
+                        &DiffContent::Empty,
+                        0,
+                        true,
+                        false,
+                        None,
+                        None,
+                        Some(&crumb),
+                    );
+                })
+                .unwrap();
+            let buffer = buffer_to_string(terminal.backend().buffer());
+            buffer.lines().next().unwrap().to_string()
+        };
+
+        let wide = title_at(60, None);
+        assert!(
+            wide.contains(" src/ui/diff_panel.rs · unstaged · modified · +5/-3 "),
+            "{wide}"
+        );
+        let relative = title_at(60, Some("src"));
+        assert!(
+            relative.contains(" ui/diff_panel.rs · unstaged"),
+            "{relative}"
+        );
+        let narrow = title_at(24, None);
+        assert!(narrow.contains("diff_panel.rs "), "{narrow}");
+        assert!(!narrow.contains("unstaged"), "{narrow}");
+    }
+
     #[test]
     fn diff_panel_clean_shows_message() {
         let backend = TestBackend::new(80, 20);
         let mut terminal = Terminal::new(backend).unwrap();
         terminal
             .draw(|frame| {
-                diff_panel::draw(frame, frame.area(), &DiffContent::Clean, 0);
+                diff_panel::draw(
+                    frame,
+                    frame.area(),
+                    &DiffContent::Clean,
+                    0,
+                    true,
+                    false,
+                    None,
+                    None,
+                    None,
+                );
             })
             .unwrap();
         let buffer = terminal.backend().buffer().clone();
@@ -333,11 +648,29 @@ mod tests {
         let mut terminal = Terminal::new(backend).unwrap();
         terminal
             .draw(|frame| {
-                diff_panel::draw(frame, frame.area(), &DiffContent::Binary, 0);
+                let info = crate::types::BinaryInfo {
+                    change: crate::types::BinaryChange::Added,
+                    old_size: None,
+                    new_size: Some(4),
+                    mime_type: "image/png".to_string(),
+                    content: None,
+                };
+                diff_panel::draw(
+                    frame,
+                    frame.area(),
+                    &DiffContent::Binary(info),
+                    0,
+                    true,
+                    false,
+                    None,
+                    None,
+                    None,
+                );
             })
             .unwrap();
         let buffer = terminal.backend().buffer().clone();
         assert!(buffer_contains(&buffer, "Binary file"));
+        assert!(buffer_contains(&buffer, "image/png"));
     }
 
     #[test]
@@ -346,7 +679,17 @@ mod tests {
         let mut terminal = Terminal::new(backend).unwrap();
         terminal
             .draw(|frame| {
-                diff_panel::draw(frame, frame.area(), &DiffContent::InvalidUtf8, 0);
+                diff_panel::draw(
+                    frame,
+                    frame.area(),
+                    &DiffContent::InvalidUtf8,
+                    0,
+                    true,
+                    false,
+                    None,
+                    None,
+                    None,
+                );
             })
             .unwrap();
         let buffer = terminal.backend().buffer().clone();
@@ -359,11 +702,114 @@ mod tests {
         let mut terminal = Terminal::new(backend).unwrap();
         terminal
             .draw(|frame| {
-                diff_panel::draw(frame, frame.area(), &DiffContent::Conflict, 0);
+                diff_panel::draw(
+                    frame,
+                    frame.area(),
+                    &DiffContent::Conflict(ConflictPreview {
+                        kind: Some(ConflictKind::DeletedByThem),
+                        lines: Vec::new(),
+                    }),
+                    0,
+                    true,
+                    false,
+                    None,
+                    None,
+                    None,
+                );
+            })
+            .unwrap();
+        let buffer = terminal.backend().buffer().clone();
+        assert!(buffer_contains(&buffer, "Conflict (UD: deleted by them)"));
+    }
+
+    #[test]
+    fn diff_panel_conflict_shows_numbered_regions() {
+        let preview = ConflictPreview {
+            kind: Some(ConflictKind::BothModified),
+            lines: [
+                "a",
+                "<<<<<<< HEAD",
+                "ours",
+                "=======",
+                "theirs",
+                ">>>>>>> topic",
+            ]
+            .map(String::from)
+            .to_vec(),
+        };
+        let backend = TestBackend::new(60, 10);
+        let mut terminal = Terminal::new(backend).unwrap();
+        terminal
+            .draw(|frame| {
+                diff_panel::draw(
+                    frame,
+                    frame.area(),
+                    &DiffContent::Conflict(preview.clone()),
+                    0,
+                    true,
+                    false,
+                    None,
+                    None,
+                    None,
+                );
             })
             .unwrap();
         let buffer = terminal.backend().buffer().clone();
-        assert!(buffer_contains(&buffer, "Conflict"));
+        assert!(buffer_contains(
+            &buffer,
+            "Conflict (UU: both modified) · 1 region"
+        ));
+        assert!(buffer_contains(&buffer, "  2 │ <<<<<<< HEAD"));
+        let (_, rows) = diff_panel::conflict_lines(&preview, 58, true, false);
+        assert_eq!(rows, vec![2]);
+    }
+
+    #[test]
+    fn status_bar_reports_where_counts_are() {
+        use crate::types::BranchInfo;
+        use status_bar::CountLink;
+
+        let backend = TestBackend::new(80, 1);
+        let mut terminal = Terminal::new(backend).unwrap();
+        let branch = BranchInfo::Branch("main".to_string());
+        let keymap = Keymap::default();
+        let mut links = Vec::new();
+        terminal
+            .draw(|frame| {
+                links = status_bar::draw(
+                    frame,
+                    frame.area(),
+                    status_bar::StatusBarState {
+                        branch: &branch,
+                        upstream: None,
+                        upstream_notice: None,
+                        rebase_progress: None,
+                        staged_count: 4,
+                        unstaged_count: 11,
+                        untracked_count: 3,
+                        conflict_count: 2,
+                        confirm_prompt: None,
+                        flash_message: None,
+                        network: None,
+                        polling: None,
+                        keymap: &keymap,
+                        hints: DEFAULT_HINTS,
+                        pending_keys: &[],
+                    },
+                );
+            })
+            .unwrap();
+        let buffer = buffer_to_string(terminal.backend().buffer());
+        assert!(buffer.starts_with(" main !:2 S:4 U:11 ?:3 "), "{buffer}");
+        assert_eq!(
+            links,
+            vec![
+                (6..9, CountLink::Conflict),
+                (10..13, CountLink::Staged),
+                (14..18, CountLink::Unstaged),
+                (19..22, CountLink::Untracked),
+            ]
+        );
     }
 
     #[test]
@@ -373,6 +819,7 @@ mod tests {
         let backend = TestBackend::new(80, 1);
         let mut terminal = Terminal::new(backend).unwrap();
         let branch = BranchInfo::Branch("main".to_string());
+        let keymap = Keymap::default();
         let flash = FlashMessage::success("Staged 3 files");
 
         terminal
@@ -382,11 +829,20 @@ mod tests {
                     frame.area(),
                     status_bar::StatusBarState {
                         branch: &branch,
+                        upstream: None,
+                        upstream_notice: None,
+                        rebase_progress: None,
                         staged_count: 0,
                         unstaged_count: 0,
                         untracked_count: 0,
+                        conflict_count: 0,
                         confirm_prompt: None,
                         flash_message: Some(&flash),
+                        network: None,
+                        polling: None,
+                        keymap: &keymap,
+                        hints: DEFAULT_HINTS,
+                        pending_keys: &[],
                     },
                 );
             })
@@ -403,6 +859,7 @@ mod tests {
         let backend = TestBackend::new(80, 1);
         let mut terminal = Terminal::new(backend).unwrap();
         let branch = BranchInfo::Branch("main".to_string());
+        let keymap = Keymap::default();
         let flash = FlashMessage::error("Something went wrong");
 
         terminal
@@ -412,11 +869,20 @@ mod tests {
                     frame.area(),
                     status_bar::StatusBarState {
                         branch: &branch,
+                        upstream: None,
+                        upstream_notice: None,
+                        rebase_progress: None,
                         staged_count: 0,
                         unstaged_count: 0,
                         untracked_count: 0,
+                        conflict_count: 0,
                         confirm_prompt: None,
                         flash_message: Some(&flash),
+                        network: None,
+                        polling: None,
+                        keymap: &keymap,
+                        hints: DEFAULT_HINTS,
+                        pending_keys: &[],
                     },
                 );
             })
@@ -426,6 +892,277 @@ mod tests {
         assert!(buffer_contains(&buffer, "Something went wrong"));
     }
 
+    #[test]
+    fn status_bar_shows_rebase_progress() {
+        use crate::types::{BranchInfo, RebaseProgress};
+
+        let backend = TestBackend::new(100, 1);
+        let mut terminal = Terminal::new(backend).unwrap();
+        let branch = BranchInfo::Detached("abc1234".to_string());
+        let keymap = Keymap::default();
+        let progress = RebaseProgress {
+            current: 4,
+            total: 9,
+            subject: "Fix parser".to_string(),
+        };
+
+        terminal
+            .draw(|frame| {
+                status_bar::draw(
+                    frame,
+                    frame.area(),
+                    status_bar::StatusBarState {
+                        branch: &branch,
+                        upstream: None,
+                        upstream_notice: None,
+                        rebase_progress: Some(&progress),
+                        staged_count: 0,
+                        unstaged_count: 1,
+                        untracked_count: 0,
+                        conflict_count: 0,
+                        confirm_prompt: None,
+                        flash_message: None,
+                        network: None,
+                        polling: None,
+                        keymap: &keymap,
+                        hints: DEFAULT_HINTS,
+                        pending_keys: &[],
+                    },
+                );
+            })
+            .unwrap();
+        let buffer = terminal.backend().buffer().clone();
+        assert!(buffer_contains(&buffer, "applying 4/9: Fix parser"));
+        assert!(buffer_contains(&buffer, "HEAD@abc1234 detached"));
+    }
+
+    #[test]
+    fn status_bar_shows_polling_reason() {
+        use crate::types::{BranchInfo, PollReason};
+
+        let backend = TestBackend::new(100, 1);
+        let mut terminal = Terminal::new(backend).unwrap();
+        let branch = BranchInfo::Branch("main".to_string());
+        let keymap = Keymap::default();
+        let reason = PollReason::NetworkFs("nfs4".to_string());
+
+        terminal
+            .draw(|frame| {
+                status_bar::draw(
+                    frame,
+                    frame.area(),
+                    status_bar::StatusBarState {
+                        branch: &branch,
+                        upstream: None,
+                        upstream_notice: None,
+                        rebase_progress: None,
+                        staged_count: 0,
+                        unstaged_count: 1,
+                        untracked_count: 0,
+                        conflict_count: 0,
+                        confirm_prompt: None,
+                        flash_message: None,
+                        network: None,
+                        polling: Some(&reason),
+                        keymap: &keymap,
+                        hints: DEFAULT_HINTS,
+                        pending_keys: &[],
+                    },
+                );
+            })
+            .unwrap();
+        let buffer = terminal.backend().buffer().clone();
+        assert!(buffer_contains(&buffer, "[polling: nfs4 mount]"));
+    }
+
+    #[test]
+    fn status_bar_shows_network_progress() {
+        use crate::types::{BranchInfo, NetworkOp, NetworkStatus, TransferPhase, TransferProgress};
+
+        let backend = TestBackend::new(100, 1);
+        let mut terminal = Terminal::new(backend).unwrap();
+        let branch = BranchInfo::Branch("main".to_string());
+        let keymap = Keymap::default();
+        let network = NetworkStatus {
+            op: NetworkOp::Fetch,
+            progress: Some(TransferProgress {
+                phase: TransferPhase::Receiving,
+                current: 3,
+                total: 12,
+                bytes: 4096,
+            }),
+            cancelling: false,
+        };
+
+        terminal
+            .draw(|frame| {
+                status_bar::draw(
+                    frame,
+                    frame.area(),
+                    status_bar::StatusBarState {
+                        branch: &branch,
+                        upstream: None,
+                        upstream_notice: None,
+                        rebase_progress: None,
+                        staged_count: 0,
+                        unstaged_count: 0,
+                        untracked_count: 0,
+                        conflict_count: 0,
+                        confirm_prompt: None,
+                        flash_message: None,
+                        network: Some(&network),
+                        polling: None,
+                        keymap: &keymap,
+                        hints: DEFAULT_HINTS,
+                        pending_keys: &[],
+                    },
+                );
+            })
+            .unwrap();
+        let buffer = terminal.backend().buffer().clone();
+        assert!(buffer_contains(
+            &buffer,
+            "Fetching… receiving objects 3/12 (25%), 4.0 KiB"
+        ));
+        assert!(buffer_contains(&buffer, "Esc:cancel"));
+    }
+
+    #[test]
+    fn status_bar_shows_pending_keys_and_keymap_hints() {
+        use crate::keymap::Key;
+        use crate::types::BranchInfo;
+        use std::collections::BTreeMap;
+
+        let backend = TestBackend::new(100, 1);
+        let mut terminal = Terminal::new(backend).unwrap();
+        let branch = BranchInfo::Branch("main".to_string());
+        let overrides = BTreeMap::from([("stage".to_string(), "a s".to_string())]);
+        let keymap = Keymap::new(Default::default(), &overrides).unwrap();
+        let pending = [Key::char('g')];
+
+        terminal
+            .draw(|frame| {
+                status_bar::draw(
+                    frame,
+                    frame.area(),
+                    status_bar::StatusBarState {
+                        branch: &branch,
+                        upstream: None,
+                        upstream_notice: None,
+                        rebase_progress: None,
+                        staged_count: 0,
+                        unstaged_count: 0,
+                        untracked_count: 0,
+                        conflict_count: 0,
+                        confirm_prompt: None,
+                        flash_message: None,
+                        network: None,
+                        polling: None,
+                        keymap: &keymap,
+                        hints: DEFAULT_HINTS,
+                        pending_keys: &pending,
+                    },
+                );
+            })
+            .unwrap();
+        let buffer = terminal.backend().buffer().clone();
+        assert!(buffer_contains(&buffer, "main g… S:0"));
+        assert!(buffer_contains(&buffer, "a s:stage u:unstage q:quit"));
+    }
+
+    #[test]
+    fn status_bar_shows_ahead_behind() {
+        use crate::types::{BranchInfo, UpstreamInfo};
+
+        let backend = TestBackend::new(80, 1);
+        let mut terminal = Terminal::new(backend).unwrap();
+        let branch = BranchInfo::Branch("main".to_string());
+        let keymap = Keymap::default();
+        let upstream = UpstreamInfo {
+            name: "origin/main".to_string(),
+            ahead: 2,
+            behind: 5,
+        };
+
+        terminal
+            .draw(|frame| {
+                status_bar::draw(
+                    frame,
+                    frame.area(),
+                    status_bar::StatusBarState {
+                        branch: &branch,
+                        upstream: Some(&upstream),
+                        upstream_notice: None,
+                        rebase_progress: None,
+                        staged_count: 0,
+                        unstaged_count: 0,
+                        untracked_count: 0,
+                        conflict_count: 0,
+                        confirm_prompt: None,
+                        flash_message: None,
+                        network: None,
+                        polling: None,
+                        keymap: &keymap,
+                        hints: DEFAULT_HINTS,
+                        pending_keys: &[],
+                    },
+                );
+            })
+            .unwrap();
+        let buffer = terminal.backend().buffer().clone();
+        assert!(buffer_contains(&buffer, "main ↑2 ↓5"));
+    }
+
+    #[test]
+    fn status_bar_shows_upstream_notice() {
+        use crate::types::{BranchInfo, UpstreamInfo, UpstreamNotice};
+
+        let backend = TestBackend::new(100, 1);
+        let mut terminal = Terminal::new(backend).unwrap();
+        let branch = BranchInfo::Branch("main".to_string());
+        let keymap = Keymap::default();
+        let upstream = UpstreamInfo {
+            name: "origin/main".to_string(),
+            ahead: 0,
+            behind: 5,
+        };
+        let notice = UpstreamNotice {
+            upstream: "origin/main".to_string(),
+            behind: 5,
+        };
+
+        terminal
+            .draw(|frame| {
+                status_bar::draw(
+                    frame,
+                    frame.area(),
+                    status_bar::StatusBarState {
+                        branch: &branch,
+                        upstream: Some(&upstream),
+                        upstream_notice: Some(&notice),
+                        rebase_progress: None,
+                        staged_count: 0,
+                        unstaged_count: 0,
+                        untracked_count: 0,
+                        conflict_count: 0,
+                        confirm_prompt: None,
+                        flash_message: None,
+                        network: None,
+                        polling: None,
+                        keymap: &keymap,
+                        hints: DEFAULT_HINTS,
+                        pending_keys: &[],
+                    },
+                );
+            })
+            .unwrap();
+        let buffer = terminal.backend().buffer().clone();
+        assert!(buffer_contains(
+            &buffer,
+            "main ↓5 new on origin/main — pull? l:pull"
+        ));
+    }
+
     #[test]
     fn status_bar_prompt_takes_priority_over_flash() {
         use crate::types::{BranchInfo, ConfirmAction, ConfirmPrompt, FlashMessage};
@@ -433,6 +1170,7 @@ mod tests {
         let backend = TestBackend::new(80, 1);
         let mut terminal = Terminal::new(backend).unwrap();
         let branch = BranchInfo::Branch("main".to_string());
+        let keymap = Keymap::default();
         let flash = FlashMessage::success("This should not appear");
         let prompt = ConfirmPrompt {
             message: "Stage 5 files? [y/N]".to_string(),
@@ -446,11 +1184,20 @@ mod tests {
                     frame.area(),
                     status_bar::StatusBarState {
                         branch: &branch,
+                        upstream: None,
+                        upstream_notice: None,
+                        rebase_progress: None,
                         staged_count: 0,
                         unstaged_count: 0,
                         untracked_count: 0,
+                        conflict_count: 0,
                         confirm_prompt: Some(&prompt),
                         flash_message: Some(&flash),
+                        network: None,
+                        polling: None,
+                        keymap: &keymap,
+                        hints: DEFAULT_HINTS,
+                        pending_keys: &[],
                     },
                 );
             })
@@ -0,0 +1,979 @@
+use crate::config::{ConfirmLevel, KeyPreset};
+use crate::types::{
+    BranchPrompt, CommitPrompt, CredentialPrompt, ErrorDetail, MessagePanel, Modal, PreCommitPanel,
+    RecoverPanel, RemoteInputKind, RemotePanel, SetupForm, StashPanel,
+};
+use crate::ui::{colors, symbols};
+use ratatui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame,
+};
+use std::time::{Duration, Instant, SystemTime};
+
+pub fn draw(frame: &mut Frame, area: Rect, modal: &Modal) {
+    match modal {
+        Modal::Remotes(panel) => draw_remote_panel(frame, area, panel),
+        Modal::Credentials(prompt) => draw_credential_prompt(frame, area, prompt),
+        Modal::Messages(panel) => draw_message_panel(frame, area, panel),
+        Modal::Stashes(panel) => draw_stash_panel(frame, area, panel),
+        Modal::Recover(panel) => draw_recover_panel(frame, area, panel),
+        Modal::InitialCommit(prompt) => draw_commit_prompt(frame, area, prompt),
+        Modal::NewBranch(prompt) => draw_branch_prompt(frame, area, prompt),
+        Modal::ErrorDetail(detail) => draw_error_detail(frame, area, detail),
+        Modal::Setup(form) => draw_setup(frame, area, form),
+        Modal::PreCommit(panel) => draw_pre_commit_panel(frame, area, panel),
+    }
+}
+
+/// Draw a bordered, titled overlay centered in `area` and return its inner area.
+fn draw_overlay(frame: &mut Frame, area: Rect, title: &str, height: u16) -> Rect {
+    let overlay = centered_rect(area, 80, height);
+    frame.render_widget(Clear, overlay);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_set(symbols::border_set())
+        .border_style(Style::default().fg(colors::blue()))
+        .title(title.to_string());
+    let inner = block.inner(overlay);
+    frame.render_widget(block, overlay);
+    inner
+}
+
+/// A rectangle `percent_x` wide and `height` rows tall, centered in `area`.
+fn centered_rect(area: Rect, percent_x: u16, height: u16) -> Rect {
+    let height = height.min(area.height);
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(height),
+            Constraint::Fill(1),
+        ])
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
+fn hint(key: &'static str, label: &'static str) -> [Span<'static>; 2] {
+    [
+        Span::styled(symbols::text(key), Style::default().fg(colors::cyan())),
+        Span::styled(label, Style::default().fg(colors::gray())),
+    ]
+}
+
+fn draw_remote_panel(frame: &mut Frame, area: Rect, panel: &RemotePanel) {
+    let lines = remote_panel_lines(panel);
+    let inner = draw_overlay(frame, area, "Remotes", lines.len() as u16 + 2);
+    frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), inner);
+}
+
+fn remote_panel_lines(panel: &RemotePanel) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+
+    if panel.remotes.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "No remotes configured",
+            Style::default().fg(colors::gray()),
+        )));
+    }
+
+    for (i, remote) in panel.remotes.iter().enumerate() {
+        let is_selected = i == panel.selected;
+        let marker = if is_selected { "> " } else { "  " };
+        let name_style = if is_selected {
+            Style::default()
+                .fg(colors::text())
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(colors::text())
+        };
+        let fetch_state = if remote.tracking_branches == 0 {
+            "never fetched".to_string()
+        } else {
+            let suffix = if remote.tracking_branches == 1 {
+                ""
+            } else {
+                "es"
+            };
+            format!("{} branch{}", remote.tracking_branches, suffix)
+        };
+
+        let mut spans = vec![
+            Span::styled(marker, Style::default().fg(colors::text())),
+            Span::styled(remote.name.clone(), name_style),
+            Span::styled(
+                format!("  {}", fetch_state),
+                Style::default().fg(colors::gray()),
+            ),
+        ];
+        if remote.is_upstream {
+            spans.push(Span::styled(
+                "  upstream",
+                Style::default().fg(colors::green()),
+            ));
+        }
+        lines.push(Line::from(spans));
+
+        let fetch_url = remote.fetch_url.clone().unwrap_or_default();
+        lines.push(Line::from(vec![
+            Span::styled("    fetch ", Style::default().fg(colors::gray())),
+            Span::styled(fetch_url, Style::default().fg(colors::text())),
+        ]));
+        if let Some(push_url) = &remote.push_url {
+            lines.push(Line::from(vec![
+                Span::styled("    push  ", Style::default().fg(colors::gray())),
+                Span::styled(push_url.clone(), Style::default().fg(colors::text())),
+            ]));
+        }
+    }
+
+    lines.push(Line::from(""));
+
+    if let Some(name) = &panel.confirm_remove {
+        lines.push(Line::from(Span::styled(
+            format!("Remove remote '{}'? [y/N]", name),
+            Style::default().fg(colors::yellow()),
+        )));
+    } else if let Some(input) = &panel.input {
+        let label = match &input.kind {
+            RemoteInputKind::Add => "Add remote (name url): ".to_string(),
+            RemoteInputKind::Rename(old) => format!("Rename {} to: ", old),
+            RemoteInputKind::SetUpstream => "Upstream (remote/branch): ".to_string(),
+        };
+        lines.push(Line::from(vec![
+            Span::styled(label, Style::default().fg(colors::yellow())),
+            Span::styled(
+                symbols::text(format!("{}█", input.value)),
+                Style::default().fg(colors::text()),
+            ),
+        ]));
+    } else {
+        let mut spans = Vec::new();
+        spans.extend(hint("a", ":add "));
+        spans.extend(hint("r", ":rename "));
+        spans.extend(hint("x", ":remove "));
+        spans.extend(hint("u", ":set upstream "));
+        spans.extend(hint("Esc", ":close"));
+        lines.push(Line::from(spans));
+    }
+
+    if let Some(error) = &panel.error {
+        lines.push(Line::from(Span::styled(
+            symbols::text(format!("✗ {}", error)),
+            Style::default().fg(colors::red()),
+        )));
+    }
+
+    lines
+}
+
+fn draw_stash_panel(frame: &mut Frame, area: Rect, panel: &StashPanel) {
+    let (lines, focus) = stash_panel_lines(panel);
+    let height = (lines.len() as u16 + 2).min(area.height.saturating_sub(4));
+    let inner = draw_overlay(frame, area, "Stashes", height);
+    // Keep the highlighted row in view above the hint and error lines.
+    let list_height = (inner.height as usize).saturating_sub(3).max(1);
+    let scroll = focus.saturating_sub(list_height - 1);
+    frame.render_widget(Paragraph::new(lines).scroll((scroll as u16, 0)), inner);
+}
+
+/// The stash list, with the selected stash's files under it once opened,
+/// and the index of the highlighted line.
+fn stash_panel_lines(panel: &StashPanel) -> (Vec<Line<'static>>, usize) {
+    let mut lines = Vec::new();
+    let mut focus = 0;
+
+    if panel.stashes.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "No stashes",
+            Style::default().fg(colors::gray()),
+        )));
+    }
+
+    for (i, stash) in panel.stashes.iter().enumerate() {
+        let is_selected = i == panel.selected;
+        if is_selected {
+            focus = lines.len();
+        }
+        let marker = if is_selected { "> " } else { "  " };
+        let message_style = if is_selected {
+            Style::default()
+                .fg(colors::text())
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(colors::text())
+        };
+        lines.push(Line::from(vec![
+            Span::styled(marker, Style::default().fg(colors::text())),
+            Span::styled(
+                format!("stash@{{{}}} ", stash.index),
+                Style::default().fg(colors::cyan()),
+            ),
+            Span::styled(stash.message.clone(), message_style),
+            Span::styled(
+                format!(
+                    "  {} file{}",
+                    stash.files.len(),
+                    if stash.files.len() == 1 { "" } else { "s" }
+                ),
+                Style::default().fg(colors::gray()),
+            ),
+        ]));
+
+        let Some(cursor) = panel.file_cursor.filter(|_| is_selected) else {
+            continue;
+        };
+        for (j, file) in stash.files.iter().enumerate() {
+            let check = if panel.chosen.contains(&j) {
+                "[x] "
+            } else {
+                "[ ] "
+            };
+            if j == cursor {
+                focus = lines.len();
+            }
+            let style = if j == cursor {
+                Style::default()
+                    .fg(colors::text())
+                    .add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default().fg(colors::text())
+            };
+            lines.push(Line::from(vec![
+                Span::raw("    "),
+                Span::styled(check, Style::default().fg(colors::green())),
+                Span::styled(file.clone(), style),
+            ]));
+        }
+    }
+
+    lines.push(Line::from(""));
+
+    let mut spans = Vec::new();
+    if panel.file_cursor.is_some() {
+        spans.extend(hint("Space", ":choose "));
+        spans.extend(hint("a", ":all "));
+        spans.extend(hint("Enter", ":restore "));
+        spans.extend(hint("Esc", ":back"));
+    } else {
+        spans.extend(hint("Enter", ":choose files "));
+        spans.extend(hint("Esc", ":close"));
+    }
+    lines.push(Line::from(spans));
+
+    if let Some(error) = &panel.error {
+        lines.push(Line::from(Span::styled(
+            symbols::text(format!("✗ {}", error)),
+            Style::default().fg(colors::red()),
+        )));
+    }
+
+    (lines, focus)
+}
+
+fn draw_recover_panel(frame: &mut Frame, area: Rect, panel: &RecoverPanel) {
+    let (lines, focus) = recover_panel_lines(panel, SystemTime::now());
+    let height = (lines.len() as u16 + 2).min(area.height.saturating_sub(4));
+    let inner = draw_overlay(frame, area, "Recover discarded files", height);
+    let list_height = (inner.height as usize).saturating_sub(3).max(1);
+    let scroll = focus.saturating_sub(list_height - 1);
+    frame.render_widget(Paragraph::new(lines).scroll((scroll as u16, 0)), inner);
+}
+
+/// The discard backups, with the selected one's files under it once
+/// opened, then a preview of the highlighted file; and the index of the
+/// highlighted line.
+fn recover_panel_lines(panel: &RecoverPanel, now: SystemTime) -> (Vec<Line<'static>>, usize) {
+    let mut lines = Vec::new();
+    let mut focus = 0;
+
+    if panel.backups.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "No discarded files to recover",
+            Style::default().fg(colors::gray()),
+        )));
+    }
+
+    for (i, backup) in panel.backups.iter().enumerate() {
+        let is_selected = i == panel.selected;
+        if is_selected {
+            focus = lines.len();
+        }
+        let marker = if is_selected { "> " } else { "  " };
+        let age = format_age(now.duration_since(backup.time).unwrap_or_default());
+        let names: Vec<&str> = backup.files.iter().map(|f| f.path.as_str()).collect();
+        let names_style = if is_selected {
+            Style::default()
+                .fg(colors::text())
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(colors::text())
+        };
+        lines.push(Line::from(vec![
+            Span::styled(marker, Style::default().fg(colors::text())),
+            Span::styled(format!("{:<8} ", age), Style::default().fg(colors::cyan())),
+            Span::styled(names.join(", "), names_style),
+        ]));
+
+        let Some(cursor) = panel.file_cursor.filter(|_| is_selected) else {
+            continue;
+        };
+        for (j, file) in backup.files.iter().enumerate() {
+            if j == cursor {
+                focus = lines.len();
+            }
+            let style = if j == cursor {
+                Style::default()
+                    .fg(colors::text())
+                    .add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default().fg(colors::text())
+            };
+            lines.push(Line::from(vec![
+                Span::raw("    "),
+                Span::styled(file.path.clone(), style),
+            ]));
+        }
+    }
+
+    if let Some(file) = panel.previewed_file() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            file.path.clone(),
+            Style::default().fg(colors::yellow()),
+        )));
+        match &file.preview {
+            Some(preview) => {
+                for line in preview {
+                    lines.push(Line::from(Span::styled(
+                        format!("  {}", line),
+                        Style::default().fg(colors::gray()),
+                    )));
+                }
+            }
+            None => lines.push(Line::from(Span::styled(
+                "  (binary)",
+                Style::default().fg(colors::gray()),
+            ))),
+        }
+    }
+
+    lines.push(Line::from(""));
+
+    let mut spans = Vec::new();
+    if panel.file_cursor.is_some() {
+        spans.extend(hint("Enter", ":recover "));
+        spans.extend(hint("a", ":recover all "));
+        spans.extend(hint("Esc", ":back"));
+    } else {
+        spans.extend(hint("Enter", ":open "));
+        spans.extend(hint("Esc", ":close"));
+    }
+    lines.push(Line::from(spans));
+
+    if let Some(error) = &panel.error {
+        lines.push(Line::from(Span::styled(
+            symbols::text(format!("✗ {}", error)),
+            Style::default().fg(colors::red()),
+        )));
+    }
+
+    (lines, focus)
+}
+
+fn draw_commit_prompt(frame: &mut Frame, area: Rect, prompt: &CommitPrompt) {
+    let lines = commit_prompt_lines(prompt);
+    let inner = draw_overlay(frame, area, "Initial commit", lines.len() as u16 + 2);
+    frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), inner);
+}
+
+fn commit_prompt_lines(prompt: &CommitPrompt) -> Vec<Line<'static>> {
+    let mut hints = Vec::new();
+    hints.extend(hint("Enter", ":commit "));
+    hints.extend(hint("Esc", ":cancel"));
+
+    let mut lines = vec![
+        Line::from(vec![
+            Span::styled("Message: ", Style::default().fg(colors::yellow())),
+            Span::styled(
+                symbols::text(format!("{}█", prompt.message)),
+                Style::default().fg(colors::text()),
+            ),
+        ]),
+        Line::from(""),
+        Line::from(hints),
+    ];
+    if let Some(error) = &prompt.error {
+        lines.push(Line::from(Span::styled(
+            symbols::text(format!("✗ {}", error)),
+            Style::default().fg(colors::red()),
+        )));
+    }
+    lines
+}
+
+fn draw_branch_prompt(frame: &mut Frame, area: Rect, prompt: &BranchPrompt) {
+    let lines = branch_prompt_lines(prompt);
+    let inner = draw_overlay(frame, area, "New branch", lines.len() as u16 + 2);
+    frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), inner);
+}
+
+fn branch_prompt_lines(prompt: &BranchPrompt) -> Vec<Line<'static>> {
+    let mut hints = Vec::new();
+    hints.extend(hint("Enter", ":create "));
+    hints.extend(hint("Esc", ":cancel"));
+
+    let mut lines = vec![
+        Line::from(Span::styled(
+            "Create a branch at the current commit and check it out.",
+            Style::default().fg(colors::gray()),
+        )),
+        Line::from(vec![
+            Span::styled("Name: ", Style::default().fg(colors::yellow())),
+            Span::styled(
+                symbols::text(format!("{}█", prompt.name)),
+                Style::default().fg(colors::text()),
+            ),
+        ]),
+        Line::from(""),
+        Line::from(hints),
+    ];
+    if let Some(error) = &prompt.error {
+        lines.push(Line::from(Span::styled(
+            symbols::text(format!("✗ {}", error)),
+            Style::default().fg(colors::red()),
+        )));
+    }
+    lines
+}
+
+fn draw_credential_prompt(frame: &mut Frame, area: Rect, prompt: &CredentialPrompt) {
+    let lines = credential_prompt_lines(prompt);
+    let inner = draw_overlay(frame, area, "Credentials", lines.len() as u16 + 2);
+    frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), inner);
+}
+
+fn credential_prompt_lines(prompt: &CredentialPrompt) -> Vec<Line<'static>> {
+    let field = |label: &'static str, value: String, active: bool| {
+        let cursor = if active { "█" } else { "" };
+        let label_style = if active {
+            Style::default()
+                .fg(colors::yellow())
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(colors::gray())
+        };
+        Line::from(vec![
+            Span::styled(label, label_style),
+            Span::styled(
+                format!("{}{}", value, symbols::text(cursor)),
+                Style::default().fg(colors::text()),
+            ),
+        ])
+    };
+
+    let mut hints = Vec::new();
+    hints.extend(hint("Tab", ":switch field "));
+    hints.extend(hint("Enter", ":submit "));
+    hints.extend(hint("Esc", ":cancel"));
+
+    vec![
+        Line::from(Span::styled(
+            format!("{} {}", prompt.op.verb(), prompt.url),
+            Style::default().fg(colors::text()),
+        )),
+        Line::from(""),
+        field(
+            "Username: ",
+            prompt.username.clone(),
+            !prompt.editing_password,
+        ),
+        field(
+            "Password: ",
+            symbols::text("•").repeat(prompt.password.chars().count()),
+            prompt.editing_password,
+        ),
+        Line::from(""),
+        Line::from(hints),
+    ]
+}
+
+fn draw_message_panel(frame: &mut Frame, area: Rect, panel: &MessagePanel) {
+    let lines = message_panel_lines(panel, Instant::now());
+    let inner = draw_overlay(frame, area, "Messages", area.height.saturating_sub(4));
+    let (messages, footer) = lines.split_at(lines.len() - 2);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Fill(1), Constraint::Length(2)])
+        .split(inner);
+    frame.render_widget(
+        Paragraph::new(messages.to_vec()).scroll((panel.scroll as u16, 0)),
+        chunks[0],
+    );
+    frame.render_widget(Paragraph::new(footer.to_vec()), chunks[1]);
+}
+
+/// One line per message, newest first, followed by a blank line and hints.
+fn message_panel_lines(panel: &MessagePanel, now: Instant) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+
+    if panel.messages.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "No messages yet",
+            Style::default().fg(colors::gray()),
+        )));
+    }
+
+    for message in &panel.messages {
+        let (symbol, color) = if message.is_error {
+            ("✗ ", colors::red())
+        } else {
+            ("✓ ", colors::green())
+        };
+        let age = format_age(now.saturating_duration_since(message.shown_at));
+        lines.push(Line::from(vec![
+            Span::styled(format!("{:>8} ", age), Style::default().fg(colors::gray())),
+            Span::styled(symbols::text(symbol), Style::default().fg(color)),
+            Span::styled(message.text.clone(), Style::default().fg(color)),
+        ]));
+    }
+
+    let mut hints = Vec::new();
+    hints.extend(hint("↑/↓", ":scroll "));
+    hints.extend(hint("Esc", ":close"));
+    lines.push(Line::from(""));
+    lines.push(Line::from(hints));
+    lines
+}
+
+fn draw_pre_commit_panel(frame: &mut Frame, area: Rect, panel: &PreCommitPanel) {
+    let inner = draw_overlay(frame, area, "pre-commit", area.height.saturating_sub(4));
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Fill(1), Constraint::Length(2)])
+        .split(inner);
+    frame.render_widget(
+        Paragraph::new(pre_commit_output_lines(panel, chunks[0].height as usize)),
+        chunks[0],
+    );
+    frame.render_widget(Paragraph::new(pre_commit_footer(panel)), chunks[1]);
+}
+
+/// The `height` output lines ending `scroll_back` lines before the last.
+fn pre_commit_output_lines(panel: &PreCommitPanel, height: usize) -> Vec<Line<'static>> {
+    let end = panel.lines.len() - panel.scroll_back.min(panel.lines.len());
+    let start = end.saturating_sub(height);
+    panel.lines[start..end]
+        .iter()
+        .map(|line| {
+            Line::from(Span::styled(
+                line.clone(),
+                Style::default().fg(colors::text()),
+            ))
+        })
+        .collect()
+}
+
+/// Whether the hooks are still running or how they ended, then hints.
+fn pre_commit_footer(panel: &PreCommitPanel) -> Vec<Line<'static>> {
+    let status = match &panel.result {
+        None => Span::styled(
+            format!("Running hooks{}", symbols::ellipsis()),
+            Style::default().fg(colors::gray()),
+        ),
+        Some(result) => {
+            let (symbol, color) = if panel.failed {
+                ("✗ ", colors::red())
+            } else {
+                ("✓ ", colors::green())
+            };
+            Span::styled(
+                format!("{}{}", symbols::text(symbol), result),
+                Style::default().fg(color),
+            )
+        }
+    };
+    let mut hints = Vec::new();
+    hints.extend(hint("↑/↓", ":scroll "));
+    hints.extend(hint("Esc", ":close"));
+    vec![Line::from(status), Line::from(hints)]
+}
+
+fn draw_error_detail(frame: &mut Frame, area: Rect, detail: &ErrorDetail) {
+    let lines = error_detail_lines(detail);
+    let inner = draw_overlay(frame, area, "Error", lines.len() as u16 + 2);
+    frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), inner);
+}
+
+/// The error, each cause on its own line, then the files involved.
+fn error_detail_lines(detail: &ErrorDetail) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+    let mut chain = detail.chain.iter();
+
+    if let Some(message) = chain.next() {
+        lines.push(Line::from(Span::styled(
+            message.clone(),
+            Style::default()
+                .fg(colors::red())
+                .add_modifier(Modifier::BOLD),
+        )));
+    }
+    for cause in chain {
+        lines.push(Line::from(vec![
+            Span::styled("  caused by: ", Style::default().fg(colors::gray())),
+            Span::styled(cause.clone(), Style::default().fg(colors::text())),
+        ]));
+    }
+
+    if !detail.paths.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            "Files:",
+            Style::default().fg(colors::gray()),
+        )));
+        for path in &detail.paths {
+            lines.push(Line::from(Span::styled(
+                format!("  {}", path),
+                Style::default().fg(colors::text()),
+            )));
+        }
+    }
+
+    lines.push(Line::from(""));
+    lines.push(Line::from(hint("Esc", ":close").to_vec()));
+    lines
+}
+
+fn draw_setup(frame: &mut Frame, area: Rect, form: &SetupForm) {
+    let lines = setup_lines(form);
+    let inner = draw_overlay(frame, area, "Welcome", lines.len() as u16 + 2);
+    frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), inner);
+}
+
+fn setup_lines(form: &SetupForm) -> Vec<Line<'static>> {
+    let keys = match form.keys {
+        KeyPreset::Default => "default (arrows, PgUp/PgDn)",
+        KeyPreset::Vim => "vim (j/k, ctrl+d/ctrl+u)",
+        KeyPreset::Emacs => "emacs (ctrl+n/ctrl+p, ctrl+v/alt+v)",
+        KeyPreset::Lazygit => "lazygit (space stages, p/P pull/push)",
+    };
+    let confirm = match form.confirm {
+        ConfirmLevel::All => "stage all, unstage all, and discards",
+        ConfirmLevel::Destructive => "discards only",
+    };
+    let rows = [
+        ("Theme", form.theme.name().replace('_', " ")),
+        ("Keys", keys.to_string()),
+        ("Confirm", confirm.to_string()),
+    ];
+
+    let mut lines = vec![
+        Line::from(Span::styled(
+            "No config file found. Pick a few settings to get started:",
+            Style::default().fg(colors::text()),
+        )),
+        Line::from(""),
+    ];
+    for (i, (label, value)) in rows.into_iter().enumerate() {
+        let active = i == form.row;
+        let marker = if active { "> " } else { "  " };
+        let label_style = if active {
+            Style::default()
+                .fg(colors::yellow())
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(colors::gray())
+        };
+        lines.push(Line::from(vec![
+            Span::styled(marker, Style::default().fg(colors::text())),
+            Span::styled(format!("{:<9}", label), label_style),
+            Span::styled(
+                symbols::text(format!("← {} →", value)),
+                Style::default().fg(colors::text()),
+            ),
+        ]));
+    }
+
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        format!("Saved to {}", form.path.display()),
+        Style::default().fg(colors::gray()),
+    )));
+    if let Some(error) = &form.error {
+        lines.push(Line::from(Span::styled(
+            symbols::text(format!("✗ {}", error)),
+            Style::default().fg(colors::red()),
+        )));
+    }
+
+    let mut hints = Vec::new();
+    hints.extend(hint("↑/↓", ":choose "));
+    hints.extend(hint("←/→", ":change "));
+    hints.extend(hint("Enter", ":save "));
+    hints.extend(hint("Esc", ":skip"));
+    lines.push(Line::from(hints));
+    lines
+}
+
+/// Coarse age such as "now", "42s ago", "5m ago", "2h ago", or "3d ago".
+pub(crate) fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0 => "now".to_string(),
+        1..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        BackupFile, DiscardBackup, FlashMessage, NetworkOp, RemoteInfo, RemoteInput, StashInfo,
+    };
+
+    fn line_text(line: &Line) -> String {
+        line.spans.iter().map(|s| s.content.as_ref()).collect()
+    }
+
+    fn panel() -> RemotePanel {
+        RemotePanel {
+            remotes: vec![
+                RemoteInfo {
+                    name: "origin".to_string(),
+                    fetch_url: Some("git@example.com:repo.git".to_string()),
+                    push_url: None,
+                    tracking_branches: 3,
+                    is_upstream: true,
+                },
+                RemoteInfo {
+                    name: "fork".to_string(),
+                    fetch_url: Some("https://example.com/fork.git".to_string()),
+                    push_url: Some("git@example.com:fork.git".to_string()),
+                    tracking_branches: 0,
+                    is_upstream: false,
+                },
+            ],
+            ..RemotePanel::default()
+        }
+    }
+
+    #[test]
+    fn test_pre_commit_output_follows_the_end_unless_scrolled_back() {
+        let mut panel = PreCommitPanel {
+            lines: (1..=5).map(|n| format!("line {}", n)).collect(),
+            ..PreCommitPanel::default()
+        };
+        let text = |panel: &PreCommitPanel| -> Vec<String> {
+            pre_commit_output_lines(panel, 2)
+                .iter()
+                .map(line_text)
+                .collect()
+        };
+        assert_eq!(text(&panel), ["line 4", "line 5"]);
+        panel.scroll_back = 2;
+        assert_eq!(text(&panel), ["line 2", "line 3"]);
+        panel.scroll_back = 9;
+        assert!(text(&panel).is_empty());
+    }
+
+    #[test]
+    fn test_stash_panel_lines_list_files_of_the_opened_stash() {
+        let stash = |index: usize, files: &[&str]| StashInfo {
+            index,
+            message: format!("WIP {}", index),
+            files: files.iter().map(|f| f.to_string()).collect(),
+        };
+        let mut panel = StashPanel {
+            stashes: vec![stash(0, &["a.rs"]), stash(1, &["b.rs", "c.rs"])],
+            selected: 1,
+            ..StashPanel::default()
+        };
+        let (lines, focus) = stash_panel_lines(&panel);
+        let text: Vec<String> = lines.iter().map(line_text).collect();
+        assert_eq!(text[0], "  stash@{0} WIP 0  1 file");
+        assert_eq!(text[1], "> stash@{1} WIP 1  2 files");
+        assert_eq!(focus, 1);
+
+        panel.file_cursor = Some(1);
+        panel.chosen.insert(0);
+        let (lines, focus) = stash_panel_lines(&panel);
+        let text: Vec<String> = lines.iter().map(line_text).collect();
+        assert_eq!(text[2], "    [x] b.rs");
+        assert_eq!(text[3], "    [ ] c.rs");
+        assert_eq!(focus, 3);
+        assert_eq!(panel.restore_paths(), ["b.rs"]);
+    }
+
+    #[test]
+    fn test_recover_panel_lines_preview_the_highlighted_file() {
+        let now = SystemTime::now();
+        let file = |path: &str, preview: Option<&[&str]>| BackupFile {
+            path: path.to_string(),
+            raw_path: path.as_bytes().to_vec(),
+            preview: preview.map(|lines| lines.iter().map(|l| l.to_string()).collect()),
+        };
+        let mut panel = RecoverPanel {
+            backups: vec![DiscardBackup {
+                index: 0,
+                time: now - Duration::from_secs(300),
+                files: vec![file("a.rs", Some(&["fn a() {}"])), file("b.bin", None)],
+            }],
+            ..RecoverPanel::default()
+        };
+        let (lines, focus) = recover_panel_lines(&panel, now);
+        let text: Vec<String> = lines.iter().map(line_text).collect();
+        assert_eq!(text[0], "> 5m ago   a.rs, b.bin");
+        assert_eq!(text[2], "a.rs");
+        assert_eq!(text[3], "  fn a() {}");
+        assert_eq!(focus, 0);
+
+        panel.file_cursor = Some(1);
+        let (lines, focus) = recover_panel_lines(&panel, now);
+        let text: Vec<String> = lines.iter().map(line_text).collect();
+        assert_eq!(text[2], "    b.bin");
+        assert_eq!(text[4], "b.bin");
+        assert_eq!(text[5], "  (binary)");
+        assert_eq!(focus, 2);
+    }
+
+    #[test]
+    fn test_remote_panel_lines_show_state() {
+        let text: Vec<String> = remote_panel_lines(&panel()).iter().map(line_text).collect();
+        assert_eq!(text[0], "> origin  3 branches  upstream");
+        assert_eq!(text[1], "    fetch git@example.com:repo.git");
+        assert_eq!(text[2], "  fork  never fetched");
+        assert_eq!(text[4], "    push  git@example.com:fork.git");
+    }
+
+    #[test]
+    fn test_remote_panel_lines_show_input() {
+        let mut panel = panel();
+        panel.input = Some(RemoteInput {
+            kind: RemoteInputKind::Rename("origin".to_string()),
+            value: "upstream".to_string(),
+        });
+        let text: Vec<String> = remote_panel_lines(&panel).iter().map(line_text).collect();
+        assert!(text.contains(&"Rename origin to: upstream█".to_string()));
+    }
+
+    #[test]
+    fn test_remote_panel_lines_show_error_and_confirm() {
+        let mut panel = panel();
+        panel.confirm_remove = Some("fork".to_string());
+        panel.error = Some("boom".to_string());
+        let text: Vec<String> = remote_panel_lines(&panel).iter().map(line_text).collect();
+        assert!(text.contains(&"Remove remote 'fork'? [y/N]".to_string()));
+        assert!(text.contains(&"✗ boom".to_string()));
+    }
+
+    #[test]
+    fn test_credential_prompt_masks_password() {
+        let prompt = CredentialPrompt {
+            op: NetworkOp::Push,
+            url: "https://example.com/repo.git".to_string(),
+            username: "me".to_string(),
+            password: "secret".to_string(),
+            editing_password: true,
+        };
+        let text: Vec<String> = credential_prompt_lines(&prompt)
+            .iter()
+            .map(line_text)
+            .collect();
+        assert_eq!(text[0], "Pushing https://example.com/repo.git");
+        assert_eq!(text[2], "Username: me");
+        assert_eq!(text[3], "Password: ••••••█");
+        assert!(!text.iter().any(|l| l.contains("secret")));
+    }
+
+    #[test]
+    fn test_message_panel_lines_newest_first_with_age() {
+        let now = Instant::now();
+        let panel = MessagePanel {
+            messages: vec![
+                FlashMessage {
+                    shown_at: now - Duration::from_secs(5),
+                    ..FlashMessage::error("Error: boom")
+                },
+                FlashMessage {
+                    shown_at: now - Duration::from_secs(150),
+                    ..FlashMessage::success("Staged a.txt")
+                },
+            ],
+            scroll: 0,
+        };
+        let text: Vec<String> = message_panel_lines(&panel, now)
+            .iter()
+            .map(line_text)
+            .collect();
+        assert_eq!(text[0], "  5s ago ✗ Error: boom");
+        assert_eq!(text[1], "  2m ago ✓ Staged a.txt");
+        assert_eq!(text[3], "↑/↓:scroll Esc:close");
+    }
+
+    #[test]
+    fn test_error_detail_lines_show_chain_and_paths() {
+        let error = anyhow::anyhow!("permission denied")
+            .context("Failed to stage file: a.txt")
+            .context("Failed to stage 2 files");
+        let detail = ErrorDetail::new(&error, vec!["a.txt".to_string(), "b.txt".to_string()]);
+        let text: Vec<String> = error_detail_lines(&detail).iter().map(line_text).collect();
+        assert_eq!(
+            text,
+            vec![
+                "Failed to stage 2 files",
+                "  caused by: Failed to stage file: a.txt",
+                "  caused by: permission denied",
+                "",
+                "Files:",
+                "  a.txt",
+                "  b.txt",
+                "",
+                "Esc:close",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_setup_lines_show_choices() {
+        let mut form = SetupForm::new(std::path::PathBuf::from("/tmp/config.toml"));
+        form.row = 1;
+        form.keys = KeyPreset::Vim;
+        let text: Vec<String> = setup_lines(&form).iter().map(line_text).collect();
+        assert_eq!(text[2], "  Theme    ← default →");
+        assert_eq!(text[3], "> Keys     ← vim (j/k, ctrl+d/ctrl+u) →");
+        assert_eq!(
+            text[4],
+            "  Confirm  ← stage all, unstage all, and discards →"
+        );
+        assert_eq!(text[6], "Saved to /tmp/config.toml");
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(Duration::from_millis(300)), "now");
+        assert_eq!(format_age(Duration::from_secs(59)), "59s ago");
+        assert_eq!(format_age(Duration::from_secs(60)), "1m ago");
+        assert_eq!(format_age(Duration::from_secs(7200)), "2h ago");
+        assert_eq!(format_age(Duration::from_secs(3 * 86400)), "3d ago");
+    }
+
+    #[test]
+    fn test_centered_rect_fits_area() {
+        let area = Rect::new(0, 0, 100, 20);
+        let rect = centered_rect(area, 80, 30);
+        assert_eq!(rect.height, 20);
+        assert_eq!(rect.width, 80);
+    }
+}
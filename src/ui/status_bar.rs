@@ -1,71 +1,214 @@
-use crate::types::{BranchInfo, ConfirmPrompt, FlashMessage};
-use crate::ui::colors;
+use crate::keymap::{format_sequence, Action, Key, Keymap};
+use crate::types::{
+    BranchInfo, ConfirmPrompt, FlashMessage, NetworkStatus, PollReason, RebaseProgress,
+    UpstreamInfo, UpstreamNotice,
+};
+use crate::ui::{colors, symbols};
 use ratatui::{
     layout::Rect,
-    style::Style,
+    style::{Modifier, Style},
     text::{Line, Span},
     widgets::Paragraph,
     Frame,
 };
+use std::ops::Range;
 
 pub struct StatusBarState<'a> {
     pub branch: &'a BranchInfo,
+    pub upstream: Option<&'a UpstreamInfo>,
+    pub upstream_notice: Option<&'a UpstreamNotice>,
+    pub rebase_progress: Option<&'a RebaseProgress>,
     pub staged_count: usize,
     pub unstaged_count: usize,
     pub untracked_count: usize,
+    /// Conflicted files, which are also counted as unstaged.
+    pub conflict_count: usize,
     pub confirm_prompt: Option<&'a ConfirmPrompt>,
     pub flash_message: Option<&'a FlashMessage>,
+    pub network: Option<&'a NetworkStatus>,
+    /// Why changes are polled for, if the file watcher isn't in use.
+    pub polling: Option<&'a PollReason>,
+    pub keymap: &'a Keymap,
+    /// Actions to advertise, with labels, in display order.
+    pub hints: &'a [(Action, &'static str)],
+    /// Keys typed so far toward a multi-key binding.
+    pub pending_keys: &'a [Key],
+}
+
+/// A count in the status bar that jumps to its files when clicked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountLink {
+    Conflict,
+    Staged,
+    Unstaged,
+    Untracked,
 }
 
-pub fn draw(frame: &mut Frame, area: Rect, state: StatusBarState<'_>) {
+/// Draw the status bar, returning the columns each clickable count covers.
+pub fn draw(
+    frame: &mut Frame,
+    area: Rect,
+    state: StatusBarState<'_>,
+) -> Vec<(Range<u16>, CountLink)> {
+    let mut links = Vec::new();
     let line = if let Some(prompt) = state.confirm_prompt {
         Line::from(vec![
             Span::raw(" "),
-            Span::styled(&prompt.message, Style::default().fg(colors::YELLOW)),
+            Span::styled(&prompt.message, Style::default().fg(colors::yellow())),
         ])
     } else if let Some(flash) = state.flash_message {
         let (prefix, color) = if flash.is_error {
-            ("✗ ", colors::RED)
+            ("✗ ", colors::red())
         } else {
-            ("✓ ", colors::GREEN)
+            ("✓ ", colors::green())
         };
-        Line::from(vec![
+        let mut spans = vec![
             Span::raw(" "),
-            Span::styled(prefix, Style::default().fg(color)),
+            Span::styled(symbols::text(prefix), Style::default().fg(color)),
             Span::styled(&flash.text, Style::default().fg(color)),
-        ])
-    } else {
-        Line::from(vec![
-            Span::raw(" "),
-            Span::styled(state.branch.to_string(), Style::default().fg(colors::CYAN)),
+        ];
+        if flash.detail.is_some() {
+            if let Some(keys) = state.keymap.keys_for(Action::ErrorDetails) {
+                spans.extend([
+                    Span::raw("  "),
+                    Span::styled(format_sequence(keys), Style::default().fg(colors::cyan())),
+                    Span::styled(":details", Style::default().fg(colors::gray())),
+                ]);
+            }
+        }
+        Line::from(spans)
+    } else if let Some(network) = state.network {
+        let mut spans = vec![
             Span::raw(" "),
-            Span::styled("S:", Style::default().fg(colors::TEXT)),
+            Span::styled(symbols::text("⟳ "), Style::default().fg(colors::blue())),
             Span::styled(
-                state.staged_count.to_string(),
-                Style::default().fg(colors::GREEN),
+                symbols::text(network.to_string()),
+                Style::default().fg(colors::blue()),
             ),
-            Span::raw(" "),
-            Span::styled("U:", Style::default().fg(colors::TEXT)),
-            Span::styled(
-                state.unstaged_count.to_string(),
-                Style::default().fg(colors::YELLOW),
+        ];
+        if !network.cancelling {
+            spans.extend([
+                Span::raw("  "),
+                Span::styled("Esc", Style::default().fg(colors::cyan())),
+                Span::styled(":cancel", Style::default().fg(colors::gray())),
+            ]);
+        }
+        Line::from(spans)
+    } else {
+        let mut spans = vec![Span::raw(" ")];
+        if let BranchInfo::Detached(_) = state.branch {
+            // Easy to end up in by accident, so make it stand out.
+            spans.push(Span::styled(
+                state.branch.to_string(),
+                Style::default()
+                    .fg(colors::yellow())
+                    .add_modifier(Modifier::BOLD),
+            ));
+            spans.push(Span::styled(
+                " detached ",
+                Style::default()
+                    .fg(colors::yellow())
+                    .add_modifier(Modifier::REVERSED),
+            ));
+        } else {
+            spans.push(Span::styled(
+                state.branch.to_string(),
+                Style::default().fg(colors::cyan()),
+            ));
+        }
+        spans.push(Span::raw(" "));
+        if let Some(upstream) = state.upstream {
+            if upstream.ahead > 0 {
+                spans.push(Span::styled(
+                    symbols::text(format!("↑{} ", upstream.ahead)),
+                    Style::default().fg(colors::green()),
+                ));
+            }
+            if let Some(notice) = state.upstream_notice {
+                spans.push(Span::styled(
+                    symbols::text(format!("{} ", notice)),
+                    Style::default()
+                        .fg(colors::yellow())
+                        .add_modifier(Modifier::BOLD),
+                ));
+                spans.push(Span::styled("l", Style::default().fg(colors::cyan())));
+                spans.push(Span::styled(":pull ", Style::default().fg(colors::gray())));
+            } else if upstream.behind > 0 {
+                spans.push(Span::styled(
+                    symbols::text(format!("↓{} ", upstream.behind)),
+                    Style::default().fg(colors::yellow()),
+                ));
+            }
+        }
+        if !state.pending_keys.is_empty() {
+            spans.push(Span::styled(
+                symbols::text(format!("{}… ", format_sequence(state.pending_keys))),
+                Style::default()
+                    .fg(colors::yellow())
+                    .add_modifier(Modifier::BOLD),
+            ));
+        }
+        if let Some(progress) = state.rebase_progress {
+            spans.push(Span::styled(
+                format!("({}) ", progress),
+                Style::default().fg(colors::magenta()),
+            ));
+        }
+        if let Some(reason) = state.polling {
+            spans.push(Span::styled(
+                format!("[polling: {}] ", reason),
+                Style::default().fg(colors::yellow()),
+            ));
+        }
+        // The conflict count is only shown while there are conflicts.
+        let conflicts = (state.conflict_count > 0).then_some((
+            "!:",
+            state.conflict_count,
+            colors::magenta(),
+            CountLink::Conflict,
+        ));
+        let counts = [
+            ("S:", state.staged_count, colors::green(), CountLink::Staged),
+            (
+                "U:",
+                state.unstaged_count,
+                colors::yellow(),
+                CountLink::Unstaged,
             ),
-            Span::raw(" "),
-            Span::styled("?:", Style::default().fg(colors::TEXT)),
-            Span::styled(
-                state.untracked_count.to_string(),
-                Style::default().fg(colors::GRAY),
+            (
+                "?:",
+                state.untracked_count,
+                colors::gray(),
+                CountLink::Untracked,
             ),
-            Span::raw("  "),
-            Span::styled("s", Style::default().fg(colors::CYAN)),
-            Span::styled(":stage ", Style::default().fg(colors::GRAY)),
-            Span::styled("u", Style::default().fg(colors::CYAN)),
-            Span::styled(":unstage ", Style::default().fg(colors::GRAY)),
-            Span::styled("q", Style::default().fg(colors::CYAN)),
-            Span::styled(":quit", Style::default().fg(colors::GRAY)),
-        ])
+        ];
+        for (label, count, color, link) in conflicts.into_iter().chain(counts) {
+            let start = area.x + spans.iter().map(|s| s.width() as u16).sum::<u16>();
+            let count = count.to_string();
+            let end = start + (label.len() + count.len()) as u16;
+            links.push((start..end, link));
+            spans.extend([
+                Span::styled(label, Style::default().fg(colors::text())),
+                Span::styled(count, Style::default().fg(color)),
+                Span::raw(" "),
+            ]);
+        }
+        for &(action, label) in state.hints {
+            if let Some(keys) = state.keymap.keys_for(action) {
+                spans.push(Span::styled(
+                    symbols::text(format!(" {}", format_sequence(keys))),
+                    Style::default().fg(colors::cyan()),
+                ));
+                spans.push(Span::styled(
+                    format!(":{}", label),
+                    Style::default().fg(colors::gray()),
+                ));
+            }
+        }
+        Line::from(spans)
     };
 
-    let paragraph = Paragraph::new(line).style(Style::default().bg(colors::SURFACE));
+    let paragraph = Paragraph::new(line).style(Style::default().bg(colors::surface()));
     frame.render_widget(paragraph, area);
+    links
 }
@@ -0,0 +1,148 @@
+use crate::config::Glyphs;
+use ratatui::symbols::border;
+use std::borrow::Cow;
+use std::cell::Cell;
+
+/// Non-ASCII glyphs the UI draws, with the ASCII text used in their place
+/// when ASCII mode is on.
+const ASCII_FALLBACKS: &[(char, &str)] = &[
+    ('●', "*"),
+    ('○', "o"),
+    ('◆', "#"),
+    ('→', "->"),
+    ('←', "<-"),
+    ('↑', "^"),
+    ('↓', "v"),
+    ('│', "|"),
+    ('┆', ":"),
+    ('├', "+"),
+    ('─', "-"),
+    ('┘', "'"),
+    ('…', "..."),
+    ('✓', "+"),
+    ('✗', "x"),
+    ('⟳', "~"),
+    ('█', "_"),
+    ('•', "*"),
+    ('·', "-"),
+    ('—', "-"),
+    ('␍', "^M"),
+];
+
+const ASCII_BORDER: border::Set<'static> = border::Set {
+    top_left: "+",
+    top_right: "+",
+    bottom_left: "+",
+    bottom_right: "+",
+    vertical_left: "|",
+    vertical_right: "|",
+    horizontal_top: "-",
+    horizontal_bottom: "-",
+};
+
+thread_local! {
+    static ASCII: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Switch glyph rendering for subsequent draws on this thread. `Auto` uses
+/// ASCII when the locale isn't UTF-8.
+pub fn set_glyphs(glyphs: Glyphs) {
+    let ascii = match glyphs {
+        Glyphs::Auto => !locale_is_utf8(locale()),
+        Glyphs::Unicode => false,
+        Glyphs::Ascii => true,
+    };
+    ASCII.with(|cell| cell.set(ascii));
+}
+
+pub fn is_ascii() -> bool {
+    ASCII.with(Cell::get)
+}
+
+/// The effective locale, from the first non-empty of `LC_ALL`, `LC_CTYPE`,
+/// and `LANG`.
+fn locale() -> Option<String> {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// An unset locale is assumed to be UTF-8; an explicit `C` or `POSIX` or any
+/// locale without a UTF-8 codeset is not.
+fn locale_is_utf8(locale: Option<String>) -> bool {
+    match locale {
+        None => true,
+        Some(locale) => {
+            let locale = locale.to_ascii_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        }
+    }
+}
+
+/// `text` with every glyph from the fallback table replaced by its ASCII
+/// equivalent when ASCII mode is on; unchanged otherwise.
+pub fn text<'a>(text: impl Into<Cow<'a, str>>) -> Cow<'a, str> {
+    let text = text.into();
+    if !is_ascii() || text.is_ascii() {
+        return text;
+    }
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match ASCII_FALLBACKS.iter().find(|(glyph, _)| *glyph == c) {
+            Some((_, fallback)) => out.push_str(fallback),
+            None => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Marker for truncated text.
+pub fn ellipsis() -> &'static str {
+    if is_ascii() {
+        "..."
+    } else {
+        "…"
+    }
+}
+
+/// Border characters for panel and overlay blocks.
+pub fn border_set() -> border::Set<'static> {
+    if is_ascii() {
+        ASCII_BORDER
+    } else {
+        border::PLAIN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_unchanged_in_unicode_mode() {
+        set_glyphs(Glyphs::Unicode);
+        assert_eq!(text("a → b …"), "a → b …");
+        assert!(matches!(text("a → b"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn text_replaces_glyphs_in_ascii_mode() {
+        set_glyphs(Glyphs::Ascii);
+        assert_eq!(text("old → new"), "old -> new");
+        assert_eq!(text("✓ Staged │ ● ✗ …"), "+ Staged | * x ...");
+        assert_eq!(text("café"), "café");
+        assert_eq!(ellipsis(), "...");
+        assert_eq!(border_set().top_left, "+");
+        set_glyphs(Glyphs::Unicode);
+    }
+
+    #[test]
+    fn detects_utf8_locales() {
+        assert!(locale_is_utf8(None));
+        assert!(locale_is_utf8(Some("en_US.UTF-8".to_string())));
+        assert!(locale_is_utf8(Some("C.utf8".to_string())));
+        assert!(!locale_is_utf8(Some("C".to_string())));
+        assert!(!locale_is_utf8(Some("de_DE.ISO-8859-1".to_string())));
+    }
+}
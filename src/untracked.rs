@@ -0,0 +1,219 @@
+use crate::git::{fs_path, is_executable, raw_path};
+use git2::Repository;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Directories modified this recently are scanned again next time: their
+/// mtime may not have ticked over for a change made during the scan.
+pub(crate) const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// Modification time and size of a file, to notice it being edited.
+type Stamp = Option<(SystemTime, u64)>;
+
+/// What a scan found directly inside one directory.
+#[derive(Debug, Clone)]
+struct CachedDir {
+    /// `None` while too recent to trust.
+    mtime: Option<SystemTime>,
+    /// The directory's `.gitignore`, whose rules apply to everything below.
+    gitignore: Stamp,
+    /// Files that aren't ignored, tracked or not, and their modes. Nested
+    /// repositories are listed as entries with a trailing `/`.
+    files: Vec<(Vec<u8>, u32)>,
+    /// Subdirectories that aren't ignored.
+    dirs: Vec<Vec<u8>>,
+}
+
+/// Per-directory results of looking for untracked files, reused while a
+/// directory's mtime and `.gitignore` are unchanged, like git's untracked
+/// cache. Adding or removing an entry updates its directory's mtime, so an
+/// unchanged directory only needs a `stat`, not a listing and an ignore
+/// check per entry.
+#[derive(Debug, Default)]
+pub struct UntrackedCache {
+    dirs: HashMap<Vec<u8>, CachedDir>,
+    /// `.git/info/exclude` and `core.excludesFile`, which apply everywhere.
+    excludes: Vec<Stamp>,
+}
+
+impl UntrackedCache {
+    /// Whether to keep one for `repo`: unless `core.untrackedCache` is
+    /// false, as for filesystems that don't update directory mtimes.
+    pub fn enabled(repo: &Repository) -> bool {
+        repo.config()
+            .and_then(|config| config.get_bool("core.untrackedCache"))
+            .unwrap_or(true)
+    }
+
+    /// Untracked files in `repo`'s working tree, with their modes, as
+    /// repository-relative paths.
+    pub fn scan(&mut self, repo: &Repository) -> Vec<(Vec<u8>, u32)> {
+        let Some(workdir) = repo.workdir() else {
+            return Vec::new();
+        };
+        let excludes = exclude_files(repo, workdir)
+            .iter()
+            .map(|p| stamp(p))
+            .collect();
+        if excludes != self.excludes {
+            self.dirs.clear();
+            self.excludes = excludes;
+        }
+
+        let tracked: HashSet<Vec<u8>> = match repo.index() {
+            Ok(index) => index.iter().map(|entry| entry.path).collect(),
+            Err(_) => HashSet::new(),
+        };
+        let mut scan = Scan {
+            repo,
+            workdir,
+            recent: SystemTime::now() - RACY_WINDOW,
+            visited: HashSet::new(),
+            untracked: Vec::new(),
+        };
+        scan.dir(&mut self.dirs, Vec::new(), false, &tracked);
+
+        let visited = scan.visited;
+        self.dirs.retain(|dir, _| visited.contains(dir));
+        scan.untracked
+    }
+}
+
+struct Scan<'a> {
+    repo: &'a Repository,
+    workdir: &'a Path,
+    /// Directories modified after this aren't trusted from the cache.
+    recent: SystemTime,
+    visited: HashSet<Vec<u8>>,
+    untracked: Vec<(Vec<u8>, u32)>,
+}
+
+impl Scan<'_> {
+    /// Collect the untracked files under `rel`, rescanning it if it changed
+    /// or `force` says a parent's ignore rules did.
+    fn dir(
+        &mut self,
+        cache: &mut HashMap<Vec<u8>, CachedDir>,
+        rel: Vec<u8>,
+        force: bool,
+        tracked: &HashSet<Vec<u8>>,
+    ) {
+        let full = self.workdir.join(fs_path(&rel));
+        let Some(mtime) = std::fs::metadata(&full).and_then(|m| m.modified()).ok() else {
+            return;
+        };
+        let gitignore = stamp(&full.join(".gitignore"));
+        let cached = cache.get(&rel);
+        let reuse =
+            cached.is_some_and(|c| !force && c.mtime == Some(mtime) && c.gitignore == gitignore);
+        let force = force || cached.is_some_and(|c| c.gitignore != gitignore);
+        if !reuse {
+            let trusted = (mtime < self.recent).then_some(mtime);
+            // Kept even when untrusted, so a later `.gitignore` edit is
+            // still noticed.
+            let listed = self.list(&full, &rel, trusted, gitignore);
+            cache.insert(rel.clone(), listed);
+        }
+
+        let entry = &cache[&rel];
+        for (path, mode) in &entry.files {
+            let key = path.strip_suffix(b"/").unwrap_or(path);
+            if !tracked.contains(key) {
+                self.untracked.push((path.clone(), *mode));
+            }
+        }
+        let dirs = entry.dirs.clone();
+        self.visited.insert(rel);
+        for dir in dirs {
+            self.dir(cache, dir, force, tracked);
+        }
+    }
+
+    /// Read `full` and sort its entries into files and directories,
+    /// leaving out ignored ones.
+    fn list(
+        &self,
+        full: &Path,
+        rel: &[u8],
+        mtime: Option<SystemTime>,
+        gitignore: Stamp,
+    ) -> CachedDir {
+        let mut entry = CachedDir {
+            mtime,
+            gitignore,
+            files: Vec::new(),
+            dirs: Vec::new(),
+        };
+        let Ok(read) = std::fs::read_dir(full) else {
+            return entry;
+        };
+        for child in read.flatten() {
+            let name = child.file_name();
+            if name == ".git" {
+                continue;
+            }
+            let mut path = rel.to_vec();
+            if !path.is_empty() {
+                path.push(b'/');
+            }
+            path.extend_from_slice(&raw_path(&name));
+            if self.repo.is_path_ignored(fs_path(&path)).unwrap_or(false) {
+                continue;
+            }
+            let Ok(file_type) = child.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                let dir = child.path();
+                if dir.join(".git").exists() {
+                    // A nested repository is listed whole, as git does,
+                    // unless there's nothing in it besides `.git`.
+                    let has_content = std::fs::read_dir(&dir).is_ok_and(|mut read| {
+                        read.any(|e| e.is_ok_and(|e| e.file_name() != ".git"))
+                    });
+                    if has_content {
+                        path.push(b'/');
+                        entry.files.push((path, u32::from(git2::FileMode::Tree)));
+                    }
+                } else {
+                    entry.dirs.push(path);
+                }
+            } else {
+                entry.files.push((path, file_mode(&child)));
+            }
+        }
+        entry
+    }
+}
+
+/// Files of exclude patterns that apply to the whole repository.
+fn exclude_files(repo: &Repository, workdir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![repo.path().join("info").join("exclude")];
+    let configured = repo
+        .config()
+        .and_then(|config| config.get_path("core.excludesFile"));
+    if let Ok(path) = configured {
+        files.push(workdir.join(path));
+    } else if let Some(config_home) = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    {
+        files.push(config_home.join("git").join("ignore"));
+    }
+    files
+}
+
+fn stamp(path: &Path) -> Stamp {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+fn file_mode(entry: &std::fs::DirEntry) -> u32 {
+    let mode = match entry.metadata() {
+        Ok(meta) if meta.file_type().is_symlink() => git2::FileMode::Link,
+        Ok(meta) if is_executable(&meta) => git2::FileMode::BlobExecutable,
+        _ => git2::FileMode::Blob,
+    };
+    u32::from(mode)
+}
@@ -1,49 +1,297 @@
+use crate::fsmonitor::{self, Changes, FsmonitorClient};
 use anyhow::Result;
+use git2::Repository;
 use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::Path;
-use std::sync::mpsc::{channel, Receiver};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::time::Duration;
 
+/// How often git's fsmonitor is asked for changes.
+const FSMONITOR_INTERVAL: Duration = Duration::from_millis(300);
+
 pub enum WatcherEvent {
-    Changed,
+    /// Files changed at these absolute paths, which may be directories.
+    /// Empty when the backend couldn't say which.
+    Changed(Vec<PathBuf>),
+    /// The backend reported an error; events may have been lost.
+    Error,
+    /// The OS limit on watches was hit; new directories go unwatched.
+    LimitReached,
 }
 
 pub struct FileWatcher {
     _watcher: RecommendedWatcher,
     pub receiver: Receiver<WatcherEvent>,
+    /// Only the top-level directories are watched because the OS limit on
+    /// watches was reached.
+    pub limited: bool,
 }
 
 impl FileWatcher {
+    /// Watch `repo`'s working tree at `repo_path`, through the fsmonitor
+    /// `core.fsmonitor` configures if `use_fsmonitor` and it answers, or
+    /// else by watching the tree ourselves.
+    pub fn start(repo: &Repository, repo_path: &Path, use_fsmonitor: bool) -> Result<Self> {
+        if let Some(monitor) = fsmonitor::detect(repo).filter(|_| use_fsmonitor) {
+            match FsmonitorClient::new(monitor.clone(), repo_path) {
+                Ok(client) => {
+                    tracing::info!(?monitor, "watching through git's fsmonitor");
+                    return Self::with_fsmonitor(repo_path, client);
+                }
+                Err(e) => {
+                    tracing::warn!(error = %format!("{:#}", e), "fsmonitor unavailable; watching the tree");
+                }
+            }
+        }
+        Self::new(repo_path)
+    }
+
     pub fn new(repo_path: &Path) -> Result<Self> {
         let (tx, rx) = channel();
+        let mut watcher = event_watcher(tx)?;
 
-        let event_tx = tx.clone();
-        let mut watcher = RecommendedWatcher::new(
-            move |res: Result<notify::Event, notify::Error>| {
-                if res.is_ok() {
-                    let _ = event_tx.send(WatcherEvent::Changed);
-                }
-            },
-            Config::default().with_poll_interval(Duration::from_secs(2)),
-        )?;
+        let limited = match watcher.watch(repo_path, RecursiveMode::Recursive) {
+            Ok(()) => false,
+            Err(e) if is_watch_limit(&e) => {
+                tracing::warn!("watch limit reached; watching top-level directories only");
+                let _ = watcher.unwatch(repo_path);
+                watch_top_level(&mut watcher, repo_path);
+                true
+            }
+            Err(e) => return Err(e.into()),
+        };
+        watch_git_files(&mut watcher, repo_path);
 
-        watcher.watch(repo_path, RecursiveMode::Recursive)?;
+        Ok(Self {
+            _watcher: watcher,
+            receiver: rx,
+            limited,
+        })
+    }
 
-        let git_dir = repo_path.join(".git");
-        if git_dir.exists() {
-            let index_path = git_dir.join("index");
-            if index_path.exists() {
-                let _ = watcher.watch(&index_path, RecursiveMode::NonRecursive);
-            }
-            let head_path = git_dir.join("HEAD");
-            if head_path.exists() {
-                let _ = watcher.watch(&head_path, RecursiveMode::NonRecursive);
+    /// Take working tree changes from `client` rather than watching every
+    /// directory, which on a large tree is slow to set up and can exhaust
+    /// the OS limit. The index and HEAD are still watched directly.
+    fn with_fsmonitor(repo_path: &Path, mut client: FsmonitorClient) -> Result<Self> {
+        let (tx, rx) = channel();
+        let mut watcher = event_watcher(tx.clone())?;
+        watch_git_files(&mut watcher, repo_path);
+        let root = repo_path.to_path_buf();
+
+        std::thread::spawn(move || loop {
+            std::thread::sleep(FSMONITOR_INTERVAL);
+            let event = match client.query() {
+                Ok(changes) if changes.is_empty() => continue,
+                Ok(changes) => {
+                    tracing::debug!(?changes, "fsmonitor changes");
+                    match changes {
+                        Changes::Paths(paths) => {
+                            WatcherEvent::Changed(paths.iter().map(|p| root.join(p)).collect())
+                        }
+                        Changes::Everything => WatcherEvent::Changed(Vec::new()),
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(error = %format!("{:#}", e), "fsmonitor query failed");
+                    WatcherEvent::Error
+                }
+            };
+            if tx.send(event).is_err() {
+                break;
             }
-        }
+        });
 
         Ok(Self {
             _watcher: watcher,
             receiver: rx,
+            limited: false,
         })
     }
 }
+
+/// A watcher that forwards its events to `tx`, with nothing watched yet.
+fn event_watcher(tx: Sender<WatcherEvent>) -> notify::Result<RecommendedWatcher> {
+    RecommendedWatcher::new(
+        move |res: Result<notify::Event, notify::Error>| match res {
+            Ok(event) => {
+                tracing::debug!(kind = ?event.kind, paths = ?event.paths, "watcher event");
+                let paths = if event.need_rescan() {
+                    Vec::new()
+                } else {
+                    event.paths
+                };
+                let _ = tx.send(WatcherEvent::Changed(paths));
+            }
+            Err(e) if is_watch_limit(&e) => {
+                tracing::warn!(error = %e, "watch limit reached");
+                let _ = tx.send(WatcherEvent::LimitReached);
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "watcher error");
+                let _ = tx.send(WatcherEvent::Error);
+            }
+        },
+        Config::default().with_poll_interval(Duration::from_secs(2)),
+    )
+}
+
+/// Watch the index and HEAD, which git changes outside the working tree.
+fn watch_git_files(watcher: &mut RecommendedWatcher, repo_path: &Path) {
+    let git_dir = repo_path.join(".git");
+    if git_dir.exists() {
+        let index_path = git_dir.join("index");
+        if index_path.exists() {
+            let _ = watcher.watch(&index_path, RecursiveMode::NonRecursive);
+        }
+        let head_path = git_dir.join("HEAD");
+        if head_path.exists() {
+            let _ = watcher.watch(&head_path, RecursiveMode::NonRecursive);
+        }
+    }
+}
+
+/// Watch `repo_path` and the directories directly inside it, stopping
+/// quietly if the limit is hit again.
+fn watch_top_level(watcher: &mut RecommendedWatcher, repo_path: &Path) {
+    if watcher
+        .watch(repo_path, RecursiveMode::NonRecursive)
+        .is_err()
+    {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(repo_path) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+        if is_dir && entry.file_name() != ".git" {
+            if let Err(e) = watcher.watch(&entry.path(), RecursiveMode::NonRecursive) {
+                tracing::debug!(error = %e, "stopped adding top-level watches");
+                return;
+            }
+        }
+    }
+}
+
+/// Whether `error` means the OS ran out of watches (inotify's ENOSPC).
+fn is_watch_limit(error: &notify::Error) -> bool {
+    const ENOSPC: i32 = 28;
+    match &error.kind {
+        notify::ErrorKind::MaxFilesWatch => true,
+        notify::ErrorKind::Io(io) => cfg!(target_os = "linux") && io.raw_os_error() == Some(ENOSPC),
+        _ => false,
+    }
+}
+
+/// Filesystem types whose change notifications only cover local edits.
+const NETWORK_FS_TYPES: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "afs",
+    "9p",
+    "ceph",
+    "glusterfs",
+    "lustre",
+    "davfs",
+    "fuse.sshfs",
+    "fuse.rclone",
+    "fuse.s3fs",
+];
+
+/// The filesystem type of `path` if it is a network mount.
+pub fn network_filesystem(path: &Path) -> Option<String> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+    let path = path.canonicalize().ok()?;
+    mount_fs_type(&mounts, &path).filter(|fs_type| NETWORK_FS_TYPES.contains(&fs_type.as_str()))
+}
+
+/// The type of the innermost mount in `mounts` (`/proc/self/mounts`
+/// format) that contains `path`.
+fn mount_fs_type(mounts: &str, path: &Path) -> Option<String> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            let mount_point = PathBuf::from(unescape_mount_field(fields.next()?));
+            let fs_type = fields.next()?;
+            path.starts_with(&mount_point)
+                .then(|| (mount_point.components().count(), fs_type.to_string()))
+        })
+        // Later mounts shadow earlier ones on the same point.
+        .fold(
+            None,
+            |best: Option<(usize, String)>, (depth, fs_type)| match best {
+                Some((best_depth, _)) if best_depth > depth => best,
+                _ => Some((depth, fs_type)),
+            },
+        )
+        .map(|(_, fs_type)| fs_type)
+}
+
+/// Undo the octal escapes (`\040` for a space) used in mount fields.
+fn unescape_mount_field(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(pos) = rest.find('\\') {
+        out.push_str(&rest[..pos]);
+        let code = rest.get(pos + 1..pos + 4);
+        match code.and_then(|code| u8::from_str_radix(code, 8).ok()) {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[pos + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[pos + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTS: &str = "\
+/dev/sda1 / ext4 rw,relatime 0 0
+server:/export /home/me/work nfs4 rw,vers=4.2 0 0
+/dev/sdb1 /home/me/work/local ext4 rw 0 0
+me@host:/src /mnt/my\\040code fuse.sshfs rw 0 0
+";
+
+    #[test]
+    fn mount_fs_type_picks_innermost_mount() {
+        let fs = |path: &str| mount_fs_type(MOUNTS, Path::new(path));
+        assert_eq!(fs("/etc"), Some("ext4".to_string()));
+        assert_eq!(fs("/home/me/work/repo"), Some("nfs4".to_string()));
+        assert_eq!(fs("/home/me/work/local/repo"), Some("ext4".to_string()));
+        assert_eq!(fs("/home/me/workshop"), Some("ext4".to_string()));
+        assert_eq!(fs("/mnt/my code/repo"), Some("fuse.sshfs".to_string()));
+    }
+
+    #[test]
+    fn is_watch_limit_recognizes_enospc() {
+        assert!(is_watch_limit(&notify::Error::new(
+            notify::ErrorKind::MaxFilesWatch
+        )));
+        let enospc = notify::Error::io(std::io::Error::from_raw_os_error(28));
+        assert_eq!(is_watch_limit(&enospc), cfg!(target_os = "linux"));
+        assert!(!is_watch_limit(&notify::Error::path_not_found()));
+    }
+
+    #[test]
+    fn unescape_mount_field_decodes_octal() {
+        assert_eq!(unescape_mount_field("/mnt/a\\040b"), "/mnt/a b");
+        assert_eq!(unescape_mount_field("/mnt/tab\\011"), "/mnt/tab\t");
+        assert_eq!(unescape_mount_field("/mnt/odd\\x"), "/mnt/odd\\x");
+    }
+}
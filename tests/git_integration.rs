@@ -1,3 +1,5 @@
+use better_git_status::app::App;
+use better_git_status::config::{Config, PreviewConfig};
 use git2::{Repository, Signature};
 use std::fs;
 use std::path::Path;
@@ -53,6 +55,290 @@ impl TestRepo {
     fn path(&self) -> &Path {
         self.dir.path()
     }
+
+    /// An app on this repository with `config` applied over the defaults.
+    fn app(&self, config: &str) -> App {
+        let mut app = App::new(self.path().to_str().unwrap()).unwrap();
+        app.apply_config(Config::parse(config).unwrap());
+        app
+    }
+
+    /// Put `name` in conflict with the given base, our, and their contents
+    /// in index stages 1-3, as a merge would leave it.
+    fn conflict(&self, name: &str, stages: [Option<&str>; 3]) {
+        let mut index = self.repo.index().unwrap();
+        let _ = index.remove_path(Path::new(name));
+        for (stage, content) in (1u16..).zip(stages) {
+            let Some(content) = content else { continue };
+            let id = self.repo.blob(content.as_bytes()).unwrap();
+            index
+                .add(&git2::IndexEntry {
+                    ctime: git2::IndexTime::new(0, 0),
+                    mtime: git2::IndexTime::new(0, 0),
+                    dev: 0,
+                    ino: 0,
+                    mode: 0o100644,
+                    uid: 0,
+                    gid: 0,
+                    file_size: content.len() as u32,
+                    id,
+                    flags: (stage << 12) | name.len() as u16,
+                    flags_extended: 0,
+                    path: name.as_bytes().to_vec(),
+                })
+                .unwrap();
+        }
+        index.write().unwrap();
+    }
+}
+
+mod flash_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::keymap::Action;
+    use better_git_status::types::{FlashMessage, Modal};
+    use crossterm::event::KeyCode;
+    use std::time::{Duration, Instant};
+
+    fn app_with_flash(config: &str, flash: FlashMessage) -> (TestRepo, App) {
+        let test_repo = TestRepo::new();
+        let mut app = test_repo.app(config);
+        app.flash_message = Some(FlashMessage {
+            shown_at: Instant::now() - Duration::from_secs(5),
+            ..flash
+        });
+        (test_repo, app)
+    }
+
+    #[test]
+    fn success_flash_expires_after_configured_duration() {
+        let (_repo, mut app) = app_with_flash("", FlashMessage::success("Staged a.txt"));
+        app.check_flash_expiry();
+        assert!(app.flash_message.is_none());
+
+        let (_repo, mut app) = app_with_flash(
+            "[flash]\nduration_seconds = 10\n",
+            FlashMessage::success("Staged a.txt"),
+        );
+        app.check_flash_expiry();
+        assert!(app.flash_message.is_some());
+    }
+
+    #[test]
+    fn error_flash_is_sticky_by_default() {
+        let (_repo, mut app) = app_with_flash("", FlashMessage::error("Error: boom"));
+        app.check_flash_expiry();
+        assert!(app.flash_message.is_some());
+        app.clear_flash();
+        assert!(app.flash_message.is_none());
+    }
+
+    #[test]
+    fn message_history_keeps_flashes_newest_first() {
+        let test_repo = TestRepo::new();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.show_flash_success("Staged a.txt");
+        app.show_flash_error("Error: boom");
+        app.clear_flash();
+
+        assert!(app.perform_action(Action::Messages, (10, 80)));
+        let Some(Modal::Messages(panel)) = &app.modal else {
+            panic!("expected message panel");
+        };
+        let texts: Vec<&str> = panel.messages.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, vec!["Error: boom", "Staged a.txt"]);
+
+        app.handle_modal_key(KeyCode::Down);
+        assert!(matches!(&app.modal, Some(Modal::Messages(p)) if p.scroll == 1));
+        app.handle_modal_key(KeyCode::Esc);
+        assert!(app.modal.is_none());
+    }
+
+    #[test]
+    fn error_detail_opens_latest_error_with_paths() {
+        let test_repo = TestRepo::new();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+
+        app.perform_action(Action::ErrorDetails, (10, 80));
+        assert!(app.modal.is_none());
+        assert_eq!(
+            app.flash_message.as_ref().unwrap().text,
+            "No error details to show"
+        );
+
+        let error = anyhow::anyhow!("index locked").context("Failed to stage file: a.txt");
+        app.show_flash_error_detail(format!("Error: {}", error), &error, vec!["a.txt".into()]);
+        app.show_flash_success("Fetched origin");
+        app.clear_flash();
+
+        app.perform_action(Action::ErrorDetails, (10, 80));
+        let Some(Modal::ErrorDetail(detail)) = &app.modal else {
+            panic!("expected error detail popup");
+        };
+        assert_eq!(
+            detail.chain,
+            vec!["Failed to stage file: a.txt", "index locked"]
+        );
+        assert_eq!(detail.paths, vec!["a.txt"]);
+
+        app.handle_modal_key(KeyCode::Esc);
+        assert!(app.modal.is_none());
+    }
+
+    #[test]
+    fn message_history_is_bounded() {
+        let test_repo = TestRepo::new();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        for i in 0..150 {
+            app.show_flash_success(format!("message {}", i));
+        }
+        assert_eq!(app.message_history.len(), 100);
+        assert_eq!(app.message_history.front().unwrap().text, "message 50");
+    }
+
+    #[test]
+    fn error_flash_expires_when_not_sticky() {
+        let (_repo, mut app) = app_with_flash(
+            "[flash]\nsticky_errors = false\n",
+            FlashMessage::error("Error: boom"),
+        );
+        app.check_flash_expiry();
+        assert!(app.flash_message.is_none());
+    }
+}
+
+mod setup_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::config::{Config, ConfirmLevel, Theme};
+    use better_git_status::types::Modal;
+    use crossterm::event::KeyCode;
+
+    #[test]
+    fn setup_writes_and_applies_choices() {
+        let test_repo = TestRepo::new();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let config_dir = TempDir::new().unwrap();
+        let path = config_dir
+            .path()
+            .join("better-git-status")
+            .join("config.toml");
+
+        app.open_setup(path.clone());
+        app.handle_modal_key(KeyCode::Right);
+        app.handle_modal_key(KeyCode::Down);
+        app.handle_modal_key(KeyCode::Down);
+        app.handle_modal_key(KeyCode::Right);
+        app.handle_modal_key(KeyCode::Enter);
+
+        assert!(app.modal.is_none());
+        assert_eq!(app.config.ui.theme, Theme::Colorblind);
+        assert_eq!(app.config.confirm.level, ConfirmLevel::Destructive);
+        let written = Config::load_from(&path).unwrap();
+        assert_eq!(written, app.config);
+    }
+
+    #[test]
+    fn skipping_setup_writes_empty_config() {
+        let test_repo = TestRepo::new();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let config_dir = TempDir::new().unwrap();
+        let path = config_dir.path().join("config.toml");
+
+        app.open_setup(path.clone());
+        assert!(matches!(app.modal, Some(Modal::Setup(_))));
+        app.handle_modal_key(KeyCode::Esc);
+
+        assert!(app.modal.is_none());
+        assert_eq!(Config::load_from(&path).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn destructive_confirm_level_stages_all_without_prompt() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a");
+        test_repo.write_file("b.txt", "b");
+        let mut app = test_repo.app("[confirm]\nlevel = \"destructive\"\n");
+
+        app.show_discard_all_confirm();
+        assert!(app.confirm_prompt.is_some());
+        app.handle_confirm(false).unwrap();
+
+        app.show_stage_all_confirm();
+        assert!(app.confirm_prompt.is_none());
+        assert_eq!(app.staged_files.len(), 2);
+    }
+}
+
+mod config_reload_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::config::Config;
+    use better_git_status::keymap::{Action, Key};
+    use std::time::Instant;
+
+    #[test]
+    fn reload_config_applies_valid_file() {
+        let test_repo = TestRepo::new();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let config_dir = TempDir::new().unwrap();
+        let path = config_dir.path().join("config.toml");
+        fs::write(&path, "[ui]\nlow_bandwidth = true\n[keys]\nquit = \"Q\"\n").unwrap();
+
+        app.reload_config(&path);
+        assert!(app.config.ui.low_bandwidth);
+        assert_eq!(
+            app.resolve_key(Key::char('Q'), Instant::now()),
+            Some(Action::Quit)
+        );
+        assert_eq!(app.flash_message.as_ref().unwrap().text, "Config reloaded");
+    }
+
+    #[test]
+    fn reload_config_keeps_settings_when_invalid() {
+        let test_repo = TestRepo::new();
+        let mut app = test_repo.app("[flash]\nduration_seconds = 7\n");
+        let config_dir = TempDir::new().unwrap();
+        let path = config_dir.path().join("config.toml");
+        fs::write(&path, "[flash]\nduration_seconds = \"long\"\n").unwrap();
+
+        app.reload_config(&path);
+        assert_eq!(app.config.flash.duration_seconds, 7);
+        let flash = app.flash_message.as_ref().unwrap();
+        assert!(flash.is_error);
+        assert!(flash.text.starts_with("Config not reloaded"));
+        assert!(flash.detail.is_some());
+    }
+
+    #[test]
+    fn low_bandwidth_option_survives_reload() {
+        let test_repo = TestRepo::new();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.options.low_bandwidth = true;
+        app.apply_config(Config::default());
+        assert!(app.config.ui.low_bandwidth);
+    }
+}
+
+mod auto_select_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::types::DiffContent;
+
+    #[test]
+    fn select_first_file_opens_its_diff() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        assert!(app.selected.is_none());
+
+        app.select_first_file();
+        assert_eq!(
+            app.selected.as_ref().map(|(_, path)| &path[..]),
+            Some(&b"a.txt"[..])
+        );
+        assert!(!matches!(app.current_diff, DiffContent::Empty));
+    }
 }
 
 mod status_tests {
@@ -65,7 +351,7 @@ mod status_tests {
         let test_repo = TestRepo::new();
         test_repo.write_file("file.txt", "content\n");
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
 
         assert_eq!(status.unstaged_files.len(), 1);
         assert_eq!(status.unstaged_files[0].status, FileStatus::Untracked);
@@ -79,7 +365,7 @@ mod status_tests {
         test_repo.write_file("file.txt", "content\n");
         test_repo.stage("file.txt");
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
 
         assert_eq!(status.staged_files.len(), 1);
         assert_eq!(status.staged_files[0].status, FileStatus::Added);
@@ -94,7 +380,7 @@ mod status_tests {
         test_repo.commit("initial");
         test_repo.write_file("file.txt", "modified\n");
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
 
         assert_eq!(status.unstaged_files.len(), 1);
         assert_eq!(status.unstaged_files[0].status, FileStatus::Modified);
@@ -110,7 +396,7 @@ mod status_tests {
         test_repo.write_file("file.txt", "modified\n");
         test_repo.stage("file.txt");
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
 
         assert_eq!(status.staged_files.len(), 1);
         assert_eq!(status.staged_files[0].status, FileStatus::Modified);
@@ -125,7 +411,7 @@ mod status_tests {
         test_repo.commit("initial");
         fs::remove_file(test_repo.path().join("file.txt")).unwrap();
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
 
         assert_eq!(status.unstaged_files.len(), 1);
         assert_eq!(status.unstaged_files[0].status, FileStatus::Deleted);
@@ -149,7 +435,7 @@ mod status_tests {
         index.add_path(Path::new("new.txt")).unwrap();
         index.write().unwrap();
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
 
         assert_eq!(status.staged_files.len(), 1);
         assert_eq!(status.staged_files[0].status, FileStatus::Renamed);
@@ -162,11 +448,11 @@ mod status_tests {
         let test_repo = TestRepo::new();
         fs::write(
             test_repo.path().join("binary.bin"),
-            &[0x00, 0x01, 0x02, 0x03],
+            [0x00, 0x01, 0x02, 0x03],
         )
         .unwrap();
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
 
         assert_eq!(status.unstaged_files.len(), 1);
         assert!(status.unstaged_files[0].is_binary);
@@ -182,19 +468,67 @@ mod status_tests {
         test_repo.stage("file.txt");
         test_repo.write_file("file.txt", "unstaged change\n");
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
 
         assert_eq!(status.staged_files.len(), 1);
         assert_eq!(status.unstaged_files.len(), 1);
         assert_eq!(status.staged_files[0].path, "file.txt");
         assert_eq!(status.unstaged_files[0].path, "file.txt");
     }
+
+    #[test]
+    fn get_status_flags_whitespace_errors() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("clean.txt", "a\n");
+        test_repo.write_file("dirty.txt", "a\n");
+        test_repo.stage("clean.txt");
+        test_repo.stage("dirty.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("clean.txt", "a\nb\n");
+        test_repo.write_file("dirty.txt", "a\nb  \n");
+        test_repo.write_file("new.txt", "  \tindent\n");
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        let flagged: Vec<(&str, bool)> = status
+            .unstaged_files
+            .iter()
+            .map(|f| (f.path.as_str(), f.whitespace_errors))
+            .collect();
+        assert_eq!(
+            flagged,
+            vec![("clean.txt", false), ("dirty.txt", true), ("new.txt", true)]
+        );
+    }
+
+    #[test]
+    fn get_status_type_changed_file() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("target.txt", "target\n");
+        test_repo.write_file("link", "plain file\n");
+        test_repo.stage("target.txt");
+        test_repo.stage("link");
+        test_repo.commit("initial");
+        std::fs::remove_file(test_repo.path().join("link")).unwrap();
+        std::os::unix::fs::symlink("target.txt", test_repo.path().join("link")).unwrap();
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(status.unstaged_files.len(), 1);
+        assert_eq!(status.unstaged_files[0].status, FileStatus::TypeChanged);
+        assert!(!status.unstaged_files[0].is_submodule);
+        assert!(status.staged_files.is_empty());
+
+        test_repo.stage("link");
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(status.staged_files.len(), 1);
+        assert_eq!(status.staged_files[0].status, FileStatus::TypeChanged);
+        assert!(status.unstaged_files.is_empty());
+    }
 }
 
 mod diff_tests {
     use super::*;
-    use better_git_status::git::{get_diff, get_untracked_diff};
-    use better_git_status::types::{DiffContent, DiffLineKind, Section};
+    use better_git_status::git::{get_binary_info, get_diff, get_untracked_preview, DiffFlags};
+    use better_git_status::types::{BinaryChange, DiffContent, DiffLineKind, Section};
 
     #[test]
     fn get_diff_staged_shows_changes() {
@@ -205,7 +539,14 @@ mod diff_tests {
         test_repo.write_file("file.txt", "line1\nline2\n");
         test_repo.stage("file.txt");
 
-        let diff = get_diff(&test_repo.repo, "file.txt", None, Section::Staged);
+        let diff = get_diff(
+            &test_repo.repo,
+            "file.txt",
+            None,
+            Section::Staged,
+            DiffFlags::default(),
+            PreviewConfig::default(),
+        );
 
         match diff {
             DiffContent::Text(lines) => {
@@ -224,7 +565,14 @@ mod diff_tests {
         test_repo.commit("initial");
         test_repo.write_file("file.txt", "line1\nline2\n");
 
-        let diff = get_diff(&test_repo.repo, "file.txt", None, Section::Unstaged);
+        let diff = get_diff(
+            &test_repo.repo,
+            "file.txt",
+            None,
+            Section::Unstaged,
+            DiffFlags::default(),
+            PreviewConfig::default(),
+        );
 
         match diff {
             DiffContent::Text(lines) => {
@@ -235,135 +583,600 @@ mod diff_tests {
     }
 
     #[test]
-    fn get_untracked_diff_shows_all_lines() {
+    fn get_diff_decodes_latin1_with_detected_encoding() {
+        let test_repo = TestRepo::new();
+        fs::write(test_repo.path().join("notes.txt"), b"caf\xe9 cr\xe8me\n").unwrap();
+        test_repo.stage("notes.txt");
+        test_repo.commit("initial");
+        fs::write(
+            test_repo.path().join("notes.txt"),
+            b"caf\xe9 cr\xe8me br\xfbl\xe9e\n",
+        )
+        .unwrap();
+
+        let diff = get_diff(
+            &test_repo.repo,
+            "notes.txt",
+            None,
+            Section::Unstaged,
+            DiffFlags::default(),
+            PreviewConfig::default(),
+        );
+        let DiffContent::Text(lines) = diff else {
+            panic!("expected decoded text, got {:?}", diff);
+        };
+        assert_eq!(lines[0].content, "Encoding: windows-1252 (detected)");
+        assert!(lines
+            .iter()
+            .any(|l| l.kind == DiffLineKind::Added && l.content == "café crème brûlée"));
+    }
+
+    #[test]
+    fn get_diff_honors_working_tree_encoding_attribute() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file(".gitattributes", "*.txt working-tree-encoding=Shift_JIS\n");
+        // "日本" in Shift_JIS
+        fs::write(test_repo.path().join("jp.txt"), b"\x93\xfa\x96\x7b\n").unwrap();
+        test_repo.stage("jp.txt");
+        test_repo.commit("initial");
+        fs::write(
+            test_repo.path().join("jp.txt"),
+            b"\x93\xfa\x96\x7b\x8c\xea\n",
+        )
+        .unwrap();
+
+        let diff = get_diff(
+            &test_repo.repo,
+            "jp.txt",
+            None,
+            Section::Unstaged,
+            DiffFlags::default(),
+            PreviewConfig::default(),
+        );
+        let DiffContent::Text(lines) = diff else {
+            panic!("expected decoded text, got {:?}", diff);
+        };
+        assert_eq!(
+            lines[0].content,
+            "Encoding: Shift_JIS (working-tree-encoding)"
+        );
+        assert!(lines
+            .iter()
+            .any(|l| l.kind == DiffLineKind::Added && l.content == "日本語"));
+    }
+
+    #[test]
+    fn get_untracked_preview_decodes_legacy_encoding() {
         let test_repo = TestRepo::new();
-        test_repo.write_file("new.txt", "line1\nline2\nline3\n");
+        fs::write(test_repo.path().join("old.txt"), b"na\xefve fa\xe7ade\n").unwrap();
+
+        let diff = get_untracked_preview(&test_repo.repo, "old.txt");
+        let DiffContent::Preview(preview) = diff else {
+            panic!("expected preview, got {:?}", diff);
+        };
+        assert!(preview.file_type.ends_with("windows-1252 (detected)"));
+        let text: String = preview.lines[0].iter().map(|(_, t)| t.as_str()).collect();
+        assert_eq!(text.trim_end(), "naïve façade");
+    }
+
+    #[test]
+    fn get_untracked_preview_shows_content() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("new.rs", "fn a() {}\nfn b() {}\nfn c() {}\n");
 
-        let diff = get_untracked_diff(&test_repo.repo, "new.txt");
+        let diff = get_untracked_preview(&test_repo.repo, "new.rs");
 
         match diff {
-            DiffContent::Text(lines) => {
-                let added_count = lines
-                    .iter()
-                    .filter(|l| l.kind == DiffLineKind::Added)
-                    .count();
-                assert_eq!(added_count, 3);
+            DiffContent::Preview(preview) => {
+                assert_eq!(preview.lines.len(), 3);
+                assert_eq!(preview.size, 30);
+                assert_eq!(preview.file_type, "Rust");
             }
-            _ => panic!("Expected Text diff"),
+            _ => panic!("Expected Preview"),
         }
     }
 
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
     #[test]
-    fn get_diff_deleted_shows_removed_lines() {
+    fn get_diff_binary_reports_sizes_and_type() {
         let test_repo = TestRepo::new();
-        test_repo.write_file("file.txt", "line1\nline2\n");
-        test_repo.stage("file.txt");
+        std::fs::write(test_repo.path().join("icon.png"), PNG).unwrap();
+        test_repo.stage("icon.png");
         test_repo.commit("initial");
-        test_repo.write_file("file.txt", "line1\n");
-        test_repo.stage("file.txt");
-
-        let diff = get_diff(&test_repo.repo, "file.txt", None, Section::Staged);
+        std::fs::write(
+            test_repo.path().join("icon.png"),
+            [PNG, b"\0\0\0\0"].concat(),
+        )
+        .unwrap();
+        test_repo.stage("icon.png");
 
-        match diff {
-            DiffContent::Text(lines) => {
-                assert!(lines.iter().any(|l| l.kind == DiffLineKind::Deleted));
+        match get_diff(
+            &test_repo.repo,
+            "icon.png",
+            None,
+            Section::Staged,
+            DiffFlags::default(),
+            PreviewConfig::default(),
+        ) {
+            DiffContent::Binary(info) => {
+                assert_eq!(info.change, BinaryChange::Modified);
+                assert_eq!(info.old_size, Some(PNG.len() as u64));
+                assert_eq!(info.new_size, Some(PNG.len() as u64 + 4));
+                assert_eq!(info.mime_type, "image/png");
             }
-            _ => panic!("Expected Text diff"),
+            _ => panic!("Expected Binary"),
         }
     }
-}
-
-mod stage_unstage_tests {
-    use super::*;
-    use better_git_status::git::{get_status, stage_all, stage_files, unstage_all, unstage_files};
-    use better_git_status::types::FileStatus;
 
     #[test]
-    fn stage_files_adds_to_index() {
+    fn get_binary_info_untracked_is_added() {
         let test_repo = TestRepo::new();
-        test_repo.write_file("file.txt", "content\n");
+        std::fs::write(test_repo.path().join("data.bin"), b"\0\x01\x02").unwrap();
 
-        let status = get_status(&test_repo.repo).unwrap();
-        assert_eq!(status.unstaged_files.len(), 1);
-        assert!(status.staged_files.is_empty());
+        let info = get_binary_info(
+            &test_repo.repo,
+            "data.bin",
+            None,
+            Section::Unstaged,
+            PreviewConfig::default(),
+        );
+        assert_eq!(info.change, BinaryChange::Added);
+        assert_eq!(info.old_size, None);
+        assert_eq!(info.new_size, Some(3));
+        assert_eq!(info.mime_type, "application/octet-stream");
+    }
 
-        stage_files(&test_repo.repo, &["file.txt".to_string()]).unwrap();
+    #[cfg(unix)]
+    #[test]
+    fn status_reports_file_modes() {
+        use std::os::unix::fs::PermissionsExt;
 
-        let status = get_status(&test_repo.repo).unwrap();
-        assert!(status.unstaged_files.is_empty());
-        assert_eq!(status.staged_files.len(), 1);
-        assert_eq!(status.staged_files[0].status, FileStatus::Added);
+        let test_repo = TestRepo::new();
+        test_repo.write_file("run.sh", "echo hi\n");
+        test_repo.stage("run.sh");
+        test_repo.commit("initial");
+        let make_executable = |name: &str| {
+            let path = test_repo.path().join(name);
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        make_executable("run.sh");
+        test_repo.write_file("new.sh", "echo new\n");
+        make_executable("new.sh");
+
+        let status =
+            better_git_status::git::get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        let file = |path: &str| {
+            status
+                .unstaged_files
+                .iter()
+                .find(|f| f.path == path)
+                .unwrap()
+        };
+        assert_eq!(file("run.sh").old_mode, 0o100644);
+        assert_eq!(file("run.sh").mode, 0o100755);
+        assert!(file("run.sh").mode_only());
+        assert!(file("new.sh").is_executable());
+        assert!(!file("new.sh").mode_only());
+        assert!(file("new.sh").mtime.is_some());
+        assert_eq!(file("new.sh").size, Some(9));
+
+        test_repo.write_file("run.sh", "echo bye\n");
+        let status =
+            better_git_status::git::get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert!(!status.unstaged_files[0].mode_only());
     }
 
     #[test]
-    fn stage_files_handles_deleted_file() {
+    fn get_diff_mode_only_change() {
+        use std::os::unix::fs::PermissionsExt;
+
         let test_repo = TestRepo::new();
-        test_repo.write_file("file.txt", "content\n");
-        test_repo.stage("file.txt");
+        test_repo.write_file("run.sh", "echo hi\n");
+        test_repo.stage("run.sh");
         test_repo.commit("initial");
-        fs::remove_file(test_repo.path().join("file.txt")).unwrap();
+        let path = test_repo.path().join("run.sh");
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
 
-        stage_files(&test_repo.repo, &["file.txt".to_string()]).unwrap();
+        match get_diff(
+            &test_repo.repo,
+            "run.sh",
+            None,
+            Section::Unstaged,
+            DiffFlags::default(),
+            PreviewConfig::default(),
+        ) {
+            DiffContent::ModeChange { old_mode, new_mode } => {
+                assert_eq!(old_mode, 0o100644);
+                assert_eq!(new_mode, 0o100755);
+            }
+            other => panic!("Expected ModeChange, got {:?}", other),
+        }
 
-        let status = get_status(&test_repo.repo).unwrap();
-        assert!(status.unstaged_files.is_empty());
-        assert_eq!(status.staged_files.len(), 1);
-        assert_eq!(status.staged_files[0].status, FileStatus::Deleted);
+        test_repo.write_file("run.sh", "echo bye\n");
+        assert!(matches!(
+            get_diff(
+                &test_repo.repo,
+                "run.sh",
+                None,
+                Section::Unstaged,
+                DiffFlags::default(),
+                PreviewConfig::default()
+            ),
+            DiffContent::Text(_)
+        ));
     }
 
     #[test]
-    fn unstage_files_removes_from_index() {
-        let test_repo = TestRepo::new();
-        test_repo.write_file("file.txt", "content\n");
-        test_repo.stage("file.txt");
+    fn get_type_change_describes_both_sides() {
+        use better_git_status::git::get_type_change;
 
-        let status = get_status(&test_repo.repo).unwrap();
-        assert_eq!(status.staged_files.len(), 1);
+        let test_repo = TestRepo::new();
+        test_repo.write_file("link", "plain file\n");
+        test_repo.stage("link");
+        test_repo.commit("initial");
+        std::fs::remove_file(test_repo.path().join("link")).unwrap();
+        std::os::unix::fs::symlink("elsewhere", test_repo.path().join("link")).unwrap();
 
-        unstage_files(&test_repo.repo, &["file.txt".to_string()]).unwrap();
+        match get_type_change(&test_repo.repo, "link", Section::Unstaged) {
+            DiffContent::TypeChange { old, new } => {
+                assert_eq!(old.to_string(), "regular file");
+                assert_eq!(new.to_string(), "symlink to elsewhere");
+            }
+            other => panic!("Expected TypeChange, got {:?}", other),
+        }
 
-        let status = get_status(&test_repo.repo).unwrap();
-        assert!(status.staged_files.is_empty());
-        assert_eq!(status.unstaged_files.len(), 1);
-        assert_eq!(status.unstaged_files[0].status, FileStatus::Untracked);
+        test_repo.stage("link");
+        match get_type_change(&test_repo.repo, "link", Section::Staged) {
+            DiffContent::TypeChange { new, .. } => {
+                assert_eq!(new.to_string(), "symlink to elsewhere")
+            }
+            other => panic!("Expected TypeChange, got {:?}", other),
+        }
     }
 
     #[test]
-    fn unstage_files_resets_modified() {
+    fn line_ending_conversion_is_labelled_and_can_be_ignored() {
+        use better_git_status::git::get_status;
+        use better_git_status::types::EolChange;
+
         let test_repo = TestRepo::new();
-        test_repo.write_file("file.txt", "original\n");
+        test_repo.write_file("file.txt", "one\ntwo\nthree\n");
         test_repo.stage("file.txt");
         test_repo.commit("initial");
-        test_repo.write_file("file.txt", "modified\n");
-        test_repo.stage("file.txt");
+        test_repo.write_file("file.txt", "one\r\ntwo\r\nthree\r\n");
 
-        let status = get_status(&test_repo.repo).unwrap();
-        assert_eq!(status.staged_files.len(), 1);
-        assert!(status.unstaged_files.is_empty());
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(
+            status.unstaged_files[0].eol_change,
+            Some(EolChange::LfToCrlf)
+        );
 
-        unstage_files(&test_repo.repo, &["file.txt".to_string()]).unwrap();
+        match get_diff(
+            &test_repo.repo,
+            "file.txt",
+            None,
+            Section::Unstaged,
+            DiffFlags::default(),
+            PreviewConfig::default(),
+        ) {
+            DiffContent::Text(lines) => {
+                assert_eq!(lines[0].content, "Line endings changed: LF->CRLF")
+            }
+            other => panic!("Expected Text, got {:?}", other),
+        }
 
-        let status = get_status(&test_repo.repo).unwrap();
-        assert!(status.staged_files.is_empty());
-        assert_eq!(status.unstaged_files.len(), 1);
-        assert_eq!(status.unstaged_files[0].status, FileStatus::Modified);
+        let flags = DiffFlags { ignore_eol: true };
+        match get_diff(
+            &test_repo.repo,
+            "file.txt",
+            None,
+            Section::Unstaged,
+            flags,
+            PreviewConfig::default(),
+        ) {
+            DiffContent::Text(lines) => {
+                assert!(lines.iter().all(|l| l.kind == DiffLineKind::Header));
+                assert!(lines.last().unwrap().content.contains("hidden"));
+            }
+            other => panic!("Expected Text, got {:?}", other),
+        }
     }
 
-    #[test]
-    fn stage_all_stages_all_unstaged() {
+    /// A repository where `*.bin` files go through a textconv driver that
+    /// strips NUL bytes.
+    #[cfg(unix)]
+    fn textconv_repo() -> TestRepo {
         let test_repo = TestRepo::new();
-        test_repo.write_file("file1.txt", "content1\n");
-        test_repo.write_file("file2.txt", "content2\n");
-
-        let status = get_status(&test_repo.repo).unwrap();
-        assert_eq!(status.unstaged_files.len(), 2);
-
-        let paths = stage_all(&test_repo.repo).unwrap();
-        assert_eq!(paths.len(), 2);
-
-        let status = get_status(&test_repo.repo).unwrap();
-        assert!(status.unstaged_files.is_empty());
-        assert_eq!(status.staged_files.len(), 2);
+        test_repo
+            .repo
+            .config()
+            .unwrap()
+            .set_str("diff.strip.textconv", "tr -d '\\000' <")
+            .unwrap();
+        test_repo.write_file(".gitattributes", "*.bin diff=strip\n");
+        test_repo.stage(".gitattributes");
+        test_repo.commit("attributes");
+        test_repo
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn textconv_turns_binary_diff_into_text() {
+        use better_git_status::git::{get_file_diff, get_status};
+
+        let test_repo = textconv_repo();
+        test_repo.write_file("data.bin", "one\0\ntwo\0\n");
+        test_repo.stage("data.bin");
+        test_repo.commit("data");
+        test_repo.write_file("data.bin", "one\0\nTWO\0\n");
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        let file = &status.unstaged_files[0];
+        assert!(file.is_binary);
+        let diff = get_file_diff(
+            &test_repo.repo,
+            file,
+            Section::Unstaged,
+            DiffFlags::default(),
+            false,
+            PreviewConfig::default(),
+        );
+        let DiffContent::Text(lines) = diff else {
+            panic!("Expected Text, got {:?}", diff);
+        };
+        assert_eq!(lines[0].content, "Converted to text by textconv (strip)");
+        let changed: Vec<_> = lines
+            .iter()
+            .filter(|l| matches!(l.kind, DiffLineKind::Added | DiffLineKind::Deleted))
+            .map(|l| (l.kind, l.content.as_str(), l.new_line_number))
+            .collect();
+        assert_eq!(
+            changed,
+            [
+                (DiffLineKind::Deleted, "two", None),
+                (DiffLineKind::Added, "TWO", Some(2))
+            ]
+        );
+
+        // The hexdump toggle still shows the raw bytes.
+        let diff = get_file_diff(
+            &test_repo.repo,
+            file,
+            Section::Unstaged,
+            DiffFlags::default(),
+            true,
+            PreviewConfig::default(),
+        );
+        assert!(matches!(diff, DiffContent::Hexdump(_)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn textconv_previews_untracked_binary_and_falls_back_on_failure() {
+        use better_git_status::git::{get_file_diff, get_status};
+
+        let test_repo = textconv_repo();
+        test_repo.write_file("new.bin", "hello\0\n");
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        let file = &status.unstaged_files[0];
+        let diff = get_file_diff(
+            &test_repo.repo,
+            file,
+            Section::Unstaged,
+            DiffFlags::default(),
+            false,
+            PreviewConfig::default(),
+        );
+        let DiffContent::Preview(preview) = diff else {
+            panic!("Expected Preview, got {:?}", diff);
+        };
+        assert_eq!(preview.file_type, "Text via textconv (strip)");
+        assert_eq!(preview.lines.len(), 1);
+
+        test_repo
+            .repo
+            .config()
+            .unwrap()
+            .set_str("diff.strip.textconv", "false")
+            .unwrap();
+        let diff = get_file_diff(
+            &test_repo.repo,
+            file,
+            Section::Unstaged,
+            DiffFlags::default(),
+            false,
+            PreviewConfig::default(),
+        );
+        assert!(matches!(diff, DiffContent::Binary(_)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn textconv_that_hangs_times_out() {
+        use better_git_status::git::{get_file_diff, get_status};
+
+        let test_repo = textconv_repo();
+        test_repo
+            .repo
+            .config()
+            .unwrap()
+            .set_str("diff.strip.textconv", "sleep 60; cat")
+            .unwrap();
+        test_repo.write_file("new.bin", "hello\0\n");
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        let started = std::time::Instant::now();
+        let diff = get_file_diff(
+            &test_repo.repo,
+            &status.unstaged_files[0],
+            Section::Unstaged,
+            DiffFlags::default(),
+            false,
+            PreviewConfig::default(),
+        );
+        assert!(matches!(diff, DiffContent::Binary(_)));
+        assert!(started.elapsed() < std::time::Duration::from_secs(30));
+    }
+
+    #[test]
+    fn text_auto_crlf_checkout_is_not_a_change() {
+        use better_git_status::git::get_status;
+
+        let test_repo = TestRepo::new();
+        test_repo.write_file(".gitattributes", "* text=auto\n");
+        test_repo.write_file("file.txt", "one\ntwo\nthree\n");
+        test_repo.stage(".gitattributes");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("file.txt", "one\r\ntwo\r\nthree\r\n");
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert!(status.unstaged_files.is_empty());
+        assert_eq!(status.unstaged_count, 0);
+
+        test_repo.write_file("file.txt", "one\r\nTWO\r\nthree\r\n");
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(status.unstaged_files[0].added_lines, Some(1));
+        assert_eq!(status.unstaged_files[0].deleted_lines, Some(1));
+        assert_eq!(status.unstaged_files[0].eol_change, None);
+        match get_diff(
+            &test_repo.repo,
+            "file.txt",
+            None,
+            Section::Unstaged,
+            DiffFlags::default(),
+            PreviewConfig::default(),
+        ) {
+            DiffContent::Text(lines) => {
+                let changed: Vec<_> = lines
+                    .iter()
+                    .filter(|l| matches!(l.kind, DiffLineKind::Added | DiffLineKind::Deleted))
+                    .map(|l| l.content.as_str())
+                    .collect();
+                assert_eq!(changed, ["two", "TWO"]);
+            }
+            other => panic!("Expected Text, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn get_diff_deleted_shows_removed_lines() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "line1\nline2\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("file.txt", "line1\n");
+        test_repo.stage("file.txt");
+
+        let diff = get_diff(
+            &test_repo.repo,
+            "file.txt",
+            None,
+            Section::Staged,
+            DiffFlags::default(),
+            PreviewConfig::default(),
+        );
+
+        match diff {
+            DiffContent::Text(lines) => {
+                assert!(lines.iter().any(|l| l.kind == DiffLineKind::Deleted));
+            }
+            _ => panic!("Expected Text diff"),
+        }
+    }
+}
+
+mod stage_unstage_tests {
+    use super::*;
+    use better_git_status::git::{get_status, stage_all, stage_files, unstage_all, unstage_files};
+    use better_git_status::types::FileStatus;
+
+    #[test]
+    fn stage_files_adds_to_index() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(status.unstaged_files.len(), 1);
+        assert!(status.staged_files.is_empty());
+
+        stage_files(&test_repo.repo, &["file.txt".to_string()]).unwrap();
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert!(status.unstaged_files.is_empty());
+        assert_eq!(status.staged_files.len(), 1);
+        assert_eq!(status.staged_files[0].status, FileStatus::Added);
+    }
+
+    #[test]
+    fn stage_files_handles_deleted_file() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+        fs::remove_file(test_repo.path().join("file.txt")).unwrap();
+
+        stage_files(&test_repo.repo, &["file.txt".to_string()]).unwrap();
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert!(status.unstaged_files.is_empty());
+        assert_eq!(status.staged_files.len(), 1);
+        assert_eq!(status.staged_files[0].status, FileStatus::Deleted);
+    }
+
+    #[test]
+    fn unstage_files_removes_from_index() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(status.staged_files.len(), 1);
+
+        unstage_files(&test_repo.repo, &["file.txt".to_string()]).unwrap();
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert!(status.staged_files.is_empty());
+        assert_eq!(status.unstaged_files.len(), 1);
+        assert_eq!(status.unstaged_files[0].status, FileStatus::Untracked);
+    }
+
+    #[test]
+    fn unstage_files_resets_modified() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "original\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("file.txt", "modified\n");
+        test_repo.stage("file.txt");
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(status.staged_files.len(), 1);
+        assert!(status.unstaged_files.is_empty());
+
+        unstage_files(&test_repo.repo, &["file.txt".to_string()]).unwrap();
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert!(status.staged_files.is_empty());
+        assert_eq!(status.unstaged_files.len(), 1);
+        assert_eq!(status.unstaged_files[0].status, FileStatus::Modified);
+    }
+
+    #[test]
+    fn stage_all_stages_all_unstaged() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file1.txt", "content1\n");
+        test_repo.write_file("file2.txt", "content2\n");
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(status.unstaged_files.len(), 2);
+
+        let paths = stage_all(&test_repo.repo).unwrap();
+        assert_eq!(paths.len(), 2);
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert!(status.unstaged_files.is_empty());
+        assert_eq!(status.staged_files.len(), 2);
     }
 
     #[test]
@@ -374,86 +1187,2894 @@ mod stage_unstage_tests {
         test_repo.stage("file1.txt");
         test_repo.stage("file2.txt");
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         assert_eq!(status.staged_files.len(), 2);
 
         let paths = unstage_all(&test_repo.repo).unwrap();
         assert_eq!(paths.len(), 2);
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         assert!(status.staged_files.is_empty());
         assert_eq!(status.unstaged_files.len(), 2);
     }
 
     #[test]
-    fn stage_multiple_files() {
-        let test_repo = TestRepo::new();
-        test_repo.write_file("file1.txt", "content1\n");
-        test_repo.write_file("file2.txt", "content2\n");
-        test_repo.write_file("file3.txt", "content3\n");
+    fn stage_multiple_files() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file1.txt", "content1\n");
+        test_repo.write_file("file2.txt", "content2\n");
+        test_repo.write_file("file3.txt", "content3\n");
+
+        stage_files(
+            &test_repo.repo,
+            &["file1.txt".to_string(), "file3.txt".to_string()],
+        )
+        .unwrap();
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(status.staged_files.len(), 2);
+        assert_eq!(status.unstaged_files.len(), 1);
+        assert_eq!(status.unstaged_files[0].path, "file2.txt");
+    }
+}
+
+mod line_range_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::git::{apply_changed_lines, LineOp};
+    use better_git_status::keymap::{Action, Key};
+    use better_git_status::types::{DiffContent, DiffLine, DiffLineKind, Focus, Section};
+    use std::time::Instant;
+
+    const OLD: &str = "one\ntwo\nthree\nfour\n";
+    const NEW: &str = "one\nTWO\nthree\nfour\nfive\n";
+
+    /// A committed file with an unstaged edit whose changes are, in
+    /// order: -two, +TWO, +five.
+    fn edited_repo() -> TestRepo {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", OLD);
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("file.txt", NEW);
+        test_repo
+    }
+
+    fn index_content(test_repo: &TestRepo) -> String {
+        let mut index = test_repo.repo.index().unwrap();
+        index.read(true).unwrap();
+        let entry = index.get_path(Path::new("file.txt"), 0).unwrap();
+        let blob = test_repo.repo.find_blob(entry.id).unwrap();
+        String::from_utf8(blob.content().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn stage_some_lines_leaves_the_rest_unstaged() {
+        let test_repo = edited_repo();
+
+        let applied =
+            apply_changed_lines(&test_repo.repo, b"file.txt", None, &[2], LineOp::Stage).unwrap();
+
+        assert_eq!(applied, 1);
+        assert_eq!(index_content(&test_repo), "one\ntwo\nthree\nfour\nfive\n");
+        assert_eq!(
+            fs::read_to_string(test_repo.path().join("file.txt")).unwrap(),
+            NEW
+        );
+    }
+
+    #[test]
+    fn unstage_some_lines_keeps_the_rest_staged() {
+        let test_repo = edited_repo();
+        test_repo.stage("file.txt");
+
+        apply_changed_lines(&test_repo.repo, b"file.txt", None, &[0, 1], LineOp::Unstage).unwrap();
+
+        assert_eq!(index_content(&test_repo), "one\ntwo\nthree\nfour\nfive\n");
+    }
+
+    #[test]
+    fn discard_some_lines_keeps_crlf_checkout_line_endings() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file(".gitattributes", "*.txt text eol=crlf\n");
+        test_repo.write_file("file.txt", OLD);
+        test_repo.stage(".gitattributes");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("file.txt", &NEW.replace('\n', "\r\n"));
+
+        apply_changed_lines(&test_repo.repo, b"file.txt", None, &[0, 1], LineOp::Discard).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(test_repo.path().join("file.txt")).unwrap(),
+            "one\r\ntwo\r\nthree\r\nfour\r\nfive\r\n"
+        );
+    }
+
+    #[test]
+    fn discard_some_lines_rewrites_the_working_file() {
+        let test_repo = edited_repo();
+
+        apply_changed_lines(&test_repo.repo, b"file.txt", None, &[0, 1], LineOp::Discard).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(test_repo.path().join("file.txt")).unwrap(),
+            "one\ntwo\nthree\nfour\nfive\n"
+        );
+        assert_eq!(index_content(&test_repo), OLD);
+    }
+
+    #[test]
+    fn picking_every_change_stages_the_whole_file() {
+        let test_repo = edited_repo();
+
+        apply_changed_lines(
+            &test_repo.repo,
+            b"file.txt",
+            None,
+            &[0, 1, 2],
+            LineOp::Stage,
+        )
+        .unwrap();
+
+        assert_eq!(index_content(&test_repo), NEW);
+    }
+
+    #[test]
+    fn visual_selection_stages_and_copies_a_range() {
+        let test_repo = edited_repo();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let page = (20, 80);
+        app.perform_action(Action::Select, page);
+
+        // Starts on the first change, "-two"; extend over "+TWO".
+        assert!(app.perform_action(Action::VisualSelect, page));
+        let down = app.resolve_key(Key::char('j'), Instant::now()).unwrap();
+        assert!(app.perform_action(down, page));
+        let selection = app.diff_selection().unwrap();
+        assert_eq!(selection.range().count(), 2);
+
+        assert!(app.perform_action(Action::Copy, page));
+        assert_eq!(app.clipboard.as_deref(), Some("two\nTWO"));
+        assert!(app.diff_selection().is_none());
+
+        // The cursor stays put; select the same two lines again.
+        app.perform_action(Action::Up, page);
+        app.perform_action(Action::VisualSelect, page);
+        app.perform_action(Action::Down, page);
+        app.perform_action(Action::Stage, page);
+
+        assert!(app.diff_selection().is_none());
+        assert_eq!(index_content(&test_repo), "one\nTWO\nthree\nfour\n");
+        assert_eq!(
+            app.selected.as_ref().map(|(section, _)| *section),
+            Some(Section::Unstaged)
+        );
+    }
+
+    #[test]
+    fn diff_cursor_moves_with_focus_and_stages_its_line() {
+        let test_repo = edited_repo();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let page = (20, 80);
+        app.perform_action(Action::Select, page);
+
+        // Without focus, navigation stays in the file list.
+        app.perform_action(Action::Down, page);
+        assert_eq!(app.focus, Focus::FileList);
+
+        app.perform_action(Action::ToggleFocus, page);
+        assert_eq!(app.focus, Focus::Diff);
+        assert_eq!(app.diff_cursor, 0);
+        app.perform_action(Action::Bottom, page);
+        let DiffContent::Text(lines) = &app.current_diff else {
+            panic!("expected a text diff");
+        };
+        assert_eq!(app.diff_cursor, lines.len() - 1);
+        assert_eq!(lines[app.diff_cursor].content, "five");
+
+        app.perform_action(Action::Copy, page);
+        assert_eq!(app.clipboard.as_deref(), Some("five"));
+
+        app.perform_action(Action::Stage, page);
+        assert_eq!(index_content(&test_repo), "one\ntwo\nthree\nfour\nfive\n");
+        assert_eq!(app.focus, Focus::Diff);
+
+        app.perform_action(Action::Back, page);
+        assert_eq!(app.focus, Focus::FileList);
+    }
+
+    #[test]
+    fn discard_is_refused_if_the_diff_changed_while_confirming() {
+        let test_repo = edited_repo();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let page = (20, 80);
+        app.perform_action(Action::Select, page);
+        app.perform_action(Action::ToggleFocus, page);
+        app.perform_action(Action::Bottom, page);
+        app.perform_action(Action::Discard, page);
+        assert!(app.confirm_prompt.is_some());
+
+        // "+five" is no longer the third change once "zero" is added.
+        let edited = format!("zero\n{}", NEW);
+        test_repo.write_file("file.txt", &edited);
+        app.refresh_paths(&[test_repo.path().join("file.txt")])
+            .unwrap();
+        app.handle_confirm(true).unwrap();
+        assert_eq!(
+            fs::read_to_string(test_repo.path().join("file.txt")).unwrap(),
+            edited
+        );
+        assert!(app
+            .flash_message
+            .as_ref()
+            .unwrap()
+            .text
+            .contains("changed since the lines were picked"));
+
+        app.perform_action(Action::Bottom, page);
+        app.perform_action(Action::Discard, page);
+        app.handle_confirm(true).unwrap();
+        assert_eq!(
+            fs::read_to_string(test_repo.path().join("file.txt")).unwrap(),
+            "zero\none\nTWO\nthree\nfour\n"
+        );
+    }
+
+    #[test]
+    fn enter_on_a_file_header_selects_that_file() {
+        let test_repo = edited_repo();
+        test_repo.write_file("other.txt", "other\n");
+        test_repo.stage("other.txt");
+        test_repo.commit("add other");
+        test_repo.write_file("other.txt", "changed\n");
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let page = (20, 80);
+        app.perform_action(Action::Select, page);
+        assert_eq!(
+            app.selected,
+            Some((Section::Unstaged, b"file.txt".to_vec()))
+        );
+
+        // Stand-in for a diff spanning several files.
+        let line = |kind, content: &str| DiffLine {
+            kind,
+            content: content.to_string(),
+            new_line_number: None,
+        };
+        app.current_diff = DiffContent::Text(vec![
+            line(DiffLineKind::Header, "diff --git a/file.txt b/file.txt"),
+            line(DiffLineKind::Deleted, "two"),
+            line(DiffLineKind::Header, "diff --git a/other.txt b/other.txt"),
+        ]);
+        app.perform_action(Action::ToggleFocus, page);
+        app.perform_action(Action::Down, page);
+        app.perform_action(Action::Select, page);
+        assert_eq!(
+            app.selected,
+            Some((Section::Unstaged, b"file.txt".to_vec()))
+        );
+
+        app.perform_action(Action::Down, page);
+        app.perform_action(Action::Select, page);
+        assert_eq!(
+            app.selected,
+            Some((Section::Unstaged, b"other.txt".to_vec()))
+        );
+        assert_eq!(app.focus, Focus::Diff);
+        assert_eq!(app.diff_cursor, 0);
+    }
+
+    #[test]
+    fn next_and_prev_file_keep_diff_focus_at_the_top() {
+        let test_repo = edited_repo();
+        test_repo.write_file("other.txt", "other\n");
+        test_repo.stage("other.txt");
+        test_repo.commit("add other");
+        test_repo.write_file("other.txt", "changed\n");
+        test_repo.write_file("untracked.txt", "new\n");
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let page = (20, 80);
+        app.perform_action(Action::Select, page);
+        app.perform_action(Action::ToggleFocus, page);
+        app.perform_action(Action::Bottom, page);
+        assert!(app.diff_cursor > 0);
+
+        app.perform_action(Action::NextFile, page);
+        assert_eq!(
+            app.selected,
+            Some((Section::Unstaged, b"other.txt".to_vec()))
+        );
+        assert_eq!(app.focus, Focus::Diff);
+        assert_eq!((app.diff_cursor, app.diff_scroll), (0, 0));
+
+        // A preview has no cursor, but focus stays on the diff.
+        app.perform_action(Action::NextFile, page);
+        assert_eq!(
+            app.selected,
+            Some((Section::Unstaged, b"untracked.txt".to_vec()))
+        );
+        assert_eq!(app.focus, Focus::Diff);
+
+        app.perform_action(Action::PrevFile, page);
+        app.perform_action(Action::PrevFile, page);
+        assert_eq!(
+            app.selected,
+            Some((Section::Unstaged, b"file.txt".to_vec()))
+        );
+        assert_eq!(app.focus, Focus::Diff);
+    }
+
+    #[test]
+    fn visual_selection_refuses_to_unstage_unstaged_lines() {
+        let test_repo = edited_repo();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let page = (20, 80);
+        app.perform_action(Action::Select, page);
+
+        app.perform_action(Action::VisualSelect, page);
+        app.perform_action(Action::Unstage, page);
+
+        assert!(app.flash_message.is_some());
+        assert_eq!(index_content(&test_repo), OLD);
+    }
+}
+
+mod check_tests {
+    use super::*;
+    use better_git_status::check::{check, TreeState};
+
+    fn check_repo(test_repo: &TestRepo) -> (TreeState, String) {
+        check(test_repo.path().to_str().unwrap(), PreviewConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn clean_tree_exits_zero() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.stage("a.txt");
+        test_repo.commit("initial");
+
+        let (state, summary) = check_repo(&test_repo);
+        assert_eq!(state, TreeState::Clean);
+        assert_eq!(state.exit_code(), 0);
+        assert!(summary.ends_with(": clean"), "{}", summary);
+    }
+
+    #[test]
+    fn changes_exit_one_with_counts() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.write_file("b.txt", "b\n");
+        test_repo.stage("a.txt");
+        test_repo.stage("b.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("a.txt", "changed\n");
+        test_repo.stage("a.txt");
+        test_repo.write_file("b.txt", "changed\n");
+        test_repo.write_file("new.txt", "new\n");
+
+        let (state, summary) = check_repo(&test_repo);
+        assert_eq!(state.exit_code(), 1);
+        assert!(
+            summary.ends_with(": 1 staged, 1 unstaged, 1 untracked"),
+            "{}",
+            summary
+        );
+    }
+
+    #[test]
+    fn untracked_files_are_not_counted_as_unstaged() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.stage("a.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("new.txt", "new\n");
+
+        let (state, summary) = check_repo(&test_repo);
+        assert_eq!(state, TreeState::Dirty);
+        assert!(summary.ends_with(": 1 untracked"), "{}", summary);
+    }
+
+    #[test]
+    fn conflicts_exit_two() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "base\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+
+        let mut index = test_repo.repo.index().unwrap();
+        let mut entry = index.get_path(Path::new("file.txt"), 0).unwrap();
+        index.remove_path(Path::new("file.txt")).unwrap();
+        for stage in 1..=3u16 {
+            entry.id = test_repo
+                .repo
+                .blob(format!("stage {}\n", stage).as_bytes())
+                .unwrap();
+            entry.flags = (entry.flags & !0x3000) | (stage << 12);
+            index.add(&entry).unwrap();
+        }
+        index.write().unwrap();
+
+        let (state, summary) = check_repo(&test_repo);
+        assert_eq!(state, TreeState::Conflicted);
+        assert_eq!(state.exit_code(), 2);
+        assert!(summary.ends_with(": 1 conflicted"), "{}", summary);
+    }
+
+    #[test]
+    fn non_repository_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check(dir.path().to_str().unwrap(), PreviewConfig::default()).is_err());
+    }
+}
+
+mod events_tests {
+    use super::*;
+    use better_git_status::events::{changes, snapshot};
+    use better_git_status::types::{FileStatus, Section};
+
+    #[test]
+    fn snapshot_lists_rows_and_changes_follow_edits() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.stage("a.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("a.txt", "a\nb\n");
+
+        let before = snapshot(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(before.files.len(), 1);
+        assert_eq!(before.files[0].section, Section::Unstaged);
+        assert_eq!(before.files[0].status, FileStatus::Modified);
+        assert_eq!(before.files[0].added_lines, Some(1));
+
+        test_repo.stage("a.txt");
+        test_repo.write_file("new.txt", "new\n");
+        let after = snapshot(&test_repo.repo, PreviewConfig::default()).unwrap();
+        let changed: Vec<_> = changes(&before, &after)
+            .into_iter()
+            .map(|c| (c.section, c.path, c.status))
+            .collect();
+        assert_eq!(
+            changed,
+            vec![
+                (
+                    Section::Staged,
+                    "a.txt".to_string(),
+                    Some(FileStatus::Modified)
+                ),
+                (
+                    Section::Unstaged,
+                    "new.txt".to_string(),
+                    Some(FileStatus::Untracked)
+                ),
+                (Section::Unstaged, "a.txt".to_string(), None),
+            ]
+        );
+    }
+}
+
+mod server_tests {
+    use super::*;
+    use better_git_status::server::handle;
+    use better_git_status::types::Section;
+
+    #[test]
+    fn handle_stages_and_unstages_then_reports_status() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+
+        let response = handle(
+            &test_repo.repo,
+            r#"{"cmd":"stage","paths":["a.txt"]}"#,
+            PreviewConfig::default(),
+        );
+        assert!(response.ok, "{:?}", response.error);
+        let status = response.status.unwrap();
+        assert_eq!(status.files.len(), 1);
+        assert_eq!(status.files[0].section, Section::Staged);
+
+        let response = handle(
+            &test_repo.repo,
+            r#"{"cmd":"unstage_all"}"#,
+            PreviewConfig::default(),
+        );
+        assert_eq!(response.status.unwrap().files[0].section, Section::Unstaged);
+    }
+
+    #[test]
+    fn handle_reports_malformed_requests() {
+        let test_repo = TestRepo::new();
+
+        let response = handle(
+            &test_repo.repo,
+            r#"{"cmd":"commit"}"#,
+            PreviewConfig::default(),
+        );
+        assert!(!response.ok);
+        assert!(response.status.is_none());
+        assert!(response.error.unwrap().contains("unknown variant"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serve_answers_over_unix_socket() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixStream;
+
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        let socket = test_repo.path().join("bgs.sock");
+        let repo_path = test_repo.path().to_str().unwrap().to_string();
+        let server_socket = socket.clone();
+        std::thread::spawn(move || {
+            better_git_status::server::serve(&repo_path, &server_socket, PreviewConfig::default())
+        });
+
+        let mut stream = (0..100)
+            .find_map(|_| {
+                UnixStream::connect(&socket).ok().or_else(|| {
+                    std::thread::sleep(std::time::Duration::from_millis(20));
+                    None
+                })
+            })
+            .expect("server did not start");
+        writeln!(stream, r#"{{"cmd":"status"}}"#).unwrap();
+        let mut reply = String::new();
+        BufReader::new(&stream).read_line(&mut reply).unwrap();
+
+        assert!(reply.starts_with(r#"{"ok":true,"status":{"#), "{}", reply);
+        assert!(reply.contains(r#""path":"a.txt""#), "{}", reply);
+    }
+}
+
+mod print_tests {
+    use super::*;
+    use better_git_status::print::render_diff;
+
+    #[test]
+    fn render_diff_prints_gutter_for_unstaged_and_staged() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("src/lib.rs", "old\n");
+        test_repo.stage("src/lib.rs");
+        test_repo.commit("initial");
+        test_repo.write_file("src/lib.rs", "new\n");
+
+        let subdir = test_repo.path().join("src");
+        let unstaged =
+            render_diff(&subdir, "lib.rs", false, false, PreviewConfig::default()).unwrap();
+        assert!(unstaged.contains("+++ b/src/lib.rs\n"), "{}", unstaged);
+        assert!(unstaged.contains("  - │-old\n"), "{}", unstaged);
+        assert!(unstaged.contains("  1 │+new\n"), "{}", unstaged);
+        assert!(!unstaged.contains('\x1b'));
+
+        let err = render_diff(
+            test_repo.path(),
+            "src/lib.rs",
+            true,
+            false,
+            PreviewConfig::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("No staged changes"));
+
+        test_repo.stage("src/lib.rs");
+        let staged = render_diff(
+            test_repo.path(),
+            "src/lib.rs",
+            true,
+            true,
+            PreviewConfig::default(),
+        )
+        .unwrap();
+        assert!(staged.contains("new"));
+        assert!(staged.contains("\x1b["));
+    }
+
+    #[test]
+    fn render_diff_previews_untracked_file() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("notes.txt", "hello\n");
+
+        let preview = render_diff(
+            test_repo.path(),
+            "./notes.txt",
+            false,
+            false,
+            PreviewConfig::default(),
+        )
+        .unwrap();
+        assert!(preview.contains("hello"), "{}", preview);
+    }
+}
+
+mod custom_command_tests {
+    use super::*;
+    use better_git_status::keymap::{Action, Key};
+    use std::time::Instant;
+
+    const CONFIG: &str = "[[commands]]\nname = \"mark\"\nkey = \"M\"\n\
+                          command = \"touch done && echo {branch} > {files}\"\n";
+
+    #[test]
+    fn key_prepares_command_for_highlighted_file() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a b.txt", "a\n");
+        let mut app = test_repo.app(CONFIG);
+        assert!(app.flash_message.is_none());
+
+        let action = app.resolve_key(Key::char('M'), Instant::now()).unwrap();
+        assert_eq!(action, Action::Custom(0));
+        app.perform_action(action, (20, 80));
+        let command = app.pending_command.take().unwrap();
+        assert_eq!(command.name, "mark");
+        assert!(
+            command.command.ends_with("> 'a b.txt'"),
+            "{}",
+            command.command
+        );
+
+        #[cfg(unix)]
+        {
+            let result = command.run(app.workdir());
+            app.finish_custom_command(&command, result).unwrap();
+            assert!(test_repo.path().join("done").exists());
+            let flash = app.flash_message.as_ref().unwrap();
+            assert!(!flash.is_error, "{}", flash.text);
+            assert!(app.unstaged_files.iter().any(|f| f.path == "done"));
+        }
+    }
+
+    #[test]
+    fn command_without_files_reports_error() {
+        let test_repo = TestRepo::new();
+        let mut app = test_repo.app(CONFIG);
+
+        app.perform_action(Action::Custom(0), (20, 80));
+        assert!(app.pending_command.is_none());
+        assert!(app.flash_message.as_ref().unwrap().is_error);
+    }
+
+    #[test]
+    fn clashing_command_key_is_reported_and_ignored() {
+        let test_repo = TestRepo::new();
+        let mut app =
+            test_repo.app("[[commands]]\nname = \"x\"\nkey = \"s\"\ncommand = \"true\"\n");
+
+        assert!(app
+            .flash_message
+            .as_ref()
+            .unwrap()
+            .text
+            .contains("[[commands]]"));
+        assert_eq!(
+            app.resolve_key(Key::char('s'), Instant::now()),
+            Some(Action::Stage)
+        );
+    }
+}
+
+#[cfg(unix)]
+mod hook_tests {
+    use super::*;
+    use better_git_status::types::ConfirmAction;
+
+    #[test]
+    fn failing_post_stage_hook_reports_its_output() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        let mut app = test_repo
+            .app("[hooks]\npost_stage = 'echo \"$1 has no license header\"; echo more; exit 2'\n");
+
+        app.stage_selected().unwrap();
+
+        assert_eq!(app.staged_files.len(), 1);
+        let flash = app.flash_message.as_ref().unwrap();
+        assert!(flash.is_error);
+        assert_eq!(flash.text, "post_stage hook: a.txt has no license header");
+        let detail = flash.detail.as_ref().unwrap();
+        assert!(detail.chain.iter().any(|line| line.contains("more")));
+        assert!(app
+            .message_history
+            .iter()
+            .any(|m| m.text == "Staged 1 file"));
+    }
+
+    #[test]
+    fn post_discard_hook_output_is_shown() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.stage("a.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("a.txt", "b\n");
+        let mut app = test_repo.app("[hooks]\npost_discard = 'echo \"discarded $#\"'\n");
+
+        app.confirm_prompt = Some(better_git_status::types::ConfirmPrompt {
+            message: String::new(),
+            action: ConfirmAction::DiscardAll,
+        });
+        app.handle_confirm(true).unwrap();
+
+        let flash = app.flash_message.as_ref().unwrap();
+        assert!(!flash.is_error);
+        assert_eq!(flash.text, "discarded 1");
+    }
+
+    #[test]
+    fn silent_successful_hook_keeps_action_message() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        let mut app = test_repo.app("[hooks]\npost_stage = 'true'\n");
+
+        app.stage_selected().unwrap();
+        assert_eq!(app.flash_message.as_ref().unwrap().text, "Staged 1 file");
+    }
+
+    #[test]
+    fn failing_pre_commit_prompt_hook_keeps_prompt_closed() {
+        use better_git_status::keymap::Action;
+        use better_git_status::types::Modal;
+
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.stage("a.txt");
+        let mut app = test_repo.app(
+            "[hooks]\npre_commit_prompt = 'test -e ok || { echo \"$1 not reviewed\"; exit 1; }; echo ready'\n",
+        );
+
+        app.perform_action(Action::InitialCommit, (20, 80));
+        assert!(app.modal.is_none());
+        let flash = app.flash_message.as_ref().unwrap();
+        assert!(flash.is_error);
+        assert_eq!(flash.text, "pre_commit_prompt hook: a.txt not reviewed");
+
+        test_repo.write_file("ok", "");
+        app.perform_action(Action::InitialCommit, (20, 80));
+        assert!(matches!(app.modal, Some(Modal::InitialCommit(_))));
+        let flash = app.flash_message.as_ref().unwrap();
+        assert!(!flash.is_error);
+        assert_eq!(flash.text, "ready");
+    }
+}
+
+#[cfg(unix)]
+mod format_stage_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::keymap::Action;
+
+    /// An app whose formatter is a script with body `script`, kept outside
+    /// the working tree.
+    fn app_with_formatter(test_repo: &TestRepo, script: &str) -> (tempfile::TempDir, App) {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fmt.sh");
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let app = test_repo.app(&format!("[format]\ncommand = '{}'\n", path.display()));
+        (dir, app)
+    }
+
+    #[test]
+    fn formats_then_stages_target_file() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "messy\n");
+        let (_dir, mut app) = app_with_formatter(&test_repo, "for f; do echo tidy > \"$f\"; done");
+        assert!(app
+            .context_hints()
+            .contains(&(Action::FormatStage, "format+stage")));
+
+        app.perform_action(Action::FormatStage, (20, 80));
+
+        let flash = app.flash_message.as_ref().unwrap();
+        assert_eq!(flash.text, "Formatted and staged 1 file");
+        assert!(app.unstaged_files.is_empty());
+        assert_eq!(app.staged_files[0].path, "a.txt");
+        let content = std::fs::read_to_string(test_repo.path().join("a.txt")).unwrap();
+        assert_eq!(content, "tidy\n");
+    }
+
+    #[test]
+    fn failed_formatter_leaves_files_unstaged() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "messy\n");
+        let (_dir, mut app) = app_with_formatter(&test_repo, "echo syntax error in $1; exit 1");
+
+        app.perform_action(Action::FormatStage, (20, 80));
+
+        let flash = app.flash_message.as_ref().unwrap();
+        assert!(flash.is_error);
+        assert_eq!(flash.text, "Formatter failed: syntax error in a.txt");
+        assert!(app.staged_files.is_empty());
+    }
+
+    #[test]
+    fn missing_formatter_is_reported() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "messy\n");
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+
+        app.perform_action(Action::FormatStage, (20, 80));
+        assert!(app.flash_message.as_ref().unwrap().is_error);
+        assert!(app.staged_files.is_empty());
+    }
+}
+
+#[cfg(unix)]
+mod pre_commit_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::keymap::Action;
+    use better_git_status::types::Modal;
+    use std::time::{Duration, Instant};
+
+    /// A repository with a committed pre-commit config, and a stand-in for
+    /// `pre-commit` with body `script`, kept outside the working tree.
+    fn setup(script: &str) -> (TestRepo, tempfile::TempDir, String) {
+        use std::os::unix::fs::PermissionsExt;
+        let test_repo = TestRepo::new();
+        test_repo.write_file(".pre-commit-config.yaml", "repos: []\n");
+        test_repo.stage(".pre-commit-config.yaml");
+        test_repo.commit("add pre-commit config");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pre-commit");
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let program = path.to_str().unwrap().to_string();
+        (test_repo, dir, program)
+    }
+
+    fn wait_for_hooks(app: &mut App) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            app.poll_pre_commit();
+            if matches!(&app.modal, Some(Modal::PreCommit(panel)) if panel.result.is_some()) {
+                return;
+            }
+            assert!(Instant::now() < deadline, "pre-commit run timed out");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn streams_output_and_flags_files_hooks_modified() {
+        let (test_repo, _dir, program) = setup(
+            "echo 'trim trailing whitespace...Failed'; shift 3; for f; do echo fixed > \"$f\"; done; exit 1",
+        );
+        test_repo.write_file("a.txt", "messy   \n");
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        assert!(app.has_pre_commit);
+        assert!(app
+            .context_hints()
+            .contains(&(Action::PreCommit, "pre-commit")));
+
+        app.start_pre_commit(&program).unwrap();
+        wait_for_hooks(&mut app);
+
+        let Some(Modal::PreCommit(panel)) = &app.modal else {
+            panic!("pre-commit panel closed");
+        };
+        assert_eq!(panel.lines, ["trim trailing whitespace...Failed"]);
+        assert!(panel.failed);
+        let flash = app.flash_message.as_ref().unwrap();
+        assert_eq!(
+            flash.text,
+            "pre-commit hooks modified 1 file; review before staging"
+        );
+        assert!(app.needs_review.contains("a.txt"));
+        let content = std::fs::read_to_string(test_repo.path().join("a.txt")).unwrap();
+        assert_eq!(content, "fixed\n");
+
+        app.stage_selected().unwrap();
+        assert!(app.needs_review.is_empty());
+    }
+
+    #[test]
+    fn passing_hooks_flag_nothing() {
+        let (test_repo, _dir, program) = setup("echo ok");
+        test_repo.write_file("a.txt", "tidy\n");
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+
+        app.start_pre_commit(&program).unwrap();
+        wait_for_hooks(&mut app);
+
+        assert_eq!(
+            app.flash_message.as_ref().unwrap().text,
+            "pre-commit passed"
+        );
+        assert!(app.needs_review.is_empty());
+    }
+
+    #[test]
+    fn missing_config_is_reported() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+
+        app.perform_action(Action::PreCommit, (20, 80));
+        let flash = app.flash_message.as_ref().unwrap();
+        assert!(flash.is_error);
+        assert_eq!(flash.text, "No .pre-commit-config.yaml in this repository");
+        assert!(app.modal.is_none());
+    }
+}
+
+mod generated_tests {
+    use super::*;
+    use better_git_status::git::get_status;
+    use better_git_status::keymap::Action;
+    use better_git_status::types::DiffContent;
+
+    #[test]
+    fn attributes_mark_files_generated() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file(
+            ".gitattributes",
+            "*.lock linguist-generated\n*.pb.go -diff\nkeep.lock linguist-generated=false\n",
+        );
+        test_repo.stage(".gitattributes");
+        test_repo.commit("attributes");
+        for name in ["Cargo.lock", "api.pb.go", "keep.lock", "main.rs"] {
+            test_repo.write_file(name, "x\n");
+        }
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        let generated: Vec<_> = status
+            .unstaged_files
+            .iter()
+            .filter(|f| f.generated)
+            .map(|f| f.path.as_str())
+            .collect();
+        assert_eq!(generated, ["Cargo.lock", "api.pb.go"]);
+    }
+
+    #[test]
+    fn generated_diff_starts_collapsed_until_expanded() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("dist/app.js", "one\n");
+        test_repo.stage("dist/app.js");
+        test_repo.commit("initial");
+        test_repo.write_file("dist/app.js", "two\n");
+
+        let mut app = test_repo.app("[generated]\npatterns = [\"dist/*\"]\n");
+        assert!(app.unstaged_files[0].generated);
+
+        app.perform_action(Action::Select, (20, 80));
+        assert!(matches!(
+            app.current_diff,
+            DiffContent::Collapsed {
+                added: Some(1),
+                deleted: Some(1)
+            }
+        ));
+        assert!(app
+            .context_hints()
+            .contains(&(Action::ExpandLines, "expand diff")));
+
+        app.perform_action(Action::ExpandLines, (20, 80));
+        assert!(matches!(app.current_diff, DiffContent::Text(_)));
+
+        // Stays expanded across refreshes.
+        test_repo.write_file("dist/app.js", "three\n");
+        app.perform_action(Action::Select, (20, 80));
+        assert!(matches!(app.current_diff, DiffContent::Text(_)));
+    }
+}
+
+mod minified_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::keymap::Action;
+    use better_git_status::types::DiffContent;
+
+    #[test]
+    fn minified_diff_is_summarized_until_expanded() {
+        let test_repo = TestRepo::new();
+        let bundle = |n: usize| format!("{}\n", "var a=1;".repeat(n));
+        test_repo.write_file("app.min.js", &bundle(2000));
+        test_repo.write_file("small.js", "let a = 1;\n");
+        test_repo.stage("app.min.js");
+        test_repo.stage("small.js");
+        test_repo.commit("initial");
+        test_repo.write_file("app.min.js", &bundle(2001));
+        test_repo.write_file("small.js", "let a = 2;\n");
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.perform_action(Action::Select, (20, 80));
+        assert!(matches!(
+            app.current_diff,
+            DiffContent::Minified {
+                lines: 1,
+                bytes: 16008
+            }
+        ));
+
+        app.perform_action(Action::ExpandLines, (20, 80));
+        assert!(matches!(app.current_diff, DiffContent::Text(_)));
+
+        app.perform_action(Action::Down, (20, 80));
+        app.perform_action(Action::Select, (20, 80));
+        assert!(matches!(app.current_diff, DiffContent::Text(_)));
+    }
+}
+
+mod preview_limit_tests {
+    use super::*;
+    use better_git_status::config::PreviewConfig;
+    use better_git_status::git::{self, DiffFlags};
+    use better_git_status::types::{DiffContent, DiffLineKind, Section};
+
+    #[test]
+    fn text_over_the_limit_is_summarized() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("big.txt", &"line of text\n".repeat(200));
+        test_repo.write_file("small.txt", "hello\n");
+        let preview = PreviewConfig {
+            max_text_kib: 1,
+            ..PreviewConfig::default()
+        };
+
+        let status = git::get_status(&test_repo.repo, preview).unwrap();
+        let diff_of = |path: &str| {
+            let file = status
+                .unstaged_files
+                .iter()
+                .find(|f| f.path == path)
+                .unwrap();
+            git::get_file_diff(
+                &test_repo.repo,
+                file,
+                Section::Unstaged,
+                DiffFlags::default(),
+                false,
+                preview,
+            )
+        };
+        match diff_of("big.txt") {
+            DiffContent::TooLarge(info) => assert_eq!(info.new_size, Some(2600)),
+            other => panic!("expected a size summary, got {:?}", other),
+        }
+        assert!(matches!(diff_of("small.txt"), DiffContent::Preview(_)));
+    }
+
+    #[test]
+    fn diff_over_the_memory_limit_keeps_its_start_and_hunk_index() {
+        let test_repo = TestRepo::new();
+        let original: String = (0..400).map(|i| format!("line {}\n", i)).collect();
+        test_repo.write_file("long.txt", &original);
+        test_repo.stage("long.txt");
+        test_repo.commit("initial");
+        // Every 20th line changes, so each change is its own hunk.
+        let changed: String = (0..400)
+            .map(|i| {
+                if i % 20 == 0 {
+                    format!("changed {}\n", i)
+                } else {
+                    format!("line {}\n", i)
+                }
+            })
+            .collect();
+        test_repo.write_file("long.txt", &changed);
+        let preview = PreviewConfig {
+            max_diff_kib: 1,
+            ..PreviewConfig::default()
+        };
+
+        let lines = match git::get_diff(
+            &test_repo.repo,
+            "long.txt",
+            None,
+            Section::Unstaged,
+            DiffFlags::default(),
+            preview,
+        ) {
+            DiffContent::Text(lines) => lines,
+            other => panic!("expected a text diff, got {:?}", other),
+        };
+        let truncated_at = lines
+            .iter()
+            .position(|l| l.content.starts_with("Diff truncated at 1 KiB"))
+            .expect("truncation notice");
+        assert!(lines[..truncated_at]
+            .iter()
+            .any(|l| l.content == "changed 0"));
+        let index = &lines[truncated_at + 1..lines.len() - 1];
+        assert!(!index.is_empty());
+        assert!(index.iter().all(|l| l.kind == DiffLineKind::Hunk));
+        let hunks = lines
+            .iter()
+            .filter(|l| l.kind == DiffLineKind::Hunk)
+            .count();
+        assert_eq!(hunks, 20);
+        assert!(lines
+            .last()
+            .unwrap()
+            .content
+            .starts_with("Lines not shown: "));
+    }
+}
+
+mod count_link_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::ui::status_bar::CountLink;
+
+    #[test]
+    fn jumps_to_the_section_a_count_stands_for() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.write_file("b.txt", "b\n");
+        test_repo.stage("a.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("a.txt", "changed\n");
+        test_repo.write_file("c.txt", "c\n");
+        test_repo.write_file("d.txt", "d\n");
+        test_repo.stage("d.txt");
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.count_links = vec![(10..14, CountLink::Untracked)];
+        app.click_status_bar(12);
+        assert_eq!(app.highlight_index, Some(2));
+        app.jump_to(CountLink::Unstaged);
+        assert_eq!(app.highlight_index, Some(1));
+        app.jump_to(CountLink::Staged);
+        assert_eq!(app.highlight_index, Some(0));
+    }
+}
+
+mod relative_path_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::keymap::Action;
+
+    #[test]
+    fn opens_from_a_subdirectory_and_toggles_relative_paths() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("src/lib.rs", "fn a() {}\n");
+        let sub = test_repo.path().join("src");
+
+        let mut app = App::new(sub.to_str().unwrap()).unwrap();
+        assert_eq!(app.unstaged_files[0].path, "src/lib.rs");
+        assert_eq!(app.path_base(), None);
+
+        app.launch_dir = Some("src".to_string());
+        app.perform_action(Action::RelativePaths, (20, 80));
+        assert_eq!(app.path_base(), Some("src"));
+        app.perform_action(Action::RelativePaths, (20, 80));
+        assert_eq!(app.path_base(), None);
+
+        app.launch_dir = None;
+        app.perform_action(Action::RelativePaths, (20, 80));
+        assert!(!app.relative_paths);
+    }
+}
+
+mod conflict_kind_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::git::{get_file_diff, get_status, DiffFlags};
+    use better_git_status::keymap::Action;
+    use better_git_status::types::{
+        ConflictKind, ConflictPreview, DiffContent, FileStatus, Section,
+    };
+    use better_git_status::ui::status_bar::CountLink;
+
+    #[test]
+    fn conflicts_are_told_apart_by_their_index_stages() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("base.txt", "base\n");
+        test_repo.stage("base.txt");
+        test_repo.commit("Initial");
+        test_repo.write_file("both.txt", "<<<<<<< ours\n");
+        test_repo.conflict("both.txt", [Some("base\n"), Some("a\n"), Some("b\n")]);
+        test_repo.conflict("new.txt", [None, Some("a\n"), Some("b\n")]);
+        test_repo.conflict("ours.txt", [Some("base\n"), None, Some("b\n")]);
+        test_repo.conflict("theirs.txt", [Some("base\n"), Some("a\n"), None]);
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        let kind = |path: &str| {
+            let file = status
+                .unstaged_files
+                .iter()
+                .find(|f| f.path == path)
+                .unwrap();
+            assert_eq!(file.status, FileStatus::Conflict);
+            file.conflict
+        };
+        assert_eq!(kind("both.txt"), Some(ConflictKind::BothModified));
+        assert_eq!(kind("new.txt"), Some(ConflictKind::BothAdded));
+        assert_eq!(kind("ours.txt"), Some(ConflictKind::DeletedByUs));
+        assert_eq!(kind("theirs.txt"), Some(ConflictKind::DeletedByThem));
+
+        let file = status
+            .unstaged_files
+            .iter()
+            .find(|f| f.path == "ours.txt")
+            .unwrap();
+        let diff = get_file_diff(
+            &test_repo.repo,
+            file,
+            Section::Unstaged,
+            DiffFlags::default(),
+            false,
+            PreviewConfig::default(),
+        );
+        assert!(matches!(
+            diff,
+            DiffContent::Conflict(ConflictPreview {
+                kind: Some(ConflictKind::DeletedByUs),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn region_keys_scroll_between_conflict_markers() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("base.txt", "base\n");
+        test_repo.stage("base.txt");
+        test_repo.commit("Initial");
+        let region = "<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> topic\n";
+        let filler = "line\n".repeat(20);
+        test_repo.write_file(
+            "file.txt",
+            &format!("{filler}{region}{filler}{region}{filler}"),
+        );
+        test_repo.conflict("file.txt", [Some("a\n"), Some("b\n"), Some("c\n")]);
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.perform_action(Action::NextConflict, (10, 80));
+        let DiffContent::Conflict(preview) = &app.current_diff else {
+            panic!("expected a conflict view");
+        };
+        assert_eq!(preview.region_starts(), vec![20, 45]);
+
+        app.perform_action(Action::NextRegion, (10, 80));
+        assert_eq!(app.diff_scroll, 21);
+        app.perform_action(Action::NextRegion, (10, 80));
+        assert_eq!(app.diff_scroll, 46);
+        app.perform_action(Action::NextRegion, (10, 80));
+        assert_eq!(app.diff_scroll, 46);
+        app.perform_action(Action::PrevRegion, (10, 80));
+        assert_eq!(app.diff_scroll, 21);
+    }
+
+    #[test]
+    fn staging_a_file_with_markers_left_asks_first() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("base.txt", "base\n");
+        test_repo.stage("base.txt");
+        test_repo.commit("Initial");
+        test_repo.write_file("file.txt", "<<<<<<< HEAD\na\n=======\nb\n>>>>>>> topic\n");
+        test_repo.conflict("file.txt", [Some("base\n"), Some("a\n"), Some("b\n")]);
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.perform_action(Action::NextConflict, (20, 80));
+        app.perform_action(Action::Stage, (20, 80));
+        let prompt = app.confirm_prompt.as_ref().expect("a confirmation prompt");
+        assert_eq!(
+            prompt.message,
+            "file.txt still has conflict markers. Stage anyway? [y/N]"
+        );
+        app.handle_confirm(false).unwrap();
+        assert_eq!(app.conflict_count(), 1);
+
+        app.perform_action(Action::StageAll, (20, 80));
+        assert!(app
+            .confirm_prompt
+            .as_ref()
+            .is_some_and(|p| p.message.contains("1 still has conflict markers")));
+        app.handle_confirm(false).unwrap();
+
+        test_repo.write_file("file.txt", "a\nb\n");
+        app.perform_action(Action::Stage, (20, 80));
+        assert!(app.confirm_prompt.is_none());
+        assert_eq!(app.conflict_count(), 0);
+        assert_eq!(app.staged_files[0].path, "file.txt");
+    }
+
+    #[test]
+    fn next_conflict_wraps_through_conflicted_files() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("base.txt", "base\n");
+        test_repo.stage("base.txt");
+        test_repo.commit("Initial");
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.conflict("b.txt", [None, Some("a\n"), Some("b\n")]);
+        test_repo.write_file("c.txt", "c\n");
+        test_repo.conflict("d.txt", [None, Some("a\n"), Some("b\n")]);
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        assert_eq!(app.conflict_count(), 2);
+        let highlighted = |app: &App| app.selected_file().map(|(_, f)| f.path.clone());
+
+        app.perform_action(Action::NextConflict, (20, 80));
+        assert_eq!(highlighted(&app).as_deref(), Some("b.txt"));
+        let first = app.highlight_index;
+        app.perform_action(Action::NextConflict, (20, 80));
+        assert_eq!(highlighted(&app).as_deref(), Some("d.txt"));
+        app.perform_action(Action::NextConflict, (20, 80));
+        assert_eq!(highlighted(&app).as_deref(), Some("b.txt"));
+
+        app.perform_action(Action::Bottom, (20, 80));
+        app.jump_to(CountLink::Conflict);
+        assert_eq!(app.highlight_index, first);
+    }
+}
+
+mod state_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::keymap::Action;
+    use better_git_status::types::Section;
+
+    #[test]
+    fn ui_state_restores_highlight_selection_and_toggles() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.write_file("b.txt", "b\n");
+        test_repo.write_file("c.txt", "c\n");
+        let page = (20, 80);
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.perform_action(Action::Down, page);
+        app.perform_action(Action::Select, page);
+        app.perform_action(Action::Down, page);
+        app.perform_action(Action::IgnoreEol, page);
+        let state = app.ui_state();
+
+        let mut restored = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        restored.restore_ui_state(state.clone());
+        assert_eq!(
+            restored.selected,
+            Some((Section::Unstaged, b"b.txt".to_vec()))
+        );
+        assert_eq!(restored.highlight_index, Some(2));
+        assert!(restored.diff_flags.ignore_eol);
+        assert_eq!(restored.ui_state(), state);
+
+        // Files that are no longer changed are skipped.
+        fs::remove_file(test_repo.path().join("b.txt")).unwrap();
+        fs::remove_file(test_repo.path().join("c.txt")).unwrap();
+        let mut restored = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        restored.restore_ui_state(state);
+        assert!(restored.selected.is_none());
+        assert_eq!(restored.highlight_index, Some(0));
+    }
+}
+
+mod branch_tests {
+    use super::*;
+    use better_git_status::git::get_branch_info;
+    use better_git_status::types::BranchInfo;
+
+    #[test]
+    fn get_branch_info_on_branch() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+
+        let info = get_branch_info(&test_repo.repo);
+
+        match info {
+            BranchInfo::Branch(name) => {
+                assert!(name == "main" || name == "master");
+            }
+            _ => panic!("Expected Branch"),
+        }
+    }
+
+    #[test]
+    fn get_branch_info_detached() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+
+        let head = test_repo.repo.head().unwrap();
+        let oid = head.target().unwrap();
+        test_repo.repo.set_head_detached(oid).unwrap();
+
+        let info = get_branch_info(&test_repo.repo);
+
+        match info {
+            BranchInfo::Detached(hash) => {
+                assert_eq!(hash.len(), 7);
+            }
+            _ => panic!("Expected Detached"),
+        }
+    }
+}
+
+mod rebase_tests {
+    use super::*;
+    use better_git_status::git::get_rebase_progress;
+
+    fn write_state(test_repo: &TestRepo, dir: &str, files: &[(&str, &str)]) {
+        let state_dir = test_repo.repo.path().join(dir);
+        fs::create_dir_all(&state_dir).unwrap();
+        for (name, content) in files {
+            fs::write(state_dir.join(name), content).unwrap();
+        }
+    }
+
+    #[test]
+    fn no_rebase_returns_none() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+
+        assert!(get_rebase_progress(&test_repo.repo).is_none());
+    }
+
+    #[test]
+    fn rebase_merge_reads_progress_and_commit_subject() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("Add parser module");
+        let oid = test_repo.repo.head().unwrap().target().unwrap();
+
+        let done = format!("pick 1111111 First\npick {} stale subject\n", oid);
+        write_state(
+            &test_repo,
+            "rebase-merge",
+            &[("msgnum", "4\n"), ("end", "9\n"), ("done", &done)],
+        );
+
+        let progress = get_rebase_progress(&test_repo.repo).unwrap();
+        assert_eq!(progress.current, 4);
+        assert_eq!(progress.total, 9);
+        assert_eq!(progress.subject, "Add parser module");
+        assert_eq!(progress.to_string(), "applying 4/9: Add parser module");
+    }
+
+    #[test]
+    fn rebase_merge_falls_back_to_todo_text() {
+        let test_repo = TestRepo::new();
+        write_state(
+            &test_repo,
+            "rebase-merge",
+            &[
+                ("msgnum", "2"),
+                ("end", "3"),
+                ("done", "pick 1111111 First\nfixup -C deadbeef # Tidy up\n"),
+            ],
+        );
+
+        let progress = get_rebase_progress(&test_repo.repo).unwrap();
+        assert_eq!(progress.current, 2);
+        assert_eq!(progress.total, 3);
+        assert_eq!(progress.subject, "Tidy up");
+    }
+
+    #[test]
+    fn rebase_apply_reads_progress() {
+        let test_repo = TestRepo::new();
+        write_state(
+            &test_repo,
+            "rebase-apply",
+            &[
+                ("next", "1\n"),
+                ("last", "5\n"),
+                ("final-commit-summary", "Apply patch\n"),
+            ],
+        );
+
+        let progress = get_rebase_progress(&test_repo.repo).unwrap();
+        assert_eq!(progress.current, 1);
+        assert_eq!(progress.total, 5);
+        assert_eq!(progress.subject, "Apply patch");
+    }
+
+    #[test]
+    fn app_exposes_rebase_progress() {
+        use better_git_status::app::App;
+
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        write_state(
+            &test_repo,
+            "rebase-merge",
+            &[("msgnum", "1"), ("end", "2"), ("message", "Pending\n")],
+        );
+
+        let app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let progress = app.rebase_progress.as_ref().unwrap();
+        assert_eq!(progress.subject, "Pending");
+    }
+}
+
+mod upstream_tests {
+    use super::*;
+    use better_git_status::git::{get_divergence_graph, get_upstream_info};
+
+    /// Point `origin/<branch>` at HEAD and configure it as the upstream.
+    fn setup_upstream(test_repo: &TestRepo) -> String {
+        let repo = &test_repo.repo;
+        let branch_name = repo.head().unwrap().shorthand().unwrap().to_string();
+        let head_oid = repo.head().unwrap().target().unwrap();
+        repo.remote("origin", "https://example.invalid/repo.git")
+            .unwrap();
+        repo.reference(
+            &format!("refs/remotes/origin/{}", branch_name),
+            head_oid,
+            true,
+            "test upstream",
+        )
+        .unwrap();
+        let mut branch = repo
+            .find_branch(&branch_name, git2::BranchType::Local)
+            .unwrap();
+        branch
+            .set_upstream(Some(&format!("origin/{}", branch_name)))
+            .unwrap();
+        branch_name
+    }
+
+    /// Create a commit on the remote-tracking ref without touching HEAD.
+    fn commit_on_upstream(test_repo: &TestRepo, branch_name: &str, message: &str) {
+        let repo = &test_repo.repo;
+        let refname = format!("refs/remotes/origin/{}", branch_name);
+        let parent = repo
+            .find_reference(&refname)
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        let tree = parent.tree().unwrap();
+        let sig = Signature::now("Test User", "test@example.com").unwrap();
+        repo.commit(Some(&refname), &sig, &sig, message, &tree, &[&parent])
+            .unwrap();
+    }
+
+    #[test]
+    fn upstream_info_none_without_upstream() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+
+        assert!(get_upstream_info(&test_repo.repo).is_none());
+        assert!(get_divergence_graph(&test_repo.repo, 10).is_none());
+    }
+
+    #[test]
+    fn upstream_info_counts_ahead_and_behind() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("base");
+        let branch_name = setup_upstream(&test_repo);
+
+        test_repo.write_file("local.txt", "local\n");
+        test_repo.stage("local.txt");
+        test_repo.commit("local work");
+        commit_on_upstream(&test_repo, &branch_name, "remote one");
+        commit_on_upstream(&test_repo, &branch_name, "remote two");
+
+        let info = get_upstream_info(&test_repo.repo).unwrap();
+        assert_eq!(info.name, format!("origin/{}", branch_name));
+        assert_eq!(info.ahead, 1);
+        assert_eq!(info.behind, 2);
+    }
+
+    #[test]
+    fn divergence_graph_lists_both_sides_and_merge_base() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("base");
+        let branch_name = setup_upstream(&test_repo);
+
+        test_repo.write_file("local.txt", "local\n");
+        test_repo.stage("local.txt");
+        test_repo.commit("local work");
+        commit_on_upstream(&test_repo, &branch_name, "remote one");
+        commit_on_upstream(&test_repo, &branch_name, "remote two");
+
+        let graph = get_divergence_graph(&test_repo.repo, 10).unwrap();
+        let local: Vec<&str> = graph.local.iter().map(|c| c.subject.as_str()).collect();
+        let remote: Vec<&str> = graph.remote.iter().map(|c| c.subject.as_str()).collect();
+        assert_eq!(local, vec!["local work"]);
+        assert_eq!(remote, vec!["remote two", "remote one"]);
+        assert_eq!(graph.merge_base.unwrap().subject, "base");
+    }
+
+    #[test]
+    fn divergence_graph_respects_limit() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("base");
+        let branch_name = setup_upstream(&test_repo);
+
+        for i in 0..4 {
+            commit_on_upstream(&test_repo, &branch_name, &format!("remote {}", i));
+        }
+
+        let graph = get_divergence_graph(&test_repo.repo, 2).unwrap();
+        assert_eq!(graph.remote.len(), 2);
+        assert_eq!(graph.upstream.behind, 4);
+        assert!(graph.local.is_empty());
+    }
+}
+
+mod unborn_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::git::{
+        create_initial_commit, get_branch_info, get_file_diff, get_status, unborn_branch, DiffFlags,
+    };
+    use better_git_status::keymap::Action;
+    use better_git_status::types::{BranchInfo, DiffContent, DiffLineKind, Modal, Section};
+    use crossterm::event::KeyCode;
+
+    #[test]
+    fn staged_files_diff_against_the_empty_tree() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "one\ntwo\n");
+        test_repo.stage("file.txt");
+
+        let branch = unborn_branch(&test_repo.repo).unwrap();
+        assert!(matches!(get_branch_info(&test_repo.repo), BranchInfo::Unborn(b) if b == branch));
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        let file = &status.staged_files[0];
+        assert_eq!(file.added_lines, Some(2));
+        let diff = get_file_diff(
+            &test_repo.repo,
+            file,
+            Section::Staged,
+            DiffFlags::default(),
+            false,
+            PreviewConfig::default(),
+        );
+        let DiffContent::Text(lines) = diff else {
+            panic!("expected a text diff, got {:?}", diff);
+        };
+        let added: Vec<_> = lines
+            .iter()
+            .filter(|l| l.kind == DiffLineKind::Added)
+            .map(|l| l.content.as_str())
+            .collect();
+        assert_eq!(added, ["one", "two"]);
+    }
+
+    #[test]
+    fn initial_commit_is_made_from_the_prompt() {
+        let test_repo = TestRepo::new();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.perform_action(Action::InitialCommit, (20, 80));
+        assert!(app.modal.is_none());
+        assert!(app
+            .flash_message
+            .as_ref()
+            .unwrap()
+            .text
+            .contains("Stage files"));
+
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        assert!(app.branch.to_string().ends_with("(no commits yet)"));
+        app.perform_action(Action::InitialCommit, (20, 80));
+        assert!(app.modal_has_text_input());
+        app.handle_modal_key(KeyCode::Enter);
+        let Some(Modal::InitialCommit(prompt)) = &app.modal else {
+            panic!("Expected commit prompt");
+        };
+        assert_eq!(prompt.error.as_deref(), Some("Enter a commit message"));
+        for c in "Initial commit".chars() {
+            app.handle_modal_key(KeyCode::Char(c));
+        }
+        app.handle_modal_key(KeyCode::Enter);
+
+        assert!(app.modal.is_none());
+        assert!(app.staged_files.is_empty());
+        assert!(matches!(app.branch, BranchInfo::Branch(_)));
+        let head = test_repo.repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("Initial commit"));
+        assert_eq!(head.parent_count(), 0);
+        assert!(create_initial_commit(&test_repo.repo, "again").is_err());
+    }
+}
+
+mod untracked_cache_tests {
+    use super::*;
+    use better_git_status::git::{get_status, get_status_cached, StatusCache};
+    use better_git_status::types::FileStatus;
+    use std::time::{Duration, SystemTime};
+
+    /// Unstaged paths with their status and mode, for comparing a cached
+    /// status with libgit2's own.
+    fn unstaged(status: &better_git_status::git::StatusResult) -> Vec<(String, FileStatus, u32)> {
+        status
+            .unstaged_files
+            .iter()
+            .map(|f| (f.path.clone(), f.status, f.mode))
+            .collect()
+    }
+
+    /// Set every directory's mtime to the same time a minute ago, so the
+    /// cache trusts them and a later scan sees them unchanged.
+    fn settle(dir: &Path, time: SystemTime) {
+        for entry in fs::read_dir(dir).unwrap().flatten() {
+            if entry.file_type().unwrap().is_dir() && entry.file_name() != ".git" {
+                settle(&entry.path(), time);
+            }
+        }
+        fs::File::open(dir).unwrap().set_modified(time).unwrap();
+    }
+
+    fn sample_repo() -> TestRepo {
+        let test_repo = TestRepo::new();
+        test_repo.write_file(".gitignore", "build/\n*.log\n");
+        test_repo.write_file("src/lib.rs", "lib\n");
+        test_repo.stage(".gitignore");
+        test_repo.stage("src/lib.rs");
+        test_repo.commit("Initial");
+        test_repo.write_file("src/new.rs", "new\n");
+        test_repo.write_file("src/deep/er/x.rs", "x\n");
+        test_repo.write_file("notes.txt", "notes\n");
+        test_repo.write_file("debug.log", "log\n");
+        test_repo.write_file("build/out.o", "obj\n");
+        test_repo.write_file("logs/only.log", "log\n");
+        Repository::init(test_repo.path().join("vendor/nested")).unwrap();
+        test_repo.write_file("vendor/nested/file.txt", "x\n");
+        Repository::init(test_repo.path().join("vendor/empty")).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            test_repo.write_file("run.sh", "#!/bin/sh\n");
+            let script = test_repo.path().join("run.sh");
+            fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+            std::os::unix::fs::symlink("notes.txt", test_repo.path().join("link")).unwrap();
+        }
+        test_repo
+    }
+
+    #[test]
+    fn cached_status_matches_libgit2() {
+        let test_repo = sample_repo();
+        let mut cache = StatusCache::default();
+        let expected = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        let cached =
+            get_status_cached(&test_repo.repo, &mut cache, PreviewConfig::default()).unwrap();
+        assert_eq!(unstaged(&cached), unstaged(&expected));
+        assert_eq!(cached.untracked_count, expected.untracked_count);
+        assert!(unstaged(&cached)
+            .iter()
+            .any(|(path, _, _)| path == "src/deep/er/x.rs"));
+
+        let cached =
+            get_status_cached(&test_repo.repo, &mut cache, PreviewConfig::default()).unwrap();
+        assert_eq!(unstaged(&cached), unstaged(&expected));
+
+        // Pairing a deleted file with an untracked one as a rename is left
+        // to libgit2.
+        fs::rename(
+            test_repo.path().join("src/lib.rs"),
+            test_repo.path().join("src/moved.rs"),
+        )
+        .unwrap();
+        let expected = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        let cached =
+            get_status_cached(&test_repo.repo, &mut cache, PreviewConfig::default()).unwrap();
+        assert_eq!(unstaged(&cached), unstaged(&expected));
+    }
+
+    #[test]
+    fn changed_directories_are_rescanned() {
+        let test_repo = sample_repo();
+        let settled = SystemTime::now() - Duration::from_secs(60);
+        settle(test_repo.path(), settled);
+        let mut cache = StatusCache::default();
+        get_status_cached(&test_repo.repo, &mut cache, PreviewConfig::default()).unwrap();
+
+        // An unchanged directory isn't read again...
+        test_repo.write_file("src/unseen.rs", "x\n");
+        settle(test_repo.path(), settled);
+        let cached =
+            get_status_cached(&test_repo.repo, &mut cache, PreviewConfig::default()).unwrap();
+        assert!(!cached
+            .unstaged_files
+            .iter()
+            .any(|f| f.path == "src/unseen.rs"));
+
+        // ...but one whose mtime moved is.
+        test_repo.write_file("src/seen.rs", "x\n");
+        let cached =
+            get_status_cached(&test_repo.repo, &mut cache, PreviewConfig::default()).unwrap();
+        assert!(cached
+            .unstaged_files
+            .iter()
+            .any(|f| f.path == "src/seen.rs"));
+        assert_eq!(
+            unstaged(&cached),
+            unstaged(&get_status(&test_repo.repo, PreviewConfig::default()).unwrap())
+        );
+    }
+
+    #[test]
+    fn ignore_and_index_changes_apply_without_mtime_changes() {
+        let test_repo = sample_repo();
+        let settled = SystemTime::now() - Duration::from_secs(60);
+        settle(test_repo.path(), settled);
+        let mut cache = StatusCache::default();
+        get_status_cached(&test_repo.repo, &mut cache, PreviewConfig::default()).unwrap();
+
+        test_repo.write_file(".gitignore", "build/\n*.log\nsrc/deep/\n");
+        test_repo.stage("notes.txt");
+        settle(test_repo.path(), settled);
+
+        let cached =
+            get_status_cached(&test_repo.repo, &mut cache, PreviewConfig::default()).unwrap();
+        let expected = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(unstaged(&cached), unstaged(&expected));
+        assert!(!cached
+            .unstaged_files
+            .iter()
+            .any(|f| f.path == "src/deep/er/x.rs"));
+        assert!(cached.staged_files.iter().any(|f| f.path == "notes.txt"));
+    }
+
+    #[test]
+    fn disabled_by_core_untracked_cache() {
+        let test_repo = sample_repo();
+        let settled = SystemTime::now() - Duration::from_secs(60);
+        settle(test_repo.path(), settled);
+        let mut config = test_repo.repo.config().unwrap();
+        config.set_bool("core.untrackedCache", false).unwrap();
+        let mut cache = StatusCache::default();
+        get_status_cached(&test_repo.repo, &mut cache, PreviewConfig::default()).unwrap();
+
+        test_repo.write_file("src/unseen.rs", "x\n");
+        settle(test_repo.path(), settled);
+        let cached =
+            get_status_cached(&test_repo.repo, &mut cache, PreviewConfig::default()).unwrap();
+        assert!(cached
+            .unstaged_files
+            .iter()
+            .any(|f| f.path == "src/unseen.rs"));
+    }
+}
+
+mod fsmonitor_tests {
+    use super::*;
+    use better_git_status::fsmonitor::{detect, Fsmonitor};
+
+    #[test]
+    fn detects_daemon_and_hook() {
+        let test_repo = TestRepo::new();
+        assert_eq!(detect(&test_repo.repo), None);
+
+        let mut config = test_repo.repo.config().unwrap();
+        config.set_str("core.fsmonitor", "true").unwrap();
+        let expected = test_repo.repo.path().join("fsmonitor--daemon.ipc");
+        if cfg!(unix) {
+            assert_eq!(detect(&test_repo.repo), Some(Fsmonitor::Daemon(expected)));
+        }
+
+        config
+            .set_str("core.fsmonitor", ".git/hooks/query-watchman")
+            .unwrap();
+        assert_eq!(
+            detect(&test_repo.repo),
+            Some(Fsmonitor::Hook(".git/hooks/query-watchman".to_string()))
+        );
+
+        config.set_bool("core.fsmonitor", false).unwrap();
+        assert_eq!(detect(&test_repo.repo), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hook_changes_reach_the_watcher() {
+        use better_git_status::watcher::{FileWatcher, WatcherEvent};
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, Instant};
+
+        let test_repo = TestRepo::new();
+        // Answers with the paths queued in .git/changes, then forgets them.
+        let hook = test_repo.path().join(".git/fsmonitor-hook");
+        fs::write(
+            &hook,
+            "#!/bin/sh\nprintf 'token\\0'\ncat .git/changes 2>/dev/null\n: > .git/changes\n",
+        )
+        .unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+        let mut config = test_repo.repo.config().unwrap();
+        config
+            .set_str("core.fsmonitor", ".git/fsmonitor-hook")
+            .unwrap();
+
+        let watcher = FileWatcher::start(&test_repo.repo, test_repo.path(), true).unwrap();
+        fs::write(test_repo.path().join(".git/changes"), ".git/index\0").unwrap();
+        std::thread::sleep(Duration::from_millis(800));
+        assert!(watcher.receiver.try_recv().is_err());
+
+        fs::write(test_repo.path().join(".git/changes"), "a.txt\0").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let changed = loop {
+            match watcher.receiver.try_recv() {
+                Ok(WatcherEvent::Changed(paths)) => {
+                    break paths == [test_repo.path().join("a.txt")]
+                }
+                _ if Instant::now() >= deadline => break false,
+                _ => std::thread::sleep(Duration::from_millis(50)),
+            }
+        };
+        assert!(changed);
+    }
+}
+
+mod incremental_refresh_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::git::get_status;
+    use better_git_status::types::FileStatus;
+
+    fn repo_with_two_files() -> TestRepo {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.write_file("b.txt", "b\n");
+        test_repo.stage("a.txt");
+        test_repo.stage("b.txt");
+        test_repo.commit("initial");
+        test_repo
+    }
+
+    fn paths(files: &[better_git_status::types::FileEntry]) -> Vec<(String, FileStatus)> {
+        files.iter().map(|f| (f.path.clone(), f.status)).collect()
+    }
+
+    #[test]
+    fn patches_only_the_changed_paths() {
+        let test_repo = repo_with_two_files();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        assert!(app.unstaged_files.is_empty());
+
+        test_repo.write_file("a.txt", "a\nmore\n");
+        test_repo.write_file("dir/new.txt", "new\n");
+        // b.txt changed too, but the watcher didn't say so: only what it
+        // reported is re-read.
+        test_repo.write_file("b.txt", "changed\n");
+        let root = test_repo.path();
+        app.refresh_paths(&[root.join("a.txt"), root.join("dir")])
+            .unwrap();
+        assert_eq!(
+            paths(&app.unstaged_files),
+            [
+                ("a.txt".to_string(), FileStatus::Modified),
+                ("dir/new.txt".to_string(), FileStatus::Untracked),
+            ]
+        );
+        assert_eq!(app.unstaged_files[0].added_lines, Some(1));
+        assert_eq!((app.unstaged_count, app.untracked_count), (2, 1));
+
+        fs::remove_file(root.join("dir/new.txt")).unwrap();
+        app.refresh_paths(&[root.join("dir/new.txt"), root.join("b.txt")])
+            .unwrap();
+        let full = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(paths(&app.unstaged_files), paths(&full.unstaged_files));
+        assert_eq!(
+            (app.unstaged_count, app.untracked_count),
+            (full.unstaged_count, full.untracked_count)
+        );
+    }
+
+    #[test]
+    fn index_and_deletions_refresh_everything() {
+        let test_repo = repo_with_two_files();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let root = test_repo.path();
+
+        test_repo.write_file("a.txt", "staged\n");
+        test_repo.stage("a.txt");
+        test_repo.write_file("b.txt", "changed\n");
+        app.refresh_paths(&[root.join(".git/index")]).unwrap();
+        assert_eq!(
+            paths(&app.staged_files),
+            [("a.txt".to_string(), FileStatus::Modified)]
+        );
+        assert_eq!(
+            paths(&app.unstaged_files),
+            [("b.txt".to_string(), FileStatus::Modified)]
+        );
+
+        // Once a file is deleted, a new one may be its rename.
+        fs::remove_file(root.join("b.txt")).unwrap();
+        app.refresh_paths(&[root.join("b.txt")]).unwrap();
+        test_repo.write_file("c.txt", "changed\n");
+        app.refresh_paths(&[root.join("c.txt")]).unwrap();
+        let full = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(paths(&app.unstaged_files), paths(&full.unstaged_files));
+    }
+
+    #[test]
+    fn conflicted_file_is_counted_once() {
+        let test_repo = repo_with_two_files();
+        test_repo.conflict("a.txt", [Some("a\n"), Some("ours\n"), Some("theirs\n")]);
+        test_repo.write_file(
+            "a.txt",
+            "<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\n",
+        );
+        test_repo.write_file("b.txt", "changed\n");
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        assert_eq!(app.conflict_count(), 1);
+
+        test_repo.write_file("a.txt", "resolved\n");
+        let root = test_repo.path();
+        app.refresh_paths(&[root.join("a.txt"), root.join("b.txt")])
+            .unwrap();
+        let full = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(paths(&app.staged_files), paths(&full.staged_files));
+        assert_eq!(paths(&app.unstaged_files), paths(&full.unstaged_files));
+        assert_eq!(
+            paths(&app.unstaged_files),
+            [
+                ("a.txt".to_string(), FileStatus::Conflict),
+                ("b.txt".to_string(), FileStatus::Modified),
+            ]
+        );
+        assert_eq!(
+            (app.staged_count, app.unstaged_count, app.untracked_count),
+            (full.staged_count, full.unstaged_count, full.untracked_count)
+        );
+        assert_eq!(app.conflict_count(), 1);
+    }
+}
+
+mod partial_clone_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::git::{get_status, is_partial_clone, missing_blobs};
+    use better_git_status::keymap::Action;
+    use better_git_status::types::{DiffContent, NetworkOp, Section};
+    use std::process::Command;
+    use std::time::{Duration, Instant};
+
+    fn git(dir: &Path, args: &[&str]) -> bool {
+        Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .is_ok_and(|out| out.status.success())
+    }
+
+    /// A blobless clone of a two-commit repo, soft-reset to the first commit
+    /// so a.txt is staged against a blob that was never fetched. `None` if
+    /// git isn't available to make one.
+    fn blobless_clone() -> Option<(TestRepo, TempDir)> {
+        let origin = TestRepo::new();
+        origin.write_file("a.txt", "one\n");
+        origin.stage("a.txt");
+        origin.commit("First");
+        origin.write_file("a.txt", "two\n");
+        origin.stage("a.txt");
+        origin.commit("Second");
+        if !git(origin.path(), &["config", "uploadpack.allowFilter", "true"]) {
+            return None;
+        }
+
+        let dir = TempDir::new().unwrap();
+        let url = format!("file://{}", origin.path().display());
+        let cloned = Command::new("git")
+            .args(["clone", "-q", "--filter=blob:none", &url])
+            .arg(dir.path().join("clone"))
+            .status()
+            .is_ok_and(|status| status.success());
+        let clone = dir.path().join("clone");
+        if !cloned || !git(&clone, &["reset", "-q", "--soft", "HEAD~"]) {
+            return None;
+        }
+        Some((origin, dir))
+    }
+
+    #[test]
+    fn missing_blob_leaves_counts_blank() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "one\n");
+        test_repo.stage("a.txt");
+        test_repo.commit("First");
+        test_repo.write_file("a.txt", "two\n");
+        test_repo.stage("a.txt");
+        let head_blob = test_repo
+            .repo
+            .head()
+            .unwrap()
+            .peel_to_tree()
+            .unwrap()
+            .get_name("a.txt")
+            .unwrap()
+            .id()
+            .to_string();
+        let (dir, file) = head_blob.split_at(2);
+        fs::remove_file(test_repo.path().join(".git/objects").join(dir).join(file)).unwrap();
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        let file = &status.staged_files[0];
+        assert_eq!(file.path, "a.txt");
+        assert_eq!(file.added_lines, None);
+        assert_eq!(
+            missing_blobs(&test_repo.repo, file, Section::Staged)
+                .iter()
+                .map(|oid| oid.to_string())
+                .collect::<Vec<_>>(),
+            vec![head_blob]
+        );
+        assert!(!is_partial_clone(&test_repo.repo));
+    }
+
+    #[test]
+    fn selecting_a_file_fetches_its_blobs() {
+        let Some((_origin, dir)) = blobless_clone() else {
+            return;
+        };
+        let clone = dir.path().join("clone");
+        let repo = Repository::open(&clone).unwrap();
+        assert!(is_partial_clone(&repo));
+
+        let mut app = App::new(clone.to_str().unwrap()).unwrap();
+        assert_eq!(app.staged_files[0].path, "a.txt");
+        assert_eq!(app.staged_files[0].added_lines, None);
+        app.perform_action(Action::Select, (20, 80));
+        // The fetch runs in the background behind a placeholder.
+        assert!(matches!(app.current_diff, DiffContent::Fetching));
+        assert_eq!(
+            app.network_task.as_ref().unwrap().status.op,
+            NetworkOp::FetchObjects
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while app.network_task.is_some() {
+            assert!(Instant::now() < deadline, "object fetch timed out");
+            app.poll_network();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let DiffContent::Text(lines) = &app.current_diff else {
+            panic!("Expected a text diff, got {:?}", app.current_diff);
+        };
+        assert!(lines.iter().any(|line| line.content == "one"));
+        let file = &app.staged_files[0];
+        assert!(missing_blobs(&repo, file, Section::Staged).is_empty());
+    }
+}
+
+mod detached_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::git::{get_branch_info, previous_branch};
+    use better_git_status::keymap::Action;
+    use better_git_status::types::{BranchInfo, Modal};
+    use crossterm::event::KeyCode;
+
+    /// A repo with two commits and HEAD detached at the first, returning
+    /// the branch it was detached from.
+    fn detached_repo() -> (TestRepo, String) {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "one\n");
+        test_repo.stage("a.txt");
+        test_repo.commit("First");
+        let first = test_repo.repo.head().unwrap().target().unwrap();
+        test_repo.write_file("a.txt", "two\n");
+        test_repo.stage("a.txt");
+        test_repo.commit("Second");
+        let BranchInfo::Branch(branch) = get_branch_info(&test_repo.repo) else {
+            panic!("Expected a branch");
+        };
+        {
+            let commit = test_repo.repo.find_commit(first).unwrap();
+            test_repo
+                .repo
+                .checkout_tree(commit.as_object(), None)
+                .unwrap();
+        }
+        test_repo.repo.set_head_detached(first).unwrap();
+        (test_repo, branch)
+    }
+
+    #[test]
+    fn previous_branch_comes_from_the_reflog() {
+        let (test_repo, branch) = detached_repo();
+        assert!(matches!(
+            get_branch_info(&test_repo.repo),
+            BranchInfo::Detached(_)
+        ));
+        assert_eq!(previous_branch(&test_repo.repo), Some(branch));
+    }
+
+    #[test]
+    fn hints_offer_branch_helpers() {
+        let (test_repo, _) = detached_repo();
+        let app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let actions: Vec<Action> = app.context_hints().into_iter().map(|(a, _)| a).collect();
+        assert!(actions.contains(&Action::CreateBranch));
+        assert!(actions.contains(&Action::PreviousBranch));
+    }
+
+    #[test]
+    fn creates_a_branch_at_the_current_commit() {
+        let (test_repo, _) = detached_repo();
+        let detached_at = test_repo.repo.head().unwrap().target();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+
+        app.perform_action(Action::CreateBranch, (20, 80));
+        for c in "fix".chars() {
+            app.handle_modal_key(KeyCode::Char(c));
+        }
+        app.handle_modal_key(KeyCode::Enter);
+
+        assert!(app.modal.is_none());
+        assert!(matches!(&app.branch, BranchInfo::Branch(name) if name == "fix"));
+        assert_eq!(test_repo.repo.head().unwrap().target(), detached_at);
+    }
+
+    #[test]
+    fn existing_branch_name_is_reported_in_the_prompt() {
+        let (test_repo, branch) = detached_repo();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+
+        app.perform_action(Action::CreateBranch, (20, 80));
+        for c in branch.chars() {
+            app.handle_modal_key(KeyCode::Char(c));
+        }
+        app.handle_modal_key(KeyCode::Enter);
+
+        let Some(Modal::NewBranch(prompt)) = &app.modal else {
+            panic!("Expected the branch prompt to stay open");
+        };
+        assert!(prompt.error.is_some());
+    }
+
+    #[test]
+    fn returns_to_the_previous_branch() {
+        let (test_repo, branch) = detached_repo();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+
+        app.perform_action(Action::PreviousBranch, (20, 80));
+
+        assert!(matches!(&app.branch, BranchInfo::Branch(name) if *name == branch));
+        assert_eq!(
+            std::fs::read_to_string(test_repo.path().join("a.txt")).unwrap(),
+            "two\n"
+        );
+    }
+
+    #[test]
+    fn local_changes_block_returning() {
+        let (test_repo, _) = detached_repo();
+        test_repo.write_file("a.txt", "local\n");
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+
+        app.perform_action(Action::PreviousBranch, (20, 80));
+
+        assert!(matches!(app.branch, BranchInfo::Detached(_)));
+        assert_eq!(
+            std::fs::read_to_string(test_repo.path().join("a.txt")).unwrap(),
+            "local\n"
+        );
+    }
+}
+
+mod stash_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::git::{list_stashes, restore_stash_files};
+    use better_git_status::types::{FileStatus, Modal};
+    use crossterm::event::KeyCode;
+
+    /// A repo with a stash of changes to a.txt and b.txt and an untracked
+    /// c.txt, and a clean working tree.
+    fn stashed_repo() -> TestRepo {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.write_file("b.txt", "b\n");
+        test_repo.stage("a.txt");
+        test_repo.stage("b.txt");
+        test_repo.commit("Initial");
+        test_repo.write_file("a.txt", "a stashed\n");
+        test_repo.write_file("b.txt", "b stashed\n");
+        test_repo.write_file("c.txt", "c\n");
+        let mut repo = Repository::open(test_repo.path()).unwrap();
+        let sig = Signature::now("Test User", "test@example.com").unwrap();
+        repo.stash_save(&sig, "wip", Some(git2::StashFlags::INCLUDE_UNTRACKED))
+            .unwrap();
+        test_repo
+    }
+
+    #[test]
+    fn lists_stashes_with_their_files() {
+        let test_repo = stashed_repo();
+        let stashes = list_stashes(&test_repo.repo).unwrap();
+        assert_eq!(stashes.len(), 1);
+        assert_eq!(stashes[0].index, 0);
+        assert!(
+            stashes[0].message.ends_with("wip"),
+            "{}",
+            stashes[0].message
+        );
+        assert_eq!(stashes[0].files, ["a.txt", "b.txt", "c.txt"]);
+
+        assert!(list_stashes(&TestRepo::new().repo).unwrap().is_empty());
+    }
+
+    #[test]
+    fn restores_only_the_chosen_files() {
+        let test_repo = stashed_repo();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        assert!(app.unstaged_files.is_empty());
+
+        app.open_stash_panel();
+        app.handle_modal_key(KeyCode::Enter);
+        app.handle_modal_key(KeyCode::Char(' '));
+        app.handle_modal_key(KeyCode::Down);
+        app.handle_modal_key(KeyCode::Down);
+        app.handle_modal_key(KeyCode::Char(' '));
+        app.handle_modal_key(KeyCode::Enter);
+
+        assert!(app.modal.is_none());
+        let files: Vec<_> = app
+            .unstaged_files
+            .iter()
+            .map(|f| (f.path.as_str(), f.status))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("a.txt", FileStatus::Modified),
+                ("c.txt", FileStatus::Untracked)
+            ]
+        );
+        assert!(app.staged_files.is_empty());
+        let b = fs::read_to_string(test_repo.path().join("b.txt")).unwrap();
+        assert_eq!(b, "b\n");
+        assert_eq!(list_stashes(&test_repo.repo).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn restore_refuses_to_overwrite_local_changes() {
+        let test_repo = stashed_repo();
+        test_repo.write_file("a.txt", "local\n");
+        assert!(restore_stash_files(&test_repo.repo, 0, &["a.txt".to_string()]).is_err());
+        let a = fs::read_to_string(test_repo.path().join("a.txt")).unwrap();
+        assert_eq!(a, "local\n");
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.open_stash_panel();
+        app.handle_modal_key(KeyCode::Enter);
+        app.handle_modal_key(KeyCode::Enter);
+        let Some(Modal::Stashes(panel)) = &app.modal else {
+            panic!("Expected stash panel");
+        };
+        assert!(panel.error.is_some());
+    }
+}
+
+mod remote_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::git::{
+        add_remote, get_upstream_info, list_remotes, remove_remote, rename_remote, set_upstream,
+    };
+    use better_git_status::types::Modal;
+    use crossterm::event::KeyCode;
+
+    fn committed_repo() -> TestRepo {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+        test_repo
+    }
+
+    #[test]
+    fn add_list_rename_remove_remote() {
+        let test_repo = committed_repo();
+
+        add_remote(&test_repo.repo, "origin", "https://example.invalid/a.git").unwrap();
+        let remotes = list_remotes(&test_repo.repo).unwrap();
+        assert_eq!(remotes.len(), 1);
+        assert_eq!(remotes[0].name, "origin");
+        assert_eq!(
+            remotes[0].fetch_url.as_deref(),
+            Some("https://example.invalid/a.git")
+        );
+        assert_eq!(remotes[0].tracking_branches, 0);
+
+        let problems = rename_remote(&test_repo.repo, "origin", "upstream").unwrap();
+        assert!(problems.is_empty());
+        let remotes = list_remotes(&test_repo.repo).unwrap();
+        assert_eq!(remotes[0].name, "upstream");
+
+        remove_remote(&test_repo.repo, "upstream").unwrap();
+        assert!(list_remotes(&test_repo.repo).unwrap().is_empty());
+    }
+
+    #[test]
+    fn set_upstream_without_fetched_branch() {
+        let test_repo = committed_repo();
+        add_remote(&test_repo.repo, "origin", "https://example.invalid/a.git").unwrap();
+
+        set_upstream(&test_repo.repo, "origin/main").unwrap();
+
+        let branch = test_repo
+            .repo
+            .head()
+            .unwrap()
+            .shorthand()
+            .unwrap()
+            .to_string();
+        let config = test_repo.repo.config().unwrap();
+        assert_eq!(
+            config
+                .get_string(&format!("branch.{}.remote", branch))
+                .unwrap(),
+            "origin"
+        );
+        assert_eq!(
+            config
+                .get_string(&format!("branch.{}.merge", branch))
+                .unwrap(),
+            "refs/heads/main"
+        );
+        let remotes = list_remotes(&test_repo.repo).unwrap();
+        assert!(remotes[0].is_upstream);
+        // Upstream branch hasn't been fetched, so there is nothing to compare.
+        assert!(get_upstream_info(&test_repo.repo).is_none());
+    }
+
+    #[test]
+    fn set_upstream_rejects_unknown_remote() {
+        let test_repo = committed_repo();
+        assert!(set_upstream(&test_repo.repo, "nowhere/main").is_err());
+    }
+
+    #[test]
+    fn app_remote_panel_adds_remote_via_keys() {
+        let test_repo = committed_repo();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+
+        app.open_remote_panel();
+        assert!(matches!(app.modal, Some(Modal::Remotes(_))));
+
+        app.handle_modal_key(KeyCode::Char('a'));
+        assert!(app.modal_has_text_input());
+        for c in "origin https://example.invalid/a.git".chars() {
+            app.handle_modal_key(KeyCode::Char(c));
+        }
+        app.handle_modal_key(KeyCode::Enter);
+
+        let Some(Modal::Remotes(panel)) = &app.modal else {
+            panic!("Expected remote panel");
+        };
+        assert_eq!(panel.remotes.len(), 1);
+        assert!(panel.error.is_none());
+        assert!(app.flash_message.as_ref().unwrap().text.contains("Added"));
+
+        app.handle_modal_key(KeyCode::Char('x'));
+        app.handle_modal_key(KeyCode::Char('y'));
+        assert!(list_remotes(&test_repo.repo).unwrap().is_empty());
+
+        app.handle_modal_key(KeyCode::Esc);
+        assert!(app.modal.is_none());
+    }
+
+    #[test]
+    fn app_remote_panel_reports_invalid_add_input() {
+        let test_repo = committed_repo();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+
+        app.open_remote_panel();
+        app.handle_modal_key(KeyCode::Char('a'));
+        app.handle_modal_key(KeyCode::Char('x'));
+        app.handle_modal_key(KeyCode::Enter);
+
+        let Some(Modal::Remotes(panel)) = &app.modal else {
+            panic!("Expected remote panel");
+        };
+        assert!(panel.error.is_some());
+        assert!(list_remotes(&test_repo.repo).unwrap().is_empty());
+    }
+}
+
+mod network_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::credentials::Credentials;
+    use better_git_status::git::{fetch, get_upstream_info, pull, push, PullOutcome};
+    use better_git_status::network::AutoFetch;
+    use better_git_status::types::{CredentialPrompt, Modal, NetworkOp, TransferProgress};
+    use crossterm::event::KeyCode;
+    use std::time::{Duration, Instant};
+
+    fn no_progress(_: TransferProgress) -> bool {
+        true
+    }
+
+    /// Poll the app until its background network operation finishes.
+    fn wait_for_network(app: &mut App) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while app.network_task.is_some() {
+            assert!(Instant::now() < deadline, "network operation timed out");
+            app.poll_network();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// A committed repo with `origin` pointing at a local bare repository.
+    fn repo_with_bare_origin() -> (TestRepo, TempDir) {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+
+        let bare_dir = TempDir::new().unwrap();
+        Repository::init_bare(bare_dir.path()).unwrap();
+        test_repo
+            .repo
+            .remote("origin", bare_dir.path().to_str().unwrap())
+            .unwrap();
+        (test_repo, bare_dir)
+    }
+
+    /// Clone the bare remote, commit to it, and push back.
+    fn commit_from_other_clone(bare_dir: &TempDir, branch: &str, name: &str) {
+        let clone_dir = TempDir::new().unwrap();
+        let clone = Repository::clone(bare_dir.path().to_str().unwrap(), clone_dir.path()).unwrap();
+        fs::write(clone_dir.path().join(name), "remote\n").unwrap();
+        let mut index = clone.index().unwrap();
+        index.add_path(Path::new(name)).unwrap();
+        index.write().unwrap();
+        let tree = clone.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = Signature::now("Other User", "other@example.com").unwrap();
+        let parent = clone.head().unwrap().peel_to_commit().unwrap();
+        clone
+            .commit(Some("HEAD"), &sig, &sig, "remote change", &tree, &[&parent])
+            .unwrap();
+        let mut remote = clone.find_remote("origin").unwrap();
+        let refspec = format!("refs/heads/{}:refs/heads/{}", branch, branch);
+        remote.push(&[refspec.as_str()], None).unwrap();
+    }
+
+    fn branch_name(test_repo: &TestRepo) -> String {
+        test_repo
+            .repo
+            .head()
+            .unwrap()
+            .shorthand()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn push_sets_upstream_when_missing() {
+        let (test_repo, _bare) = repo_with_bare_origin();
+        let branch = branch_name(&test_repo);
+
+        let destination = push(&test_repo.repo, &mut Credentials::new(), &mut no_progress).unwrap();
+
+        assert_eq!(destination, format!("origin/{}", branch));
+        let info = get_upstream_info(&test_repo.repo).unwrap();
+        assert_eq!(info.ahead, 0);
+        assert_eq!(info.behind, 0);
+    }
+
+    #[test]
+    fn fetch_then_pull_fast_forwards() {
+        let (test_repo, bare) = repo_with_bare_origin();
+        let branch = branch_name(&test_repo);
+        push(&test_repo.repo, &mut Credentials::new(), &mut no_progress).unwrap();
+        commit_from_other_clone(&bare, &branch, "remote.txt");
+
+        assert_eq!(
+            fetch(&test_repo.repo, &mut Credentials::new(), &mut no_progress).unwrap(),
+            "origin"
+        );
+        assert_eq!(get_upstream_info(&test_repo.repo).unwrap().behind, 1);
+
+        let outcome = pull(&test_repo.repo, &mut Credentials::new(), &mut no_progress).unwrap();
+        assert_eq!(outcome, PullOutcome::FastForwarded);
+        assert!(test_repo.path().join("remote.txt").exists());
+        assert_eq!(get_upstream_info(&test_repo.repo).unwrap().behind, 0);
+
+        let outcome = pull(&test_repo.repo, &mut Credentials::new(), &mut no_progress).unwrap();
+        assert_eq!(outcome, PullOutcome::UpToDate);
+    }
+
+    #[test]
+    fn pull_refuses_diverged_branch() {
+        let (test_repo, bare) = repo_with_bare_origin();
+        let branch = branch_name(&test_repo);
+        push(&test_repo.repo, &mut Credentials::new(), &mut no_progress).unwrap();
+        commit_from_other_clone(&bare, &branch, "remote.txt");
+        test_repo.write_file("local.txt", "local\n");
+        test_repo.stage("local.txt");
+        test_repo.commit("local change");
+
+        let err = pull(&test_repo.repo, &mut Credentials::new(), &mut no_progress).unwrap_err();
+        assert!(err.to_string().contains("diverged"));
+        assert!(!test_repo.path().join("remote.txt").exists());
+    }
+
+    #[test]
+    fn pull_requires_upstream() {
+        let (test_repo, _bare) = repo_with_bare_origin();
+        assert!(pull(&test_repo.repo, &mut Credentials::new(), &mut no_progress).is_err());
+    }
+
+    #[test]
+    fn push_refuses_detached_head() {
+        let (test_repo, _bare) = repo_with_bare_origin();
+        let head = test_repo.repo.head().unwrap().target().unwrap();
+        test_repo.repo.set_head_detached(head).unwrap();
+
+        let err = push(&test_repo.repo, &mut Credentials::new(), &mut no_progress).unwrap_err();
+        assert!(err.to_string().contains("detached HEAD"));
+    }
+
+    #[test]
+    fn fetch_without_remote_reports_error() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+
+        let err = fetch(&test_repo.repo, &mut Credentials::new(), &mut no_progress).unwrap_err();
+        assert!(err.to_string().contains("No remote named 'origin'"));
+    }
+
+    #[test]
+    fn app_push_flashes_destination() {
+        let (test_repo, _bare) = repo_with_bare_origin();
+        let branch = branch_name(&test_repo);
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+
+        app.run_network_op(NetworkOp::Push);
+        assert!(app.network_task.is_some());
+        wait_for_network(&mut app);
+
+        let flash = app.flash_message.as_ref().unwrap();
+        assert!(!flash.is_error);
+        assert_eq!(flash.text, format!("Pushed to origin/{}", branch));
+        assert!(app.upstream.is_some());
+        assert!(app.modal.is_none());
+    }
+
+    #[test]
+    fn fetch_reports_progress_and_can_be_cancelled() {
+        let (test_repo, bare) = repo_with_bare_origin();
+        let branch = branch_name(&test_repo);
+        push(&test_repo.repo, &mut Credentials::new(), &mut no_progress).unwrap();
+        commit_from_other_clone(&bare, &branch, "remote.txt");
+
+        let mut reports = Vec::new();
+        let result = fetch(&test_repo.repo, &mut Credentials::new(), &mut |progress| {
+            reports.push(progress);
+            false
+        });
+
+        assert!(result.is_err());
+        assert_eq!(reports.len(), 1);
+        assert_eq!(get_upstream_info(&test_repo.repo).unwrap().behind, 0);
+    }
+
+    #[test]
+    fn app_rejects_second_network_op_while_running() {
+        let (test_repo, _bare) = repo_with_bare_origin();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+
+        app.run_network_op(NetworkOp::Push);
+        app.run_network_op(NetworkOp::Fetch);
+
+        let flash = app.flash_message.as_ref().unwrap();
+        assert!(flash.is_error);
+        assert_eq!(flash.text, "Push already in progress");
+        assert_eq!(
+            app.network_task.as_ref().unwrap().status.op,
+            NetworkOp::Push
+        );
+        wait_for_network(&mut app);
+    }
+
+    #[test]
+    fn app_auto_fetch_updates_behind_count_silently() {
+        let (test_repo, bare) = repo_with_bare_origin();
+        let branch = branch_name(&test_repo);
+        push(&test_repo.repo, &mut Credentials::new(), &mut no_progress).unwrap();
+        commit_from_other_clone(&bare, &branch, "remote.txt");
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let interval = Duration::from_secs(60);
+        app.auto_fetch = Some(AutoFetch::new(interval, Instant::now()));
+        app.maybe_auto_fetch(Instant::now() + interval);
+        assert!(app.network_task.as_ref().unwrap().automatic);
+        assert!(!app.cancel_network_op());
+        wait_for_network(&mut app);
+
+        assert_eq!(app.upstream.as_ref().unwrap().behind, 1);
+        assert!(app.flash_message.is_none());
+        assert_eq!(
+            app.upstream_notice.as_ref().unwrap().to_string(),
+            format!("↓1 new on origin/{} — pull?", branch)
+        );
+        assert!(!app.auto_fetch.as_ref().unwrap().is_due(Instant::now()));
+    }
+
+    #[test]
+    fn app_auto_fetch_backs_off_after_failure() {
+        let (test_repo, bare) = repo_with_bare_origin();
+        push(&test_repo.repo, &mut Credentials::new(), &mut no_progress).unwrap();
+        drop(bare);
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let interval = Duration::from_secs(60);
+        app.auto_fetch = Some(AutoFetch::new(interval, Instant::now()));
+        app.maybe_auto_fetch(Instant::now() + interval);
+        wait_for_network(&mut app);
+
+        let flash = app.flash_message.as_ref().unwrap();
+        assert!(flash.is_error);
+        assert!(flash.text.starts_with("Auto-fetch failed"));
+        let auto = app.auto_fetch.as_ref().unwrap();
+        assert_eq!(auto.failures(), 1);
+        assert!(!auto.is_due(Instant::now()));
+    }
+
+    #[test]
+    fn app_auto_fetch_skips_branch_without_upstream() {
+        let (test_repo, _bare) = repo_with_bare_origin();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let interval = Duration::from_secs(60);
+        app.auto_fetch = Some(AutoFetch::new(interval, Instant::now()));
+        app.maybe_auto_fetch(Instant::now() + interval);
+        assert!(app.network_task.is_none());
+    }
+
+    #[test]
+    fn app_upstream_notice_dismissed_until_more_commits_then_cleared_by_pull() {
+        let (test_repo, bare) = repo_with_bare_origin();
+        let branch = branch_name(&test_repo);
+        push(&test_repo.repo, &mut Credentials::new(), &mut no_progress).unwrap();
+        commit_from_other_clone(&bare, &branch, "one.txt");
 
-        stage_files(
-            &test_repo.repo,
-            &["file1.txt".to_string(), "file3.txt".to_string()],
-        )
-        .unwrap();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        assert!(app.upstream_notice.is_none());
+
+        app.run_network_op(NetworkOp::Fetch);
+        wait_for_network(&mut app);
+        assert_eq!(app.upstream_notice.as_ref().unwrap().behind, 1);
+
+        assert!(app.dismiss_upstream_notice());
+        assert!(!app.dismiss_upstream_notice());
+        app.run_network_op(NetworkOp::Fetch);
+        wait_for_network(&mut app);
+        assert!(app.upstream_notice.is_none());
+
+        commit_from_other_clone(&bare, &branch, "two.txt");
+        app.run_network_op(NetworkOp::Fetch);
+        wait_for_network(&mut app);
+        assert_eq!(app.upstream_notice.as_ref().unwrap().behind, 2);
+
+        app.run_network_op(NetworkOp::Pull);
+        wait_for_network(&mut app);
+        assert!(app.upstream_notice.is_none());
+        assert_eq!(app.upstream.as_ref().unwrap().behind, 0);
+    }
 
-        let status = get_status(&test_repo.repo).unwrap();
-        assert_eq!(status.staged_files.len(), 2);
-        assert_eq!(status.unstaged_files.len(), 1);
-        assert_eq!(status.unstaged_files[0].path, "file2.txt");
+    #[test]
+    fn app_credential_prompt_collects_fields() {
+        let (test_repo, _bare) = repo_with_bare_origin();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.modal = Some(Modal::Credentials(CredentialPrompt {
+            op: NetworkOp::Fetch,
+            url: "https://example.invalid/repo.git".to_string(),
+            username: String::new(),
+            password: String::new(),
+            editing_password: false,
+        }));
+        assert!(app.modal_has_text_input());
+
+        app.handle_modal_key(KeyCode::Char('m'));
+        app.handle_modal_key(KeyCode::Char('e'));
+        app.handle_modal_key(KeyCode::Enter);
+        app.handle_modal_key(KeyCode::Char('p'));
+        app.handle_modal_key(KeyCode::Char('w'));
+        app.handle_modal_key(KeyCode::Backspace);
+
+        let Some(Modal::Credentials(prompt)) = &app.modal else {
+            panic!("Expected credential prompt");
+        };
+        assert_eq!(prompt.username, "me");
+        assert_eq!(prompt.password, "p");
+        assert!(prompt.editing_password);
+
+        app.handle_modal_key(KeyCode::Esc);
+        assert!(app.modal.is_none());
     }
 }
 
-mod branch_tests {
+mod hint_tests {
     use super::*;
-    use better_git_status::git::get_branch_info;
-    use better_git_status::types::BranchInfo;
+    use better_git_status::app::App;
+    use better_git_status::keymap::Action;
+
+    fn labels(app: &App) -> Vec<&'static str> {
+        app.context_hints().into_iter().map(|(_, l)| l).collect()
+    }
 
     #[test]
-    fn get_branch_info_on_branch() {
+    fn hints_follow_highlighted_file() {
         let test_repo = TestRepo::new();
-        test_repo.write_file("file.txt", "content\n");
-        test_repo.stage("file.txt");
+        test_repo.write_file("tracked.txt", "one\n");
+        test_repo.stage("tracked.txt");
         test_repo.commit("initial");
+        test_repo.write_file("tracked.txt", "two\n");
+        test_repo.write_file("new.txt", "new\n");
+        test_repo.write_file("staged.txt", "staged\n");
+        test_repo.stage("staged.txt");
 
-        let info = get_branch_info(&test_repo.repo);
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        // Rows: staged.txt (staged), new.txt (untracked), tracked.txt (modified).
+        assert_eq!(labels(&app), vec!["unstage", "mark", "quit"]);
 
-        match info {
-            BranchInfo::Branch(name) => {
-                assert!(name == "main" || name == "master");
-            }
-            _ => panic!("Expected Branch"),
-        }
+        app.move_highlight(1);
+        assert_eq!(labels(&app), vec!["add", "delete", "mark", "quit"]);
+
+        app.move_highlight(1);
+        assert_eq!(labels(&app), vec!["stage", "discard", "mark", "quit"]);
+        assert_eq!(app.context_hints()[0].0, Action::Stage);
     }
 
     #[test]
-    fn get_branch_info_detached() {
+    fn hints_for_marked_files() {
         let test_repo = TestRepo::new();
-        test_repo.write_file("file.txt", "content\n");
-        test_repo.stage("file.txt");
-        test_repo.commit("initial");
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.stage("a.txt");
+        test_repo.write_file("b.txt", "b\n");
 
-        let head = test_repo.repo.head().unwrap();
-        let oid = head.target().unwrap();
-        test_repo.repo.set_head_detached(oid).unwrap();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.toggle_multi_select();
+        assert_eq!(labels(&app), vec!["unstage marked", "clear marks", "quit"]);
 
-        let info = get_branch_info(&test_repo.repo);
+        app.move_highlight(1);
+        app.toggle_multi_select();
+        assert_eq!(
+            labels(&app),
+            vec![
+                "stage marked",
+                "unstage marked",
+                "discard marked",
+                "clear marks",
+                "quit"
+            ]
+        );
+    }
 
-        match info {
-            BranchInfo::Detached(hash) => {
-                assert_eq!(hash.len(), 7);
-            }
-            _ => panic!("Expected Detached"),
-        }
+    #[test]
+    fn hints_for_clean_tree() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.stage("a.txt");
+        test_repo.commit("initial");
+
+        let app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        assert_eq!(labels(&app), vec!["fetch", "quit"]);
     }
 }
 
 mod app_stage_unstage_tests {
     use super::*;
     use better_git_status::app::App;
+    use better_git_status::config::Config;
     use better_git_status::git::get_status;
+    use better_git_status::keymap::{Action, Key};
     use better_git_status::types::{FileStatus, Section};
+    use std::time::Instant;
 
     #[test]
     fn app_stage_selected_single_file() {
@@ -470,7 +4091,7 @@ mod app_stage_unstage_tests {
         assert_eq!(app.unstaged_count, 0);
         assert_eq!(app.staged_count, 1);
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         assert!(status.unstaged_files.is_empty());
         assert_eq!(status.staged_files.len(), 1);
         assert_eq!(status.staged_files[0].status, FileStatus::Added);
@@ -492,7 +4113,7 @@ mod app_stage_unstage_tests {
         assert_eq!(app.staged_count, 0);
         assert_eq!(app.unstaged_count, 1);
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         assert!(status.staged_files.is_empty());
         assert_eq!(status.unstaged_files.len(), 1);
     }
@@ -519,7 +4140,7 @@ mod app_stage_unstage_tests {
         assert_eq!(app.staged_count, 2);
         assert!(app.multi_selected.is_empty());
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         assert_eq!(status.staged_files.len(), 2);
     }
 
@@ -561,9 +4182,9 @@ mod app_stage_unstage_tests {
         assert_eq!(app.unstaged_count, 1);
 
         app.multi_selected
-            .insert((Section::Staged, "staged.txt".to_string()));
+            .insert((Section::Staged, b"staged.txt".to_vec()));
         app.multi_selected
-            .insert((Section::Unstaged, "unstaged.txt".to_string()));
+            .insert((Section::Unstaged, b"unstaged.txt".to_vec()));
 
         app.stage_selected().unwrap();
 
@@ -581,9 +4202,9 @@ mod app_stage_unstage_tests {
         let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
 
         app.multi_selected
-            .insert((Section::Staged, "staged.txt".to_string()));
+            .insert((Section::Staged, b"staged.txt".to_vec()));
         app.multi_selected
-            .insert((Section::Unstaged, "unstaged.txt".to_string()));
+            .insert((Section::Unstaged, b"unstaged.txt".to_vec()));
 
         app.unstage_selected().unwrap();
 
@@ -691,6 +4312,157 @@ mod app_stage_unstage_tests {
         assert!(app.flash_message.is_none());
         assert!(app.last_action.is_none());
     }
+
+    #[test]
+    fn app_two_key_sequences_move_highlight() {
+        let test_repo = TestRepo::new();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            test_repo.write_file(name, "content\n");
+        }
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let now = Instant::now();
+
+        assert_eq!(app.resolve_key(Key::char('G'), now), Some(Action::Bottom));
+        assert!(app.perform_action(Action::Bottom, (10, 80)));
+        assert_eq!(app.highlight_index, Some(2));
+
+        assert_eq!(app.resolve_key(Key::char('g'), now), None);
+        assert_eq!(app.key_sequence.pending(), &[Key::char('g')]);
+        let action = app.resolve_key(Key::char('g'), now).unwrap();
+        assert!(app.perform_action(action, (10, 80)));
+        assert_eq!(app.highlight_index, Some(0));
+    }
+
+    #[test]
+    fn app_toggles_hexdump_for_binary_files() {
+        use better_git_status::types::DiffContent;
+
+        let test_repo = TestRepo::new();
+        std::fs::write(test_repo.path().join("data.bin"), b"\0\x01\x02").unwrap();
+        test_repo.write_file("a.txt", "text\n");
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.highlight_last();
+        app.perform_action(Action::Select, (10, 80));
+        assert!(matches!(app.current_diff, DiffContent::Binary(_)));
+
+        app.perform_action(Action::Hexdump, (10, 80));
+        assert!(matches!(app.current_diff, DiffContent::Hexdump(_)));
+        app.perform_action(Action::Hexdump, (10, 80));
+        assert!(matches!(app.current_diff, DiffContent::Binary(_)));
+
+        app.highlight_first();
+        app.perform_action(Action::Select, (10, 80));
+        app.perform_action(Action::Hexdump, (10, 80));
+        assert!(app.flash_message.as_ref().unwrap().is_error);
+    }
+
+    #[test]
+    fn app_expands_long_lines_on_demand() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "short\n");
+        test_repo.write_file("long.txt", &format!("{}\n", "x".repeat(5_000)));
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+
+        app.highlight_first();
+        app.perform_action(Action::Select, (10, 80));
+        app.perform_action(Action::ExpandLines, (10, 80));
+        assert!(!app.expand_long_lines);
+        assert!(app.flash_message.as_ref().unwrap().is_error);
+
+        app.highlight_last();
+        app.perform_action(Action::Select, (10, 80));
+        app.perform_action(Action::ExpandLines, (10, 80));
+        assert!(app.expand_long_lines);
+        app.perform_action(Action::ExpandLines, (10, 80));
+        assert!(!app.expand_long_lines);
+    }
+
+    #[test]
+    fn app_key_overrides_from_config() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "content\n");
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        let mut config = Config::default();
+        config
+            .keys
+            .overrides
+            .insert("discard".to_string(), "d d".to_string());
+        app.apply_config(config);
+        let now = Instant::now();
+
+        assert_eq!(app.resolve_key(Key::char('d'), now), None);
+        assert_eq!(app.resolve_key(Key::char('d'), now), Some(Action::Discard));
+        assert!(app.perform_action(Action::Discard, (10, 80)));
+        assert!(app.confirm_prompt.is_some());
+
+        let mut config = Config::default();
+        config
+            .keys
+            .overrides
+            .insert("stage".to_string(), "q".to_string());
+        app.apply_config(config);
+        assert!(app
+            .flash_message
+            .as_ref()
+            .unwrap()
+            .text
+            .contains("conflicts"));
+        assert_eq!(app.resolve_key(Key::char('s'), now), Some(Action::Stage));
+    }
+
+    #[test]
+    fn app_quit_actions_stop_the_loop() {
+        let test_repo = TestRepo::new();
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        assert!(!app.perform_action(Action::Quit, (10, 80)));
+        assert!(!app.perform_action(Action::Back, (10, 80)));
+    }
+
+    #[test]
+    fn app_selected_target_ignores_highlight() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.write_file("b.txt", "b\n");
+        let mut app = test_repo.app("[actions]\ntarget = \"selected\"\n");
+
+        app.stage_selected().unwrap();
+        assert_eq!(app.staged_count, 0);
+        assert!(app.flash_message.as_ref().unwrap().is_error);
+
+        app.selected = Some((Section::Unstaged, b"b.txt".to_vec()));
+        app.stage_selected().unwrap();
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(status.staged_files.len(), 1);
+        assert_eq!(status.staged_files[0].path, "b.txt");
+    }
+
+    #[test]
+    fn app_multi_select_target_requires_marked_files() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.write_file("b.txt", "b\n");
+        let mut app = test_repo.app("[actions]\ntarget = \"multi_select\"\n");
+
+        app.stage_selected().unwrap();
+        app.show_discard_selected_confirm();
+        assert_eq!(app.staged_count, 0);
+        assert!(app.confirm_prompt.is_none());
+        assert!(app
+            .flash_message
+            .as_ref()
+            .unwrap()
+            .text
+            .contains("No files marked"));
+
+        app.move_highlight(1);
+        app.toggle_multi_select();
+        app.stage_selected().unwrap();
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(status.staged_files.len(), 1);
+        assert_eq!(status.staged_files[0].path, "b.txt");
+    }
 }
 
 mod confirm_prompt_tests {
@@ -900,7 +4672,9 @@ mod confirm_prompt_tests {
 mod discard_tests {
     use super::*;
     use better_git_status::app::App;
-    use better_git_status::git::{discard_all_unstaged, discard_unstaged_file, discard_untracked_file, get_status};
+    use better_git_status::git::{
+        discard_all_unstaged, discard_unstaged_file, discard_untracked_file, get_status,
+    };
     use better_git_status::types::FileStatus;
     use std::fs;
 
@@ -912,7 +4686,7 @@ mod discard_tests {
         test_repo.commit("initial");
         test_repo.write_file("file.txt", "modified\n");
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         assert_eq!(status.unstaged_files.len(), 1);
         assert_eq!(status.unstaged_files[0].status, FileStatus::Modified);
 
@@ -921,7 +4695,7 @@ mod discard_tests {
         let content = fs::read_to_string(test_repo.path().join("file.txt")).unwrap();
         assert_eq!(content, "original\n");
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         assert!(status.unstaged_files.is_empty());
     }
 
@@ -930,7 +4704,7 @@ mod discard_tests {
         let test_repo = TestRepo::new();
         test_repo.write_file("untracked.txt", "content\n");
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         assert_eq!(status.unstaged_files.len(), 1);
         assert_eq!(status.unstaged_files[0].status, FileStatus::Untracked);
 
@@ -938,7 +4712,7 @@ mod discard_tests {
 
         assert!(!test_repo.path().join("untracked.txt").exists());
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         assert!(status.unstaged_files.is_empty());
     }
 
@@ -952,7 +4726,7 @@ mod discard_tests {
         fs::remove_file(test_repo.path().join("file.txt")).unwrap();
         assert!(!test_repo.path().join("file.txt").exists());
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         assert_eq!(status.unstaged_files.len(), 1);
         assert_eq!(status.unstaged_files[0].status, FileStatus::Deleted);
 
@@ -962,7 +4736,7 @@ mod discard_tests {
         let content = fs::read_to_string(test_repo.path().join("file.txt")).unwrap();
         assert_eq!(content, "original content\n");
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         assert!(status.unstaged_files.is_empty());
     }
 
@@ -975,7 +4749,7 @@ mod discard_tests {
         test_repo.write_file("file1.txt", "modified\n");
         test_repo.write_file("untracked.txt", "new content\n");
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         assert_eq!(status.unstaged_files.len(), 2);
 
         let (discarded, skipped) = discard_all_unstaged(&test_repo.repo).unwrap();
@@ -986,7 +4760,7 @@ mod discard_tests {
         assert_eq!(content, "original\n");
         assert!(!test_repo.path().join("untracked.txt").exists());
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         assert!(status.unstaged_files.is_empty());
     }
 
@@ -1182,7 +4956,11 @@ mod discard_tests {
 
         test_repo
             .repo
-            .branch("branch1", &test_repo.repo.find_commit(base_oid).unwrap(), false)
+            .branch(
+                "branch1",
+                &test_repo.repo.find_commit(base_oid).unwrap(),
+                false,
+            )
             .unwrap();
 
         test_repo.write_file("file.txt", "branch1 content\n");
@@ -1198,25 +4976,25 @@ mod discard_tests {
             .unwrap();
         test_repo.repo.checkout_head(None).unwrap();
 
+        test_repo.repo.set_head("refs/heads/branch1").unwrap();
         test_repo
             .repo
-            .set_head("refs/heads/branch1")
+            .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
             .unwrap();
-        test_repo.repo.checkout_head(Some(
-            git2::build::CheckoutBuilder::new().force(),
-        )).unwrap();
 
         test_repo
             .repo
-            .branch("branch2", &test_repo.repo.find_commit(base_oid).unwrap(), false)
+            .branch(
+                "branch2",
+                &test_repo.repo.find_commit(base_oid).unwrap(),
+                false,
+            )
             .unwrap();
+        test_repo.repo.set_head("refs/heads/branch2").unwrap();
         test_repo
             .repo
-            .set_head("refs/heads/branch2")
+            .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
             .unwrap();
-        test_repo.repo.checkout_head(Some(
-            git2::build::CheckoutBuilder::new().force(),
-        )).unwrap();
 
         test_repo.write_file("file.txt", "branch2 content\n");
         test_repo.stage("file.txt");
@@ -1236,7 +5014,7 @@ mod discard_tests {
             .unwrap();
         let _ = test_repo.repo.merge(&[&annotated], None, None);
 
-        let status = get_status(&test_repo.repo).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
         let has_conflict = status
             .unstaged_files
             .iter()
@@ -1258,3 +5036,348 @@ mod discard_tests {
         }
     }
 }
+
+mod recover_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::git::{
+        backup_discard, discard_unstaged_file, list_discard_backups, list_stashes,
+        restore_discard_backup,
+    };
+    use better_git_status::types::Modal;
+    use crossterm::event::KeyCode;
+    use std::fs;
+
+    #[test]
+    fn discarded_files_can_be_recovered_from_the_panel() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.stage("a.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("a.txt", "a changed\n");
+        test_repo.write_file("new.txt", "new\n");
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.show_discard_all_confirm();
+        app.handle_confirm(true).unwrap();
+        assert!(app.unstaged_files.is_empty());
+
+        let stashes = list_stashes(&test_repo.repo).unwrap();
+        assert_eq!(stashes.len(), 1);
+        assert!(stashes[0]
+            .message
+            .ends_with(": better-git-status discard: a.txt, new.txt"));
+        assert_eq!(stashes[0].files, ["a.txt", "new.txt"]);
+
+        let backups = list_discard_backups(&test_repo.repo).unwrap();
+        assert_eq!(backups.len(), 1);
+        let paths: Vec<_> = backups[0].files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["a.txt", "new.txt"]);
+        assert_eq!(
+            backups[0].files[0].preview.as_deref(),
+            Some(&["a changed".to_string()][..])
+        );
+
+        app.open_recover_panel();
+        app.handle_modal_key(KeyCode::Enter);
+        app.handle_modal_key(KeyCode::Char('a'));
+        assert!(app.modal.is_none());
+        assert_eq!(app.unstaged_files.len(), 2);
+        let a = fs::read_to_string(test_repo.path().join("a.txt")).unwrap();
+        assert_eq!(a, "a changed\n");
+        let new = fs::read_to_string(test_repo.path().join("new.txt")).unwrap();
+        assert_eq!(new, "new\n");
+    }
+
+    #[test]
+    fn recover_refuses_to_overwrite_changes_made_since() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.stage("a.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("a.txt", "a changed\n");
+
+        assert!(backup_discard(&test_repo.repo, &["a.txt"]).unwrap());
+        discard_unstaged_file(&test_repo.repo, "a.txt").unwrap();
+        test_repo.write_file("a.txt", "later\n");
+
+        assert!(restore_discard_backup(&test_repo.repo, 0, &["a.txt"]).is_err());
+        let a = fs::read_to_string(test_repo.path().join("a.txt")).unwrap();
+        assert_eq!(a, "later\n");
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.open_recover_panel();
+        app.handle_modal_key(KeyCode::Enter);
+        app.handle_modal_key(KeyCode::Enter);
+        let Some(Modal::Recover(panel)) = &app.modal else {
+            panic!("Expected recover panel");
+        };
+        assert!(panel.error.is_some());
+    }
+
+    #[test]
+    fn other_stashes_are_not_listed() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("a.txt", "a\n");
+        test_repo.stage("a.txt");
+        test_repo.commit("initial");
+        test_repo.write_file("a.txt", "a changed\n");
+        assert!(backup_discard(&test_repo.repo, &["a.txt"]).unwrap());
+        let sig = Signature::now("Test User", "test@example.com").unwrap();
+        let mut repo = Repository::open(test_repo.path()).unwrap();
+        repo.stash_save(&sig, "mine", None).unwrap();
+
+        let backups = list_discard_backups(&test_repo.repo).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].index, 1);
+        assert_eq!(backups[0].files[0].path, "a.txt");
+    }
+
+    #[test]
+    fn nothing_to_back_up_makes_no_backup() {
+        let test_repo = TestRepo::new();
+        assert!(!backup_discard(&test_repo.repo, &["missing.txt"]).unwrap());
+        assert!(list_discard_backups(&test_repo.repo).unwrap().is_empty());
+    }
+}
+
+mod symlink_tests {
+    use super::*;
+    use better_git_status::git::{
+        discard_unstaged_file, discard_untracked_file, get_diff, get_status, get_untracked_preview,
+        stage_files, unstage_files, DiffFlags,
+    };
+    use better_git_status::types::{DiffContent, FileStatus, Section};
+    use std::os::unix::fs::symlink;
+
+    fn retarget(test_repo: &TestRepo, name: &str, target: &str) {
+        let path = test_repo.path().join(name);
+        std::fs::remove_file(&path).unwrap();
+        symlink(target, &path).unwrap();
+    }
+
+    fn targets(diff: DiffContent) -> (Option<String>, Option<String>) {
+        match diff {
+            DiffContent::Symlink {
+                old_target,
+                new_target,
+            } => (old_target, new_target),
+            other => panic!("Expected Symlink, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn symlink_retarget_diff_stage_unstage_discard() {
+        let test_repo = TestRepo::new();
+        symlink("old-target", test_repo.path().join("link")).unwrap();
+        test_repo.stage("link");
+        test_repo.commit("initial");
+        retarget(&test_repo, "link", "new-target");
+
+        let unstaged = get_diff(
+            &test_repo.repo,
+            "link",
+            None,
+            Section::Unstaged,
+            DiffFlags::default(),
+            PreviewConfig::default(),
+        );
+        assert_eq!(
+            targets(unstaged),
+            (
+                Some("old-target".to_string()),
+                Some("new-target".to_string())
+            )
+        );
+
+        stage_files(&test_repo.repo, &["link".to_string()]).unwrap();
+        let staged = get_diff(
+            &test_repo.repo,
+            "link",
+            None,
+            Section::Staged,
+            DiffFlags::default(),
+            PreviewConfig::default(),
+        );
+        assert_eq!(targets(staged).1, Some("new-target".to_string()));
+
+        unstage_files(&test_repo.repo, &["link".to_string()]).unwrap();
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert!(status.staged_files.is_empty());
+        assert_eq!(status.unstaged_files[0].status, FileStatus::Modified);
+
+        discard_unstaged_file(&test_repo.repo, "link").unwrap();
+        let target = std::fs::read_link(test_repo.path().join("link")).unwrap();
+        assert_eq!(target.to_str(), Some("old-target"));
+    }
+
+    #[test]
+    fn dangling_symlink_is_staged_not_removed() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("file.txt", "content\n");
+        test_repo.stage("file.txt");
+        test_repo.commit("initial");
+        symlink("does-not-exist", test_repo.path().join("dangling")).unwrap();
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(status.unstaged_files[0].status, FileStatus::Untracked);
+        assert!(!status.unstaged_files[0].is_binary);
+        assert_eq!(
+            targets(get_untracked_preview(&test_repo.repo, "dangling")),
+            (None, Some("does-not-exist".to_string()))
+        );
+
+        stage_files(&test_repo.repo, &["dangling".to_string()]).unwrap();
+        let index = test_repo.repo.index().unwrap();
+        let entry = index.get_path(Path::new("dangling"), 0).unwrap();
+        assert_eq!(entry.mode, 0o120000);
+    }
+
+    #[test]
+    fn discard_untracked_symlink_keeps_target() {
+        let test_repo = TestRepo::new();
+        test_repo.write_file("target.txt", "keep me\n");
+        symlink("target.txt", test_repo.path().join("link")).unwrap();
+
+        discard_untracked_file(&test_repo.repo, "link").unwrap();
+        assert!(std::fs::symlink_metadata(test_repo.path().join("link")).is_err());
+        assert!(test_repo.path().join("target.txt").exists());
+    }
+}
+
+#[cfg(unix)]
+mod non_utf8_path_tests {
+    use super::*;
+    use better_git_status::app::App;
+    use better_git_status::git::{
+        discard_unstaged_file, discard_untracked_file, get_diff, get_status, DiffFlags,
+    };
+    use better_git_status::types::{DiffContent, FileStatus, Section};
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    const NAME: &[u8] = b"caf\xe9.txt";
+
+    fn write_raw(test_repo: &TestRepo, content: &str) {
+        fs::write(test_repo.path().join(OsStr::from_bytes(NAME)), content).unwrap();
+    }
+
+    #[test]
+    fn non_utf8_path_is_listed_lossily() {
+        let test_repo = TestRepo::new();
+        write_raw(&test_repo, "hello\n");
+
+        let status = get_status(&test_repo.repo, PreviewConfig::default()).unwrap();
+        assert_eq!(status.unstaged_files.len(), 1);
+        let file = &status.unstaged_files[0];
+        assert_eq!(file.path, "caf\u{fffd}.txt");
+        assert_eq!(file.raw_path, NAME);
+        assert_eq!(file.status, FileStatus::Untracked);
+        assert_eq!(file.added_lines, Some(1));
+    }
+
+    #[test]
+    fn app_stages_and_unstages_non_utf8_path() {
+        let test_repo = TestRepo::new();
+        write_raw(&test_repo, "hello\n");
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.stage_selected().unwrap();
+        assert_eq!(app.staged_count, 1);
+        assert_eq!(app.unstaged_count, 0);
+
+        let index = test_repo.repo.index().unwrap();
+        assert!(index
+            .get_path(Path::new(OsStr::from_bytes(NAME)), 0)
+            .is_some());
+
+        app.undo().unwrap();
+        assert_eq!(app.staged_count, 0);
+        assert_eq!(app.unstaged_count, 1);
+    }
+
+    #[test]
+    fn names_shown_alike_are_acted_on_separately() {
+        let test_repo = TestRepo::new();
+        let other: &[u8] = b"caf\xe8.txt";
+        write_raw(&test_repo, "hello\n");
+        fs::write(test_repo.path().join(OsStr::from_bytes(other)), "x\n").unwrap();
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        assert_eq!(app.unstaged_files.len(), 2);
+        assert_eq!(app.unstaged_files[0].path, app.unstaged_files[1].path);
+
+        app.highlight_index = Some(1);
+        let picked = app.unstaged_files[1].raw_path.clone();
+        let left = app.unstaged_files[0].raw_path.clone();
+        app.stage_selected().unwrap();
+        assert_eq!(app.staged_files.len(), 1);
+        assert_eq!(app.staged_files[0].raw_path, picked);
+        assert_eq!(app.unstaged_files[0].raw_path, left);
+
+        app.undo().unwrap();
+        assert_eq!(app.staged_count, 0);
+        assert_eq!(app.unstaged_count, 2);
+
+        app.highlight_index = Some(0);
+        app.stage_selected().unwrap();
+        app.highlight_index = Some(1);
+        app.show_discard_selected_confirm();
+        app.handle_confirm(true).unwrap();
+        assert_eq!(app.staged_files[0].raw_path, left);
+        assert!(app.unstaged_files.is_empty());
+        assert!(test_repo.path().join(OsStr::from_bytes(&left)).exists());
+        assert!(!test_repo.path().join(OsStr::from_bytes(&picked)).exists());
+    }
+
+    #[test]
+    fn names_shown_alike_restore_to_their_own_row() {
+        let test_repo = TestRepo::new();
+        let other: &[u8] = b"caf\xe8.txt";
+        write_raw(&test_repo, "hello\n");
+        fs::write(test_repo.path().join(OsStr::from_bytes(other)), "x\n").unwrap();
+
+        let mut app = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        app.highlight_index = Some(1);
+        let state = app.ui_state();
+
+        let mut restored = App::new(test_repo.path().to_str().unwrap()).unwrap();
+        restored.restore_ui_state(state);
+        assert_eq!(restored.highlight_index, Some(1));
+    }
+
+    #[test]
+    fn non_utf8_path_diff_and_discard() {
+        let test_repo = TestRepo::new();
+        write_raw(&test_repo, "old\n");
+        let mut index = test_repo.repo.index().unwrap();
+        index.add_path(Path::new(OsStr::from_bytes(NAME))).unwrap();
+        index.write().unwrap();
+        test_repo.commit("add");
+        write_raw(&test_repo, "new\n");
+
+        let diff = get_diff(
+            &test_repo.repo,
+            NAME,
+            None,
+            Section::Unstaged,
+            DiffFlags::default(),
+            PreviewConfig::default(),
+        );
+        let DiffContent::Text(lines) = diff else {
+            panic!("expected text diff, got {:?}", diff);
+        };
+        assert!(lines.iter().any(|l| l.content == "new"));
+
+        discard_unstaged_file(&test_repo.repo, NAME).unwrap();
+        let content = fs::read_to_string(test_repo.path().join(OsStr::from_bytes(NAME))).unwrap();
+        assert_eq!(content, "old\n");
+
+        fs::write(test_repo.path().join(OsStr::from_bytes(b"\xff.tmp")), "x").unwrap();
+        discard_untracked_file(&test_repo.repo, b"\xff.tmp").unwrap();
+        assert!(get_status(&test_repo.repo, PreviewConfig::default())
+            .unwrap()
+            .unstaged_files
+            .is_empty());
+    }
+}